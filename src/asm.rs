@@ -0,0 +1,1315 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use crate::bus::{Bus, Ram};
+use crate::cpu::*;
+
+// Assembler for Motorola syntax sources.
+//
+// Sources are parsed once into statements, INCLUDE and INCBIN being
+// expanded while reading them. Passes then lay the statements out until
+// the symbols and the branch sizes stop changing: branches given no size
+// start short and grow when their target is out of reach. A last pass
+// builds every instruction as an `Instruction` and encodes it.
+//
+// Operands are written as the disassembler prints them in Motorola
+// syntax: d16(An), d8(An,Xn.s*scale) or their (d,An) forms, target(pc),
+// $1234.w for short absolute addresses. Instruction lengths only depend
+// on how operands are written, absolute addresses being long unless
+// given .w.
+
+//Most layout passes before giving up on symbols settling
+const MAX_PASSES: usize = 64;
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+//Where and why assembling stopped
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+//Bytes laid out from an ORG
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub org: u32,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub chunks: Vec<Chunk>,
+    //Labels and EQU values, local labels prefixed by their global label
+    pub symbols: HashMap<String, u32>,
+}
+
+impl Program {
+    //Copies the chunks to the RAM behind the CPU's bus, where the model's
+    //address bus puts them. Nothing is loaded when one doesn't fit.
+    pub fn load<B: Bus + AsRef<Ram> + AsMut<Ram>>(&self, cpu: &mut CPU<B>) -> Result<(), String> {
+        let mask = cpu.get_model().address_mask();
+        let size = cpu.bus().as_ref().len();
+        if let Some(chunk) = self.chunks.iter().find(|chunk| (chunk.org & mask) as usize + chunk.bytes.len() > size) {
+            return Err(format!("{} bytes at ${:x} don't fit in the RAM", chunk.bytes.len(), chunk.org));
+        }
+        for chunk in &self.chunks {
+            cpu.load((chunk.org & mask) as usize, &chunk.bytes);
+        }
+        Ok(())
+    }
+
+    //The chunks as a single image starting at the lowest address, gaps
+    //filled with zeros, with that address
+    pub fn image(&self) -> (u32, Vec<u8>) {
+        let start = self.chunks.iter().map(|chunk| chunk.org).min().unwrap_or(0);
+        let mut image = Vec::new();
+        for chunk in &self.chunks {
+            let at = (chunk.org - start) as usize;
+            if image.len() < at + chunk.bytes.len() {
+                image.resize(at + chunk.bytes.len(), 0);
+            }
+            image[at..(at + chunk.bytes.len())].copy_from_slice(&chunk.bytes);
+        }
+        (start, image)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    NUMBER(i64),
+    SYMBOL(String),
+    //*, the address of the statement
+    HERE,
+    NEG(Box<Expr>),
+    NOT(Box<Expr>),
+    BINARY(&'static str, Box<Expr>, Box<Expr>),
+}
+
+//Binary operators from the loosest to the tightest binding
+const OPERATORS: [&[&str]; 6] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+
+impl Expr {
+    fn eval(&self, lookup: &dyn Fn(&str) -> Option<i64>, here: u32) -> Result<i64, String> {
+        Ok(match self {
+            Expr::NUMBER(val) => *val,
+            Expr::SYMBOL(name) => lookup(name).ok_or_else(|| format!("undefined symbol {}", name))?,
+            Expr::HERE => here as i64,
+            Expr::NEG(expr) => expr.eval(lookup, here)?.wrapping_neg(),
+            Expr::NOT(expr) => !expr.eval(lookup, here)?,
+            Expr::BINARY(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(lookup, here)?, rhs.eval(lookup, here)?);
+                match *op {
+                    "|" => lhs | rhs,
+                    "^" => lhs ^ rhs,
+                    "&" => lhs & rhs,
+                    "<<" => lhs.wrapping_shl(rhs as u32),
+                    ">>" => lhs.wrapping_shr(rhs as u32),
+                    "+" => lhs.wrapping_add(rhs),
+                    "-" => lhs.wrapping_sub(rhs),
+                    "*" => lhs.wrapping_mul(rhs),
+                    _ if rhs == 0 => return Err("division by zero".to_string()),
+                    "/" => lhs.wrapping_div(rhs),
+                    _ => lhs.wrapping_rem(rhs),
+                }
+            },
+        })
+    }
+}
+
+//Offset or width of a bitfield operand
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    VALUE(Expr),
+    REGISTER(usize),
+}
+
+//Operands as written, PC relative ones holding their target
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    DATA(usize),
+    ADDRESS(usize),
+    INDIRECT(usize),
+    POSTINCREMENT(usize),
+    PREDECREMENT(usize),
+    //d(An), or d(PC) without a register
+    DISPLACEMENT(Expr, Option<usize>),
+    //d(An,Xn), or d(PC,Xn) without a register
+    INDEXED(Expr, Option<usize>, Index),
+    //Short when given .w
+    ABSOLUTE(Expr, bool),
+    IMMEDIATE(Expr),
+    SR,
+    CCR,
+    USP,
+    CONTROL(u16),
+    //Bit 0 is D0 and bit 15 is A7
+    LIST(u16),
+    //Dx:Dy
+    PAIR(usize, usize),
+    BITFIELD(Box<Operand>, Field, Field),
+}
+
+//Register names
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Name {
+    DATA(usize),
+    ADDRESS(usize),
+    PC,
+    SR,
+    CCR,
+    USP,
+    CONTROL(u16),
+}
+
+fn _name(name: &str) -> Option<Name> {
+    let number = |name: &str| name.parse::<usize>().ok().filter(|&reg| reg < 8);
+    Some(match name.to_ascii_lowercase().as_str() {
+        "sp" => Name::ADDRESS(7),
+        "pc" => Name::PC,
+        "sr" => Name::SR,
+        "ccr" => Name::CCR,
+        "usp" => Name::USP,
+        "sfc" => Name::CONTROL(0x000),
+        "dfc" => Name::CONTROL(0x001),
+        "cacr" => Name::CONTROL(0x002),
+        "vbr" => Name::CONTROL(0x801),
+        "caar" => Name::CONTROL(0x802),
+        "msp" => Name::CONTROL(0x803),
+        "isp" => Name::CONTROL(0x804),
+        name if name.len() == 2 && name.starts_with('d') => Name::DATA(number(&name[1..])?),
+        name if name.len() == 2 && name.starts_with('a') => Name::ADDRESS(number(&name[1..])?),
+        _ => return None,
+    })
+}
+
+fn _condition(name: &str) -> Option<Condition> {
+    use Condition::*;
+    Some(match name {
+        "t" => T,
+        "f" => F,
+        "hi" => HI,
+        "ls" => LS,
+        "cc" | "hs" => CC,
+        "cs" | "lo" => CS,
+        "ne" => NE,
+        "eq" => EQ,
+        "vc" => VC,
+        "vs" => VS,
+        "pl" => PL,
+        "mi" => MI,
+        "ge" => GE,
+        "lt" => LT,
+        "gt" => GT,
+        "le" => LE,
+        _ => return None,
+    })
+}
+
+//Mnemonics as written, ADD standing for ADDA and ADDI as well until the
+//operands are known. The FPU and MMU instructions aren't assembled.
+fn _mnemonic(name: &str) -> Option<Mnemonic> {
+    Some(match name {
+        "move" => MOVE,
+        "movea" => MOVEA,
+        "moveq" => MOVEQ,
+        "movem" => MOVEM,
+        "movep" => MOVEP,
+        "movec" => MOVEC,
+        "moves" => MOVES,
+        "add" => ADD,
+        "adda" => ADDA,
+        "addi" => ADDI,
+        "addq" => ADDQ,
+        "addx" => ADDX,
+        "sub" => SUB,
+        "suba" => SUBA,
+        "subi" => SUBI,
+        "subq" => SUBQ,
+        "subx" => SUBX,
+        "and" => AND,
+        "andi" => ANDI,
+        "or" => OR,
+        "ori" => ORI,
+        "eor" => EOR,
+        "eori" => EORI,
+        "not" => NOT,
+        "neg" => NEG,
+        "negx" => NEGX,
+        "clr" => CLR,
+        "tst" => TST,
+        "cmp" => CMP,
+        "cmpa" => CMPA,
+        "cmpi" => CMPI,
+        "cmpm" => CMPM,
+        "mulu" => MULU,
+        "muls" => MULS,
+        "divu" => DIVU,
+        "divs" => DIVS,
+        "divul" => DIVUL,
+        "divsl" => DIVSL,
+        "abcd" => ABCD,
+        "sbcd" => SBCD,
+        "nbcd" => NBCD,
+        "asl" => ASL,
+        "asr" => ASR,
+        "lsl" => LSL,
+        "lsr" => LSR,
+        "rol" => ROL,
+        "ror" => ROR,
+        "roxl" => ROXL,
+        "roxr" => ROXR,
+        "btst" => BTST,
+        "bchg" => BCHG,
+        "bclr" => BCLR,
+        "bset" => BSET,
+        "bftst" => BFTST,
+        "bfextu" => BFEXTU,
+        "bfchg" => BFCHG,
+        "bfexts" => BFEXTS,
+        "bfclr" => BFCLR,
+        "bfffo" => BFFFO,
+        "bfset" => BFSET,
+        "bfins" => BFINS,
+        "lea" => LEA,
+        "pea" => PEA,
+        "jmp" => JMP,
+        "jsr" => JSR,
+        "bra" => BRA,
+        "bsr" => BSR,
+        "dbra" => DBCC(Condition::F),
+        "rts" => RTS,
+        "rte" => RTE,
+        "rtr" => RTR,
+        "rtd" => RTD,
+        "trap" => TRAP,
+        "trapv" => TRAPV,
+        "tpf" => TRAPCC(Condition::F),
+        "chk" => CHK,
+        "chk2" => CHK2,
+        "cmp2" => CMP2,
+        "cas" => CAS,
+        "link" => LINK,
+        "unlk" => UNLK,
+        "swap" => SWAP,
+        "ext" => EXT,
+        "extb" => EXTB,
+        "exg" => EXG,
+        "nop" => NOP,
+        "reset" => RESET,
+        "stop" => STOP,
+        "illegal" => ILLEGAL,
+        "tas" => TAS,
+        "mov3q" => MOV3Q,
+        "mvs" => MVS,
+        "mvz" => MVZ,
+        "sats" => SATS,
+        name => {
+            if let Some(cond) = name.strip_prefix("db").and_then(_condition) {
+                DBCC(cond)
+            }
+            else if let Some(cond) = name.strip_prefix("trap").and_then(_condition) {
+                TRAPCC(cond)
+            }
+            else if let Some(cond) = name.strip_prefix('s').and_then(_condition) {
+                SCC(cond)
+            }
+            else {
+                match name.strip_prefix('b').and_then(_condition) {
+                    Some(Condition::T | Condition::F) | None => return None,
+                    Some(cond) => BCC(cond),
+                }
+            }
+        },
+    })
+}
+
+//Items of DC, strings only going in bytes
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    VALUE(Expr),
+    TEXT(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Body {
+    NONE,
+    ORG(Expr),
+    EQU(Expr),
+    DC(OpSize, Vec<Item>),
+    DS(OpSize, Expr),
+    EVEN,
+    INCLUDE(String),
+    INCBIN(String),
+    BINARY(Vec<u8>),
+    //Mnemonic with the size letter it was given
+    INSTRUCTION(Mnemonic, Option<u8>, Vec<Operand>),
+    END,
+}
+
+struct Statement {
+    file: usize,
+    line: usize,
+    label: Option<String>,
+    body: Body,
+}
+
+fn _name_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_'
+}
+
+fn _name_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+//Reads a line of source. Spaces end the operand field, what follows
+//them being a comment, except right after a comma.
+struct Cursor<'a> {
+    text: &'a [u8],
+    pos: usize,
+    //Global label local labels belong to
+    scope: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.eat(c) { Ok(()) } else { Err(format!("expected {}", c as char)) }
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    //The end of the line or a comment
+    fn at_end(&self) -> bool {
+        matches!(self.peek(), None | Some(b';'))
+    }
+
+    //Nothing but a comment may follow
+    fn finish(&mut self) -> Result<(), String> {
+        match self.peek() {
+            None | Some(b';') => Ok(()),
+            Some(c) if c.is_ascii_whitespace() => Ok(()),
+            Some(_) => Err(format!("unexpected {}", String::from_utf8_lossy(&self.text[self.pos..]))),
+        }
+    }
+
+    fn take_while(&mut self, pred: impl Fn(u8) -> bool) -> &'a str {
+        let start = self.pos;
+        while self.peek().is_some_and(&pred) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.text[start..self.pos]).unwrap_or_default()
+    }
+
+    //Label or symbol, local ones starting with a dot and getting the
+    //current global label in front
+    fn symbol(&mut self) -> Option<String> {
+        let start = self.pos;
+        let local = self.eat(b'.');
+        if !self.peek().is_some_and(_name_start) {
+            self.pos = start;
+            return None;
+        }
+        let name = self.take_while(_name_char);
+        Some(if local { format!("{}.{}", self.scope, name) } else { name.to_string() })
+    }
+
+    fn register(&mut self) -> Option<Name> {
+        let start = self.pos;
+        let name = _name(self.take_while(_name_char));
+        if name.is_none() {
+            self.pos = start;
+        }
+        name
+    }
+
+    //.w or .l, true for .l
+    fn size_suffix(&mut self) -> Option<bool> {
+        let start = self.pos;
+        if self.eat(b'.') {
+            match self.take_while(_name_char).to_ascii_lowercase().as_str() {
+                "w" => return Some(false),
+                "l" => return Some(true),
+                _ => {},
+            }
+        }
+        self.pos = start;
+        None
+    }
+
+    fn quoted(&mut self) -> Result<Vec<u8>, String> {
+        let quote = self.peek().ok_or("expected a string")?;
+        self.pos += 1;
+        let mut text = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err("unterminated string".to_string()),
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    if !self.eat(quote) {
+                        return Ok(text);
+                    }
+                    text.push(quote);
+                },
+                Some(c) => {
+                    self.pos += 1;
+                    text.push(c);
+                },
+            }
+        }
+    }
+
+    //Path of INCLUDE and INCBIN, quoted or not
+    fn path(&mut self) -> Result<String, String> {
+        let path = if matches!(self.peek(), Some(b'"' | b'\'')) {
+            self.quoted()?
+        }
+        else {
+            self.take_while(|c| !c.is_ascii_whitespace() && c != b';').as_bytes().to_vec()
+        };
+        if path.is_empty() {
+            return Err("expected a file name".to_string());
+        }
+        self.finish()?;
+        Ok(String::from_utf8_lossy(&path).into_owned())
+    }
+
+    fn number(&mut self, radix: u32) -> Result<Expr, String> {
+        let digits = self.take_while(_name_char);
+        i64::from_str_radix(digits, radix).map(Expr::NUMBER).map_err(|_| format!("bad number {}", digits))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(b'(') => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(b')')?;
+                Ok(expr)
+            },
+            Some(b'$') => {
+                self.pos += 1;
+                self.number(16)
+            },
+            Some(b'%') => {
+                self.pos += 1;
+                self.number(2)
+            },
+            Some(b'@') => {
+                self.pos += 1;
+                self.number(8)
+            },
+            Some(b'0') if matches!(self.text.get(self.pos + 1), Some(b'x' | b'X')) => {
+                self.pos += 2;
+                self.number(16)
+            },
+            Some(c) if c.is_ascii_digit() => self.number(10),
+            Some(b'\'' | b'"') => {
+                let text = self.quoted()?;
+                Ok(Expr::NUMBER(text.iter().fold(0, |val, &c| val << 8 | c as i64)))
+            },
+            Some(b'*') => {
+                self.pos += 1;
+                Ok(Expr::HERE)
+            },
+            _ => self.symbol().map(Expr::SYMBOL).ok_or_else(|| "expected an expression".to_string()),
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat(b'-') {
+            Ok(Expr::NEG(Box::new(self.unary()?)))
+        }
+        else if self.eat(b'~') {
+            Ok(Expr::NOT(Box::new(self.unary()?)))
+        }
+        else if self.eat(b'+') {
+            self.unary()
+        }
+        else {
+            self.primary()
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == OPERATORS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        'operators: loop {
+            for op in OPERATORS[level] {
+                if self.text[self.pos..].starts_with(op.as_bytes()) {
+                    self.pos += op.len();
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::BINARY(op, Box::new(lhs), Box::new(rhs));
+                    continue 'operators;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        self.binary(0)
+    }
+
+    //Xn.s*scale
+    fn index(&mut self) -> Result<Index, String> {
+        let register = match self.register() {
+            Some(Name::DATA(reg)) => reg,
+            Some(Name::ADDRESS(reg)) => reg + 8,
+            _ => return Err("expected an index register".to_string()),
+        };
+        let long = self.size_suffix().unwrap_or(false);
+        let scale = if self.eat(b'*') {
+            match self.take_while(|c| c.is_ascii_digit()) {
+                "1" => 1,
+                "2" => 2,
+                "4" => 4,
+                "8" => 8,
+                _ => return Err("bad index scale".to_string()),
+            }
+        }
+        else {
+            1
+        };
+        Ok(Index { register, long, scale })
+    }
+
+    //An or PC base of a displacement, then an optional index and the
+    //closing parenthesis
+    fn based(&mut self, disp: Expr) -> Result<Operand, String> {
+        let base = match self.register() {
+            Some(Name::ADDRESS(reg)) => Some(reg),
+            Some(Name::PC) => None,
+            _ => return Err("expected an address register or pc".to_string()),
+        };
+        let operand = if self.eat(b',') {
+            Operand::INDEXED(disp, base, self.index()?)
+        }
+        else {
+            Operand::DISPLACEMENT(disp, base)
+        };
+        self.expect(b')')?;
+        Ok(operand)
+    }
+
+    //Registers from `first` on joined by - and /, numbered as in Operand::LIST
+    fn list(&mut self, first: usize) -> Result<u16, String> {
+        let number = |name: Option<Name>| match name {
+            Some(Name::DATA(reg)) => Ok(reg),
+            Some(Name::ADDRESS(reg)) => Ok(reg + 8),
+            _ => Err("expected a register".to_string()),
+        };
+        let mut mask = 0;
+        let mut from = first;
+        loop {
+            let to = if self.eat(b'-') { number(self.register())? } else { from };
+            if to < from {
+                return Err("bad register range".to_string());
+            }
+            mask |= ((2u32 << to) - (1 << from)) as u16;
+            if !self.eat(b'/') {
+                return Ok(mask);
+            }
+            from = number(self.register())?;
+        }
+    }
+
+    fn effective_address(&mut self) -> Result<Operand, String> {
+        if self.eat(b'#') {
+            return Ok(Operand::IMMEDIATE(self.expr()?));
+        }
+        let start = self.pos;
+        if self.eat(b'-') && self.eat(b'(') {
+            if let Some(Name::ADDRESS(reg)) = self.register() {
+                if self.eat(b')') {
+                    return Ok(Operand::PREDECREMENT(reg));
+                }
+            }
+        }
+        self.pos = start;
+        if self.eat(b'(') {
+            match self.register() {
+                Some(Name::ADDRESS(reg)) if self.eat(b')') => {
+                    return Ok(if self.eat(b'+') { Operand::POSTINCREMENT(reg) } else { Operand::INDIRECT(reg) });
+                },
+                Some(Name::ADDRESS(_) | Name::PC) => {
+                    self.pos = start + 1;
+                    return self.based(Expr::NUMBER(0));
+                },
+                Some(_) => return Err("expected an address register".to_string()),
+                None => {},
+            }
+            //(d,An) forms, or an expression in parentheses
+            if let Ok(disp) = self.expr() {
+                if self.eat(b',') {
+                    return self.based(disp);
+                }
+            }
+            self.pos = start;
+        }
+        if let Some(name) = self.register() {
+            return Ok(match name {
+                Name::DATA(reg) if self.eat(b':') => match self.register() {
+                    Some(Name::DATA(other)) => Operand::PAIR(reg, other),
+                    _ => return Err("expected a data register".to_string()),
+                },
+                Name::DATA(reg) | Name::ADDRESS(reg) if matches!(self.peek(), Some(b'-' | b'/')) => {
+                    let first = if let Name::DATA(_) = name { reg } else { reg + 8 };
+                    Operand::LIST(self.list(first)?)
+                },
+                Name::DATA(reg) => Operand::DATA(reg),
+                Name::ADDRESS(reg) => Operand::ADDRESS(reg),
+                Name::SR => Operand::SR,
+                Name::CCR => Operand::CCR,
+                Name::USP => Operand::USP,
+                Name::CONTROL(code) => Operand::CONTROL(code),
+                Name::PC => return Err("pc can't be an operand".to_string()),
+            });
+        }
+        let expr = self.expr()?;
+        if self.eat(b'(') {
+            return self.based(expr);
+        }
+        Ok(match self.size_suffix() {
+            Some(false) => Operand::ABSOLUTE(expr, true),
+            _ => Operand::ABSOLUTE(expr, false),
+        })
+    }
+
+    fn field(&mut self) -> Result<Field, String> {
+        match self.register() {
+            Some(Name::DATA(reg)) => Ok(Field::REGISTER(reg)),
+            Some(_) => Err("expected a data register".to_string()),
+            None => Ok(Field::VALUE(self.expr()?)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let ea = self.effective_address()?;
+        if !self.eat(b'{') {
+            return Ok(ea);
+        }
+        let offset = self.field()?;
+        self.expect(b':')?;
+        let width = self.field()?;
+        self.expect(b'}')?;
+        Ok(Operand::BITFIELD(Box::new(ea), offset, width))
+    }
+
+    //Comma separated list ending the line
+    fn list_of<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let mut items = Vec::new();
+        self.skip_space();
+        if self.at_end() {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if !self.eat(b',') {
+                break;
+            }
+            self.skip_space();
+        }
+        self.finish()?;
+        Ok(items)
+    }
+
+    fn single(&mut self) -> Result<Expr, String> {
+        self.skip_space();
+        let expr = self.expr()?;
+        self.finish()?;
+        Ok(expr)
+    }
+}
+
+fn _data_size(suffix: &str) -> Result<OpSize, String> {
+    match suffix {
+        "" | "w" => Ok(WORD),
+        "b" => Ok(BYTE),
+        "l" => Ok(LONG),
+        _ => Err(format!("bad size .{}", suffix)),
+    }
+}
+
+//Label and statement of a line, `scope` following the global labels
+fn _parse_line(line: &str, scope: &mut String) -> Result<(Option<String>, Body), String> {
+    let text = line.as_bytes();
+    if matches!(text.first(), Some(b'*' | b';')) {
+        return Ok((None, Body::NONE));
+    }
+    let mut cursor = Cursor { text, pos: 0, scope };
+    let mut label = None;
+    if text.first().is_some_and(|c| !c.is_ascii_whitespace()) {
+        label = Some(cursor.symbol().ok_or("bad label")?);
+        cursor.eat(b':');
+    }
+    cursor.skip_space();
+    let start = cursor.pos;
+    if label.is_none() {
+        if let Some(name) = cursor.symbol() {
+            if cursor.eat(b':') {
+                label = Some(name);
+                cursor.skip_space();
+            }
+            else {
+                cursor.pos = start;
+            }
+        }
+    }
+    let pos = cursor.pos;
+    if let Some(label) = &label {
+        if !label.contains('.') {
+            *scope = label.clone();
+        }
+    }
+    let mut cursor = Cursor { text, pos, scope };
+    if cursor.eat(b'=') {
+        return Ok((label, Body::EQU(cursor.single()?)));
+    }
+    let word = cursor.take_while(|c| _name_char(c) || c == b'.').to_ascii_lowercase();
+    let (name, suffix) = word.split_once('.').unwrap_or((&word, ""));
+    let body = match name {
+        "" => {
+            cursor.finish()?;
+            Body::NONE
+        },
+        "equ" => Body::EQU(cursor.single()?),
+        "org" => Body::ORG(cursor.single()?),
+        "dc" => {
+            let size = _data_size(suffix)?;
+            Body::DC(size, cursor.list_of(|cursor| match cursor.peek() {
+                Some(b'"' | b'\'') if size == BYTE => Ok(Item::TEXT(cursor.quoted()?)),
+                _ => Ok(Item::VALUE(cursor.expr()?)),
+            })?)
+        },
+        "ds" => Body::DS(_data_size(suffix)?, cursor.single()?),
+        "even" => {
+            cursor.finish()?;
+            Body::EVEN
+        },
+        "include" => {
+            cursor.skip_space();
+            Body::INCLUDE(cursor.path()?)
+        },
+        "incbin" => {
+            cursor.skip_space();
+            Body::INCBIN(cursor.path()?)
+        },
+        "end" => Body::END,
+        _ => {
+            let op = _mnemonic(name).ok_or_else(|| format!("unknown instruction {}", name))?;
+            let suffix = match suffix.as_bytes() {
+                [] => None,
+                [size @ (b'b' | b'w' | b'l' | b's')] => Some(*size),
+                _ => return Err(format!("bad size .{}", suffix)),
+            };
+            Body::INSTRUCTION(op, suffix, cursor.list_of(Cursor::operand)?)
+        },
+    };
+    if matches!(body, Body::EQU(_)) && label.is_none() {
+        return Err("EQU without a label".to_string());
+    }
+    Ok((label, body))
+}
+
+//Immediates the instruction holds in its opcode word
+fn _quick(op: Mnemonic) -> bool {
+    matches!(op, MOVEQ | ADDQ | SUBQ | MOV3Q | TRAP | ASL | ASR | LSL | LSR | ROL | ROR | ROXL | ROXR)
+}
+
+fn _bitfield_op(op: Mnemonic) -> bool {
+    matches!(op, BFTST | BFEXTU | BFCHG | BFEXTS | BFCLR | BFFFO | BFSET | BFINS)
+}
+
+//The instruction generic mnemonics stand for with these operands
+fn _specific(op: Mnemonic, operands: &[Operand]) -> Mnemonic {
+    let immediate = matches!(operands.first(), Some(Operand::IMMEDIATE(_)));
+    let address = matches!(operands.last(), Some(Operand::ADDRESS(_))) && operands.len() == 2;
+    match op {
+        MOVE if address && operands[0] != Operand::USP => MOVEA,
+        ADD if address => ADDA,
+        SUB if address => SUBA,
+        CMP if address => CMPA,
+        ADD if immediate => ADDI,
+        SUB if immediate => SUBI,
+        CMP if immediate => CMPI,
+        AND if immediate => ANDI,
+        OR if immediate => ORI,
+        EOR if immediate => EORI,
+        CMP if matches!(operands, [Operand::POSTINCREMENT(_), Operand::POSTINCREMENT(_)]) => CMPM,
+        op => op,
+    }
+}
+
+//Size of instructions written without one
+fn _default_size(op: Mnemonic, operands: &[Operand]) -> OpSize {
+    match op {
+        SCC(_) | NBCD | TAS | ABCD | SBCD => BYTE,
+        ANDI | ORI | EORI if operands.contains(&Operand::CCR) => BYTE,
+        MOVE if operands.contains(&Operand::USP) => LONG,
+        MOVEQ | LEA | PEA | JMP | JSR | EXG | SWAP | UNLK | MOVEC | EXTB | MOV3Q | SATS | DIVUL | DIVSL => LONG,
+        op if _bitfield_op(op) => LONG,
+        _ => WORD,
+    }
+}
+
+//Mnemonic and size of an instruction. Bit operations are byte sized
+//whatever they are given.
+fn _shape(op: Mnemonic, suffix: Option<u8>, operands: &[Operand]) -> Result<(Mnemonic, OpSize), String> {
+    let op = _specific(op, operands);
+    let size = match (op, suffix) {
+        (BTST | BCHG | BCLR | BSET, Some(b'b' | b'l') | None) => BYTE,
+        (BRA | BSR | BCC(_), _) => WORD,
+        (_, None) => _default_size(op, operands),
+        (_, Some(b'b')) => BYTE,
+        (_, Some(b'w')) => WORD,
+        (_, Some(b'l')) => LONG,
+        (_, Some(size)) => return Err(format!("bad size .{}", size as char)),
+    };
+    Ok((op, size))
+}
+
+//Extension words an operand takes, in bytes
+fn _operand_length(operand: &Operand, size: OpSize, quick: bool) -> u32 {
+    match operand {
+        Operand::DISPLACEMENT(..) | Operand::INDEXED(..) | Operand::ABSOLUTE(_, true) => 2,
+        Operand::ABSOLUTE(_, false) => 4,
+        Operand::IMMEDIATE(_) if quick => 0,
+        Operand::IMMEDIATE(_) => if size == LONG { 4 } else { 2 },
+        Operand::BITFIELD(ea, ..) => _operand_length(ea, size, quick),
+        _ => 0,
+    }
+}
+
+//Branch sizes: short, word and long
+const BRANCH_LENGTHS: [u32; 3] = [2, 4, 6];
+
+fn _length(op: Mnemonic, size: OpSize, operands: &[Operand], branch: u8) -> u32 {
+    match op {
+        BRA | BSR | BCC(_) => return BRANCH_LENGTHS[branch as usize],
+        DBCC(_) => return 4,
+        _ => {},
+    }
+    //Register list or register extension word
+    let command = match op {
+        MULU | MULS | DIVU | DIVS => size == LONG,
+        DIVUL | DIVSL | MOVEC | MOVES | CHK2 | CMP2 | CAS | MOVEM => true,
+        op => _bitfield_op(op),
+    };
+    let quick = _quick(op);
+    2 + if command { 2 } else { 0 } + operands.iter().map(|operand| _operand_length(operand, size, quick)).sum::<u32>()
+}
+
+fn _signed(val: i64, bits: u32) -> bool {
+    val >= -(1 << (bits - 1)) && val < 1 << (bits - 1)
+}
+
+//Values written signed or unsigned
+fn _fits(val: i64, bits: u32) -> bool {
+    val >= -(1 << (bits - 1)) && val < 1 << bits
+}
+
+fn _in_range(val: i64, bits: u32) -> Result<i64, String> {
+    if _fits(val, bits) { Ok(val) } else { Err(format!("{} doesn't fit in {} bits", val, bits)) }
+}
+
+//Displacement from `pc` to `target`. Addresses wrap around at 32 bits,
+//so a target below 0 can also be written as the address it wraps to.
+fn _relative(target: i64, pc: u32) -> i64 {
+    let disp = target - pc as i64;
+    if (0..1 << 32).contains(&target) { disp as i32 as i64 } else { disp }
+}
+
+//Whether a short branch can reach `disp`, 0 and on the 68020 -1 selecting
+//the longer forms
+fn _short_branch(disp: i64, model: CpuModel) -> bool {
+    _signed(disp, 8) && disp != 0 && (disp != -1 || model < CpuModel::MC68020)
+}
+
+fn _pc_relative(operand: &Operand) -> bool {
+    match operand {
+        Operand::DISPLACEMENT(_, None) | Operand::INDEXED(_, None, _) => true,
+        Operand::BITFIELD(ea, ..) => _pc_relative(ea),
+        _ => false,
+    }
+}
+
+fn _register_mask(operand: &Operand) -> Option<u16> {
+    match *operand {
+        Operand::LIST(mask) => Some(mask),
+        Operand::DATA(reg) => Some(1 << reg),
+        Operand::ADDRESS(reg) => Some(1 << (reg + 8)),
+        _ => None,
+    }
+}
+
+//Values of the symbols as a pass sees them
+type Lookup<'a> = &'a dyn Fn(&str) -> Option<i64>;
+
+struct Assembler {
+    model: CpuModel,
+    files: Vec<String>,
+    statements: Vec<Statement>,
+    //Size of each statement's branch, as in BRANCH_LENGTHS
+    branches: Vec<u8>,
+    //Symbols of the last pass
+    symbols: HashMap<String, i64>,
+}
+
+impl Assembler {
+    fn read(&mut self, name: String, source: &str, dir: &Path, depth: usize, scope: &mut String) -> Result<(), AsmError> {
+        let file = self.files.len();
+        self.files.push(name.clone());
+        for (number, line) in source.lines().enumerate() {
+            let error = |message: String| AsmError { file: name.clone(), line: number + 1, message };
+            let (label, body) = _parse_line(line, scope).map_err(error)?;
+            let body = match body {
+                Body::INCLUDE(path) => {
+                    if depth == MAX_INCLUDE_DEPTH {
+                        return Err(error("INCLUDE nested too deep".to_string()));
+                    }
+                    if label.is_some() {
+                        self.statements.push(Statement { file, line: number + 1, label, body: Body::NONE });
+                    }
+                    let path = dir.join(path);
+                    let source = fs::read_to_string(&path).map_err(|err| error(format!("{}: {}", path.display(), err)))?;
+                    let dir = path.parent().unwrap_or(dir);
+                    self.read(path.display().to_string(), &source, dir, depth + 1, scope)?;
+                    continue;
+                },
+                Body::INCBIN(path) => {
+                    let path = dir.join(path);
+                    Body::BINARY(fs::read(&path).map_err(|err| error(format!("{}: {}", path.display(), err)))?)
+                },
+                body => body,
+            };
+            let end = body == Body::END;
+            self.statements.push(Statement { file, line: number + 1, label, body });
+            if end {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    //Operand as the instruction holds it, PC relative ones being relative
+    //to `pc`
+    fn container(&self, operand: &Operand, op: Mnemonic, size: OpSize, pc: u32, lookup: Lookup, here: u32) -> Result<DataContainer, String> {
+        let eval = |expr: &Expr| expr.eval(lookup, here);
+        let relative = |expr: &Expr| Ok::<i64, String>(_relative(eval(expr)?, pc));
+        let out_of_range = || "displacement out of range".to_string();
+        Ok(match operand {
+            Operand::DATA(reg) => DATA_REGISTER(*reg),
+            Operand::ADDRESS(reg) => ADDRESS_REGISTER(*reg),
+            Operand::INDIRECT(reg) => ADDRESS_INDIRECT(*reg),
+            Operand::POSTINCREMENT(reg) => POSTINCREMENT(*reg),
+            Operand::PREDECREMENT(reg) => PREDECREMENT(*reg),
+            Operand::DISPLACEMENT(disp, Some(reg)) => {
+                let disp = Some(eval(disp)?).filter(|&disp| _signed(disp, 16)).ok_or_else(out_of_range)?;
+                DISPLACEMENT(*reg, disp as i16)
+            },
+            Operand::DISPLACEMENT(target, None) => {
+                let disp = Some(relative(target)?).filter(|&disp| _signed(disp, 16)).ok_or_else(out_of_range)?;
+                PC_DISPLACEMENT(pc, disp as i16)
+            },
+            Operand::INDEXED(disp, Some(reg), index) => {
+                let disp = Some(eval(disp)?).filter(|&disp| _signed(disp, 8)).ok_or_else(out_of_range)?;
+                INDEXED(*reg, *index, disp as i8)
+            },
+            Operand::INDEXED(target, None, index) => {
+                let disp = Some(relative(target)?).filter(|&disp| _signed(disp, 8)).ok_or_else(out_of_range)?;
+                PC_INDEXED(pc, *index, disp as i8)
+            },
+            Operand::ABSOLUTE(addr, true) => {
+                let addr = eval(addr)?;
+                let addr = if (0xffff8000..=0xffffffff).contains(&addr) { addr - 0x100000000 } else { addr };
+                SHORT_ADDR(Some(addr).filter(|&addr| _signed(addr, 16)).ok_or("address out of range")? as i16)
+            },
+            Operand::ABSOLUTE(addr, false) => MEMORY_ADDR(_in_range(eval(addr)?, 32)? as u32),
+            Operand::IMMEDIATE(val) if _quick(op) => IMEDIATE_VALUE(eval(val)? as u32),
+            Operand::IMMEDIATE(val) => IMEDIATE_VALUE(_in_range(eval(val)?, size.bytes() * 8)? as u32 & size.mask()),
+            Operand::SR => SR,
+            Operand::CCR => CCR,
+            Operand::USP if op == MOVEC => CONTROL_REGISTER(0x800),
+            Operand::USP => USP,
+            Operand::CONTROL(code) => CONTROL_REGISTER(*code),
+            Operand::LIST(mask) => REGISTER_LIST(*mask),
+            Operand::PAIR(first, second) => REGISTER_PAIR(*first, *second),
+            Operand::BITFIELD(..) => return Err("unexpected bitfield".to_string()),
+        })
+    }
+
+    fn bitfield(&self, register: usize, offset: &Field, width: &Field, lookup: Lookup, here: u32) -> Result<DataContainer, String> {
+        let param = |field: &Field, max: i64| match field {
+            Field::REGISTER(reg) => Ok(FieldParam::REGISTER(*reg)),
+            Field::VALUE(expr) => match expr.eval(lookup, here)? {
+                val if (0..=max).contains(&val) => Ok(FieldParam::IMMEDIATE(val as u8 & 0x1f)),
+                _ => Err("bitfield out of range".to_string()),
+            },
+        };
+        Ok(BITFIELD(Bitfield { register, offset: param(offset, 31)?, width: param(width, 32)? }))
+    }
+
+    //Operands of the instruction in the order it holds them
+    fn operands(&self, op: Mnemonic, size: OpSize, operands: &[Operand], pc: u32, lookup: Lookup, here: u32) -> Result<(DataContainer, DataContainer), String> {
+        let container = |operand: &Operand| self.container(operand, op, size, pc, lookup, here);
+        Ok(match (op, operands) {
+            (MOVEM, [list, ea]) if _register_mask(list).is_some() && _register_mask(ea).is_none() => {
+                (REGISTER_LIST(_register_mask(list).unwrap_or_default()), container(ea)?)
+            },
+            (MOVEM, [ea, list]) => (container(ea)?, REGISTER_LIST(_register_mask(list).ok_or("expected registers")?)),
+            (CAS, [Operand::DATA(dc), Operand::DATA(du), ea]) => (REGISTER_PAIR(*dc, *du), container(ea)?),
+            (BFINS, [Operand::DATA(reg), Operand::BITFIELD(ea, offset, width)]) => {
+                (container(ea)?, self.bitfield(*reg, offset, width, lookup, here)?)
+            },
+            (BFEXTU | BFEXTS | BFFFO, [Operand::BITFIELD(ea, offset, width), Operand::DATA(reg)]) => {
+                (container(ea)?, self.bitfield(*reg, offset, width, lookup, here)?)
+            },
+            (BFTST | BFCHG | BFCLR | BFSET, [Operand::BITFIELD(ea, offset, width)]) => {
+                (container(ea)?, self.bitfield(0, offset, width, lookup, here)?)
+            },
+            (EXG, [Operand::ADDRESS(address), Operand::DATA(data)]) => (DATA_REGISTER(*data), ADDRESS_REGISTER(*address)),
+            (_, []) => (EMPTY, EMPTY),
+            (_, [operand]) => (container(operand)?, EMPTY),
+            (_, [lhs, rhs]) => (container(lhs)?, container(rhs)?),
+            _ => return Err("too many operands".to_string()),
+        })
+    }
+
+    //Branches and DBcc, `branch` picking the size of the former
+    fn branch(&self, op: Mnemonic, operands: &[Operand], branch: u8, lookup: Lookup, here: u32) -> Result<Instruction, String> {
+        let pc = here.wrapping_add(2);
+        let (reg, target) = match (op, operands) {
+            (DBCC(_), [Operand::DATA(reg), Operand::ABSOLUTE(target, false)]) => (Some(*reg), target),
+            (BRA | BSR | BCC(_), [Operand::ABSOLUTE(target, false)]) => (None, target),
+            _ => return Err("expected a branch target".to_string()),
+        };
+        let disp = _relative(target.eval(lookup, here)?, pc);
+        let out_of_range = || "branch target out of range".to_string();
+        if let Some(reg) = reg {
+            let disp = Some(disp).filter(|&disp| _signed(disp, 16)).ok_or_else(out_of_range)?;
+            return Ok(Instruction::new(op, WORD, DATA_REGISTER(reg), PC_DISPLACEMENT(pc, disp as i16)));
+        }
+        Ok(match branch {
+            0 if _short_branch(disp, self.model) => Instruction::new(op, BYTE, PC_DISPLACEMENT(pc, disp as i16), EMPTY),
+            1 if _signed(disp, 16) => Instruction::new(op, WORD, PC_DISPLACEMENT(pc, disp as i16), EMPTY),
+            2 if _signed(disp, 32) => {
+                let target = Extended { base: Base::PC(pc), index: None, bd: disp as i32, od: 0, indirect: Indirect::NONE };
+                Instruction::new(op, LONG, EXTENDED(target), EMPTY)
+            },
+            _ => return Err(out_of_range()),
+        })
+    }
+
+    //PC relative operands are tried with their extension word at each
+    //place it may be, only the right one encoding
+    fn instruction(&self, op: Mnemonic, size: OpSize, operands: &[Operand], branch: u8, lookup: Lookup, here: u32) -> Result<Vec<u16>, String> {
+        let places = if operands.iter().any(_pc_relative) { 5 } else { 1 };
+        let mut error = None;
+        for place in 1..=places {
+            let inst = match op {
+                BRA | BSR | BCC(_) | DBCC(_) => self.branch(op, operands, branch, lookup, here),
+                _ => {
+                    let pc = here.wrapping_add(2 * place);
+                    self.operands(op, size, operands, pc, lookup, here).map(|(lhs, trg)| Instruction::new(op, size, lhs, trg))
+                },
+            };
+            match inst.map(|inst| inst.encode(self.model, here)) {
+                Ok(Ok(words)) => return Ok(words),
+                Ok(Err(EncodeError::UNSUPPORTED)) => return Err(format!("instruction not available on the {:?}", self.model)),
+                Ok(Err(EncodeError::INVALID_OPERANDS)) => {
+                    error.get_or_insert_with(|| "invalid operands".to_string());
+                },
+                Err(err) => {
+                    error.get_or_insert(err);
+                },
+            }
+        }
+        Err(error.unwrap_or_default())
+    }
+
+    //Lays the statements out, growing the branches that don't reach and
+    //returning the symbols with whether a branch grew. The final pass
+    //also emits the chunks, anything unresolved being an error.
+    fn pass(&mut self, chunks: Option<&mut Vec<Chunk>>) -> Result<(HashMap<String, i64>, bool), AsmError> {
+        let emit = chunks.is_some();
+        let mut out = Vec::new();
+        let mut symbols: HashMap<String, i64> = HashMap::new();
+        let mut grew = false;
+        let mut pc: u32 = 0;
+        let mut chunk = Chunk { org: 0, bytes: Vec::new() };
+        let files = &self.files;
+        for (i, statement) in self.statements.iter().enumerate() {
+            let error = |message: String| AsmError { file: files[statement.file].clone(), line: statement.line, message };
+            let here = pc;
+            let previous = &self.symbols;
+            let lookup = |name: &str| symbols.get(name).or_else(|| previous.get(name)).copied();
+            //Values that must be known, forward references being taken
+            //as 0 until the final pass
+            let value = |expr: &Expr| match expr.eval(&lookup, here) {
+                Ok(val) => Ok(val),
+                Err(err) if emit => Err(error(err)),
+                Err(_) => Ok(0),
+            };
+            let mut bytes = Vec::new();
+            match &statement.body {
+                Body::NONE | Body::END | Body::INCLUDE(_) | Body::INCBIN(_) => {},
+                Body::ORG(addr) => {
+                    pc = value(addr)? as u32;
+                    out.push(std::mem::replace(&mut chunk, Chunk { org: pc, bytes: Vec::new() }));
+                },
+                Body::EQU(expr) => {
+                    match expr.eval(&lookup, here) {
+                        Ok(val) => {
+                            let label = statement.label.clone().unwrap_or_default();
+                            if symbols.insert(label.clone(), val).is_some() {
+                                return Err(error(format!("{} defined twice", label)));
+                            }
+                        },
+                        Err(err) if emit => return Err(error(err)),
+                        Err(_) => {},
+                    }
+                    continue;
+                },
+                Body::DC(size, items) => {
+                    if *size != BYTE && pc & 1 != 0 && emit {
+                        return Err(error("data at an odd address".to_string()));
+                    }
+                    for item in items {
+                        match item {
+                            Item::TEXT(text) => bytes.extend(text),
+                            Item::VALUE(expr) => {
+                                let val = _in_range(value(expr)?, size.bytes() * 8).map_err(error)?;
+                                bytes.extend(&(val as u32).to_be_bytes()[(4 - size.bytes() as usize)..]);
+                            },
+                        }
+                    }
+                },
+                Body::DS(size, count) => {
+                    let count = value(count)?;
+                    if count < 0 {
+                        return Err(error("negative DS count".to_string()));
+                    }
+                    let length = (count as u64).saturating_mul(size.bytes() as u64);
+                    if pc as u64 + length > 1 << 32 {
+                        return Err(error("DS runs past the end of the address space".to_string()));
+                    }
+                    //Left as a gap, what follows going in a new chunk
+                    pc = pc.wrapping_add(length as u32);
+                    out.push(std::mem::replace(&mut chunk, Chunk { org: pc, bytes: Vec::new() }));
+                },
+                Body::EVEN => bytes.resize(pc as usize & 1, 0),
+                Body::BINARY(data) => bytes.extend(data),
+                Body::INSTRUCTION(op, suffix, operands) => {
+                    let (op, size) = _shape(*op, *suffix, operands).map_err(error)?;
+                    if emit {
+                        if pc & 1 != 0 {
+                            return Err(error("instruction at an odd address".to_string()));
+                        }
+                        let words = self.instruction(op, size, operands, self.branches[i], &lookup, here).map_err(error)?;
+                        bytes.extend(words.iter().flat_map(|word| word.to_be_bytes()));
+                    }
+                    else if suffix.is_none() && matches!(op, BRA | BSR | BCC(_)) {
+                        //Targets not known yet are left for another pass
+                        if let [Operand::ABSOLUTE(target, false)] = operands.as_slice() {
+                            if let Ok(target) = target.eval(&lookup, here) {
+                                let disp = _relative(target, here.wrapping_add(2));
+                                let size = match self.branches[i] {
+                                    0 if !_short_branch(disp, self.model) => 1,
+                                    1 if !_signed(disp, 16) && self.model >= CpuModel::MC68020 => 2,
+                                    size => size,
+                                };
+                                grew |= size != self.branches[i];
+                                self.branches[i] = size;
+                            }
+                        }
+                    }
+                    let length = _length(op, size, operands, self.branches[i]);
+                    if emit && bytes.len() != length as usize {
+                        return Err(error(format!("instruction took {} bytes instead of {}", bytes.len(), length)));
+                    }
+                    bytes.resize(length as usize, 0);
+                },
+            }
+            if let Some(label) = &statement.label {
+                if symbols.insert(label.clone(), here as i64).is_some() {
+                    return Err(error(format!("{} defined twice", label)));
+                }
+            }
+            pc = pc.wrapping_add(bytes.len() as u32);
+            chunk.bytes.extend(bytes);
+        }
+        out.push(chunk);
+        if let Some(chunks) = chunks {
+            chunks.extend(out.into_iter().filter(|chunk| !chunk.bytes.is_empty()));
+        }
+        Ok((symbols, grew))
+    }
+
+    fn assemble(mut self) -> Result<Program, AsmError> {
+        self.branches = self.statements.iter().map(|statement| match statement.body {
+            Body::INSTRUCTION(_, Some(b'w'), _) => 1,
+            Body::INSTRUCTION(_, Some(b'l'), _) => 2,
+            _ => 0,
+        }).collect();
+        let mut settled = false;
+        for _ in 0..MAX_PASSES {
+            let (symbols, grew) = self.pass(None)?;
+            settled = symbols == self.symbols && !grew;
+            self.symbols = symbols;
+            if settled {
+                break;
+            }
+        }
+        if !settled {
+            let file = self.files.first().cloned().unwrap_or_default();
+            return Err(AsmError { file, line: 0, message: "symbols don't settle".to_string() });
+        }
+        let mut chunks = Vec::new();
+        let (symbols, _) = self.pass(Some(&mut chunks))?;
+        let symbols = symbols.into_iter().map(|(name, val)| (name, val as u32)).collect();
+        Ok(Program { chunks, symbols })
+    }
+}
+
+fn _assemble(name: String, source: &str, dir: &Path, model: CpuModel) -> Result<Program, AsmError> {
+    let mut assembler = Assembler { model, files: Vec::new(), statements: Vec::new(), branches: Vec::new(), symbols: HashMap::new() };
+    assembler.read(name, source, dir, 0, &mut String::new())?;
+    assembler.assemble()
+}
+
+//INCLUDE and INCBIN paths are relative to the current directory
+pub fn assemble(source: &str, model: CpuModel) -> Result<Program, AsmError> {
+    _assemble("<source>".to_string(), source, Path::new(""), model)
+}
+
+//INCLUDE and INCBIN paths are relative to the including file
+pub fn assemble_file(path: &Path, model: CpuModel) -> Result<Program, AsmError> {
+    let name = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|err| AsmError { file: name.clone(), line: 0, message: err.to_string() })?;
+    _assemble(name, &source, path.parent().unwrap_or(Path::new("")), model)
+}
@@ -0,0 +1,265 @@
+mod map;
+
+use std::sync::Arc;
+
+pub use map::*;
+
+// Memory bus seen by the CPU.
+//
+// Addresses are already truncated to the CPU's address bus width when
+// they reach the bus.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FunctionCode {
+    //Only reachable through MOVES from the 68010 on
+    UNDEFINED_0 = 0,
+    USER_DATA = 1,
+    USER_PROGRAM = 2,
+    USER_RESERVED = 3,
+    SUPERVISOR_RESERVED = 4,
+    SUPERVISOR_DATA = 5,
+    SUPERVISOR_PROGRAM = 6,
+    CPU_SPACE = 7,
+}
+
+impl FunctionCode {
+    //From the FC2-FC0 pins, higher bits are ignored
+    pub fn from_bits(bits: u32) -> FunctionCode {
+        use FunctionCode::*;
+        [UNDEFINED_0, USER_DATA, USER_PROGRAM, USER_RESERVED,
+        SUPERVISOR_RESERVED, SUPERVISOR_DATA, SUPERVISOR_PROGRAM, CPU_SPACE][bits as usize & 0b111]
+    }
+}
+
+// Returned by a bus cycle that got terminated by BERR.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BusError;
+
+pub type BusResult<T> = Result<T, BusError>;
+
+// Word and long accesses default to consecutive byte accesses, which is
+// fine for memory but devices that care about access width override them.
+pub trait Bus {
+    fn read_byte(&mut self, fc: FunctionCode, addr: u32) -> BusResult<u8>;
+
+    fn write_byte(&mut self, fc: FunctionCode, addr: u32, val: u8) -> BusResult<()>;
+
+    fn read_word(&mut self, fc: FunctionCode, addr: u32) -> BusResult<u16> {
+        let hi = self.read_byte(fc, addr)? as u16;
+        let lo = self.read_byte(fc, addr.wrapping_add(1))? as u16;
+        Ok(hi << 8 | lo)
+    }
+
+    fn write_word(&mut self, fc: FunctionCode, addr: u32, val: u16) -> BusResult<()> {
+        self.write_byte(fc, addr, (val >> 8) as u8)?;
+        self.write_byte(fc, addr.wrapping_add(1), val as u8)
+    }
+
+    fn read_long(&mut self, fc: FunctionCode, addr: u32) -> BusResult<u32> {
+        let hi = self.read_word(fc, addr)? as u32;
+        let lo = self.read_word(fc, addr.wrapping_add(2))? as u32;
+        Ok(hi << 16 | lo)
+    }
+
+    fn write_long(&mut self, fc: FunctionCode, addr: u32, val: u32) -> BusResult<()> {
+        self.write_word(fc, addr, (val >> 16) as u16)?;
+        self.write_word(fc, addr.wrapping_add(2), val as u16)
+    }
+
+    //The rest is for buses with devices on them, see Device
+
+    fn reset(&mut self) {}
+
+    fn interrupt_level(&self) -> u8 {
+        0
+    }
+
+    fn acknowledge(&mut self, _level: u8) -> Option<u8> {
+        None
+    }
+
+    fn tick(&mut self, _cycles: u64) {}
+
+    fn next_event(&self) -> Option<u64> {
+        None
+    }
+
+    //Whether code at `addr` may be decoded once and kept, which is only
+    //true of memory that doesn't change unless the CPU writes to it
+    fn cacheable(&self, _addr: u32) -> bool {
+        true
+    }
+
+    //Byte at `addr` read without side effects, for disassembly. None where
+    //reading could disturb a device.
+    fn peek(&self, _addr: u32) -> Option<u8> {
+        None
+    }
+}
+
+const PAGE_BITS: u32 = 12;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+//Shared between clones until written to
+type Page = Arc<[u8; PAGE_SIZE]>;
+
+//What untouched pages read as
+static ZERO_PAGE: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
+
+// Flat RAM starting at address 0, accesses past its end are bus errors.
+//
+// Memory is allocated a page at a time on the first write to it, pages
+// that were never written read as zero. Clones share their pages and only
+// copy the ones they write to.
+#[derive(Clone)]
+pub struct Ram {
+    pages: Vec<Option<Page>>,
+    size: usize,
+    //Ranges spanning pages are copied here to be handed out in one piece
+    scratch: Vec<u8>,
+}
+
+impl Default for Ram {
+    fn default() -> Ram {
+        Ram::new(0x1000000)
+    }
+}
+
+impl Ram {
+    pub fn new(size: usize) -> Ram {
+        let mut pages = Vec::new();
+        pages.resize_with(size.div_ceil(PAGE_SIZE), || None);
+        Ram {
+            pages,
+            size,
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    //Bytes actually allocated
+    pub fn resident(&self) -> usize {
+        self.pages.iter().filter(|page| page.is_some()).count() * PAGE_SIZE
+    }
+
+    //Bytes allocated that are shared with a clone
+    pub fn shared(&self) -> usize {
+        self.pages.iter().flatten().filter(|page| Arc::strong_count(page) > 1).count() * PAGE_SIZE
+    }
+
+    pub fn get(&mut self, offset: usize, len: usize) -> Option<&[u8]> {
+        let end = offset.checked_add(len)?;
+        if end > self.size {
+            return None;
+        }
+        let in_page = offset & (PAGE_SIZE - 1);
+        if in_page + len <= PAGE_SIZE {
+            return Some(&self.page(offset)[in_page..(in_page + len)]);
+        }
+        self.scratch.clear();
+        for addr in offset..end {
+            self.scratch.push(self.page(addr)[addr & (PAGE_SIZE - 1)]);
+        }
+        Some(&self.scratch)
+    }
+
+    //Same as get for a shared RAM, always copying
+    pub fn read(&self, offset: usize, len: usize) -> Option<Vec<u8>> {
+        let end = offset.checked_add(len)?;
+        if end > self.size {
+            return None;
+        }
+        Some((offset..end).map(|addr| self.page(addr)[addr & (PAGE_SIZE - 1)]).collect())
+    }
+
+    pub fn load(&mut self, offset: usize, data: &[u8]) {
+        assert!(offset + data.len() <= self.size, "loading {:#x} bytes at {:#x} past the end of RAM", data.len(), offset);
+        let mut addr = offset;
+        for chunk in data.chunks(PAGE_SIZE) {
+            //Chunks that don't start on a page boundary spill over the next one
+            let in_page = addr & (PAGE_SIZE - 1);
+            let first = chunk.len().min(PAGE_SIZE - in_page);
+            self.page_mut(addr)[in_page..(in_page + first)].copy_from_slice(&chunk[..first]);
+            if first < chunk.len() {
+                self.page_mut(addr + first)[..(chunk.len() - first)].copy_from_slice(&chunk[first..]);
+            }
+            addr += chunk.len();
+        }
+    }
+
+    fn page(&self, addr: usize) -> &[u8; PAGE_SIZE] {
+        match &self.pages[addr >> PAGE_BITS] {
+            Some(page) => page,
+            None => &ZERO_PAGE,
+        }
+    }
+
+    fn page_mut(&mut self, addr: usize) -> &mut [u8; PAGE_SIZE] {
+        let page = self.pages[addr >> PAGE_BITS].get_or_insert_with(|| Arc::new([0; PAGE_SIZE]));
+        Arc::make_mut(page)
+    }
+
+    fn check(&self, addr: u32, len: usize) -> BusResult<usize> {
+        let addr = addr as usize;
+        if addr + len <= self.size { Ok(addr) } else { Err(BusError) }
+    }
+}
+
+impl AsRef<Ram> for Ram {
+    fn as_ref(&self) -> &Ram {
+        self
+    }
+}
+
+impl AsMut<Ram> for Ram {
+    fn as_mut(&mut self) -> &mut Ram {
+        self
+    }
+}
+
+impl Bus for Ram {
+    fn read_byte(&mut self, _fc: FunctionCode, addr: u32) -> BusResult<u8> {
+        let addr = self.check(addr, 1)?;
+        Ok(self.page(addr)[addr & (PAGE_SIZE - 1)])
+    }
+
+    fn write_byte(&mut self, _fc: FunctionCode, addr: u32, val: u8) -> BusResult<()> {
+        let addr = self.check(addr, 1)?;
+        self.page_mut(addr)[addr & (PAGE_SIZE - 1)] = val;
+        Ok(())
+    }
+
+    fn read_word(&mut self, fc: FunctionCode, addr: u32) -> BusResult<u16> {
+        let addr = self.check(addr, 2)?;
+        let in_page = addr & (PAGE_SIZE - 1);
+        if in_page == PAGE_SIZE - 1 {
+            let hi = self.read_byte(fc, addr as u32)? as u16;
+            return Ok(hi << 8 | self.read_byte(fc, addr as u32 + 1)? as u16);
+        }
+        let page = self.page(addr);
+        Ok(u16::from_be_bytes([page[in_page], page[in_page + 1]]))
+    }
+
+    fn write_word(&mut self, fc: FunctionCode, addr: u32, val: u16) -> BusResult<()> {
+        let addr = self.check(addr, 2)?;
+        let in_page = addr & (PAGE_SIZE - 1);
+        if in_page == PAGE_SIZE - 1 {
+            self.write_byte(fc, addr as u32, (val >> 8) as u8)?;
+            return self.write_byte(fc, addr as u32 + 1, val as u8);
+        }
+        self.page_mut(addr)[in_page..(in_page + 2)].copy_from_slice(&val.to_be_bytes());
+        Ok(())
+    }
+
+    fn peek(&self, addr: u32) -> Option<u8> {
+        let addr = self.check(addr, 1).ok()?;
+        Some(self.page(addr)[addr & (PAGE_SIZE - 1)])
+    }
+}
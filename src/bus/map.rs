@@ -0,0 +1,303 @@
+use super::*;
+use std::convert::TryFrom;
+use crate::device::Device;
+
+struct Region {
+    start: u32,
+    //Inclusive
+    end: u32,
+    device: Box<dyn Device>,
+}
+
+//What happens to writes into a read-only range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WritePolicy {
+    IGNORE,
+    //Dropped and kept in the log, see take_rom_log
+    LOG,
+    BUS_ERROR,
+}
+
+//A write dropped by a LOG range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RomWrite {
+    pub addr: u32,
+    pub val: u32,
+    //Bytes written
+    pub len: u32,
+}
+
+//Logged writes kept until taken, later ones are only counted
+const ROM_LOG_SIZE: usize = 256;
+
+struct Protected {
+    start: u32,
+    //Inclusive
+    end: u32,
+    policy: WritePolicy,
+}
+
+enum Route {
+    RAM,
+    DEVICE(usize, u32),
+    //Straddles a region boundary, has to be done byte per byte
+    SPLIT,
+}
+
+// Bus made of RAM with devices mapped over address windows.
+//
+// Accesses that fall in a window go to its device instead of the RAM
+// underneath. Parts of the RAM can be made read-only to hold ROM images.
+// Interrupts, RESET and time are forwarded to every device, including the
+// attached ones that aren't mapped anywhere.
+pub struct MemoryMap {
+    ram: Ram,
+    //Sorted by start address, never overlapping
+    regions: Vec<Region>,
+    attached: Vec<Box<dyn Device>>,
+    protected: Vec<Protected>,
+    //Writes refused by a read-only range
+    rom_writes: u64,
+    rom_log: Vec<RomWrite>,
+}
+
+impl Default for MemoryMap {
+    fn default() -> MemoryMap {
+        MemoryMap::new(Ram::default())
+    }
+}
+
+impl AsRef<Ram> for MemoryMap {
+    fn as_ref(&self) -> &Ram {
+        &self.ram
+    }
+}
+
+impl AsMut<Ram> for MemoryMap {
+    fn as_mut(&mut self) -> &mut Ram {
+        &mut self.ram
+    }
+}
+
+impl MemoryMap {
+    pub fn new(ram: Ram) -> MemoryMap {
+        MemoryMap {
+            ram,
+            regions: Vec::new(),
+            attached: Vec::new(),
+            protected: Vec::new(),
+            rom_writes: 0,
+            rom_log: Vec::new(),
+        }
+    }
+
+    //Makes `start..=end` of the RAM read-only
+    pub fn protect(&mut self, start: u32, end: u32, policy: WritePolicy) {
+        assert!(start <= end, "empty protected range {:#x}..={:#x}", start, end);
+        self.protected.push(Protected { start, end, policy });
+    }
+
+    //Loads `data` at `start` and makes it read-only
+    pub fn map_rom(&mut self, start: u32, data: &[u8], policy: WritePolicy) {
+        if data.is_empty() {
+            return;
+        }
+        let end = u32::try_from(data.len() - 1).ok()
+            .and_then(|last| start.checked_add(last))
+            .expect("ROM image runs past the end of the address space");
+        self.ram.load(start as usize, data);
+        self.protect(start, end, policy);
+    }
+
+    pub fn rom_writes(&self) -> u64 {
+        self.rom_writes
+    }
+
+    //Writes dropped by LOG ranges since the last call, oldest first
+    pub fn take_rom_log(&mut self) -> Vec<RomWrite> {
+        std::mem::take(&mut self.rom_log)
+    }
+
+    //Whether a RAM write of `len` bytes at `addr` may go through
+    fn check_write(&mut self, addr: u32, len: u32, val: u32) -> BusResult<bool> {
+        let last = addr.wrapping_add(len - 1);
+        let policy = match self.protected.iter().find(|p| p.start <= last && addr <= p.end) {
+            Some(p) => p.policy,
+            None => return Ok(true),
+        };
+        self.rom_writes += 1;
+        match policy {
+            WritePolicy::IGNORE => Ok(false),
+            WritePolicy::LOG => {
+                if self.rom_log.len() < ROM_LOG_SIZE {
+                    self.rom_log.push(RomWrite { addr, val, len });
+                }
+                Ok(false)
+            },
+            WritePolicy::BUS_ERROR => Err(BusError),
+        }
+    }
+
+    //Refuses a write straddling devices and RAM before any part of it lands
+    //if one of its RAM bytes would be a bus error, the ignored ones are
+    //counted as they are written
+    fn check_split(&mut self, addr: u32, len: u32) -> BusResult<()> {
+        let refused = (0..len).map(|i| addr.wrapping_add(i))
+            .filter(|&a| self.find(a).is_none())
+            .any(|a| self.protected.iter().any(|p| p.start <= a && a <= p.end && p.policy == WritePolicy::BUS_ERROR));
+        if refused {
+            self.rom_writes += 1;
+            return Err(BusError);
+        }
+        Ok(())
+    }
+
+    //Maps `device` at `start..=end`, panics if it overlaps another device
+    pub fn map(&mut self, start: u32, end: u32, device: Box<dyn Device>) {
+        assert!(start <= end, "empty device window {:#x}..={:#x}", start, end);
+        let i = self.regions.partition_point(|r| r.end < start);
+        if let Some(r) = self.regions.get(i) {
+            assert!(r.start > end, "{:#x}..={:#x} overlaps device at {:#x}..={:#x}",
+                start, end, r.start, r.end);
+        }
+        self.regions.insert(i, Region { start, end, device });
+    }
+
+    //For devices that only raise interrupts or want RESET and time
+    pub fn attach(&mut self, device: Box<dyn Device>) {
+        self.attached.push(device);
+    }
+
+    fn devices(&self) -> impl Iterator<Item = &Box<dyn Device>> {
+        self.regions.iter().map(|r| &r.device).chain(self.attached.iter())
+    }
+
+    fn devices_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Device>> {
+        self.regions.iter_mut().map(|r| &mut r.device).chain(self.attached.iter_mut())
+    }
+
+    fn find(&self, addr: u32) -> Option<usize> {
+        let i = self.regions.partition_point(|r| r.end < addr);
+        match self.regions.get(i) {
+            Some(r) if r.start <= addr => Some(i),
+            _ => None,
+        }
+    }
+
+    fn route_word(&self, addr: u32) -> Route {
+        match (self.find(addr), self.find(addr.wrapping_add(1))) {
+            (None, None) => Route::RAM,
+            (Some(i), Some(j)) if i == j => Route::DEVICE(i, addr - self.regions[i].start),
+            _ => Route::SPLIT,
+        }
+    }
+}
+
+impl Bus for MemoryMap {
+    fn read_byte(&mut self, fc: FunctionCode, addr: u32) -> BusResult<u8> {
+        match self.find(addr) {
+            Some(i) => {
+                let r = &mut self.regions[i];
+                r.device.read_byte(addr - r.start)
+            },
+            None => self.ram.read_byte(fc, addr),
+        }
+    }
+
+    fn write_byte(&mut self, fc: FunctionCode, addr: u32, val: u8) -> BusResult<()> {
+        match self.find(addr) {
+            Some(i) => {
+                let r = &mut self.regions[i];
+                r.device.write_byte(addr - r.start, val)
+            },
+            None => {
+                if self.check_write(addr, 1, val as u32)? {
+                    self.ram.write_byte(fc, addr, val)?;
+                }
+                Ok(())
+            },
+        }
+    }
+
+    fn read_word(&mut self, fc: FunctionCode, addr: u32) -> BusResult<u16> {
+        match self.route_word(addr) {
+            Route::RAM => self.ram.read_word(fc, addr),
+            Route::DEVICE(i, offset) => self.regions[i].device.read_word(offset),
+            Route::SPLIT => {
+                let hi = self.read_byte(fc, addr)? as u16;
+                let lo = self.read_byte(fc, addr.wrapping_add(1))? as u16;
+                Ok(hi << 8 | lo)
+            },
+        }
+    }
+
+    fn write_word(&mut self, fc: FunctionCode, addr: u32, val: u16) -> BusResult<()> {
+        match self.route_word(addr) {
+            Route::RAM => {
+                if self.check_write(addr, 2, val as u32)? {
+                    self.ram.write_word(fc, addr, val)?;
+                }
+                Ok(())
+            },
+            Route::DEVICE(i, offset) => self.regions[i].device.write_word(offset, val),
+            Route::SPLIT => {
+                self.check_split(addr, 2)?;
+                self.write_byte(fc, addr, (val >> 8) as u8)?;
+                self.write_byte(fc, addr.wrapping_add(1), val as u8)
+            },
+        }
+    }
+
+    fn write_long(&mut self, fc: FunctionCode, addr: u32, val: u32) -> BusResult<()> {
+        let low = addr.wrapping_add(2);
+        match (self.route_word(addr), self.route_word(low)) {
+            (Route::RAM, Route::RAM) => {
+                if self.check_write(addr, 4, val)? {
+                    self.ram.write_word(fc, addr, (val >> 16) as u16)?;
+                    self.ram.write_word(fc, low, val as u16)?;
+                }
+                Ok(())
+            },
+            _ => {
+                self.check_split(addr, 4)?;
+                self.write_word(fc, addr, (val >> 16) as u16)?;
+                self.write_word(fc, low, val as u16)
+            },
+        }
+    }
+
+    fn reset(&mut self) {
+        for device in self.devices_mut() {
+            device.reset();
+        }
+    }
+
+    fn interrupt_level(&self) -> u8 {
+        self.devices().map(|d| d.interrupt_level() & 0b111).max().unwrap_or(0)
+    }
+
+    fn acknowledge(&mut self, level: u8) -> Option<u8> {
+        self.devices_mut()
+            .find(|d| d.interrupt_level() & 0b111 == level)
+            .and_then(|d| d.acknowledge(level))
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        for device in self.devices_mut() {
+            device.tick(cycles);
+        }
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        self.devices().filter_map(|d| d.next_event()).min()
+    }
+
+    //Devices are read anew every time
+    fn cacheable(&self, addr: u32) -> bool {
+        self.find(addr).is_none()
+    }
+
+    fn peek(&self, addr: u32) -> Option<u8> {
+        if self.find(addr).is_some() { None } else { self.ram.peek(addr) }
+    }
+}
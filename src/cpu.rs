@@ -1,195 +1,645 @@
-mod cpu_actions;
+mod addressing;
+mod blocks;
+mod coldfire;
+pub(crate) mod cpu_actions;
+mod decoder;
+mod disasm;
+mod dispatch;
+mod encoder;
+mod exception;
+pub(crate) mod flags;
+pub(crate) mod float;
+mod fpu;
+mod icache;
 mod instruction;
+mod mc68020;
+mod mmu;
+mod monitor;
+mod prefetch;
+pub(crate) mod timing;
 
 use std::fmt::Display;
-use cpu_actions::*;
+use crate::bus::*;
+use exception::VECTOR_TRACE;
 pub use instruction::*;
 pub use DataContainer::*;
 pub use OpSize::*;
 pub use Mnemonic::*;
+pub use disasm::disassemble;
 
-fn write_byte_array(f: &mut std::fmt::Formatter<'_>, b_array: &Vec<u8>) -> std::result::Result<(), std::fmt::Error> {
+fn write_byte_array(f: &mut std::fmt::Formatter<'_>, b_array: &[u8]) -> std::result::Result<(), std::fmt::Error> {
     for byte_ in b_array {
         write!(f, "{:02x}", byte_)?;
     };
-    write!(f, "\n")?;
+    writeln!(f)?;
     Ok(())
 }
 
-pub struct CPU {
-    //Big endian
-    pc: Vec<u8>,
-    usp: Vec<u8>,
-    data_register: Vec<Vec<u8>>,
-    address_register: Vec<Vec<u8>>,
-    memory: Vec<u8>,
-    sr: Vec<u8>,
-    cache: Vec<u8>,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpuState {
+    RUNNING,
+    //STOP was executed, waiting for an unmasked interrupt
+    STOPPED,
+    //Double bus fault, only an external reset gets out of it
+    HALTED,
 }
 
-impl Default for CPU {
-    fn default() -> CPU {
+//Processor emulated, fixed when the CPU is built
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CpuModel {
+    MC68000,
+    //68000 core on an 8-bit data bus and 20 address lines
+    MC68008,
+    MC68010,
+    MC68020,
+    //ColdFire V2 cores, running a cut down 68020 instruction set. ISA_B
+    //adds a few instructions and a separate user stack pointer.
+    COLDFIRE_ISA_A,
+    COLDFIRE_ISA_B,
+}
+
+impl CpuModel {
+    //Address lines wired out, higher bits are dropped
+    pub fn address_mask(&self) -> u32 {
+        match self {
+            CpuModel::MC68008 => 0xfffff,
+            CpuModel::MC68000 | CpuModel::MC68010 => 0xffffff,
+            CpuModel::MC68020 | CpuModel::COLDFIRE_ISA_A | CpuModel::COLDFIRE_ISA_B => 0xffffffff,
+        }
+    }
+
+    //Exception frames start with a format and vector offset word. ColdFire
+    //frames are laid out differently.
+    pub fn has_frame_format(&self) -> bool {
+        matches!(self, CpuModel::MC68010 | CpuModel::MC68020)
+    }
+
+    pub fn is_coldfire(&self) -> bool {
+        matches!(self, CpuModel::COLDFIRE_ISA_A | CpuModel::COLDFIRE_ISA_B)
+    }
+
+    //Data words and longs may sit at odd addresses, code never can
+    pub fn misaligned_data(&self) -> bool {
+        *self >= CpuModel::MC68020
+    }
+}
+
+//Assembler syntax instructions are displayed in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    //move.l d0,(a1)+
+    MOTOROLA,
+    //movel %d0,%a1@+, as GNU as reads it
+    MIT,
+}
+
+//Why an instruction can't be encoded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncodeError {
+    //The model has no instruction with that mnemonic
+    UNSUPPORTED,
+    //No form of the mnemonic takes these operands, or a value doesn't fit
+    //in its field
+    INVALID_OPERANDS,
+}
+
+//Floating point coprocessor attached to a 68020
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FpuModel {
+    MC68881,
+    MC68882,
+}
+
+//A bus cycle that ended in a bus error, with what the CPU was doing.
+//Address errors are word accesses at odd addresses, never put on the bus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BusFault {
+    pub addr: u32,
+    pub fc: FunctionCode,
+    pub write: bool,
+    pub size: OpSize,
+    pub address_error: bool,
+}
+
+impl BusFault {
+    fn new(addr: u32, fc: FunctionCode, size: OpSize, write: bool) -> BusFault {
+        BusFault { addr, fc, write, size, address_error: false }
+    }
+}
+
+//A bus cycle as it appears on the pins. A0 isn't on the bus, the data
+//strobes say which halves of the data bus are used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BusCycle {
+    //Word aligned
+    pub addr: u32,
+    pub data: u16,
+    pub write: bool,
+    pub fc: FunctionCode,
+    //D8-D15, the byte at the even address
+    pub uds: bool,
+    //D0-D7, the byte at the odd address
+    pub lds: bool,
+    //Cycle count when the bus cycle started
+    pub cycle: u64,
+}
+
+pub type BusMonitor = Box<dyn FnMut(&BusCycle) + Send>;
+
+//Instruction fetches served by the 68020 cache or missing it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Register {
+    D0, D1, D2, D3, D4, D5, D6, D7,
+    A0, A1, A2, A3, A4, A5, A6, A7,
+    PC,
+    SR,
+    USP,
+    SSP,
+    //68010 and later
+    VBR,
+    SFC,
+    DFC,
+    //68020 and later
+    CACR,
+    CAAR,
+    //With an FPU
+    FPCR,
+    FPSR,
+    FPIAR,
+}
+
+impl Register {
+    pub fn data(i: usize) -> Register {
+        [Register::D0, Register::D1, Register::D2, Register::D3,
+        Register::D4, Register::D5, Register::D6, Register::D7][i]
+    }
+
+    pub fn addr(i: usize) -> Register {
+        [Register::A0, Register::A1, Register::A2, Register::A3,
+        Register::A4, Register::A5, Register::A6, Register::A7][i]
+    }
+}
+
+//Condition codes, valued by their bit in the CCR
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flag {
+    C = 0b00001,
+    V = 0b00010,
+    Z = 0b00100,
+    N = 0b01000,
+    X = 0b10000,
+}
+
+#[derive(Clone)]
+pub struct CPU<B: Bus = Ram> {
+    pc: u32,
+    //Only up to date in supervisor mode, A7 is the USP in user mode
+    usp: u32,
+    //Only up to date in user mode, A7 is the SSP in supervisor mode
+    ssp: u32,
+    data_register: [u32; 8],
+    address_register: [u32; 8],
+    bus: B,
+    //System byte of SR, the condition codes live in `flags`
+    sr: u16,
+    flags: flags::Flags,
+    //68020 instruction cache
+    cache: icache::InstructionCache,
+    fpu: Option<fpu::Fpu>,
+    mmu: Option<mmu::Mmu>,
+    state: CpuState,
+    cycles: u64,
+    //Start of the instruction being executed
+    inst_pc: u32,
+    //Cycle count when it started
+    inst_cycles: u64,
+    //First word of the instruction being executed
+    ir: u16,
+    //Level raised through request_interrupt, cleared on acknowledge
+    ipl: u8,
+    //Level 7 was taken and hasn't dropped since, it only interrupts again
+    //once it rises anew
+    nmi_taken: bool,
+    //Decoded form and handler of every opcode word
+    opcodes: &'static [dispatch::Opcode],
+    //Decoded blocks, None when the cache is disabled
+    blocks: Option<blocks::BlockCache>,
+    //Prefetch queue, None when not emulated
+    prefetch: Option<prefetch::Prefetch>,
+    //Callback watching bus cycles
+    monitor: monitor::Monitor,
+    model: CpuModel,
+    //Vector base register, always 0 on the 68000
+    vbr: u32,
+    //Address spaces of MOVES reads and writes
+    sfc: FunctionCode,
+    dfc: FunctionCode,
+    //DBcc closing the loop the 68010 is running in loop mode
+    loop_mode: Option<u32>,
+}
+
+impl Default for CPU<Ram> {
+    fn default() -> CPU<Ram> {
+        CPU::new(Ram::default())
+    }
+}
+
+impl<B: Bus> CPU<B> {
+    pub fn new(bus: B) -> CPU<B> {
+        CPU::with_model(bus, CpuModel::MC68000)
+    }
+
+    pub fn with_model(bus: B, model: CpuModel) -> CPU<B> {
         CPU {
-            pc: vec![0;4],
-            usp: vec![0;4],
-            data_register: vec![vec![0;4];8],
-            address_register: vec![vec![0;4];8],
-            memory: vec![0;0x1000000],
-            sr: vec![0;2],
-            cache: vec![0;4],
+            pc: 0,
+            usp: 0,
+            ssp: 0,
+            data_register: [0; 8],
+            address_register: [0; 8],
+            bus,
+            sr: 0,
+            flags: flags::Flags::new(0),
+            cache: icache::InstructionCache::default(),
+            fpu: None,
+            mmu: None,
+            state: CpuState::RUNNING,
+            cycles: 0,
+            inst_pc: 0,
+            inst_cycles: 0,
+            ir: 0,
+            ipl: 0,
+            nmi_taken: false,
+            opcodes: dispatch::opcode_table(model),
+            blocks: None,
+            prefetch: None,
+            monitor: monitor::Monitor::default(),
+            model,
+            vbr: 0,
+            sfc: FunctionCode::UNDEFINED_0,
+            dfc: FunctionCode::UNDEFINED_0,
+            loop_mode: None,
         }
     }
 }
 
 
-impl Display for CPU {
+impl<B: Bus> Display for CPU<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        write!(f, "* PC = 0x")?;
-        write_byte_array(f, &(self.pc))?;
-        write!(f, "* USP = 0x")?;
-        write_byte_array(f, &(self.usp))?;
-        write!(f, "* Data Registers: \n")?;
+        match self.peek_instruction(self.pc) {
+            Some(inst) => writeln!(f, "* PC = 0x{:08x}: {}", self.pc, inst)?,
+            None => writeln!(f, "* PC = 0x{:08x}", self.pc)?,
+        }
+        writeln!(f, "* USP = 0x{:08x}", self.reg(Register::USP))?;
+        writeln!(f, "* Data Registers: ")?;
         for (n, x) in self.data_register.iter().enumerate() {
-            write!(f, "\t* D[{}] = 0x", n)?;
-            write_byte_array(f, &x)?;
+            writeln!(f, "\t* D[{}] = 0x{:08x}", n, x)?;
         }
-        write!(f, "* Address Registers: \n")?;
+        writeln!(f, "* Address Registers: ")?;
         for (n, x) in self.address_register.iter().enumerate() {
-            write!(f, "\t* A[{}] = 0x", n)?;
-            write_byte_array(f, &x)?;
+            writeln!(f, "\t* A[{}] = 0x{:08x}", n, x)?;
         }
-        write!(f, "SR = ")?;
-        for x in &(self.sr) {
-            write!(f, "{:08b}", x)?;
-        }
-        write!(f, "\n")?;
+        writeln!(f, "SR = {:016b}", self.get_sr())?;
         write!(f, "Cache = 0x")?;
-        write_byte_array(f, &(self.cache))?;
-        write!(f, "\n")?;
+        write_byte_array(f, self.cache.data())?;
+        writeln!(f, "State = {:?}, cycles = {}", self.state, self.cycles)?;
         Ok(())
     }
 }
 
-impl CPU {
+impl<B: Bus> CPU<B> {
 
-    pub fn foo(&mut self) {
-        self.set_x_flag();
-        self.set_z_flag();
-        self.set_v_flag();
-        self.set_c_flag();
-        self.clear_n_flag();
-        println!("{}", self);
+    //Condition codes are worked out from the last operation on demand
+    pub fn get_ccr(&self) -> u8 {
+        self.flags.ccr()
     }
 
-    //Set flags:
+    pub fn set_ccr(&mut self, ccr: u8) {
+        self.flags = flags::Flags::new(ccr & 0b00011111);
+    }
 
-    fn set_c_flag(&mut self) {
-        self.sr[1] = self.sr[1] | 0b00000001;
+    pub fn flag(&self, flag: Flag) -> bool {
+        self.get_ccr() & flag as u8 != 0
     }
 
-    fn set_v_flag(&mut self) {
-        self.sr[1] = self.sr[1] | 0b00000010;
+    //Works out every pending flag, left to the bit ops, CHK, CHK2 and the
+    //division corner cases which only touch some of them
+    pub fn set_flag(&mut self, flag: Flag, val: bool) {
+        let ccr = self.get_ccr();
+        if val {
+            self.set_ccr(ccr | flag as u8);
+        }
+        else {
+            self.set_ccr(ccr & !(flag as u8));
+        }
     }
 
-    fn set_z_flag(&mut self) {
-        self.sr[1] = self.sr[1] | 0b00000100;
+    //A7 is whichever of USP and SSP is active
+    pub fn reg(&self, reg: Register) -> u32 {
+        match reg {
+            Register::PC => self.get_pc(),
+            Register::SR => self.get_sr() as u32,
+            Register::USP if self.is_supervisor() => self.usp,
+            Register::SSP if !self.is_supervisor() => self.ssp,
+            Register::USP | Register::SSP => self.address_register[7],
+            Register::VBR => self.vbr,
+            Register::SFC => self.sfc as u32,
+            Register::DFC => self.dfc as u32,
+            Register::CACR => self.cacr(),
+            Register::CAAR => self.caar(),
+            Register::FPCR | Register::FPSR | Register::FPIAR => self.fp_control(reg),
+            r if (r as usize) < 8 => self.data_register[r as usize],
+            r => self.address_register[r as usize - 8],
+        }
     }
 
-    fn set_n_flag(&mut self) {
-        self.sr[1] = self.sr[1] | 0b00001000;
+    pub fn set_reg(&mut self, reg: Register, val: u32) {
+        match reg {
+            Register::PC => self.set_pc(val),
+            Register::SR => self.set_sr(val as u16),
+            Register::USP if self.is_supervisor() => self.usp = val,
+            Register::SSP if !self.is_supervisor() => self.ssp = val,
+            Register::USP | Register::SSP => self.address_register[7] = val,
+            Register::VBR => self.vbr = val,
+            Register::SFC => self.sfc = FunctionCode::from_bits(val),
+            Register::DFC => self.dfc = FunctionCode::from_bits(val),
+            Register::CACR => self.set_cacr(val),
+            Register::CAAR => self.set_caar(val),
+            Register::FPCR | Register::FPSR | Register::FPIAR => self.set_fp_control(reg, val),
+            r if (r as usize) < 8 => self.data_register[r as usize] = val,
+            r => self.address_register[r as usize - 8] = val,
+        }
     }
 
-    fn set_x_flag(&mut self) {
-        self.sr[1] = self.sr[1] | 0b00010000;
+    pub fn get_sr(&self) -> u16 {
+        self.sr | self.get_ccr() as u16
     }
 
-    //Clear Flags: 
+    //Switches A7 between USP and SSP when the S bit changes, except on
+    //ISA_A ColdFire cores which have a single A7
+    pub fn set_sr(&mut self, val: u16) {
+        let was_supervisor = self.is_supervisor();
+        self.sr = val & 0b10100111_00000000;
+        self.set_ccr(val as u8);
+        if self.model == CpuModel::COLDFIRE_ISA_A {
+            return;
+        }
+        if was_supervisor && !self.is_supervisor() {
+            self.ssp = self.address_register[7];
+            self.address_register[7] = self.usp;
+        }
+        else if !was_supervisor && self.is_supervisor() {
+            self.usp = self.address_register[7];
+            self.address_register[7] = self.ssp;
+        }
+    }
 
-    fn clear_c_flag(&mut self) {
-        self.sr[1] = self.sr[1] & 0b11111110
+    pub fn is_supervisor(&self) -> bool {
+        self.sr & 0x2000 != 0
     }
 
-    fn clear_v_flag(&mut self) {
-        self.sr[1] = self.sr[1] & 0b11111101;
+    pub fn get_pc(&self) -> u32 {
+        self.pc
     }
 
-    fn clear_z_flag(&mut self) {
-        self.sr[1] = self.sr[1] & 0b11111011;
+    pub fn set_pc(&mut self, val: u32) {
+        self.pc = val;
     }
 
-    fn clear_n_flag(&mut self) {
-        self.sr[1] = self.sr[1] & 0b11110111;
+    pub fn get_state(&self) -> CpuState {
+        self.state
     }
 
-    fn clear_x_flag(&mut self) {
-        self.sr[1] = self.sr[1] & 0b11101111;
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
     }
 
-    //Other specific funcs:
+    pub fn get_model(&self) -> CpuModel {
+        self.model
+    }
 
-    fn cache_value<'a>(&'a mut self, val: &'a Vec<u8>, n: usize) -> &'a mut [u8] {
-        self.cache = val.clone();
-        &mut self.cache[n..]
+    //Raises the interrupt line to `level` until it gets acknowledged
+    pub fn request_interrupt(&mut self, level: u8) {
+        self.ipl = self.ipl.max(level & 0b111);
     }
 
-    pub fn get_ccr(&self) -> u8 {
-        self.sr[1]
+    //Independent copy of the emulator. With Ram, memory pages are shared
+    //with the original until either of them writes to them.
+    pub fn fork(&self) -> CPU<B> where B: Clone {
+        self.clone()
     }
 
-    pub fn get_data_reg(&self, i: usize) -> Option<&[u8]> {
-        if i < 8 {
-            Some(&(self.data_register[i])[..])
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    //The bus may get changed in any way, cached code is dropped
+    pub fn bus_mut(&mut self) -> &mut B {
+        self.flush_blocks();
+        self.flush_prefetch();
+        &mut self.bus
+    }
+
+    fn data_fc(&self) -> FunctionCode {
+        if self.is_supervisor() { FunctionCode::SUPERVISOR_DATA } else { FunctionCode::USER_DATA }
+    }
+
+    fn program_fc(&self) -> FunctionCode {
+        if self.is_supervisor() { FunctionCode::SUPERVISOR_PROGRAM } else { FunctionCode::USER_PROGRAM }
+    }
+
+    //Drops the address lines the model lacks and raises address errors
+    fn check_access(&self, fc: FunctionCode, addr: u32, size: OpSize, write: bool) -> Result<u32, BusFault> {
+        let addr = addr & self.model.address_mask();
+        let program = matches!(fc, FunctionCode::USER_PROGRAM | FunctionCode::SUPERVISOR_PROGRAM);
+        if size != BYTE && addr & 1 != 0 && (program || !self.model.misaligned_data()) {
+            return Err(BusFault { address_error: true, ..BusFault::new(addr, fc, size, write) });
         }
-        else {
-            None
+        Ok(addr)
+    }
+
+    //Physical address of an access, after the address error checks and
+    //the MMU. Faults report the logical address.
+    fn physical(&mut self, fc: FunctionCode, addr: u32, size: OpSize, write: bool) -> Result<(u32, u32), BusFault> {
+        let addr = self.check_access(fc, addr, size, write)?;
+        match self.translate(fc, addr, write) {
+            Some(physical) => Ok((addr, physical)),
+            None => Err(BusFault::new(addr, fc, size, write)),
         }
     }
 
-    pub fn get_addr_reg(&self, i: usize) -> Option<&[u8]> {
-        if i < 8 {
-            Some(&(self.address_register[i])[..])
+    fn read_byte(&mut self, fc: FunctionCode, addr: u32) -> Result<u8, BusFault> {
+        let (addr, physical) = self.physical(fc, addr, BYTE, false)?;
+        let val = self.bus.read_byte(fc, physical).map_err(|_| BusFault::new(addr, fc, BYTE, false))?;
+        self.bus_cycle(fc, physical, &BYTE, val as u32, false);
+        Ok(val)
+    }
+
+    fn write_byte(&mut self, fc: FunctionCode, addr: u32, val: u8) -> Result<(), BusFault> {
+        let (addr, physical) = self.physical(fc, addr, BYTE, true)?;
+        self.invalidate_code(physical, 1);
+        self.bus.write_byte(fc, physical, val).map_err(|_| BusFault::new(addr, fc, BYTE, true))?;
+        self.bus_cycle(fc, physical, &BYTE, val as u32, true);
+        Ok(())
+    }
+
+    fn read_word(&mut self, fc: FunctionCode, addr: u32) -> Result<u16, BusFault> {
+        if self.splits_page(addr, 2) {
+            return Ok(u16::from_be_bytes([self.read_byte(fc, addr)?, self.read_byte(fc, addr.wrapping_add(1))?]));
         }
-        else {
-            None
+        let (addr, physical) = self.physical(fc, addr, WORD, false)?;
+        let val = self.bus.read_word(fc, physical).map_err(|_| BusFault::new(addr, fc, WORD, false))?;
+        self.bus_cycle(fc, physical, &WORD, val as u32, false);
+        Ok(val)
+    }
+
+    fn write_word(&mut self, fc: FunctionCode, addr: u32, val: u16) -> Result<(), BusFault> {
+        if self.splits_page(addr, 2) {
+            self.write_byte(fc, addr, (val >> 8) as u8)?;
+            return self.write_byte(fc, addr.wrapping_add(1), val as u8);
         }
+        let (addr, physical) = self.physical(fc, addr, WORD, true)?;
+        self.invalidate_code(physical, 2);
+        self.bus.write_word(fc, physical, val).map_err(|_| BusFault::new(addr, fc, WORD, true))?;
+        self.bus_cycle(fc, physical, &WORD, val as u32, true);
+        Ok(())
     }
 
-    pub fn get_memory_offset(&self, offset: usize, len: usize) -> Option<&[u8]> {
-        if offset >= 0x1000000 || offset + len >= 0x1000000 {
-            None
+    fn read_long(&mut self, fc: FunctionCode, addr: u32) -> Result<u32, BusFault> {
+        if self.splits_page(addr, 4) {
+            let high = self.read_word(fc, addr)? as u32;
+            return Ok(high << 16 | self.read_word(fc, addr.wrapping_add(2))? as u32);
         }
-        else {
-            Some(&self.memory[offset..(offset + len)])
+        let (addr, physical) = self.physical(fc, addr, LONG, false)?;
+        let val = self.bus.read_long(fc, physical).map_err(|_| BusFault::new(addr, fc, LONG, false))?;
+        self.bus_cycle(fc, physical, &LONG, val, false);
+        Ok(val)
+    }
+
+    fn write_long(&mut self, fc: FunctionCode, addr: u32, val: u32) -> Result<(), BusFault> {
+        if self.splits_page(addr, 4) {
+            self.write_word(fc, addr, (val >> 16) as u16)?;
+            return self.write_word(fc, addr.wrapping_add(2), val as u16);
+        }
+        let (addr, physical) = self.physical(fc, addr, LONG, true)?;
+        self.invalidate_code(physical, 4);
+        self.bus.write_long(fc, physical, val).map_err(|_| BusFault::new(addr, fc, LONG, true))?;
+        self.bus_cycle(fc, physical, &LONG, val, true);
+        Ok(())
+    }
+
+    pub fn execute(&mut self, inst: &Instruction) {
+        if let Err(fault) = self.dispatch(inst) {
+            self.bus_error(fault);
+        }
+    }
+
+    //Executes a single instruction, or takes a pending interrupt.
+    //A stopped CPU idles until the next device event instead.
+    //Returns the number of cycles elapsed.
+    pub fn step(&mut self) -> u64 {
+        let start = self.cycles;
+        self.inst_cycles = start;
+        if !self.check_interrupts() {
+            if self.state == CpuState::HALTED {
+                return 0;
+            }
+            if self.state == CpuState::STOPPED {
+                if let Some(idle) = self.next_device_event() {
+                    self.advance(idle.max(1));
+                }
+                return self.cycles - start;
+            }
+            //Tracing applies to instructions started with T set, unless
+            //they raised an exception themselves
+            let trace = self.sr & 0x8000 != 0;
+            let restart = self.restart_point();
+            let result = if self.use_blocks() { self.execute_cached() } else { self.execute_next() };
+            match result {
+                Ok(()) if trace && self.sr & 0x8000 != 0 => {
+                    self.raise_exception(VECTOR_TRACE);
+                    self.cycles += 34;
+                },
+                Ok(()) => {},
+                Err(fault) => {
+                    if let Some(restart) = restart {
+                        self.restart(restart);
+                    }
+                    self.bus_error(fault);
+                },
+            }
         }
+        let elapsed = self.cycles - start;
+        self.tick_devices(elapsed);
+        elapsed
+    }
+
+    //Runs for at least `cycles` cycles, returns how many actually elapsed.
+    //Time spent stopped is skipped up to the next device event.
+    pub fn run(&mut self, cycles: u64) -> u64 {
+        let start = self.cycles;
+        while self.cycles - start < cycles {
+            if self.state == CpuState::HALTED {
+                break;
+            }
+            if self.state == CpuState::STOPPED && self.pending_interrupt().is_none() {
+                let left = cycles - (self.cycles - start);
+                let idle = match self.next_device_event() {
+                    Some(event) => event.clamp(1, left),
+                    None => left,
+                };
+                self.advance(idle);
+            }
+            else {
+                self.step();
+            }
+        }
+        self.cycles - start
+    }
+
+    fn advance(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        self.tick_devices(cycles);
+    }
+
+    fn tick_devices(&mut self, cycles: u64) {
+        self.bus.tick(cycles);
+    }
+
+    fn next_device_event(&self) -> Option<u64> {
+        self.bus.next_event()
+    }
+}
+
+//Direct access to the RAM behind the bus, bypassing devices
+impl<B: Bus + AsRef<Ram> + AsMut<Ram>> CPU<B> {
+
+    pub fn load(&mut self, offset: usize, data: &[u8]) {
+        self.invalidate_code(offset as u32, data.len().max(1) as u32);
+        self.flush_prefetch();
+        self.bus.as_mut().load(offset, data);
+    }
+
+    pub fn get_memory_offset(&mut self, offset: usize, len: usize) -> Option<&[u8]> {
+        self.bus.as_mut().get(offset, len)
     }
 
     pub fn print_mem(&self, offset: usize, len: usize) {
-        let mem = self.get_memory_offset(offset, len);
+        let mem = self.bus.as_ref().read(offset, len);
         if let Some(x) = mem {
             print!("[0x{:x}] = 0x", offset);
-            for e in x {
+            for e in x.iter() {
                 print!("{:02x}", e);
             }
-            println!("");
+            println!();
         }
         else { 
             println!("print_mem: None");
         }
     }
-
-    pub fn execute(&mut self, inst: &Instruction) {
-        match inst.get_op() {
-            MOVE => self.perform_move(inst),
-            TST => self.perform_tst(inst),
-            ADD => self.perform_add(inst),
-            _ => (),
-        }
-    }
-
-    //test_purposes, felt cute, might delete late
-    pub fn cpu_flag_move_test(&mut self) {
-        self.set_n_flag();
-        self.set_c_flag();
-        self.set_x_flag();
-    }
 }
@@ -0,0 +1,153 @@
+use super::*;
+
+//Where an operand lives once its effective address has been computed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Location {
+    DATA(usize),
+    ADDRESS(usize),
+    MEMORY(u32),
+    //PC relative operands are read from program space
+    PROGRAM(u32),
+    VALUE(u32),
+    SR,
+    CCR,
+    USP,
+}
+
+impl<B: Bus> super::CPU<B> {
+
+    pub(super) fn read_memory(&mut self, fc: FunctionCode, addr: u32, op_size: &OpSize) -> Result<u32, BusFault> {
+        Ok(match op_size {
+            BYTE => self.read_byte(fc, addr)? as u32,
+            WORD => self.read_word(fc, addr)? as u32,
+            LONG => self.read_long(fc, addr)?,
+        })
+    }
+
+    pub(super) fn write_memory(&mut self, fc: FunctionCode, addr: u32, op_size: &OpSize, val: u32) -> Result<(), BusFault> {
+        match op_size {
+            BYTE => self.write_byte(fc, addr, val as u8),
+            WORD => self.write_word(fc, addr, val as u16),
+            LONG => self.write_long(fc, addr, val),
+        }
+    }
+
+    //Value of an index register, sign extended when only its word is used
+    fn index_value(&self, index: &Index) -> u32 {
+        let val = if index.register < 8 {
+            self.data_register[index.register]
+        }
+        else {
+            self.address_register[index.register - 8]
+        };
+        let val = if index.long { val } else { val as i16 as u32 };
+        val.wrapping_mul(index.scale as u32)
+    }
+
+    //Computes the effective address of an operand, applying the
+    //increments and decrements of (An)+ and -(An)
+    pub(super) fn locate(&mut self, data: &DataContainer, op_size: &OpSize) -> Result<Location, BusFault> {
+        //A7 stays word aligned
+        let step = |reg: usize| if reg == 7 && *op_size == BYTE { 2 } else { op_size.bytes() };
+        Ok(match self.resolve(data, op_size)? {
+            DATA_REGISTER(reg) => Location::DATA(reg),
+            ADDRESS_REGISTER(reg) => Location::ADDRESS(reg),
+            IMEDIATE_VALUE(val) => Location::VALUE(val),
+            REGISTER_LIST(mask) => Location::VALUE(mask as u32),
+            MEMORY_ADDR(addr) => Location::MEMORY(addr),
+            SHORT_ADDR(addr) => Location::MEMORY(addr as u32),
+            ADDRESS_INDIRECT(reg) => Location::MEMORY(self.address_register[reg]),
+            POSTINCREMENT(reg) => {
+                let addr = self.address_register[reg];
+                self.address_register[reg] = addr.wrapping_add(step(reg));
+                Location::MEMORY(addr)
+            },
+            PREDECREMENT(reg) => {
+                let addr = self.address_register[reg].wrapping_sub(step(reg));
+                self.address_register[reg] = addr;
+                Location::MEMORY(addr)
+            },
+            DISPLACEMENT(reg, disp) => Location::MEMORY(self.address_register[reg].wrapping_add(disp as u32)),
+            INDEXED(reg, index, disp) => {
+                let base = self.address_register[reg].wrapping_add(disp as u32);
+                Location::MEMORY(base.wrapping_add(self.index_value(&index)))
+            },
+            PC_DISPLACEMENT(pc, disp) => Location::PROGRAM(pc.wrapping_add(disp as u32)),
+            PC_INDEXED(pc, index, disp) => {
+                let base = pc.wrapping_add(disp as u32);
+                Location::PROGRAM(base.wrapping_add(self.index_value(&index)))
+            },
+            EXTENDED(ext) => self.locate_extended(&ext)?,
+            SR => Location::SR,
+            CCR => Location::CCR,
+            USP => Location::USP,
+            other => panic!("{:?} has no location", other),
+        })
+    }
+
+    //Memory indirection reads the pointer from the space of the operand
+    fn locate_extended(&mut self, ext: &Extended) -> Result<Location, BusFault> {
+        let (base, program) = match ext.base {
+            Base::ADDRESS(reg) => (self.address_register[reg], false),
+            Base::PC(pc) => (pc, true),
+            Base::SUPPRESSED => (0, false),
+            Base::SUPPRESSED_PC => (0, true),
+        };
+        let fc = if program { self.program_fc() } else { self.data_fc() };
+        let base = base.wrapping_add(ext.bd as u32);
+        let index = ext.index.map_or(0, |index| self.index_value(&index));
+        let addr = match ext.indirect {
+            Indirect::NONE => base.wrapping_add(index),
+            Indirect::PRE_INDEXED => self.read_long(fc, base.wrapping_add(index))?.wrapping_add(ext.od as u32),
+            Indirect::POST_INDEXED => self.read_long(fc, base)?.wrapping_add(index).wrapping_add(ext.od as u32),
+        };
+        Ok(if program { Location::PROGRAM(addr) } else { Location::MEMORY(addr) })
+    }
+
+    //Address of a control addressing mode, for LEA, JMP and the like
+    pub(super) fn effective_address(&mut self, data: &DataContainer) -> Result<u32, BusFault> {
+        match self.locate(data, &LONG)? {
+            Location::MEMORY(addr) | Location::PROGRAM(addr) => Ok(addr),
+            other => panic!("{:?} has no address", other),
+        }
+    }
+
+    pub(super) fn read_location(&mut self, loc: &Location, op_size: &OpSize) -> Result<u32, BusFault> {
+        let mask = op_size.mask();
+        Ok(match *loc {
+            Location::DATA(reg) => self.data_register[reg] & mask,
+            Location::ADDRESS(reg) => self.address_register[reg] & mask,
+            Location::VALUE(val) => val & mask,
+            Location::MEMORY(addr) => self.read_memory(self.data_fc(), addr, op_size)?,
+            Location::PROGRAM(addr) => self.read_memory(self.program_fc(), addr, op_size)?,
+            Location::SR => self.get_sr() as u32,
+            Location::CCR => self.get_ccr() as u32,
+            Location::USP => self.reg(Register::USP),
+        })
+    }
+
+    //Registers only get their low `op_size` bits replaced
+    pub(super) fn write_location(&mut self, loc: &Location, op_size: &OpSize, val: u32) -> Result<(), BusFault> {
+        let mask = op_size.mask();
+        match *loc {
+            Location::DATA(reg) => self.data_register[reg] = (self.data_register[reg] & !mask) | (val & mask),
+            Location::ADDRESS(reg) => self.address_register[reg] = (self.address_register[reg] & !mask) | (val & mask),
+            Location::MEMORY(addr) => self.write_memory(self.data_fc(), addr, op_size, val)?,
+            Location::PROGRAM(_) | Location::VALUE(_) => panic!("{:?} is immutable !", loc),
+            Location::SR => self.set_sr(val as u16),
+            Location::CCR => self.set_ccr(val as u8),
+            Location::USP => self.set_reg(Register::USP, val),
+        }
+        Ok(())
+    }
+
+    pub(super) fn get_target(&mut self, data: &DataContainer, op_size: &OpSize) -> Result<u32, BusFault> {
+        let loc = self.locate(data, op_size)?;
+        self.read_location(&loc, op_size)
+    }
+
+    pub(super) fn set_target(&mut self, data: &DataContainer, op_size: &OpSize, val: u32) -> Result<(), BusFault> {
+        let loc = self.locate(data, op_size)?;
+        self.write_location(&loc, op_size, val)
+    }
+}
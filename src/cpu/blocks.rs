@@ -0,0 +1,193 @@
+use super::*;
+use super::dispatch::Opcode;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+//Most instructions decoded ahead in one go
+const MAX_BLOCK: usize = 64;
+//Writes are checked against cached code with this granularity
+const PAGE_BITS: u32 = 8;
+
+//An instruction of a block, with its operands already completed
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Cached {
+    //Both without the address lines the model lacks
+    pub pc: u32,
+    //Address of the instruction following it
+    pub next_pc: u32,
+    pub opcode: u16,
+    pub handler: u8,
+    pub cycles: u16,
+    pub inst: Instruction,
+}
+
+//Anything that may not fall through to the next instruction ends a block,
+//so do writes to SR since they can switch the program address space
+fn _ends_block(inst: &Instruction) -> bool {
+    matches!(inst.get_op(), BRA | BSR | BCC(_) | DBCC(_) | JMP | JSR | RTS | RTR | RTE | RTD
+        | TRAP | TRAPV | TRAPCC(_) | CHK | CHK2 | STOP | RESET | ILLEGAL | LINE_A | LINE_F
+        | FBCC(_) | FDBCC(_) | FTRAPCC(_) | FSAVE | FRESTORE | PMOVE | PMOVEFD)
+        || *inst.get_trg() == SR
+}
+
+fn _overlaps(block: &[Cached], start: u32, end: u32) -> bool {
+    let first = block[0].pc;
+    let last = block[block.len() - 1].next_pc.wrapping_sub(1);
+    first <= end && start <= last
+}
+
+// Decoded runs of straight-line code keyed by the address of their first
+// instruction, as it goes out on the bus so that aliases of it share the
+// block.
+//
+// Every write done by the CPU is checked against the pages holding cached
+// code, and the blocks in a written page are dropped so self-modifying code
+// and code copied over older code get decoded again. Anything changing
+// memory behind the CPU's back has to flush the cache.
+#[derive(Debug, Clone, Default)]
+pub(super) struct BlockCache {
+    blocks: HashMap<u32, Arc<[Cached]>>,
+    //Pages holding cached code
+    pages: HashSet<u32>,
+    //Lowest and highest cached addresses, to skip most writes early
+    span: Option<(u32, u32)>,
+    //Block being run and index of its next instruction
+    current: Option<(Arc<[Cached]>, usize)>,
+}
+
+impl BlockCache {
+    //Instruction at `pc`, following on the block being run when possible
+    fn next(&mut self, pc: u32) -> Option<Cached> {
+        if let Some((block, i)) = &mut self.current {
+            if let Some(&entry) = block.get(*i).filter(|entry| entry.pc == pc) {
+                *i += 1;
+                return Some(entry);
+            }
+        }
+        let block = self.blocks.get(&pc)?.clone();
+        let entry = block[0];
+        self.current = Some((block, 1));
+        Some(entry)
+    }
+
+    fn insert(&mut self, block: Vec<Cached>) {
+        let start = block[0].pc;
+        let last = block[block.len() - 1].next_pc.wrapping_sub(1);
+        for page in (start >> PAGE_BITS)..=(last >> PAGE_BITS) {
+            self.pages.insert(page);
+        }
+        self.span = Some(match self.span {
+            Some((lo, hi)) => (lo.min(start), hi.max(last)),
+            None => (start, last),
+        });
+        self.blocks.insert(start, block.into());
+    }
+
+    //Drops the blocks sharing a page with the `len` bytes written at `addr`
+    fn invalidate(&mut self, addr: u32, len: u32) {
+        let last = addr.wrapping_add(len - 1);
+        match self.span {
+            Some((lo, hi)) if addr <= hi && lo <= last => {},
+            _ => return,
+        }
+        for page in (addr >> PAGE_BITS)..=(last >> PAGE_BITS) {
+            if !self.pages.remove(&page) {
+                continue;
+            }
+            let (start, end) = (page << PAGE_BITS, (page << PAGE_BITS) | ((1 << PAGE_BITS) - 1));
+            self.blocks.retain(|_, block| !_overlaps(block, start, end));
+            if matches!(&self.current, Some((block, _)) if _overlaps(block, start, end)) {
+                self.current = None;
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = BlockCache::default();
+    }
+}
+
+impl<B: Bus> super::CPU<B> {
+
+    //The cache is off by default
+    pub fn set_block_cache(&mut self, enabled: bool) {
+        self.blocks = if enabled { Some(BlockCache::default()) } else { None };
+    }
+
+    pub fn block_cache_enabled(&self) -> bool {
+        self.blocks.is_some()
+    }
+
+    //Prefetch emulation, the 68020 cache, address translation and bus
+    //monitoring need every fetch to be done
+    pub(super) fn use_blocks(&self) -> bool {
+        self.blocks.is_some() && self.prefetch.is_none() && !self.monitor.is_active() && !self.icache_enabled()
+            && !self.translating()
+    }
+
+    pub(super) fn invalidate_code(&mut self, addr: u32, len: u32) {
+        if let Some(blocks) = &mut self.blocks {
+            blocks.invalidate(addr, len);
+        }
+    }
+
+    pub(super) fn flush_blocks(&mut self) {
+        if let Some(blocks) = &mut self.blocks {
+            blocks.clear();
+        }
+    }
+
+    //Decodes from `start` up to the end of the block. Stops early on code
+    //the bus won't let be cached and on fetches that fault, which are left
+    //for execute_next to run.
+    fn build_block(&mut self, start: u32) -> Vec<Cached> {
+        let (pc, ir) = (self.get_pc(), self.ir);
+        let mask = self.model.address_mask();
+        self.set_pc(start);
+        let mut block = Vec::new();
+        while block.len() < MAX_BLOCK && self.bus.cacheable(self.get_pc() & mask) {
+            let inst_pc = self.get_pc() & mask;
+            let inst = match self.decode() {
+                Ok(inst) => inst,
+                Err(_) => break,
+            };
+            let next_pc = self.get_pc() & mask;
+            if !self.bus.cacheable(next_pc.wrapping_sub(1) & mask) {
+                break;
+            }
+            let Opcode { handler, cycles, .. } = self.opcodes[self.ir as usize];
+            block.push(Cached { pc: inst_pc, next_pc, opcode: self.ir, handler, cycles, inst });
+            if _ends_block(&inst) {
+                break;
+            }
+        }
+        self.set_pc(pc);
+        self.ir = ir;
+        block
+    }
+
+    //Same as execute_next, with the instruction taken from its block
+    pub(super) fn execute_cached(&mut self) -> Result<(), BusFault> {
+        let (pc, mask) = (self.get_pc(), self.model.address_mask());
+        let entry = match self.blocks.as_mut().and_then(|blocks| blocks.next(pc & mask)) {
+            Some(entry) => entry,
+            None => {
+                let block = self.build_block(pc);
+                match self.blocks.as_mut() {
+                    Some(blocks) if !block.is_empty() => {
+                        blocks.insert(block);
+                        blocks.next(pc & mask).expect("block just inserted")
+                    },
+                    _ => return self.execute_next(),
+                }
+            },
+        };
+        self.inst_pc = pc;
+        self.inst_cycles = self.cycles;
+        self.ir = entry.opcode;
+        //PC keeps the address lines the model lacks
+        self.set_pc(pc.wrapping_add(entry.next_pc.wrapping_sub(entry.pc) & mask));
+        self.cycles += entry.cycles as u64;
+        self.run_handler(entry.handler, &entry.inst)
+    }
+}
@@ -0,0 +1,50 @@
+use super::*;
+use super::cpu_actions::_sign_extend;
+use super::dispatch::handler_index;
+use super::exception::VECTOR_ILLEGAL;
+
+// Instructions ColdFire cores added to the 68000 set, and the checks on
+// what the 68020 decoding can't tell apart from a valid ColdFire encoding.
+
+impl<B: Bus> super::CPU<B> {
+
+    //N and Z of the long stored, even to an address register
+    pub fn perform_mov3q(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let val = self.get_target(inst.get_lhs(), &LONG)?;
+        self.set_target(inst.get_trg(), &LONG, val)?;
+        self.flags.logic(val, &LONG);
+        Ok(())
+    }
+
+    pub fn perform_mvx(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let val = self.get_target(inst.get_lhs(), size)?;
+        let val = if *inst.get_op() == MVS { _sign_extend(val, size) } else { val };
+        self.set_target(inst.get_trg(), &LONG, val)?;
+        self.flags.logic(val, &LONG);
+        Ok(())
+    }
+
+    //After an overflow the register holds the wrong sign, so it gets the
+    //largest value of the sign it should have had
+    pub fn perform_sats(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let mut val = self.get_target(inst.get_lhs(), &LONG)?;
+        if self.get_ccr() & Flag::V as u8 != 0 {
+            val = if val & 0x80000000 == 0 { 0x80000000 } else { 0x7fffffff };
+            self.set_target(inst.get_lhs(), &LONG, val)?;
+        }
+        self.flags.logic(val, &LONG);
+        Ok(())
+    }
+
+    //Indexed modes only take a long index register scaled by 1, 2 or 4 in
+    //a brief extension word. The instructions ColdFire allows an indexed
+    //operand on all have its extension word right after the opcode.
+    pub(super) fn perform_coldfire_indexed(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let ext = self.read_word(self.program_fc(), self.inst_pc.wrapping_add(2))?;
+        if ext & 0x0900 != 0x0800 || (ext >> 9) & 0b11 == 3 {
+            return self.raise_fault(VECTOR_ILLEGAL);
+        }
+        self.run_handler(handler_index(inst.get_op()), inst)
+    }
+}
@@ -1,237 +1,908 @@
 use super::instruction::*;
+use super::addressing::Location;
+use super::exception::*;
+use super::{timing, BusFault, CpuModel, CpuState, Flag, Register};
+use crate::bus::{Bus, FunctionCode};
 use DataContainer::*;
 use Mnemonic::*;
 use OpSize::*;
-use std::ops::Deref;
 
 
-fn _get_usize(op_size: &OpSize) -> usize {
+pub(crate) fn _is_null(val: u32, op_size: &OpSize) -> bool {
+    val & op_size.mask() == 0
+}
+
+pub(crate) fn _is_negative(val: u32, op_size: &OpSize) -> bool {
+    val & op_size.msb() != 0
+}
+
+pub(super) fn _sign_extend(val: u32, op_size: &OpSize) -> u32 {
     match op_size {
-        BYTE => 3,
-        WORD => 2,
-        LONG => 0,
+        BYTE => val as i8 as u32,
+        WORD => val as i16 as u32,
+        LONG => val,
     }
 }
 
-fn _is_null(val: &[u8]) -> bool {
-    for byte_ in val {
-        if *byte_ != 0 {
-            return false;
-        }
+//Returns the carry out and the result truncated to `op_size`
+pub(crate) fn _perform_add(op_size: &OpSize, v1: u32, v2: u32) -> (bool, u32) {
+    let mask = op_size.mask() as u64;
+    let result = (v1 as u64 & mask) + (v2 as u64 & mask);
+    (result > mask, (result & mask) as u32)
+}
+
+//Borrow and overflow of `dst - src`
+fn _sub_flags(src: u32, dst: u32, result: u32, op_size: &OpSize) -> (bool, bool) {
+    let borrow = _is_negative((src & !dst) | (result & !dst) | (src & result), op_size);
+    let overflow = _is_negative((src ^ dst) & (result ^ dst), op_size);
+    (borrow, overflow)
+}
+
+//Decimal adjusts of ABCD and SBCD on packed bytes, returning the result,
+//the decimal carry and the undocumented overflow
+fn _bcd_add(src: u32, dst: u32, x: u32) -> (u32, bool, bool) {
+    let mut result = (src & 0xf) + (dst & 0xf) + x;
+    let overflow = !result;
+    if result > 9 {
+        result += 6;
     }
-    return true;
+    result += (src & 0xf0) + (dst & 0xf0);
+    let carry = result > 0x99;
+    if carry {
+        result -= 0xa0;
+    }
+    (result & 0xff, carry, overflow & result & 0x80 != 0)
 }
 
-fn _is_negative(val: &[u8]) -> bool {
-    if val[0] > 0x7f {
-        true
+fn _bcd_sub(src: u32, dst: u32, x: u32) -> (u32, bool, bool) {
+    let mut result = (dst & 0xf).wrapping_sub(src & 0xf).wrapping_sub(x);
+    let overflow = !result;
+    if result > 9 {
+        result = result.wrapping_sub(6);
     }
-    else {
-        false
+    result = result.wrapping_add(dst & 0xf0).wrapping_sub(src & 0xf0);
+    let carry = result > 0x99;
+    if carry {
+        result = result.wrapping_add(0xa0);
     }
+    (result & 0xff, carry, overflow & !result & 0x80 != 0)
 }
 
-fn _perform_add(v1: &[u8], v2: &[u8]) -> (bool, Vec<u8>) {
-    let mut c = 0;
-    let mut result = v1.iter().rev().zip(v2.iter().rev())
-                    .map(|(x, y)| {
-                        let y = match y.checked_add(c) {
-                            Some(r) => {c = 0; r},
-                            None => (((*y as u16) + (c as u16)) % 0x100) as u8,
-                        };
-                        match x.checked_add(y) {
-                            Some(r) => {c = 0; r},
-                            None => {c = 1; 
-                                (((*x as u16) + (y as u16)) % 0x100) as u8   
-                            },
-                        }
-                    })
-                    .collect::<Vec<u8>>();
-    while result.len() < 4 {
-        result.push(0);
-    }
-    result.reverse();
-    return (c == 1, result)
+//MOVEC control register codes
+fn _control_register(model: CpuModel, code: u16) -> Option<Register> {
+    match code {
+        _ if model < CpuModel::MC68010 => None,
+        0x801 if model.is_coldfire() => Some(Register::VBR),
+        _ if model.is_coldfire() => None,
+        0x000 => Some(Register::SFC),
+        0x001 => Some(Register::DFC),
+        0x800 => Some(Register::USP),
+        0x801 => Some(Register::VBR),
+        _ if model < CpuModel::MC68020 => None,
+        0x002 => Some(Register::CACR),
+        0x802 => Some(Register::CAAR),
+        _ => None,
+    }
 }
 
-mod internal_tests {
-    use super::*;
-    #[test]
-    fn test_perform_add_basic() {
-        let v1 = vec![0xff];
-        let v2 = vec![0x1];
-        let v3 = vec![0x2];
+//Whether MOVEC takes the code, ColdFire cores writing registers they have
+//nothing to act on
+pub(super) fn _movec_code(model: CpuModel, code: u16, to_control: bool) -> bool {
+    _control_register(model, code).is_some() || to_control && model.is_coldfire()
+}
 
-        let (flag, r) = _perform_add(&v1[..], &v2[..]);
-        assert_eq!(r, vec![0, 0, 0, 0]);
-        assert_eq!(flag, true);
-        let (flag, r) = _perform_add(&v2[..], &v3[..]);
-        assert_eq!(r, vec![0x00, 0x00, 0x00, 0x3]);
-        assert_eq!(flag, false);
+//One word instructions the 68010 can run in loop mode
+fn _loopable(inst: &Instruction) -> bool {
+    let memory = |data: &DataContainer| matches!(data, ADDRESS_INDIRECT(_) | POSTINCREMENT(_) | PREDECREMENT(_));
+    let operand = |data: &DataContainer| memory(data) || matches!(data, DATA_REGISTER(_) | ADDRESS_REGISTER(_) | EMPTY);
+    let (lhs, trg) = (inst.get_lhs(), inst.get_trg());
+    matches!(inst.get_op(), MOVE | ADD | ADDA | ADDX | SUB | SUBA | SUBX | AND | OR | EOR | CMP | CMPA | CMPM
+        | ABCD | SBCD | CLR | NEG | NEGX | NOT | NBCD | TST | ASL | ASR | LSL | LSR | ROL | ROR | ROXL | ROXR)
+        && operand(lhs) && operand(trg) && (memory(lhs) || memory(trg))
+}
+
+impl<B: Bus> super::CPU<B> {
 
-        let (flag, r) = _perform_add(&v2[..], &(vec![0])[..]);
-        assert_eq!(r, vec![0x00, 0x00, 0x00, 0x1]);
-        assert_eq!(flag, false);
+    pub(super) fn test_condition(&self, cond: Condition) -> bool {
+        let ccr = self.get_ccr();
+        let flag = |flag: Flag| ccr & flag as u8 != 0;
+        let (c, v, z, n) = (flag(Flag::C), flag(Flag::V), flag(Flag::Z), flag(Flag::N));
+        match cond {
+            Condition::T => true,
+            Condition::F => false,
+            Condition::HI => !c && !z,
+            Condition::LS => c || z,
+            Condition::CC => !c,
+            Condition::CS => c,
+            Condition::NE => !z,
+            Condition::EQ => z,
+            Condition::VC => !v,
+            Condition::VS => v,
+            Condition::PL => !n,
+            Condition::MI => n,
+            Condition::GE => n == v,
+            Condition::LT => n != v,
+            Condition::GT => !z && n == v,
+            Condition::LE => z || n != v,
+        }
     }
 
-    #[test]
-    fn test_perform_add_report() {
-        let v1 = vec![0x00, 0x00, 0x00, 0xff];
-        let v2 = vec![0x00, 0x00, 0x00, 0x01];
+    fn push(&mut self, op_size: &OpSize, val: u32) -> Result<(), BusFault> {
+        self.set_target(&PREDECREMENT(7), op_size, val)
+    }
 
-        let (flag, r) = _perform_add(&v1[..], &v2[..]);
-        assert_eq!(r, vec![0x00, 0x00, 0x01, 0x00]);
-        assert_eq!(flag, false);
+    fn pop(&mut self, op_size: &OpSize) -> Result<u32, BusFault> {
+        self.get_target(&POSTINCREMENT(7), op_size)
+    }
 
-        let (flag, r) = _perform_add(&v1[1..], &v2[1..]);
-        assert_eq!(r, vec![0x00, 0x00, 0x01, 0x00]);
-        assert_eq!(flag, false);
+    //Registers as numbered in MOVEM masks, D0-D7 then A0-A7
+    fn list_register(&self, i: usize) -> u32 {
+        if i < 8 { self.data_register[i] } else { self.address_register[i - 8] }
+    }
 
-        let (flag, r) = _perform_add(&v1[2..], &v2[2..]);
-        assert_eq!(r, vec![0x00, 0x00, 0x01, 0x00]);
-        assert_eq!(flag, false);
+    fn set_list_register(&mut self, i: usize, val: u32) {
+        if i < 8 {
+            self.data_register[i] = val;
+        }
+        else {
+            self.address_register[i - 8] = val;
+        }
     }
 
-    #[test]
-    fn test_perform_add_max() {
-        let v1 = vec![0xff, 0x00, 0x00, 0xff];
-        let v2 = vec![0x01, 0x00, 0x00, 0x01];
+    //Moves to and from SR, CCR and USP leave the condition codes alone
+    pub fn perform_move(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let system = match (inst.get_lhs(), inst.get_trg()) {
+            (_, SR) | (USP, _) | (_, USP) if !self.is_supervisor() => {
+                return self.raise_fault(VECTOR_PRIVILEGE);
+            },
+            //Only the 68000 lets user code read the system byte
+            (SR, _) if !self.is_supervisor() && self.model >= CpuModel::MC68010 => {
+                return self.raise_fault(VECTOR_PRIVILEGE);
+            },
+            (_, SR) | (USP, _) | (_, USP) | (SR, _) | (_, CCR) | (CCR, _) => true,
+            _ => false,
+        };
 
-        let (flag, r) = _perform_add(&v1[..], &v2[..]);
-        assert_eq!(r, vec![0x00, 0x00, 0x01, 0x00]);
-        assert_eq!(flag, true);
+        let val = self.get_target(inst.get_lhs(), size)?;
+        if !system {
+            self.flags.logic(val, size);
+        }
+        self.set_target(inst.get_trg(), size, val)
+    }
 
-        let (flag, r) = _perform_add(&v1[1..], &v2[1..]);
-        assert_eq!(r, vec![0x00, 0x00, 0x01, 0x00]);
-        assert_eq!(flag, false);
+    pub fn perform_movea(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let val = _sign_extend(self.get_target(inst.get_lhs(), size)?, size);
+        self.set_target(inst.get_trg(), &LONG, val)
     }
-}
 
-impl super::CPU {
-
-    fn get_target_mut<'a>(&'a mut self, data : &'a DataContainer, op_size : &'a OpSize) -> &'a mut [u8] {
-        let adjust = _get_usize(&op_size);
-        match data {
-            DATA_REGISTER(ui) => &mut self.data_register[*ui][adjust..],
-            ADDRESS_REGISTER(ui) => &mut self.address_register[*ui][adjust..],
-            IMEDIATE_VALUE(_) => panic!("Imediate Value is immutable !"),
-            MEMORY_ADDR(addr) => &mut self.memory[*addr..(*addr + 4 - adjust)],
-            SR => &mut self.sr,
-            CCR => &mut self.sr[1..],
-            EMPTY => panic!("TODO: implement behaviour when instruction has empty args"),
-        }
-    }
-
-    fn get_target<'a>(&'a self, data : &'a DataContainer, op_size : &'a OpSize) -> &'a [u8] {
-        let adjust = _get_usize(&op_size);
-        match data {
-            DATA_REGISTER(ui) => &self.data_register[*ui][adjust..],
-            ADDRESS_REGISTER(ui) => &self.address_register[*ui][adjust..],
-            IMEDIATE_VALUE(vect) => &vect[adjust..],
-            MEMORY_ADDR(addr) => &self.memory[*addr..(*addr + 4 - adjust)],
-            SR => &self.sr,
-            CCR => &self.sr[1..],
-            EMPTY => panic!("TODO: implement behaviour when instruction has empty args"),
-        }
-    }
-
-    pub fn perform_move(&mut self, inst: &Instruction) {
-        //kills the instruction ? I don't think so #loops
-        self.clear_c_flag();
-        self.clear_v_flag();
-        
-        let lhs = self.get_target(inst.get_lhs(), inst.get_size());
-        let mut tmp: Vec<u8> = vec![0;lhs.len()];
-        tmp.copy_from_slice(lhs);
-        
-        if _is_negative(&tmp[..]) {
-            self.set_n_flag();
+    pub fn perform_movem(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let step = size.bytes();
+        //4 cycles per word transferred
+        let transfer = |mask: u32| (mask.count_ones() * step * 2) as u64;
+        if let REGISTER_MASK(_) | REGISTER_LIST(_) = inst.get_lhs() {
+            let mask = self.get_target(inst.get_lhs(), &WORD)?;
+            self.cycles += transfer(mask);
+            let listed = move |i: &usize| mask & 1 << i != 0;
+            if let PREDECREMENT(reg) = *inst.get_trg() {
+                //Stored from A7 down to D0, An is written with its initial value
+                let mut addr = self.address_register[reg];
+                for i in (0..16).rev().filter(listed) {
+                    addr = addr.wrapping_sub(step);
+                    let val = self.list_register(i);
+                    self.set_target(&MEMORY_ADDR(addr), size, val)?;
+                }
+                self.address_register[reg] = addr;
+            }
+            else {
+                let mut addr = self.effective_address(inst.get_trg())?;
+                for i in (0..16).filter(listed) {
+                    let val = self.list_register(i);
+                    self.set_target(&MEMORY_ADDR(addr), size, val)?;
+                    addr = addr.wrapping_add(step);
+                }
+            }
+            return Ok(());
         }
-        else {
-            self.clear_n_flag();
+
+        //The mask comes before the extension words of the address
+        let mask = self.get_target(inst.get_trg(), &WORD)?;
+        self.cycles += transfer(mask);
+        let mut addr = match *inst.get_lhs() {
+            POSTINCREMENT(reg) => self.address_register[reg],
+            ref lhs => self.effective_address(lhs)?,
+        };
+        for i in (0..16).filter(|i| mask & 1 << i != 0) {
+            let val = _sign_extend(self.get_target(&MEMORY_ADDR(addr), size)?, size);
+            self.set_list_register(i, val);
+            addr = addr.wrapping_add(step);
+        }
+        if let POSTINCREMENT(reg) = *inst.get_lhs() {
+            self.address_register[reg] = addr;
         }
+        Ok(())
+    }
 
-        if _is_null(&tmp[..]) {
-            self.set_z_flag();
+    //Transfers bytes to every other address, for 8 bit peripherals
+    pub fn perform_movep(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let bytes = size.bytes();
+        if let DATA_REGISTER(_) = inst.get_lhs() {
+            let val = self.get_target(inst.get_lhs(), size)?;
+            let addr = self.effective_address(inst.get_trg())?;
+            for i in 0..bytes {
+                let byte = val >> (8 * (bytes - 1 - i));
+                self.set_target(&MEMORY_ADDR(addr.wrapping_add(2 * i)), &BYTE, byte)?;
+            }
+            return Ok(());
         }
-        else {
-            self.clear_z_flag();
+
+        let addr = self.effective_address(inst.get_lhs())?;
+        let mut val = 0;
+        for i in 0..bytes {
+            val = val << 8 | self.get_target(&MEMORY_ADDR(addr.wrapping_add(2 * i)), &BYTE)?;
         }
-        
-        let trg = self.get_target_mut(inst.get_trg(), inst.get_size());
-        let mut i: usize = 0; 
-        while i < trg.len() {
-            trg[i] = tmp[i];
-            i += 1;
+        self.set_target(inst.get_trg(), size, val)
+    }
+
+    pub fn perform_tst(&mut self, inst : &Instruction) -> Result<(), BusFault> {
+        let elt = self.get_target(inst.get_lhs(), inst.get_size())?;
+        self.flags.logic(elt, inst.get_size());
+        Ok(())
+    }
+
+    pub fn perform_add(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        //ADDQ to an address register works like ADDA
+        if let ADDRESS_REGISTER(_) = inst.get_trg() {
+            return self.perform_adda(inst);
         }
+        let size = inst.get_size();
+        let lhs = self.get_target(inst.get_lhs(), size)?;
+        let loc = self.locate(inst.get_trg(), size)?;
+        let trg = self.read_location(&loc, size)?;
+
+        let (_, result) = _perform_add(size, lhs, trg);
+        self.write_location(&loc, size, result)?;
+        self.flags.add(lhs, trg, result, size);
+        Ok(())
+    }
 
+    pub fn perform_adda(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let src = _sign_extend(self.get_target(inst.get_lhs(), inst.get_size())?, inst.get_size());
+        let dst = self.get_target(inst.get_trg(), &LONG)?;
+        self.set_target(inst.get_trg(), &LONG, dst.wrapping_add(src))
     }
 
-    pub fn perform_tst(&mut self, inst : &Instruction) {
-        let elt = self.get_target(inst.get_lhs(), inst.get_size());
-        let neg = _is_negative(&elt);
-        let zero = _is_null(&elt);
-        self.clear_v_flag();
-        self.clear_c_flag();
-        if neg {
-            self.set_n_flag();
+    pub fn perform_addx(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let src = self.get_target(inst.get_lhs(), size)?;
+        let loc = self.locate(inst.get_trg(), size)?;
+        let dst = self.read_location(&loc, size)?;
+        let x = self.flag(Flag::X) as u32;
+
+        let result = dst.wrapping_add(src).wrapping_add(x) & size.mask();
+        let carry = _is_negative((src & dst) | (!result & (src | dst)), size);
+        let overflow = _is_negative((src ^ result) & (dst ^ result), size);
+        self.write_location(&loc, size, result)?;
+
+        self.flags.extended(result, size, overflow, carry);
+        Ok(())
+    }
+
+    pub fn perform_sub(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if let ADDRESS_REGISTER(_) = inst.get_trg() {
+            return self.perform_suba(inst);
         }
-        else {
-            self.clear_n_flag();
+        let size = inst.get_size();
+        let src = self.get_target(inst.get_lhs(), size)?;
+        let loc = self.locate(inst.get_trg(), size)?;
+        let dst = self.read_location(&loc, size)?;
+
+        let result = dst.wrapping_sub(src) & size.mask();
+        self.write_location(&loc, size, result)?;
+        self.flags.sub(src, dst, result, size);
+        Ok(())
+    }
+
+    pub fn perform_suba(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let src = _sign_extend(self.get_target(inst.get_lhs(), inst.get_size())?, inst.get_size());
+        let dst = self.get_target(inst.get_trg(), &LONG)?;
+        self.set_target(inst.get_trg(), &LONG, dst.wrapping_sub(src))
+    }
+
+    pub fn perform_subx(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let src = self.get_target(inst.get_lhs(), size)?;
+        let loc = self.locate(inst.get_trg(), size)?;
+        let dst = self.read_location(&loc, size)?;
+        let x = self.flag(Flag::X) as u32;
+
+        let result = dst.wrapping_sub(src).wrapping_sub(x) & size.mask();
+        let (borrow, overflow) = _sub_flags(src, dst, result, size);
+        self.write_location(&loc, size, result)?;
+
+        self.flags.extended(result, size, overflow, borrow);
+        Ok(())
+    }
+
+    pub(super) fn compare(&mut self, src: u32, dst: u32, op_size: &OpSize) {
+        let result = dst.wrapping_sub(src) & op_size.mask();
+        self.flags.compare(src, dst, result, op_size);
+    }
+
+    pub fn perform_cmp(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let src = self.get_target(inst.get_lhs(), size)?;
+        let dst = self.get_target(inst.get_trg(), size)?;
+        self.compare(src, dst, size);
+        Ok(())
+    }
+
+    pub fn perform_cmpa(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let src = _sign_extend(self.get_target(inst.get_lhs(), inst.get_size())?, inst.get_size());
+        let dst = self.get_target(inst.get_trg(), &LONG)?;
+        self.compare(src, dst, &LONG);
+        Ok(())
+    }
+
+    pub fn perform_neg(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let loc = self.locate(inst.get_lhs(), size)?;
+        let val = self.read_location(&loc, size)?;
+
+        let result = 0u32.wrapping_sub(val) & size.mask();
+        self.write_location(&loc, size, result)?;
+        self.flags.sub(val, 0, result, size);
+        Ok(())
+    }
+
+    pub fn perform_negx(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let loc = self.locate(inst.get_lhs(), size)?;
+        let val = self.read_location(&loc, size)?;
+        let x = self.flag(Flag::X) as u32;
+
+        let result = 0u32.wrapping_sub(val).wrapping_sub(x) & size.mask();
+        let (borrow, overflow) = _sub_flags(val, 0, result, size);
+        self.write_location(&loc, size, result)?;
+
+        self.flags.extended(result, size, overflow, borrow);
+        Ok(())
+    }
+
+    pub fn perform_clr(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        self.set_target(inst.get_lhs(), inst.get_size(), 0)?;
+        self.flags.logic(0, inst.get_size());
+        Ok(())
+    }
+
+    //AND, OR and EOR, the immediate forms may also target CCR and SR
+    fn perform_logic(&mut self, inst: &Instruction, f: fn(u32, u32) -> u32) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        match inst.get_trg() {
+            SR if !self.is_supervisor() => return self.raise_fault(VECTOR_PRIVILEGE),
+            SR | CCR => {
+                let val = self.get_target(inst.get_lhs(), size)?;
+                let reg = self.get_target(inst.get_trg(), size)?;
+                return self.set_target(inst.get_trg(), size, f(reg, val));
+            },
+            _ => {},
         }
-        if zero {
-            self.set_z_flag();
+        let src = self.get_target(inst.get_lhs(), size)?;
+        let loc = self.locate(inst.get_trg(), size)?;
+        let dst = self.read_location(&loc, size)?;
+        let result = f(dst, src) & size.mask();
+        self.write_location(&loc, size, result)?;
+        self.flags.logic(result, size);
+        Ok(())
+    }
+
+    pub fn perform_and(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        self.perform_logic(inst, |a, b| a & b)
+    }
+
+    pub fn perform_or(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        self.perform_logic(inst, |a, b| a | b)
+    }
+
+    pub fn perform_eor(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        self.perform_logic(inst, |a, b| a ^ b)
+    }
+
+    pub fn perform_not(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let loc = self.locate(inst.get_lhs(), size)?;
+        let result = !self.read_location(&loc, size)? & size.mask();
+        self.write_location(&loc, size, result)?;
+        self.flags.logic(result, size);
+        Ok(())
+    }
+
+    //16x16 bits into a 32 bit register
+    pub fn perform_mul(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if *inst.get_size() == LONG {
+            return self.perform_mull(inst);
+        }
+        let src = self.get_target(inst.get_lhs(), &WORD)?;
+        let dst = self.get_target(inst.get_trg(), &WORD)?;
+        self.cycles += timing::mul_cycles(inst.get_op(), src as u16) as u64;
+        let result = if *inst.get_op() == MULS {
+            (src as i16 as i32).wrapping_mul(dst as i16 as i32) as u32
         }
         else {
-            self.clear_z_flag();
-        }
-    }
-    
-    pub fn perform_add(&mut self, inst: &Instruction) {
-        //Needs testing !!!
-        let lhs = self.get_target(inst.get_lhs(), inst.get_size());
-        let trg = self.get_target(inst.get_trg(), inst.get_size());
-        let mut v_flag = false;
-        let adjust = _get_usize(inst.get_size());
-        
-        let (set_c_flag, result) = _perform_add(lhs, trg);
-        let cpy_result = result.to_vec();
-
-        let trg_container = match inst.get_trg() {
-            DATA_REGISTER(x) => DATA_REGISTER(*x),
-            ADDRESS_REGISTER(x) => ADDRESS_REGISTER(*x),
-            MEMORY_ADDR(x) => MEMORY_ADDR(*x),
-            SR => SR,
-            CCR => CCR,
-            x => panic!("Invalid data_container for add_target: {:?}", x),
+            src * dst
         };
+        self.set_target(inst.get_trg(), &LONG, result)?;
+        self.flags.logic(result, &LONG);
+        Ok(())
+    }
 
-        
-        if ((_is_negative(lhs) && _is_negative(trg) && !_is_negative(&result[adjust..])) ||
-        (!_is_negative(lhs) && !_is_negative(trg) && _is_negative(&result[adjust..]))) {
-            v_flag = true;
+    pub(super) fn divide_by_zero(&mut self) -> Result<(), BusFault> {
+        self.set_flag(Flag::C, false);
+        self.raise_exception(VECTOR_ZERO_DIVIDE);
+        self.cycles += 38;
+        Ok(())
+    }
+
+    //On overflow the destination is left as it was and V is set
+    fn set_division(&mut self, inst: &Instruction, quotient: u32, remainder: u32, overflow: bool) -> Result<(), BusFault> {
+        if overflow {
+            self.set_flag(Flag::C, false);
+            self.set_flag(Flag::V, true);
+            return Ok(());
         }
+        self.set_target(inst.get_trg(), &LONG, (remainder & 0xffff) << 16 | (quotient & 0xffff))?;
+        self.flags.logic(quotient, &WORD);
+        Ok(())
+    }
 
-        self.perform_move(&Instruction::new(MOVE, *inst.get_size(), 
-            IMEDIATE_VALUE(cpy_result), trg_container));
-        
-        //n and z flags handled by move
+    pub fn perform_divu(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if *inst.get_size() == LONG {
+            return self.perform_divl(inst);
+        }
+        let src = self.get_target(inst.get_lhs(), &WORD)?;
+        if src == 0 {
+            return self.divide_by_zero();
+        }
+        let dst = self.get_target(inst.get_trg(), &LONG)?;
+        self.cycles += timing::divu_cycles(dst, src as u16) as u64;
+        let quotient = dst / src;
+        self.set_division(inst, quotient, dst % src, quotient > 0xffff)
+    }
 
-        if set_c_flag {
-            self.set_c_flag();
-            self.set_x_flag();
+    pub fn perform_divs(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if *inst.get_size() == LONG {
+            return self.perform_divl(inst);
+        }
+        let src = self.get_target(inst.get_lhs(), &WORD)? as i16 as i64;
+        if src == 0 {
+            return self.divide_by_zero();
+        }
+        let dst = self.get_target(inst.get_trg(), &LONG)? as i32 as i64;
+        self.cycles += timing::divs_cycles(dst as i32, src as i16) as u64;
+        let quotient = dst / src;
+        let overflow = quotient != quotient as i16 as i64;
+        self.set_division(inst, quotient as u32, (dst % src) as u32, overflow)
+    }
+
+    fn set_bcd_flags(&mut self, result: u32, carry: bool, overflow: bool) {
+        self.flags.extended(result, &BYTE, overflow, carry);
+    }
+
+    pub fn perform_abcd(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let src = self.get_target(inst.get_lhs(), &BYTE)?;
+        let loc = self.locate(inst.get_trg(), &BYTE)?;
+        let dst = self.read_location(&loc, &BYTE)?;
+        let (result, carry, overflow) = _bcd_add(src, dst, self.flag(Flag::X) as u32);
+        self.write_location(&loc, &BYTE, result)?;
+        self.set_bcd_flags(result, carry, overflow);
+        Ok(())
+    }
+
+    pub fn perform_sbcd(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let src = self.get_target(inst.get_lhs(), &BYTE)?;
+        let loc = self.locate(inst.get_trg(), &BYTE)?;
+        let dst = self.read_location(&loc, &BYTE)?;
+        let (result, carry, overflow) = _bcd_sub(src, dst, self.flag(Flag::X) as u32);
+        self.write_location(&loc, &BYTE, result)?;
+        self.set_bcd_flags(result, carry, overflow);
+        Ok(())
+    }
+
+    pub fn perform_nbcd(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let loc = self.locate(inst.get_lhs(), &BYTE)?;
+        let val = self.read_location(&loc, &BYTE)?;
+        let (result, carry, overflow) = _bcd_sub(val, 0, self.flag(Flag::X) as u32);
+        self.write_location(&loc, &BYTE, result)?;
+        self.set_bcd_flags(result, carry, overflow);
+        Ok(())
+    }
+
+    //Register shifts take their count from lhs, modulo 64 when it is a
+    //register. Memory shifts are by one bit.
+    pub fn perform_shift(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let op = *inst.get_op();
+        let (count, loc) = match inst.get_trg() {
+            EMPTY => (1, self.locate(inst.get_lhs(), size)?),
+            trg => {
+                let count = match *inst.get_lhs() {
+                    DATA_REGISTER(reg) => self.data_register[reg] % 64,
+                    ref lhs => self.get_target(lhs, &LONG)?,
+                };
+                self.cycles += 2 * count as u64;
+                (count, self.locate(trg, size)?)
+            },
+        };
+        let (mask, msb) = (size.mask(), size.msb());
+        let mut val = self.read_location(&loc, size)?;
+        let mut x = self.flag(Flag::X);
+        let mut carry = false;
+        let mut overflow = false;
+
+        for _ in 0..count {
+            let (left_out, right_out) = (val & msb != 0, val & 1 != 0);
+            val = match op {
+                ASL | LSL => (val << 1) & mask,
+                ASR => (val >> 1) | (val & msb),
+                LSR => val >> 1,
+                ROL => ((val << 1) | left_out as u32) & mask,
+                ROR => (val >> 1) | if right_out { msb } else { 0 },
+                ROXL => ((val << 1) | x as u32) & mask,
+                _ => (val >> 1) | if x { msb } else { 0 },
+            };
+            carry = if matches!(op, ASL | LSL | ROL | ROXL) { left_out } else { right_out };
+            if matches!(op, ROXL | ROXR) {
+                x = carry;
+            }
+            //ASL overflows whenever the sign bit changes along the way
+            if op == ASL && (val & msb != 0) != left_out {
+                overflow = true;
+            }
+        }
+        self.write_location(&loc, size, val)?;
+
+        match op {
+            ROL | ROR => self.flags.result(val, size, overflow, count > 0 && carry),
+            ROXL | ROXR => {
+                self.flags.result(val, size, overflow, x);
+                self.flags.set_x(x);
+            },
+            _ => {
+                self.flags.result(val, size, overflow, count > 0 && carry);
+                if count > 0 {
+                    self.flags.set_x(carry);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    //Long on data registers, byte in memory
+    pub fn perform_bit(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = if let DATA_REGISTER(_) = inst.get_trg() { LONG } else { BYTE };
+        let bit = self.get_target(inst.get_lhs(), &BYTE)? % (size.bytes() * 8);
+        let loc = self.locate(inst.get_trg(), &size)?;
+        let val = self.read_location(&loc, &size)?;
+        let mask = 1 << bit;
+        if size == LONG && bit < 16 && *inst.get_op() != BTST {
+            self.cycles -= 2;
+        }
+
+        self.set_flag(Flag::Z, val & mask == 0);
+        let result = match inst.get_op() {
+            BCHG => val ^ mask,
+            BCLR => val & !mask,
+            BSET => val | mask,
+            _ => return Ok(()),
+        };
+        self.write_location(&loc, &size, result)
+    }
+
+    pub fn perform_exg(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let lhs = self.get_target(inst.get_lhs(), &LONG)?;
+        let trg = self.get_target(inst.get_trg(), &LONG)?;
+        self.set_target(inst.get_lhs(), &LONG, trg)?;
+        self.set_target(inst.get_trg(), &LONG, lhs)
+    }
+
+    pub fn perform_swap(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let val = self.get_target(inst.get_lhs(), &LONG)?.rotate_left(16);
+        self.set_target(inst.get_lhs(), &LONG, val)?;
+        self.flags.logic(val, &LONG);
+        Ok(())
+    }
+
+    pub fn perform_ext(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let half = if *size == LONG && *inst.get_op() != EXTB { WORD } else { BYTE };
+        let val = _sign_extend(self.get_target(inst.get_lhs(), &half)?, &half);
+        self.set_target(inst.get_lhs(), size, val)?;
+        self.flags.logic(val, size);
+        Ok(())
+    }
+
+    pub fn perform_tas(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let loc = self.locate(inst.get_lhs(), &BYTE)?;
+        let val = self.read_location(&loc, &BYTE)?;
+        self.flags.logic(val, &BYTE);
+        self.write_location(&loc, &BYTE, val | 0x80)
+    }
+
+    pub fn perform_lea(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let addr = self.effective_address(inst.get_lhs())?;
+        self.set_target(inst.get_trg(), &LONG, addr)
+    }
+
+    pub fn perform_pea(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let addr = self.effective_address(inst.get_lhs())?;
+        self.push(&LONG, addr)
+    }
+
+    //LINK A7 stacks the already decremented stack pointer
+    pub fn perform_link(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let disp = _sign_extend(self.get_target(inst.get_trg(), inst.get_size())?, inst.get_size());
+        let sp = self.address_register[7].wrapping_sub(4);
+        self.address_register[7] = sp;
+        let val = self.get_target(inst.get_lhs(), &LONG)?;
+        self.set_target(&ADDRESS_INDIRECT(7), &LONG, val)?;
+        self.set_target(inst.get_lhs(), &LONG, sp)?;
+        self.address_register[7] = sp.wrapping_add(disp);
+        Ok(())
+    }
+
+    pub fn perform_unlk(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        self.address_register[7] = self.get_target(inst.get_lhs(), &LONG)?;
+        let val = self.pop(&LONG)?;
+        self.set_target(inst.get_lhs(), &LONG, val)
+    }
+
+    //BRA, BSR and Bcc, the target is the operand's effective address
+    pub fn perform_branch(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let target = self.effective_address(inst.get_lhs())?;
+        match *inst.get_op() {
+            BSR => self.push(&LONG, self.get_pc())?,
+            BCC(cond) if !self.test_condition(cond) => {
+                self.cycles += if *inst.get_size() == BYTE { 8 } else { 12 };
+                return Ok(());
+            },
+            BCC(_) => self.cycles += 10,
+            _ => {},
+        }
+        self.set_pc(target);
+        Ok(())
+    }
+
+    //Loops until the condition is true or the counter reaches -1
+    pub fn perform_dbcc(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let target = self.effective_address(inst.get_trg())?;
+        if let DBCC(cond) = *inst.get_op() {
+            if self.test_condition(cond) {
+                self.cycles += 12;
+                self.loop_mode = None;
+                return Ok(());
+            }
+        }
+        let count = self.get_target(inst.get_lhs(), &WORD)?.wrapping_sub(1) & 0xffff;
+        self.set_target(inst.get_lhs(), &WORD, count)?;
+        if count != 0xffff {
+            self.cycles += 10;
+            self.set_pc(target);
+            self.loop_mode(target);
         }
         else {
-            self.clear_c_flag();
-            self.clear_x_flag();
+            self.cycles += 14;
+            self.loop_mode = None;
         }
+        Ok(())
+    }
 
-        if v_flag {
-            self.set_v_flag();
+    //The 68010 runs a DBcc looping on a single one word instruction out of
+    //its prefetch queue once the loop has gone round, neither gets fetched
+    //again.
+    fn loop_mode(&mut self, target: u32) {
+        if self.model != CpuModel::MC68010 || target != self.inst_pc.wrapping_sub(2) {
+            self.loop_mode = None;
+        }
+        else if self.loop_mode == Some(self.inst_pc) {
+            self.cycles -= 8;
         }
         else {
-            self.clear_v_flag();
+            let addr = target & self.model.address_mask();
+            let looped = self.bus.read_word(self.program_fc(), addr)
+                .map(|opcode| _loopable(&self.opcodes[opcode as usize].inst));
+            self.loop_mode = if looped == Ok(true) { Some(self.inst_pc) } else { None };
         }
+    }
 
+    pub fn perform_scc(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let set = match *inst.get_op() {
+            SCC(cond) => self.test_condition(cond),
+            _ => false,
+        };
+        if set && matches!(inst.get_lhs(), DATA_REGISTER(_)) {
+            self.cycles += 2;
+        }
+        self.set_target(inst.get_lhs(), &BYTE, if set { 0xff } else { 0 })
+    }
+
+    pub fn perform_jump(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let target = self.effective_address(inst.get_lhs())?;
+        if *inst.get_op() == JSR {
+            self.push(&LONG, self.get_pc())?;
+        }
+        self.set_pc(target);
+        Ok(())
+    }
+
+    pub fn perform_rts(&mut self, _inst: &Instruction) -> Result<(), BusFault> {
+        let pc = self.pop(&LONG)?;
+        self.set_pc(pc);
+        Ok(())
+    }
+
+    pub fn perform_rtr(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let ccr = self.pop(&WORD)?;
+        self.set_target(&CCR, &WORD, ccr)?;
+        self.perform_rts(inst)
+    }
+
+    pub fn perform_rte(&mut self, _inst: &Instruction) -> Result<(), BusFault> {
+        if !self.is_supervisor() {
+            return self.raise_fault(VECTOR_PRIVILEGE);
+        }
+        if self.model.is_coldfire() {
+            return self.coldfire_rte();
+        }
+        //The format word is checked before anything gets popped
+        let mut body = 0;
+        if self.model.has_frame_format() {
+            let sp = self.address_register[7];
+            let format = self.read_word(FunctionCode::SUPERVISOR_DATA, sp.wrapping_add(6))? >> 12;
+            body = match self.frame_body(format) {
+                Some(words) => 2 + 2 * words,
+                None => return self.raise_fault(VECTOR_FORMAT_ERROR),
+            };
+        }
+        let sr = self.pop(&WORD)?;
+        let pc = self.pop(&LONG)?;
+        self.address_register[7] = self.address_register[7].wrapping_add(body);
+        self.set_sr(sr as u16);
+        self.set_pc(pc);
+        Ok(())
+    }
+
+    //Formats 4 to 7 tell how many bytes aligned the stack
+    fn coldfire_rte(&mut self) -> Result<(), BusFault> {
+        let sp = self.address_register[7];
+        let format = self.read_word(FunctionCode::SUPERVISOR_DATA, sp)? >> 12;
+        if !(4..=7).contains(&format) {
+            return self.raise_fault(VECTOR_FORMAT_ERROR);
+        }
+        let sr = self.read_word(FunctionCode::SUPERVISOR_DATA, sp.wrapping_add(2))?;
+        let pc = self.read_long(FunctionCode::SUPERVISOR_DATA, sp.wrapping_add(4))?;
+        self.address_register[7] = sp.wrapping_add(4 + format as u32);
+        self.set_sr(sr);
+        self.set_pc(pc);
+        Ok(())
+    }
+
+    pub fn perform_rtd(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let disp = self.get_target(inst.get_lhs(), &WORD)? as i16;
+        let pc = self.pop(&LONG)?;
+        self.address_register[7] = self.address_register[7].wrapping_add(disp as u32);
+        self.set_pc(pc);
+        Ok(())
+    }
+
+    //Control registers the model lacks are illegal
+    pub fn perform_movec(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if !self.is_supervisor() {
+            return self.raise_fault(VECTOR_PRIVILEGE);
+        }
+        let inst = self.resolve_register_word(inst)?;
+        match (*inst.get_lhs(), *inst.get_trg()) {
+            (CONTROL_REGISTER(code), reg) => match _control_register(self.model, code) {
+                Some(ctrl) => self.set_target(&reg, &LONG, self.reg(ctrl)),
+                None => self.raise_fault(VECTOR_ILLEGAL),
+            },
+            (reg, CONTROL_REGISTER(code)) => match _control_register(self.model, code) {
+                Some(ctrl) => {
+                    let val = self.get_target(&reg, &LONG)?;
+                    self.set_reg(ctrl, val);
+                    self.cycles += 2;
+                    Ok(())
+                },
+                //Cache, access control and RAM base registers of ColdFire
+                //cores have nothing to act on
+                None if self.model.is_coldfire() => Ok(()),
+                None => self.raise_fault(VECTOR_ILLEGAL),
+            },
+            _ => self.raise_fault(VECTOR_ILLEGAL),
+        }
+    }
+
+    //Memory read in the SFC space or written in the DFC one. Address
+    //registers are loaded whole, sign extended.
+    pub fn perform_moves(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if !self.is_supervisor() {
+            return self.raise_fault(VECTOR_PRIVILEGE);
+        }
+        let inst = self.resolve_register_word(inst)?;
+        let size = inst.get_size();
+        if let DATA_REGISTER(_) | ADDRESS_REGISTER(_) = inst.get_lhs() {
+            let val = self.get_target(inst.get_lhs(), size)?;
+            if let Location::MEMORY(addr) = self.locate(inst.get_trg(), size)? {
+                self.write_memory(self.dfc, addr, size, val)?;
+            }
+        }
+        else if let Location::MEMORY(addr) = self.locate(inst.get_lhs(), size)? {
+            let val = self.read_memory(self.sfc, addr, size)?;
+            match inst.get_trg() {
+                ADDRESS_REGISTER(_) => self.set_target(inst.get_trg(), &LONG, _sign_extend(val, size))?,
+                trg => self.set_target(trg, size, val)?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn perform_trap(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let vector = self.get_target(inst.get_lhs(), &WORD)? as u8;
+        self.raise_exception(VECTOR_TRAP + vector);
+        Ok(())
+    }
+
+    pub fn perform_trapv(&mut self, _inst: &Instruction) -> Result<(), BusFault> {
+        if self.flag(Flag::V) {
+            self.raise_exception(VECTOR_TRAPV);
+            self.cycles += 30;
+        }
+        Ok(())
+    }
+
+    //Traps when the register is negative or above the bound
+    pub fn perform_chk(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let size = inst.get_size();
+        let bound = _sign_extend(self.get_target(inst.get_lhs(), size)?, size) as i32;
+        let val = _sign_extend(self.get_target(inst.get_trg(), size)?, size) as i32;
+        if val < 0 || val > bound {
+            self.set_flag(Flag::N, val < 0);
+            self.raise_exception(VECTOR_CHK);
+            self.cycles += 30;
+        }
+        Ok(())
+    }
+
+    pub fn perform_nop(&mut self, _inst: &Instruction) -> Result<(), BusFault> {
+        Ok(())
+    }
+
+    pub fn perform_reset(&mut self, _inst: &Instruction) -> Result<(), BusFault> {
+        if !self.is_supervisor() {
+            return self.raise_fault(VECTOR_PRIVILEGE);
+        }
+        self.bus.reset();
+        Ok(())
+    }
+
+    pub fn perform_stop(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if !self.is_supervisor() {
+            return self.raise_fault(VECTOR_PRIVILEGE);
+        }
+        let sr = self.get_target(inst.get_lhs(), &WORD)?;
+        self.set_sr(sr as u16);
+        self.state = CpuState::STOPPED;
+        Ok(())
+    }
+
+    //ILLEGAL and the line A and line F emulator traps
+    pub fn perform_illegal(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        match inst.get_op() {
+            LINE_A => self.raise_fault(VECTOR_LINE_A),
+            LINE_F => self.raise_fault(VECTOR_LINE_F),
+            _ => self.raise_fault(VECTOR_ILLEGAL),
+        }
     }
 }
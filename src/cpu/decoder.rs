@@ -0,0 +1,956 @@
+use super::*;
+use super::cpu_actions::_movec_code;
+
+//Addressing mode categories, one bit per mode as numbered by _ea_index
+const ALL: u16 = 0xfff;
+const DATA: u16 = 0xffd;
+const CONTROL: u16 = 0x7e4;
+const ALTERABLE: u16 = 0x1ff;
+const DATA_ALTERABLE: u16 = 0x1fd;
+const MEMORY_ALTERABLE: u16 = 0x1fc;
+const CONTROL_ALTERABLE: u16 = 0x1e4;
+const POSTINC: u16 = 1 << 3;
+const PREDEC: u16 = 1 << 4;
+const IMMEDIATE: u16 = 1 << 11;
+const DN: u16 = 1;
+
+fn _op_size(bits: u16) -> Option<OpSize> {
+    match bits {
+        0 => Some(BYTE),
+        1 => Some(WORD),
+        2 => Some(LONG),
+        _ => None,
+    }
+}
+
+//MOVE encodes its size differently from every other instruction
+fn _move_size(bits: u16) -> Option<OpSize> {
+    match bits {
+        1 => Some(BYTE),
+        3 => Some(WORD),
+        2 => Some(LONG),
+        _ => None,
+    }
+}
+
+//Dn, An, (An), (An)+, -(An), d16(An), d8(An,Xn), abs.W, abs.L,
+//d16(PC), d8(PC,Xn) and #imm
+fn _ea_index(mode: u16, reg: u16) -> Option<u16> {
+    match mode {
+        0..=6 => Some(mode),
+        7 if reg <= 4 => Some(7 + reg),
+        _ => None,
+    }
+}
+
+//Operand of an effective address field. The modes needing extension
+//words are left as EA for the resolver.
+fn _ea(mode: u16, reg: u16, allowed: u16) -> Option<DataContainer> {
+    let index = _ea_index(mode, reg)?;
+    if allowed & (1 << index) == 0 {
+        return None;
+    }
+    let reg = reg as usize;
+    Some(match mode {
+        0 => DATA_REGISTER(reg),
+        1 => ADDRESS_REGISTER(reg),
+        2 => ADDRESS_INDIRECT(reg),
+        3 => POSTINCREMENT(reg),
+        4 => PREDECREMENT(reg),
+        _ => EA(mode as u8, reg as u8),
+    })
+}
+
+//The effective address in the low 6 bits
+fn _src(opcode: u16, allowed: u16) -> Option<DataContainer> {
+    _ea((opcode >> 3) & 0b111, opcode & 0b111, allowed)
+}
+
+fn _reg9(opcode: u16) -> usize {
+    ((opcode >> 9) & 0b111) as usize
+}
+
+//Address registers can't be accessed as bytes
+fn _word_sized(size: &OpSize, allowed: u16) -> u16 {
+    if *size == BYTE { allowed & !0b10 } else { allowed }
+}
+
+fn _inst(op: Mnemonic, size: OpSize, lhs: DataContainer, trg: DataContainer) -> Option<Instruction> {
+    Some(Instruction::new(op, size, lhs, trg))
+}
+
+//Dy,Dx or -(Ay),-(Ax) forms of ABCD, ADDX and friends
+fn _extended(opcode: u16, op: Mnemonic, size: OpSize) -> Option<Instruction> {
+    let (x, y) = (_reg9(opcode), (opcode & 0b111) as usize);
+    if opcode & 0b1000 == 0 {
+        _inst(op, size, DATA_REGISTER(y), DATA_REGISTER(x))
+    }
+    else {
+        _inst(op, size, PREDECREMENT(y), PREDECREMENT(x))
+    }
+}
+
+fn _group_immediate(opcode: u16, model: CpuModel) -> Option<Instruction> {
+    match opcode {
+        0x003c => return _inst(ORI, BYTE, EA(7, 4), CCR),
+        0x007c => return _inst(ORI, WORD, EA(7, 4), SR),
+        0x023c => return _inst(ANDI, BYTE, EA(7, 4), CCR),
+        0x027c => return _inst(ANDI, WORD, EA(7, 4), SR),
+        0x0a3c => return _inst(EORI, BYTE, EA(7, 4), CCR),
+        0x0a7c => return _inst(EORI, WORD, EA(7, 4), SR),
+        _ => {},
+    }
+    if model >= CpuModel::MC68020 {
+        match opcode {
+            0x0cfc => return _inst(CAS2, WORD, REGISTER_WORD, REGISTER_WORD),
+            0x0efc => return _inst(CAS2, LONG, REGISTER_WORD, REGISTER_WORD),
+            _ => {},
+        }
+        //CAS sizes start at 1, CHK2 and CMP2 ones at 0
+        let bits = (opcode >> 9) & 0b11;
+        if opcode & 0xf9c0 == 0x08c0 && bits != 0 {
+            return _inst(CAS, _op_size(bits - 1)?, _src(opcode, MEMORY_ALTERABLE)?, REGISTER_WORD);
+        }
+        if opcode & 0xf9c0 == 0x00c0 {
+            return _inst(CHK2, _op_size(bits)?, _src(opcode, CONTROL)?, REGISTER_WORD);
+        }
+    }
+    if opcode & 0x0138 == 0x0108 {
+        let size = if opcode & 0x40 != 0 { LONG } else { WORD };
+        let (reg, mem) = (DATA_REGISTER(_reg9(opcode)), EA(5, (opcode & 0b111) as u8));
+        return if opcode & 0x80 != 0 {
+            _inst(MOVEP, size, reg, mem)
+        }
+        else {
+            _inst(MOVEP, size, mem, reg)
+        };
+    }
+    //Bit numbers are taken modulo 32 on registers and 8 in memory,
+    //the handler picks the width from the target
+    let bit_op = [BTST, BCHG, BCLR, BSET][((opcode >> 6) & 0b11) as usize];
+    if opcode & 0x0100 != 0 {
+        let allowed = if bit_op == BTST { DATA } else { DATA_ALTERABLE };
+        return _inst(bit_op, BYTE, DATA_REGISTER(_reg9(opcode)), _src(opcode, allowed)?);
+    }
+    if opcode & 0x0e00 == 0x0800 {
+        let allowed = if bit_op == BTST { DATA & !IMMEDIATE } else { DATA_ALTERABLE };
+        return _inst(bit_op, BYTE, EA(7, 4), _src(opcode, allowed)?);
+    }
+    if opcode & 0xff00 == 0x0e00 && model >= CpuModel::MC68010 {
+        let size = _op_size((opcode >> 6) & 0b11)?;
+        return _inst(MOVES, size, _src(opcode, MEMORY_ALTERABLE)?, REGISTER_WORD);
+    }
+    let op = match (opcode >> 9) & 0b111 {
+        0 => ORI,
+        1 => ANDI,
+        2 => SUBI,
+        3 => ADDI,
+        5 => EORI,
+        6 => CMPI,
+        _ => return None,
+    };
+    let size = _op_size((opcode >> 6) & 0b11)?;
+    _inst(op, size, EA(7, 4), _src(opcode, DATA_ALTERABLE)?)
+}
+
+fn _group_move(opcode: u16) -> Option<Instruction> {
+    let size = _move_size(opcode >> 12)?;
+    let lhs = _src(opcode, _word_sized(&size, ALL))?;
+    let mode = (opcode >> 6) & 0b111;
+    if mode == 1 {
+        if size == BYTE {
+            return None;
+        }
+        return _inst(MOVEA, size, lhs, ADDRESS_REGISTER(_reg9(opcode)));
+    }
+    let trg = _ea(mode, (opcode >> 9) & 0b111, DATA_ALTERABLE)?;
+    _inst(MOVE, size, lhs, trg)
+}
+
+fn _group_misc(opcode: u16, model: CpuModel) -> Option<Instruction> {
+    let reg = (opcode & 0b111) as usize;
+    match opcode {
+        0x4afc => return _inst(ILLEGAL, WORD, EMPTY, EMPTY),
+        0x4e70 => return _inst(RESET, WORD, EMPTY, EMPTY),
+        0x4e71 => return _inst(NOP, WORD, EMPTY, EMPTY),
+        0x4e72 => return _inst(STOP, WORD, EA(7, 4), EMPTY),
+        0x4e73 => return _inst(RTE, WORD, EMPTY, EMPTY),
+        0x4e75 => return _inst(RTS, WORD, EMPTY, EMPTY),
+        0x4e76 => return _inst(TRAPV, WORD, EMPTY, EMPTY),
+        0x4e77 => return _inst(RTR, WORD, EMPTY, EMPTY),
+        _ => {},
+    }
+    if model >= CpuModel::MC68010 {
+        match opcode {
+            0x4e74 => return _inst(RTD, WORD, EA(7, 4), EMPTY),
+            //Bit 0 set moves to the control register
+            0x4e7a | 0x4e7b => return _inst(MOVEC, LONG, REGISTER_WORD, EMPTY),
+            _ => {},
+        }
+        if opcode & 0xffc0 == 0x42c0 {
+            return _inst(MOVE, WORD, CCR, _src(opcode, DATA_ALTERABLE)?);
+        }
+    }
+    //The extension word says signed or not and which registers
+    if model >= CpuModel::MC68020 {
+        match opcode & 0xffc0 {
+            0x4c00 => return _inst(MULU, LONG, _src(opcode, DATA)?, REGISTER_WORD),
+            0x4c40 => return _inst(DIVU, LONG, _src(opcode, DATA)?, REGISTER_WORD),
+            _ => {},
+        }
+        if opcode & 0xfff8 == 0x49c0 {
+            return _inst(EXTB, LONG, DATA_REGISTER(reg), EMPTY);
+        }
+        if opcode & 0xfff8 == 0x4808 {
+            return _inst(LINK, LONG, ADDRESS_REGISTER(reg), EA(7, 4));
+        }
+        if opcode & 0xf1c0 == 0x4100 {
+            return _inst(CHK, LONG, _src(opcode, DATA)?, DATA_REGISTER(_reg9(opcode)));
+        }
+    }
+    match opcode & 0xfff8 {
+        0x4840 => return _inst(SWAP, LONG, DATA_REGISTER(reg), EMPTY),
+        0x4880 => return _inst(EXT, WORD, DATA_REGISTER(reg), EMPTY),
+        0x48c0 => return _inst(EXT, LONG, DATA_REGISTER(reg), EMPTY),
+        0x4e50 => return _inst(LINK, WORD, ADDRESS_REGISTER(reg), EA(7, 4)),
+        0x4e58 => return _inst(UNLK, LONG, ADDRESS_REGISTER(reg), EMPTY),
+        0x4e60 => return _inst(MOVE, LONG, ADDRESS_REGISTER(reg), USP),
+        0x4e68 => return _inst(MOVE, LONG, USP, ADDRESS_REGISTER(reg)),
+        _ => {},
+    }
+    if opcode & 0xfff0 == 0x4e40 {
+        return _inst(TRAP, WORD, IMEDIATE_VALUE((opcode & 0xf) as u32), EMPTY);
+    }
+    match opcode & 0xf1c0 {
+        0x41c0 => return _inst(LEA, LONG, _src(opcode, CONTROL)?, ADDRESS_REGISTER(_reg9(opcode))),
+        0x4180 => return _inst(CHK, WORD, _src(opcode, DATA)?, DATA_REGISTER(_reg9(opcode))),
+        _ => {},
+    }
+    match opcode & 0xffc0 {
+        0x40c0 => return _inst(MOVE, WORD, SR, _src(opcode, DATA_ALTERABLE)?),
+        0x44c0 => return _inst(MOVE, WORD, _src(opcode, DATA)?, CCR),
+        0x46c0 => return _inst(MOVE, WORD, _src(opcode, DATA)?, SR),
+        0x4800 => return _inst(NBCD, BYTE, _src(opcode, DATA_ALTERABLE)?, EMPTY),
+        0x4840 => return _inst(PEA, LONG, _src(opcode, CONTROL)?, EMPTY),
+        0x4ac0 => return _inst(TAS, BYTE, _src(opcode, DATA_ALTERABLE)?, EMPTY),
+        0x4e80 => return _inst(JSR, LONG, _src(opcode, CONTROL)?, EMPTY),
+        0x4ec0 => return _inst(JMP, LONG, _src(opcode, CONTROL)?, EMPTY),
+        _ => {},
+    }
+    if opcode & 0xfb80 == 0x4880 {
+        let size = if opcode & 0x40 != 0 { LONG } else { WORD };
+        if opcode & 0x0400 == 0 {
+            let ea = _src(opcode, CONTROL_ALTERABLE | PREDEC)?;
+            let reversed = matches!(ea, PREDECREMENT(_));
+            return _inst(MOVEM, size, REGISTER_MASK(reversed), ea);
+        }
+        return _inst(MOVEM, size, _src(opcode, CONTROL | POSTINC)?, REGISTER_MASK(false));
+    }
+    let op = match opcode & 0xff00 {
+        0x4000 => NEGX,
+        0x4200 => CLR,
+        0x4400 => NEG,
+        0x4600 => NOT,
+        0x4a00 => TST,
+        _ => return None,
+    };
+    let size = _op_size((opcode >> 6) & 0b11)?;
+    //The 68020 tests any operand, An only by word or long
+    let allowed = match (op, size) {
+        (TST, BYTE) if model >= CpuModel::MC68020 => DATA,
+        (TST, _) if model >= CpuModel::MC68020 => ALL,
+        _ => DATA_ALTERABLE,
+    };
+    _inst(op, size, _src(opcode, allowed)?, EMPTY)
+}
+
+fn _group_quick(opcode: u16, model: CpuModel) -> Option<Instruction> {
+    let size = match _op_size((opcode >> 6) & 0b11) {
+        Some(size) => size,
+        None => {
+            let cond = Condition::from_bits(opcode >> 8);
+            if model >= CpuModel::MC68020 {
+                match opcode & 0x3f {
+                    0x3a => return _inst(TRAPCC(cond), WORD, EA(7, 4), EMPTY),
+                    0x3b => return _inst(TRAPCC(cond), LONG, EA(7, 4), EMPTY),
+                    0x3c => return _inst(TRAPCC(cond), WORD, EMPTY, EMPTY),
+                    _ => {},
+                }
+            }
+            if (opcode >> 3) & 0b111 == 1 {
+                return _inst(DBCC(cond), WORD, DATA_REGISTER((opcode & 0b111) as usize), EA(7, 2));
+            }
+            return _inst(SCC(cond), BYTE, _src(opcode, DATA_ALTERABLE)?, EMPTY);
+        },
+    };
+    let data = match (opcode >> 9) & 0b111 {
+        0 => 8,
+        n => n as u32,
+    };
+    let op = if opcode & 0x0100 != 0 { SUBQ } else { ADDQ };
+    let trg = _src(opcode, _word_sized(&size, ALTERABLE))?;
+    _inst(op, size, IMEDIATE_VALUE(data), trg)
+}
+
+//A zero displacement byte means a 16 bit displacement follows, 0xff a
+//32 bit one from the 68020 on
+fn _group_branch(opcode: u16, model: CpuModel) -> Option<Instruction> {
+    let op = match (opcode >> 8) & 0xf {
+        0 => BRA,
+        1 => BSR,
+        cond => BCC(Condition::from_bits(cond)),
+    };
+    match opcode as u8 {
+        0 => _inst(op, WORD, EA(7, 2), EMPTY),
+        0xff if model >= CpuModel::MC68020 => _inst(op, LONG, BRANCH_LONG, EMPTY),
+        disp => _inst(op, BYTE, BRANCH_SHORT(disp as i8), EMPTY),
+    }
+}
+
+fn _group_moveq(opcode: u16) -> Option<Instruction> {
+    if opcode & 0x0100 != 0 {
+        return None;
+    }
+    let data = opcode as u8 as i8 as i32 as u32;
+    _inst(MOVEQ, LONG, IMEDIATE_VALUE(data), DATA_REGISTER(_reg9(opcode)))
+}
+
+//OR/DIVU/DIVS/SBCD and AND/MULU/MULS/ABCD/EXG share their layout
+fn _group_logic(opcode: u16, model: CpuModel, op: Mnemonic, unsigned: Mnemonic, signed: Mnemonic, bcd: Mnemonic) -> Option<Instruction> {
+    let dn = DATA_REGISTER(_reg9(opcode));
+    if op == OR && model >= CpuModel::MC68020 {
+        let (x, y) = ((opcode & 0b111) as usize, _reg9(opcode));
+        let pair = if opcode & 0b1000 != 0 { PREDECREMENT_PAIR(x, y) } else { REGISTER_PAIR(x, y) };
+        match opcode & 0x01f0 {
+            0x0140 => return _inst(PACK, WORD, EA(7, 4), pair),
+            0x0180 => return _inst(UNPK, WORD, EA(7, 4), pair),
+            _ => {},
+        }
+    }
+    match opcode & 0x01c0 {
+        0x00c0 => return _inst(unsigned, WORD, _src(opcode, DATA)?, dn),
+        0x01c0 => return _inst(signed, WORD, _src(opcode, DATA)?, dn),
+        _ => {},
+    }
+    if opcode & 0x01f0 == 0x0100 {
+        return _extended(opcode, bcd, BYTE);
+    }
+    if op == AND {
+        let (x, y) = (_reg9(opcode), (opcode & 0b111) as usize);
+        match opcode & 0x01f8 {
+            0x0140 => return _inst(EXG, LONG, DATA_REGISTER(x), DATA_REGISTER(y)),
+            0x0148 => return _inst(EXG, LONG, ADDRESS_REGISTER(x), ADDRESS_REGISTER(y)),
+            0x0188 => return _inst(EXG, LONG, DATA_REGISTER(x), ADDRESS_REGISTER(y)),
+            _ => {},
+        }
+    }
+    let size = _op_size((opcode >> 6) & 0b11)?;
+    if opcode & 0x0100 == 0 {
+        _inst(op, size, _src(opcode, DATA)?, dn)
+    }
+    else {
+        _inst(op, size, dn, _src(opcode, MEMORY_ALTERABLE)?)
+    }
+}
+
+//ADD/ADDA/ADDX and SUB/SUBA/SUBX
+fn _group_arith(opcode: u16, op: Mnemonic, address: Mnemonic, extended: Mnemonic) -> Option<Instruction> {
+    let size = match _op_size((opcode >> 6) & 0b11) {
+        Some(size) => size,
+        None => {
+            let size = if opcode & 0x0100 != 0 { LONG } else { WORD };
+            return _inst(address, size, _src(opcode, ALL)?, ADDRESS_REGISTER(_reg9(opcode)));
+        },
+    };
+    if opcode & 0x0130 == 0x0100 {
+        return _extended(opcode, extended, size);
+    }
+    let dn = DATA_REGISTER(_reg9(opcode));
+    if opcode & 0x0100 == 0 {
+        _inst(op, size, _src(opcode, _word_sized(&size, ALL))?, dn)
+    }
+    else {
+        _inst(op, size, dn, _src(opcode, MEMORY_ALTERABLE)?)
+    }
+}
+
+fn _group_compare(opcode: u16) -> Option<Instruction> {
+    let size = match _op_size((opcode >> 6) & 0b11) {
+        Some(size) => size,
+        None => {
+            let size = if opcode & 0x0100 != 0 { LONG } else { WORD };
+            return _inst(CMPA, size, _src(opcode, ALL)?, ADDRESS_REGISTER(_reg9(opcode)));
+        },
+    };
+    let dn = DATA_REGISTER(_reg9(opcode));
+    if opcode & 0x0100 == 0 {
+        return _inst(CMP, size, _src(opcode, _word_sized(&size, ALL))?, dn);
+    }
+    if (opcode >> 3) & 0b111 == 1 {
+        return _inst(CMPM, size, POSTINCREMENT((opcode & 0b111) as usize), POSTINCREMENT(_reg9(opcode)));
+    }
+    _inst(EOR, size, dn, _src(opcode, DATA_ALTERABLE)?)
+}
+
+//Register shifts take their count as lhs, memory shifts shift their
+//single operand by one. Bitfields of the 68020 sit in the gap.
+fn _group_shift(opcode: u16, model: CpuModel) -> Option<Instruction> {
+    let ops = [(ASR, ASL), (LSR, LSL), (ROXR, ROXL), (ROR, ROL)];
+    let pick = |(right, left): (Mnemonic, Mnemonic)| if opcode & 0x0100 != 0 { left } else { right };
+    let size = match _op_size((opcode >> 6) & 0b11) {
+        Some(size) => size,
+        None => {
+            if opcode & 0x0800 != 0 {
+                if model < CpuModel::MC68020 {
+                    return None;
+                }
+                let op = [BFTST, BFEXTU, BFCHG, BFEXTS, BFCLR, BFFFO, BFSET, BFINS][((opcode >> 8) & 0b111) as usize];
+                let allowed = if matches!(op, BFTST | BFEXTU | BFEXTS | BFFFO) { DN | CONTROL } else { DN | CONTROL_ALTERABLE };
+                return _inst(op, LONG, _src(opcode, allowed)?, REGISTER_WORD);
+            }
+            let op = pick(ops[((opcode >> 9) & 0b11) as usize]);
+            return _inst(op, WORD, _src(opcode, MEMORY_ALTERABLE)?, EMPTY);
+        },
+    };
+    let op = pick(ops[((opcode >> 3) & 0b11) as usize]);
+    let count = if opcode & 0x20 != 0 {
+        DATA_REGISTER(_reg9(opcode))
+    }
+    else {
+        IMEDIATE_VALUE(match _reg9(opcode) { 0 => 8, n => n as u32 })
+    };
+    _inst(op, size, count, DATA_REGISTER((opcode & 0b111) as usize))
+}
+
+//The MMU as coprocessor 0 and the 68881 and 68882 as coprocessor 1 of
+//the 68020. The command or condition word following the opcode is left
+//for the resolver.
+fn _group_coprocessor(opcode: u16, model: CpuModel) -> Option<Instruction> {
+    if model < CpuModel::MC68020 {
+        return None;
+    }
+    if opcode & 0x0fc0 == 0 {
+        return _inst(PMOVE, LONG, _src(opcode, ALL)?, REGISTER_WORD);
+    }
+    if opcode & 0x0e00 != 0x0200 {
+        return None;
+    }
+    let cond = FpCondition::from_bits(opcode);
+    match (opcode >> 6) & 0b111 {
+        0 => _inst(FOP(FpOp::MOVE, FpFormat::EXTENDED), LONG, _src(opcode, ALL)?, REGISTER_WORD),
+        1 => match (opcode & 0x3f, (opcode >> 3) & 0b111) {
+            (0x3a, _) => _inst(FTRAPCC(FpCondition::F), WORD, EA(7, 4), REGISTER_WORD),
+            (0x3b, _) => _inst(FTRAPCC(FpCondition::F), LONG, EA(7, 4), REGISTER_WORD),
+            (0x3c, _) => _inst(FTRAPCC(FpCondition::F), WORD, EMPTY, REGISTER_WORD),
+            (_, 1) => _inst(FDBCC(FpCondition::F), WORD, DATA_REGISTER((opcode & 0b111) as usize), REGISTER_WORD),
+            _ => _inst(FSCC(FpCondition::F), BYTE, _src(opcode, DATA_ALTERABLE)?, REGISTER_WORD),
+        },
+        //Conditional predicates stop at 0x1f
+        2 | 3 if opcode & 0x20 != 0 => None,
+        2 => _inst(FBCC(cond), WORD, EA(7, 2), EMPTY),
+        3 => _inst(FBCC(cond), LONG, BRANCH_LONG, EMPTY),
+        4 => _inst(FSAVE, LONG, _src(opcode, CONTROL_ALTERABLE | PREDEC)?, EMPTY),
+        5 => _inst(FRESTORE, LONG, _src(opcode, CONTROL | POSTINC)?, EMPTY),
+        _ => None,
+    }
+}
+
+//Function code field of the MMU command words
+fn _fc_select(ext: u16) -> Option<FcSelect> {
+    match ext & 0x1f {
+        0 => Some(FcSelect::SFC),
+        1 => Some(FcSelect::DFC),
+        bits @ 0x08..=0x0f => Some(FcSelect::REGISTER((bits & 0b111) as usize)),
+        bits @ 0x10..=0x17 => Some(FcSelect::IMMEDIATE((bits & 0b111) as u8)),
+        _ => None,
+    }
+}
+
+//Register in the top four bits of an extension word, A0-A7 after D0-D7
+fn _general_register(ext: u16) -> DataContainer {
+    let reg = ((ext >> 12) & 0b111) as usize;
+    if ext & 0x8000 != 0 { ADDRESS_REGISTER(reg) } else { DATA_REGISTER(reg) }
+}
+
+//{offset:width} with the register of a bitfield extension word
+fn _bitfield(ext: u16) -> Bitfield {
+    let param = |register: bool, bits: u16| {
+        if register { FieldParam::REGISTER((bits & 0b111) as usize) } else { FieldParam::IMMEDIATE((bits & 0b11111) as u8) }
+    };
+    Bitfield {
+        register: ((ext >> 12) & 0b111) as usize,
+        offset: param(ext & 0x0800 != 0, ext >> 6),
+        width: param(ext & 0x0020 != 0, ext),
+    }
+}
+
+//Modes ColdFire lets its loads, stores and long multiplies address
+//memory with: (An), (An)+, -(An) and d16(An)
+const SIMPLE: u16 = 0x3c;
+
+//Position of an operand from the opcode table in the _ea_index order
+fn _mode_index(data: &DataContainer) -> Option<u16> {
+    match *data {
+        DATA_REGISTER(_) => Some(0),
+        ADDRESS_REGISTER(_) => Some(1),
+        ADDRESS_INDIRECT(_) => Some(2),
+        POSTINCREMENT(_) => Some(3),
+        PREDECREMENT(_) => Some(4),
+        EA(mode, reg) => _ea_index(mode as u16, reg as u16),
+        _ => None,
+    }
+}
+
+fn _mode_in(data: &DataContainer, allowed: u16) -> bool {
+    _mode_index(data).is_some_and(|index| allowed & (1 << index) != 0)
+}
+
+//MOVE can't take two operands needing extension words, apart from a
+//d16(An) or d16(PC) source with a d16(An) destination and, on ISA_B, an
+//immediate byte or word stored to d16(An)
+fn _coldfire_move(lhs: &DataContainer, trg: &DataContainer, size: OpSize, isa_b: bool) -> bool {
+    let (src, dst) = match (_mode_index(lhs), _mode_index(trg)) {
+        (Some(src), Some(dst)) => (src, dst),
+        _ => return false,
+    };
+    match src {
+        0..=4 => true,
+        5 | 9 => dst <= 5,
+        11 if isa_b && size != LONG && dst == 5 => true,
+        _ => dst <= 4,
+    }
+}
+
+//What ColdFire keeps of the 68020 set. Arithmetic is mostly long only,
+//ISA_B brings back byte and word compares and long branches.
+fn _coldfire_allows(inst: &Instruction, isa_b: bool) -> bool {
+    let (op, size, lhs, trg) = (*inst.get_op(), *inst.get_size(), inst.get_lhs(), inst.get_trg());
+    let long = size == LONG;
+    match op {
+        MOVE => match (lhs, trg) {
+            (SR | CCR, _) => _mode_in(trg, DN),
+            (_, SR | CCR) => _mode_in(lhs, DN | IMMEDIATE),
+            (USP, _) | (_, USP) => isa_b,
+            _ => _coldfire_move(lhs, trg, size, isa_b),
+        },
+        MOVEA | MOVEQ | CLR | TST | LEA | PEA | JMP | JSR | SWAP | EXT | EXTB | UNLK | MOVEC
+            | RTS | RTE | TRAP | STOP | NOP | ILLEGAL => true,
+        ADD | SUB | AND | OR | EOR | ADDA | SUBA | ADDQ | SUBQ | ASL | ASR | LSL | LSR => long,
+        ADDI | SUBI | ANDI | ORI | EORI => long && _mode_in(trg, DN),
+        CMPI => (long || isa_b) && _mode_in(trg, DN),
+        CMP | CMPA => long || isa_b,
+        ADDX | SUBX | NEG | NEGX | NOT => long && _mode_in(lhs, DN),
+        MULU | MULS | DIVU | DIVS => !long || _mode_in(lhs, DN | SIMPLE),
+        BTST | BCHG | BCLR | BSET => match lhs {
+            DATA_REGISTER(_) if op == BTST => _mode_in(trg, DN | 0x67c),
+            DATA_REGISTER(_) => _mode_in(trg, DN | 0x7c),
+            _ => _mode_in(trg, DN | SIMPLE),
+        },
+        BRA | BSR | BCC(_) => !long || isa_b,
+        SCC(_) => _mode_in(lhs, DN),
+        MOVEM => long && (_mode_in(lhs, 0x24) || _mode_in(trg, 0x24)),
+        LINK => size == WORD,
+        TAS => isa_b,
+        //TPF, a trap that never happens, used as a multi word NOP
+        TRAPCC(Condition::F) => true,
+        _ => false,
+    }
+}
+
+//ColdFire decodes like a 68020 with the instructions and modes it dropped
+//made illegal, MOVEC only writing control registers. ISA_B cores add
+//MOV3Q, MVS, MVZ and SATS in holes of the 68000 map.
+fn _coldfire(opcode: u16, isa_b: bool) -> Option<Instruction> {
+    if isa_b {
+        if opcode & 0xf1c0 == 0xa140 {
+            let data = match _reg9(opcode) { 0 => u32::MAX, n => n as u32 };
+            return _inst(MOV3Q, LONG, IMEDIATE_VALUE(data), _src(opcode, ALTERABLE)?);
+        }
+        if opcode & 0xf100 == 0x7100 {
+            let op = if opcode & 0x80 != 0 { MVZ } else { MVS };
+            let size = if opcode & 0x40 != 0 { WORD } else { BYTE };
+            return _inst(op, size, _src(opcode, _word_sized(&size, ALL))?, DATA_REGISTER(_reg9(opcode)));
+        }
+        if opcode & 0xfff8 == 0x4c80 {
+            return _inst(SATS, LONG, DATA_REGISTER((opcode & 0b111) as usize), EMPTY);
+        }
+    }
+    match opcode >> 12 {
+        0xa => return _inst(LINE_A, WORD, EMPTY, EMPTY),
+        0xf => return _inst(LINE_F, WORD, EMPTY, EMPTY),
+        _ => {},
+    }
+    if opcode == 0x4e7a {
+        return None;
+    }
+    Some(decode_opcode(opcode, CpuModel::MC68020)).filter(|inst| _coldfire_allows(inst, isa_b))
+}
+
+//What the first word of an instruction encodes, with the operands
+//depending on extension words left for the resolver. Opcodes the model
+//doesn't implement decode as ILLEGAL.
+pub(super) fn decode_opcode(opcode: u16, model: CpuModel) -> Instruction {
+    let inst = match opcode >> 12 {
+        _ if model.is_coldfire() => _coldfire(opcode, model == CpuModel::COLDFIRE_ISA_B),
+        0x0 => _group_immediate(opcode, model),
+        0x1..=0x3 => _group_move(opcode),
+        0x4 => _group_misc(opcode, model),
+        0x5 => _group_quick(opcode, model),
+        0x6 => _group_branch(opcode, model),
+        0x7 => _group_moveq(opcode),
+        0x8 => _group_logic(opcode, model, OR, DIVU, DIVS, SBCD),
+        0x9 => _group_arith(opcode, SUB, SUBA, SUBX),
+        0xa => _inst(LINE_A, WORD, EMPTY, EMPTY),
+        0xb => _group_compare(opcode),
+        0xc => _group_logic(opcode, model, AND, MULU, MULS, ABCD),
+        0xd => _group_arith(opcode, ADD, ADDA, ADDX),
+        0xe => _group_shift(opcode, model),
+        _ => _group_coprocessor(opcode, model).or_else(|| _inst(LINE_F, WORD, EMPTY, EMPTY)),
+    };
+    inst.unwrap_or_else(|| Instruction::new(ILLEGAL, WORD, EMPTY, EMPTY))
+}
+
+impl<B: Bus> super::CPU<B> {
+
+    pub(super) fn fetch_word(&mut self) -> Result<u16, BusFault> {
+        let pc = self.get_pc();
+        let word = if self.prefetch.is_some() {
+            self.fetch_prefetched(pc)?
+        }
+        else if self.icache_enabled() {
+            self.fetch_cached(pc)?
+        }
+        else {
+            self.read_word(self.program_fc(), pc)?
+        };
+        self.set_pc(pc.wrapping_add(2));
+        Ok(word)
+    }
+
+    fn fetch_long(&mut self) -> Result<u32, BusFault> {
+        Ok((self.fetch_word()? as u32) << 16 | self.fetch_word()? as u32)
+    }
+
+    //Null, word or long displacement of a full extension word
+    fn fetch_displacement(&mut self, size: u16) -> Result<i32, BusFault> {
+        Ok(match size & 0b11 {
+            2 => self.fetch_word()? as i16 as i32,
+            3 => self.fetch_long()? as i32,
+            _ => 0,
+        })
+    }
+
+    //Brief extension word of the indexed modes, based on An or on PC when
+    //`reg` is None. The 68020 scales the index and takes a full extension
+    //word when bit 8 is set, ColdFire only scales.
+    fn fetch_indexed(&mut self, reg: Option<usize>) -> Result<DataContainer, BusFault> {
+        let pc = self.get_pc();
+        let ext = self.fetch_word()?;
+        let full = self.model == CpuModel::MC68020;
+        let scale = if self.model >= CpuModel::MC68020 { 1 << ((ext >> 9) & 0b11) } else { 1 };
+        let index = Index { register: (ext >> 12) as usize, long: ext & 0x0800 != 0, scale };
+        if !full || ext & 0x0100 == 0 {
+            return Ok(match reg {
+                Some(reg) => INDEXED(reg, index, ext as i8),
+                None => PC_INDEXED(pc, index, ext as i8),
+            });
+        }
+        let base = match (reg, ext & 0x0080 != 0) {
+            (Some(reg), false) => Base::ADDRESS(reg),
+            (Some(_), true) => Base::SUPPRESSED,
+            (None, false) => Base::PC(pc),
+            (None, true) => Base::SUPPRESSED_PC,
+        };
+        let index = if ext & 0x0040 != 0 { None } else { Some(index) };
+        let bd = self.fetch_displacement(ext >> 4)?;
+        //Reserved combinations are taken as no indirection
+        let indirect = match ext & 0b111 {
+            1..=3 => Indirect::PRE_INDEXED,
+            5..=7 if index.is_some() => Indirect::POST_INDEXED,
+            _ => Indirect::NONE,
+        };
+        let od = if indirect == Indirect::NONE { 0 } else { self.fetch_displacement(ext)? };
+        Ok(EXTENDED(Extended { base, index, bd, od, indirect }))
+    }
+
+    //Completes an operand from the opcode table with its extension words.
+    //Operands that are already complete are returned as is.
+    pub(super) fn resolve(&mut self, data: &DataContainer, size: &OpSize) -> Result<DataContainer, BusFault> {
+        Ok(match *data {
+            EA(5, reg) => DISPLACEMENT(reg as usize, self.fetch_word()? as i16),
+            EA(6, reg) => self.fetch_indexed(Some(reg as usize))?,
+            EA(7, 0) => SHORT_ADDR(self.fetch_word()? as i16),
+            EA(7, 1) => MEMORY_ADDR(self.fetch_long()?),
+            EA(7, 2) => {
+                let pc = self.get_pc();
+                PC_DISPLACEMENT(pc, self.fetch_word()? as i16)
+            },
+            EA(7, 3) => self.fetch_indexed(None)?,
+            EA(7, 4) => IMEDIATE_VALUE(match size {
+                BYTE => (self.fetch_word()? & 0xff) as u32,
+                WORD => self.fetch_word()? as u32,
+                LONG => self.fetch_long()?,
+            }),
+            EA(mode, reg) => panic!("invalid effective address {}/{}", mode, reg),
+            BRANCH_SHORT(disp) => PC_DISPLACEMENT(self.get_pc(), disp as i16),
+            BRANCH_LONG => {
+                let pc = self.get_pc();
+                let bd = self.fetch_long()? as i32;
+                EXTENDED(Extended { base: Base::PC(pc), index: None, bd, od: 0, indirect: Indirect::NONE })
+            },
+            REGISTER_MASK(reversed) => {
+                let mask = self.fetch_word()?;
+                REGISTER_LIST(if reversed { mask.reverse_bits() } else { mask })
+            },
+            other => other,
+        })
+    }
+
+    //MOVEC, MOVES and most 68020 additions name registers in a word ahead
+    //of any other extension, which settles the operands and sometimes the
+    //mnemonic. Other instructions are returned as is.
+    pub(super) fn resolve_register_word(&mut self, inst: &Instruction) -> Result<Instruction, BusFault> {
+        let (op, size) = (*inst.get_op(), *inst.get_size());
+        if let (FOP(..) | FDBCC(_) | FSCC(_) | FTRAPCC(_) | PMOVE, REGISTER_WORD) = (op, inst.get_trg()) {
+            return self.resolve_coprocessor(inst);
+        }
+        let swap = |to_trg: bool, reg, other| {
+            if to_trg { Instruction::new(op, size, reg, other) } else { Instruction::new(op, size, other, reg) }
+        };
+        let ea = match (*inst.get_lhs(), *inst.get_trg()) {
+            (REGISTER_WORD, REGISTER_WORD) => {
+                let (ext1, ext2) = (self.fetch_word()?, self.fetch_word()?);
+                let (du, dc) = (|ext: u16| ((ext >> 6) & 0b111) as usize, |ext: u16| (ext & 0b111) as usize);
+                let target = CAS2_TARGET(du(ext1), du(ext2), (ext1 >> 12) as usize, (ext2 >> 12) as usize);
+                return Ok(Instruction::new(op, size, REGISTER_PAIR(dc(ext1), dc(ext2)), target));
+            },
+            //Registers the model doesn't have make MOVEC illegal
+            (REGISTER_WORD, _) => {
+                let ext = self.fetch_word()?;
+                let to_control = self.ir & 1 != 0;
+                if !_movec_code(self.model, ext & 0xfff, to_control) {
+                    return Ok(Instruction::new(ILLEGAL, WORD, EMPTY, EMPTY));
+                }
+                return Ok(swap(to_control, _general_register(ext), CONTROL_REGISTER(ext & 0xfff)));
+            },
+            (ea, REGISTER_WORD) => ea,
+            _ => return Ok(*inst),
+        };
+        let ext = self.fetch_word()?;
+        let ea = self.resolve(&ea, &size)?;
+        let (high, low) = (((ext >> 12) & 0b111) as usize, (ext & 0b111) as usize);
+        let signed = ext & 0x0800 != 0;
+        Ok(match op {
+            MOVES => swap(ext & 0x0800 != 0, _general_register(ext), ea),
+            MULU | MULS => {
+                let op = if signed { MULS } else { MULU };
+                let trg = if ext & 0x0400 != 0 { REGISTER_PAIR(low, high) } else { DATA_REGISTER(high) };
+                Instruction::new(op, size, ea, trg)
+            },
+            //A 64 bit dividend, a 32 bit one or one with the remainder
+            //kept in another register
+            DIVU | DIVS => {
+                let (op, trg) = match (ext & 0x0400 != 0, low == high) {
+                    (true, _) => (if signed { DIVS } else { DIVU }, REGISTER_PAIR(low, high)),
+                    (false, true) => (if signed { DIVS } else { DIVU }, DATA_REGISTER(high)),
+                    (false, false) => (if signed { DIVSL } else { DIVUL }, REGISTER_PAIR(low, high)),
+                };
+                Instruction::new(op, size, ea, trg)
+            },
+            CAS => Instruction::new(op, size, REGISTER_PAIR(low, ((ext >> 6) & 0b111) as usize), ea),
+            CHK2 | CMP2 => Instruction::new(if signed { CHK2 } else { CMP2 }, size, ea, _general_register(ext)),
+            _ => Instruction::new(op, size, ea, BITFIELD(_bitfield(ext))),
+        })
+    }
+
+    //FPU immediates in the layout of FP_IMMEDIATE, bytes taking a word
+    fn fetch_fp_immediate(&mut self, bytes: u32) -> Result<DataContainer, BusFault> {
+        let mut words = [0; 3];
+        match bytes {
+            1 => words[0] = (self.fetch_word()? & 0xff) as u32,
+            2 => words[0] = self.fetch_word()? as u32,
+            _ => {
+                for word in words.iter_mut().take(bytes as usize / 4) {
+                    *word = self.fetch_long()?;
+                }
+            },
+        }
+        Ok(FP_IMMEDIATE(words))
+    }
+
+    //Conditional FPU instructions have their predicate in the word after
+    //the opcode, the others a command word. Invalid commands decode as
+    //LINE_F.
+    fn resolve_coprocessor(&mut self, inst: &Instruction) -> Result<Instruction, BusFault> {
+        let (op, size, ea) = (*inst.get_op(), *inst.get_size(), *inst.get_lhs());
+        let ext = self.fetch_word()?;
+        let cond = FpCondition::from_bits(ext);
+        if matches!(op, FDBCC(_) | FSCC(_) | FTRAPCC(_)) && ext & 0xffe0 != 0 {
+            return Ok(Instruction::new(LINE_F, WORD, EMPTY, EMPTY));
+        }
+        Ok(match op {
+            FDBCC(_) => {
+                let disp = self.resolve(&EA(7, 2), &WORD)?;
+                Instruction::new(FDBCC(cond), WORD, ea, disp)
+            },
+            FSCC(_) => Instruction::new(FSCC(cond), BYTE, self.resolve(&ea, &BYTE)?, EMPTY),
+            FTRAPCC(_) => Instruction::new(FTRAPCC(cond), size, self.resolve(&ea, &size)?, EMPTY),
+            PMOVE => self.resolve_mmu_command(ext, ea)?.unwrap_or_else(|| Instruction::new(LINE_F, WORD, EMPTY, EMPTY)),
+            _ => self.resolve_command(ext, ea)?.unwrap_or_else(|| Instruction::new(LINE_F, WORD, EMPTY, EMPTY)),
+        })
+    }
+
+    //Command word of the general FPU instructions, by its opclass in the
+    //top 3 bits
+    fn resolve_command(&mut self, ext: u16, ea: DataContainer) -> Result<Option<Instruction>, BusFault> {
+        let (spec, reg) = ((ext >> 10) & 0b111, ((ext >> 7) & 0b111) as usize);
+        let immediate = ea == EA(7, 4);
+        let program = matches!(ea, EA(7, 2) | EA(7, 3)) || immediate;
+        let register = matches!(ea, DATA_REGISTER(_) | ADDRESS_REGISTER(_));
+        let fop = |op: FpOp, format| Some(FOP(op, format));
+        let (op, lhs, trg) = match ext >> 13 {
+            0 => {
+                let op = match FpOp::from_opmode(ext) {
+                    Some(op) => op,
+                    None => return Ok(None),
+                };
+                let trg = if op == FpOp::SINCOS { FP_REGISTER_PAIR((ext & 0b111) as usize, reg) } else { FP_REGISTER(reg) };
+                (fop(op, FpFormat::EXTENDED), FP_REGISTER(spec as usize), trg)
+            },
+            2 if spec == 7 => {
+                if ea != DATA_REGISTER(0) {
+                    return Ok(None);
+                }
+                (Some(FMOVECR), IMEDIATE_VALUE((ext & 0x7f) as u32), FP_REGISTER(reg))
+            },
+            2 => {
+                let format = FpFormat::from_bits(spec, ext);
+                let op = match FpOp::from_opmode(ext) {
+                    Some(op) if !matches!(ea, ADDRESS_REGISTER(_)) && (format.bytes() <= 4 || !register) => op,
+                    _ => return Ok(None),
+                };
+                let trg = if op == FpOp::SINCOS { FP_REGISTER_PAIR((ext & 0b111) as usize, reg) } else { FP_REGISTER(reg) };
+                let lhs = if immediate { self.fetch_fp_immediate(format.bytes())? } else { self.resolve(&ea, &LONG)? };
+                (fop(op, format), lhs, trg)
+            },
+            //FMOVE out, the register field being the source
+            3 => {
+                let format = FpFormat::from_bits(spec, ext);
+                if program || matches!(ea, ADDRESS_REGISTER(_)) || (register && format.bytes() > 4) {
+                    return Ok(None);
+                }
+                (fop(FpOp::MOVE, format), FP_REGISTER(reg), self.resolve(&ea, &LONG)?)
+            },
+            //FMOVEM of control registers, a data register only moving one
+            //of them and an address register only FPIAR
+            class @ 4..=5 => {
+                let count = spec.count_ones();
+                let to_memory = class == 5;
+                match ea {
+                    _ if spec == 0 => return Ok(None),
+                    DATA_REGISTER(_) if count > 1 => return Ok(None),
+                    ADDRESS_REGISTER(_) if spec != 1 => return Ok(None),
+                    _ if to_memory && program => return Ok(None),
+                    _ => {},
+                }
+                let data = if immediate { self.fetch_fp_immediate(4 * count)? } else { self.resolve(&ea, &LONG)? };
+                let control = FP_CONTROL(spec as u8);
+                if to_memory { (Some(FMOVEM), control, data) } else { (Some(FMOVEM), data, control) }
+            },
+            //FMOVEM of data registers, -(An) taking the mask bits the
+            //other way round
+            class @ 6..=7 => {
+                if register || immediate || (class == 7 && program) {
+                    return Ok(None);
+                }
+                let dynamic = ((ext >> 4) & 0b111) as usize;
+                let list = match (ext >> 11) & 0b11 {
+                    0 => FP_REGISTER_LIST(ext as u8),
+                    1 => FP_DYNAMIC_LIST(dynamic, false),
+                    2 => FP_REGISTER_LIST((ext as u8).reverse_bits()),
+                    _ => FP_DYNAMIC_LIST(dynamic, true),
+                };
+                let data = self.resolve(&ea, &LONG)?;
+                if class == 7 { (Some(FMOVEM), list, data) } else { (Some(FMOVEM), data, list) }
+            },
+            _ => return Ok(None),
+        };
+        Ok(op.map(|op| Instruction::new(op, LONG, lhs, trg)))
+    }
+
+    //Command word of the MMU instructions, as the 68030 encodes them
+    fn resolve_mmu_command(&mut self, ext: u16, ea: DataContainer) -> Result<Option<Instruction>, BusFault> {
+        let (to_memory, fd) = (ext & 0x0200 != 0, ext & 0x0100 != 0);
+        let register = matches!(ea, DATA_REGISTER(_) | ADDRESS_REGISTER(_));
+        let program = matches!(ea, EA(7, 2) | EA(7, 3) | EA(7, 4));
+        let control = !register && !program && !matches!(ea, POSTINCREMENT(_) | PREDECREMENT(_));
+        let fc = _fc_select(ext);
+        let (op, size, lhs, trg) = match (ext >> 13, (ext >> 10) & 0b111) {
+            (0b000, 2..=3) | (0b010, 0 | 2 | 3) | (0b011, 0) => {
+                let reg = match (ext >> 13, (ext >> 10) & 0b111) {
+                    (0b000, 2) => MmuRegister::TT0,
+                    (0b000, _) => MmuRegister::TT1,
+                    (0b010, 0) => MmuRegister::TC,
+                    (0b010, 2) => MmuRegister::SRP,
+                    (0b010, _) => MmuRegister::CRP,
+                    _ => MmuRegister::MMUSR,
+                };
+                let root = matches!(reg, MmuRegister::SRP | MmuRegister::CRP);
+                let size = if reg == MmuRegister::MMUSR { WORD } else { LONG };
+                if ext & 0xff != 0 || (root && !control) || (to_memory && (program || fd)) || (fd && reg == MmuRegister::MMUSR) {
+                    return Ok(None);
+                }
+                let op = if fd { PMOVEFD } else { PMOVE };
+                let ea = self.resolve(&ea, &size)?;
+                if to_memory { (op, size, MMU_REGISTER(reg), ea) } else { (op, size, ea, MMU_REGISTER(reg)) }
+            },
+            (0b001, 0b000) if control => match fc {
+                Some(fc) => (if to_memory { PLOADR } else { PLOADW }, LONG, FUNCTION_CODE(fc), self.resolve(&ea, &LONG)?),
+                None => return Ok(None),
+            },
+            (0b001, 0b001) => (PFLUSHA, LONG, EMPTY, EMPTY),
+            (0b001, mode @ (0b100 | 0b110)) => match fc {
+                Some(fc) if mode == 0b100 || control => {
+                    let ea = if mode == 0b100 { EMPTY } else { self.resolve(&ea, &LONG)? };
+                    (PFLUSH, LONG, FC_MASK(fc, ((ext >> 5) & 0xf) as u8), ea)
+                },
+                _ => return Ok(None),
+            },
+            //The address register of PTEST is flagged by the bit of FD
+            (0b100, level) if control => {
+                let reg = if fd { Some(((ext >> 5) & 0b111) as usize) } else { None };
+                match fc {
+                    Some(fc) if level != 0 || reg.is_none() => {
+                        let op = if to_memory { PTESTR } else { PTESTW };
+                        (op, LONG, FC_LEVEL(fc, level as u8, reg), self.resolve(&ea, &LONG)?)
+                    },
+                    _ => return Ok(None),
+                }
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(Instruction::new(op, size, lhs, trg)))
+    }
+
+    //Fetches and decodes the instruction at PC, leaving PC after it.
+    //Anything that can't be executed decodes as ILLEGAL.
+    pub fn decode(&mut self) -> Result<Instruction, BusFault> {
+        let opcode = self.fetch_word()?;
+        self.ir = opcode;
+        let inst = self.opcodes[opcode as usize].inst;
+        let (op, size) = (*inst.get_op(), *inst.get_size());
+        if let (REGISTER_WORD, _) | (_, REGISTER_WORD) = (inst.get_lhs(), inst.get_trg()) {
+            return self.resolve_register_word(&inst);
+        }
+        //The MOVEM mask comes before the extension words of its address
+        let (lhs, trg) = if let REGISTER_MASK(_) = inst.get_trg() {
+            let trg = self.resolve(inst.get_trg(), &size)?;
+            (self.resolve(inst.get_lhs(), &size)?, trg)
+        }
+        else {
+            let lhs = self.resolve(inst.get_lhs(), &size)?;
+            (lhs, self.resolve(inst.get_trg(), &size)?)
+        };
+        Ok(Instruction::new(op, size, lhs, trg))
+    }
+}
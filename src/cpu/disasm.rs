@@ -0,0 +1,519 @@
+use super::*;
+use super::cpu_actions::_sign_extend;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+
+// Assembly text of decoded instructions, in Motorola syntax or in the MIT
+// syntax GNU as also reads. Branch targets and PC relative operands show
+// the address they point to rather than their displacement.
+
+//Longest instruction, a 68020 one with two full extension words
+const MAX_LENGTH: u32 = 22;
+
+//An instruction written out in `syntax`, see Instruction::display
+pub struct Listing<'a> {
+    inst: &'a Instruction,
+    syntax: Syntax,
+}
+
+//Bytes sitting at `base` for a CPU to decode, anything else reads as a
+//bus error
+struct Code<'a> {
+    base: u32,
+    bytes: &'a [u8],
+}
+
+impl Bus for Code<'_> {
+    fn read_byte(&mut self, _fc: FunctionCode, addr: u32) -> BusResult<u8> {
+        self.bytes.get(addr.wrapping_sub(self.base) as usize).copied().ok_or(BusError)
+    }
+
+    fn write_byte(&mut self, _fc: FunctionCode, _addr: u32, _val: u8) -> BusResult<()> {
+        Err(BusError)
+    }
+}
+
+//A CPU of the model decoding from bytes loaded at `base`
+struct Decoder<'a> {
+    cpu: CPU<Code<'a>>,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(model: CpuModel, base: u32, bytes: &'a [u8]) -> Decoder<'a> {
+        Decoder { cpu: CPU::with_model(Code { base: base & model.address_mask(), bytes }, model) }
+    }
+
+    //Instruction at `addr` with its length, None when it runs past the
+    //end of the bytes
+    fn decode(&mut self, addr: u32) -> Option<(Instruction, u32)> {
+        self.cpu.set_pc(addr);
+        let inst = self.cpu.decode().ok()?;
+        Some((inst, self.cpu.get_pc().wrapping_sub(addr)))
+    }
+}
+
+//Decodes the instruction `bytes` start with as if they sat at `addr`.
+//Returns it with its length, None when it runs past the end of `bytes`.
+pub fn decode_bytes(model: CpuModel, addr: u32, bytes: &[u8]) -> Option<(Instruction, u32)> {
+    Decoder::new(model, addr, bytes).decode(addr & model.address_mask())
+}
+
+//A line of a listing, an instruction or the data found where none could
+//be decoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub addr: u32,
+    //Words of the instruction or data, data ending with a single byte
+    //when the listing does
+    pub bytes: Vec<u8>,
+    pub inst: Option<Instruction>,
+}
+
+impl Line {
+    //Address, raw words and text, the text starting on the same column
+    //for up to five words
+    pub fn text(&self, syntax: Syntax) -> String {
+        let words: Vec<String> = self.bytes.chunks(2).map(|chunk| {
+            chunk.iter().map(|byte| format!("{:02x}", byte)).collect()
+        }).collect();
+        let text = match (&self.inst, self.bytes.len(), syntax) {
+            (Some(inst), _, _) => inst.display(syntax).to_string(),
+            (None, 1, Syntax::MOTOROLA) => format!("dc.b ${:02x}", self.bytes[0]),
+            (None, 1, Syntax::MIT) => format!(".byte 0x{:02x}", self.bytes[0]),
+            (None, _, Syntax::MOTOROLA) => format!("dc.w ${}", words[0]),
+            (None, _, Syntax::MIT) => format!(".word 0x{}", words[0]),
+        };
+        format!("{:08x}  {:<24} {}", self.addr, words.join(" "), text)
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text(Syntax::MOTOROLA))
+    }
+}
+
+//Opcodes that only trap, which in a listing are more likely data than
+//code. ILLEGAL itself is kept.
+fn _undecodable(inst: &Instruction, opcode: u16) -> bool {
+    match inst.get_op() {
+        ILLEGAL => opcode != 0x4afc,
+        LINE_A | LINE_F => true,
+        _ => false,
+    }
+}
+
+//Where control goes after `inst`: the target it names when it can be
+//told without running it, and whether it may carry on to the next one
+fn _flow(inst: &Instruction) -> (Option<u32>, bool) {
+    let target = [inst.get_lhs(), inst.get_trg()].iter().find_map(|data| match data {
+        PC_DISPLACEMENT(pc, disp) => Some(pc.wrapping_add(*disp as i32 as u32)),
+        EXTENDED(Extended { base: Base::PC(pc), index: None, bd, indirect: Indirect::NONE, .. }) => Some(pc.wrapping_add(*bd as u32)),
+        MEMORY_ADDR(addr) => Some(*addr),
+        SHORT_ADDR(addr) => Some(*addr as i32 as u32),
+        _ => None,
+    });
+    match inst.get_op() {
+        BRA | JMP => (target, false),
+        BSR | JSR | BCC(_) | DBCC(_) | FBCC(_) | FDBCC(_) => (target, true),
+        RTS | RTE | RTR | RTD | ILLEGAL | LINE_A | LINE_F => (None, false),
+        _ => (None, true),
+    }
+}
+
+//Lists `bytes` loaded at `base`. Without entry points every address not
+//covered by an instruction is decoded. With some, only the code reachable
+//from them through branches, calls and jumps to known addresses is, the
+//rest being listed as data.
+pub fn disassemble(model: CpuModel, base: u32, bytes: &[u8], entries: &[u32]) -> Vec<Line> {
+    let base = base & model.address_mask();
+    let end = base.wrapping_add(bytes.len() as u32);
+    let mut decoder = Decoder::new(model, base, bytes);
+    let opcode = |addr: u32| {
+        let at = addr.wrapping_sub(base) as usize;
+        u16::from_be_bytes([bytes[at], bytes[at + 1]])
+    };
+    let mut decode = |addr: u32| match decoder.decode(addr) {
+        Some((inst, len)) if !_undecodable(&inst, opcode(addr)) => Some((inst, len)),
+        _ => None,
+    };
+    let mut code = HashMap::new();
+    let mut pending = entries.to_vec();
+    while let Some(addr) = pending.pop() {
+        if addr.wrapping_sub(base) >= bytes.len() as u32 || addr & 1 != 0 || code.contains_key(&addr) {
+            continue;
+        }
+        if let Some((inst, len)) = decode(addr) {
+            let (target, next) = _flow(&inst);
+            pending.extend(target);
+            if next {
+                pending.push(addr.wrapping_add(len));
+            }
+            code.insert(addr, (inst, len));
+        }
+    }
+    let mut lines = Vec::new();
+    let mut addr = base;
+    while addr != end {
+        let found = if entries.is_empty() { decode(addr) } else { code.remove(&addr) };
+        let (inst, len) = match found {
+            Some((inst, len)) => (Some(inst), len),
+            None => (None, if end.wrapping_sub(addr) == 1 || addr & 1 != 0 { 1 } else { 2 }),
+        };
+        let at = addr.wrapping_sub(base) as usize;
+        lines.push(Line { addr, bytes: bytes[at..(at + len as usize)].to_vec(), inst });
+        addr = addr.wrapping_add(len);
+    }
+    lines
+}
+
+fn _lower(val: impl Debug) -> String {
+    format!("{:?}", val).to_lowercase()
+}
+
+fn _mnemonic(op: &Mnemonic) -> String {
+    match op {
+        BCC(cond) => format!("b{}", _lower(cond)),
+        DBCC(cond) => format!("db{}", _lower(cond)),
+        SCC(cond) => format!("s{}", _lower(cond)),
+        TRAPCC(cond) => format!("trap{}", _lower(cond)),
+        FOP(op, _) => format!("f{}", _lower(op)),
+        FBCC(cond) => format!("fb{}", _lower(cond)),
+        FDBCC(cond) => format!("fdb{}", _lower(cond)),
+        FSCC(cond) => format!("fs{}", _lower(cond)),
+        FTRAPCC(cond) => format!("ftrap{}", _lower(cond)),
+        LINE_A => "linea".to_string(),
+        LINE_F => "linef".to_string(),
+        op => _lower(op),
+    }
+}
+
+//Runs of set bits as first-last, joined by slashes
+fn _register_ranges(mask: u32, count: u32, name: impl Fn(u32) -> String) -> String {
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < count {
+        if mask & (1 << i) == 0 {
+            i += 1;
+            continue;
+        }
+        let first = i;
+        while i + 1 < count && mask & (1 << (i + 1)) != 0 {
+            i += 1;
+        }
+        ranges.push(if first == i { name(i) } else { format!("{}-{}", name(first), name(i)) });
+        i += 1;
+    }
+    ranges.join("/")
+}
+
+impl Instruction {
+    pub fn display(&self, syntax: Syntax) -> Listing<'_> {
+        Listing { inst: self, syntax }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.display(Syntax::MOTOROLA), f)
+    }
+}
+
+impl fmt::Display for Listing<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", _mnemonic(self.inst.get_op()))?;
+        match (self.size(), self.syntax) {
+            (Some(size), Syntax::MOTOROLA) => write!(f, ".{}", size)?,
+            (Some(size), Syntax::MIT) => write!(f, "{}", size)?,
+            (None, _) => {},
+        }
+        let operands = self.operands();
+        if !operands.is_empty() {
+            write!(f, " {}", operands.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl Listing<'_> {
+    fn mit(&self) -> bool {
+        self.syntax == Syntax::MIT
+    }
+
+    //Size suffix, left out where the instruction only has one size or
+    //the operands tell it
+    fn size(&self) -> Option<char> {
+        let size = match self.inst.get_size() {
+            BYTE => 'b',
+            WORD => 'w',
+            LONG => 'l',
+        };
+        match *self.inst.get_op() {
+            BRA | BSR | BCC(_) if size == 'b' => Some('s'),
+            FOP(_, format) => Some(match format {
+                FpFormat::LONG => 'l',
+                FpFormat::SINGLE => 's',
+                FpFormat::EXTENDED => 'x',
+                FpFormat::PACKED(_) => 'p',
+                FpFormat::WORD => 'w',
+                FpFormat::DOUBLE => 'd',
+                FpFormat::BYTE => 'b',
+            }),
+            FMOVECR => Some('x'),
+            FMOVEM => match (self.inst.get_lhs(), self.inst.get_trg()) {
+                (FP_CONTROL(_), _) | (_, FP_CONTROL(_)) => Some('l'),
+                _ => Some('x'),
+            },
+            TRAPCC(_) | FTRAPCC(_) if *self.inst.get_lhs() == EMPTY => None,
+            LEA | PEA | JMP | JSR | NOP | RTS | RTE | RTR | RTD | RESET | ILLEGAL | TRAP | TRAPV | STOP
+                | UNLK | SWAP | EXG | MOVEQ | MOVEC | SCC(_) | DBCC(_) | TAS | NBCD | ABCD | SBCD
+                | BTST | BCHG | BCLR | BSET | BFTST | BFEXTU | BFCHG | BFEXTS | BFCLR | BFFFO | BFSET | BFINS
+                | PACK | UNPK | FDBCC(_) | FSCC(_) | FSAVE | FRESTORE | PMOVE | PMOVEFD | PFLUSHA | PFLUSH
+                | PLOADR | PLOADW | PTESTR | PTESTW | LINE_A | LINE_F => None,
+            _ => Some(size),
+        }
+    }
+
+    fn operands(&self) -> Vec<String> {
+        let (lhs, trg) = (*self.inst.get_lhs(), *self.inst.get_trg());
+        match (*self.inst.get_op(), lhs, trg) {
+            (BFINS, ea, BITFIELD(field)) => vec![self.data(field.register), self.operand(&ea) + &self.field(&field)],
+            (BFTST | BFCHG | BFCLR | BFSET, ea, BITFIELD(field)) => vec![self.operand(&ea) + &self.field(&field)],
+            (_, ea, BITFIELD(field)) => vec![self.operand(&ea) + &self.field(&field), self.data(field.register)],
+            (PACK | UNPK, adjust, REGISTER_PAIR(x, y)) => vec![self.data(x), self.data(y), self.operand(&adjust)],
+            (PACK | UNPK, adjust, PREDECREMENT_PAIR(x, y)) => {
+                vec![self.operand(&PREDECREMENT(x)), self.operand(&PREDECREMENT(y)), self.operand(&adjust)]
+            },
+            (CAS, REGISTER_PAIR(dc, du), ea) => vec![self.data(dc), self.data(du), self.operand(&ea)],
+            (PTESTR | PTESTW, FC_LEVEL(fc, level, reg), ea) => {
+                let mut operands = vec![self.function_code(fc), self.operand(&ea), self.immediate(level as u32)];
+                operands.extend(reg.map(|reg| self.operand(&ADDRESS_REGISTER(reg))));
+                operands
+            },
+            (PFLUSH, FC_MASK(fc, mask), ea) => {
+                let mut operands = vec![self.function_code(fc), self.immediate(mask as u32)];
+                if ea != EMPTY {
+                    operands.push(self.operand(&ea));
+                }
+                operands
+            },
+            _ => [lhs, trg].iter().filter(|data| **data != EMPTY).map(|data| self.operand(data)).collect(),
+        }
+    }
+
+    fn register(&self, name: &str) -> String {
+        if self.mit() { format!("%{}", name) } else { name.to_string() }
+    }
+
+    fn data(&self, reg: usize) -> String {
+        self.register(&format!("d{}", reg))
+    }
+
+    fn address(&self, reg: usize) -> String {
+        self.register(&if reg == 7 { "sp".to_string() } else { format!("a{}", reg) })
+    }
+
+    //Addresses and immediates from 10 up are written in hex
+    fn number(&self, val: u32) -> String {
+        match (val, self.syntax) {
+            (0..=9, _) => val.to_string(),
+            (_, Syntax::MOTOROLA) => format!("${:x}", val),
+            (_, Syntax::MIT) => format!("0x{:x}", val),
+        }
+    }
+
+    fn hex(&self, val: u32) -> String {
+        if self.mit() { format!("0x{:x}", val) } else { format!("${:x}", val) }
+    }
+
+    fn immediate(&self, val: u32) -> String {
+        format!("#{}", self.number(val))
+    }
+
+    //Signed immediates where the instruction sign extends them
+    fn signed_immediate(&self, val: i32) -> String {
+        if val < 0 { format!("#-{}", self.number(val.unsigned_abs())) } else { self.immediate(val as u32) }
+    }
+
+    fn index(&self, index: &Index) -> String {
+        let reg = if index.register < 8 { self.data(index.register) } else { self.address(index.register - 8) };
+        let size = if index.long { 'l' } else { 'w' };
+        match (self.syntax, index.scale) {
+            (Syntax::MOTOROLA, 1) => format!("{}.{}", reg, size),
+            (Syntax::MOTOROLA, scale) => format!("{}.{}*{}", reg, size, scale),
+            (Syntax::MIT, 1) => format!("{}:{}", reg, size),
+            (Syntax::MIT, scale) => format!("{}:{}:{}", reg, size, scale),
+        }
+    }
+
+    fn field(&self, field: &Bitfield) -> String {
+        let param = |param: FieldParam| match param {
+            FieldParam::IMMEDIATE(val) => val.to_string(),
+            FieldParam::REGISTER(reg) => self.data(reg),
+        };
+        format!("{{{}:{}}}", param(field.offset), param(field.width))
+    }
+
+    fn function_code(&self, fc: FcSelect) -> String {
+        match fc {
+            FcSelect::SFC => self.register("sfc"),
+            FcSelect::DFC => self.register("dfc"),
+            FcSelect::REGISTER(reg) => self.data(reg),
+            FcSelect::IMMEDIATE(val) => self.immediate(val as u32),
+        }
+    }
+
+    fn control_register(&self, code: u16) -> String {
+        match code {
+            0x000 => self.register("sfc"),
+            0x001 => self.register("dfc"),
+            0x002 => self.register("cacr"),
+            0x800 => self.register("usp"),
+            0x801 => self.register("vbr"),
+            0x802 => self.register("caar"),
+            code => self.hex(code as u32),
+        }
+    }
+
+    //Where a branch or PC relative operand points
+    fn branch(&self) -> bool {
+        matches!(self.inst.get_op(), BRA | BSR | BCC(_) | DBCC(_) | FBCC(_) | FDBCC(_))
+    }
+
+    //Motorola (bd,An,Xn) and ([bd,An],Xn,od) forms, MIT An@(bd,Xn) and
+    //An@(bd)@(od,Xn) ones. PC bases show the address the displacement
+    //leads to.
+    fn extended(&self, ext: &Extended) -> String {
+        let (base, bd) = match ext.base {
+            Base::ADDRESS(reg) => (Some(self.address(reg)), ext.bd.to_string()),
+            Base::PC(pc) => (Some(self.register("pc")), self.hex(pc.wrapping_add(ext.bd as u32))),
+            Base::SUPPRESSED => (None, ext.bd.to_string()),
+            Base::SUPPRESSED_PC => (Some(self.register("zpc")), ext.bd.to_string()),
+        };
+        let index = ext.index.as_ref().map(|index| self.index(index));
+        let od = ext.od.to_string();
+        let join = |parts: &[Option<String>]| parts.iter().flatten().cloned().collect::<Vec<_>>().join(",");
+        if self.mit() {
+            let base = base.unwrap_or_default();
+            return match ext.indirect {
+                Indirect::NONE => format!("{}@({})", base, join(&[Some(bd), index])),
+                Indirect::PRE_INDEXED => format!("{}@({})@({})", base, join(&[Some(bd), index]), od),
+                Indirect::POST_INDEXED => format!("{}@({})@({})", base, bd, join(&[Some(od), index])),
+            };
+        }
+        match ext.indirect {
+            Indirect::NONE => format!("({})", join(&[Some(bd), base, index])),
+            Indirect::PRE_INDEXED => format!("([{}],{})", join(&[Some(bd), base, index]), od),
+            Indirect::POST_INDEXED => format!("([{}],{})", join(&[Some(bd), base]), join(&[index, Some(od)])),
+        }
+    }
+
+    //FPU immediates take as many longs as their format, control register
+    //moves one per register
+    fn fp_immediate(&self, longs: &[u32; 3]) -> String {
+        let count = match (*self.inst.get_op(), *self.inst.get_trg()) {
+            (FOP(_, format), _) => format.bytes().div_ceil(4),
+            (_, FP_CONTROL(bits)) => bits.count_ones(),
+            _ => 1,
+        };
+        let hex: String = longs.iter().take(count as usize).map(|long| format!("{:08x}", long)).collect();
+        if self.mit() { format!("#0x{}", hex) } else { format!("#${}", hex) }
+    }
+
+    fn operand(&self, data: &DataContainer) -> String {
+        let mit = self.mit();
+        match *data {
+            DATA_REGISTER(reg) => self.data(reg),
+            ADDRESS_REGISTER(reg) => self.address(reg),
+            IMEDIATE_VALUE(val) => match self.inst.get_op() {
+                MOVEQ | MOV3Q => self.signed_immediate(val as i32),
+                LINK => self.signed_immediate(_sign_extend(val, self.inst.get_size()) as i32),
+                _ => self.immediate(val & self.inst.get_size().mask()),
+            },
+            MEMORY_ADDR(addr) => self.hex(addr),
+            //Sign extended, as the address it stands for
+            SHORT_ADDR(addr) if mit => format!("{}:w", self.hex(addr as i32 as u32)),
+            SHORT_ADDR(addr) => format!("{}.w", self.hex(addr as i32 as u32)),
+            SR => self.register("sr"),
+            CCR => self.register("ccr"),
+            USP => self.register("usp"),
+            EMPTY => String::new(),
+            ADDRESS_INDIRECT(reg) if mit => format!("{}@", self.address(reg)),
+            ADDRESS_INDIRECT(reg) => format!("({})", self.address(reg)),
+            POSTINCREMENT(reg) if mit => format!("{}@+", self.address(reg)),
+            POSTINCREMENT(reg) => format!("({})+", self.address(reg)),
+            PREDECREMENT(reg) if mit => format!("{}@-", self.address(reg)),
+            PREDECREMENT(reg) => format!("-({})", self.address(reg)),
+            DISPLACEMENT(reg, disp) if mit => format!("{}@({})", self.address(reg), disp),
+            DISPLACEMENT(reg, disp) => format!("{}({})", disp, self.address(reg)),
+            INDEXED(reg, index, disp) if mit => format!("{}@({},{})", self.address(reg), disp, self.index(&index)),
+            INDEXED(reg, index, disp) => format!("{}({},{})", disp, self.address(reg), self.index(&index)),
+            PC_DISPLACEMENT(pc, disp) => {
+                let target = self.hex(pc.wrapping_add(disp as i32 as u32));
+                match (self.branch(), mit) {
+                    (true, _) => target,
+                    (false, true) => format!("%pc@({})", target),
+                    (false, false) => format!("{}(pc)", target),
+                }
+            },
+            PC_INDEXED(pc, index, disp) => {
+                let target = self.hex(pc.wrapping_add(disp as i32 as u32));
+                if mit { format!("%pc@({},{})", target, self.index(&index)) } else { format!("{}(pc,{})", target, self.index(&index)) }
+            },
+            EXTENDED(ext) => match ext.base {
+                Base::PC(pc) if self.branch() => self.hex(pc.wrapping_add(ext.bd as u32)),
+                _ => self.extended(&ext),
+            },
+            REGISTER_LIST(mask) => {
+                _register_ranges(mask as u32, 16, |i| self.register(&format!("{}{}", if i < 8 { 'd' } else { 'a' }, i & 7)))
+            },
+            CONTROL_REGISTER(code) => self.control_register(code),
+            REGISTER_PAIR(first, second) => format!("{}:{}", self.data(first), self.data(second)),
+            PREDECREMENT_PAIR(first, second) => format!("{},{}", self.operand(&PREDECREMENT(first)), self.operand(&PREDECREMENT(second))),
+            CAS2_TARGET(du1, du2, rn1, rn2) => {
+                let indirect = |rn: usize| {
+                    let reg = if rn < 8 { self.data(rn) } else { self.address(rn - 8) };
+                    if mit { format!("{}@", reg) } else { format!("({})", reg) }
+                };
+                format!("{}:{},{}:{}", self.data(du1), self.data(du2), indirect(rn1), indirect(rn2))
+            },
+            BITFIELD(field) => self.field(&field),
+            FP_REGISTER(reg) => self.register(&format!("fp{}", reg)),
+            FP_REGISTER_PAIR(first, second) => {
+                format!("{}:{}", self.register(&format!("fp{}", first)), self.register(&format!("fp{}", second)))
+            },
+            FP_REGISTER_LIST(mask) => _register_ranges(mask as u32, 8, |i| self.register(&format!("fp{}", i))),
+            FP_DYNAMIC_LIST(reg, _) => self.data(reg),
+            FP_CONTROL(bits) => {
+                let names = [(4, "fpcr"), (2, "fpsr"), (1, "fpiar")];
+                names.iter().filter(|(bit, _)| bits & bit != 0).map(|(_, name)| self.register(name)).collect::<Vec<_>>().join("/")
+            },
+            FP_IMMEDIATE(longs) => self.fp_immediate(&longs),
+            MMU_REGISTER(reg) => self.register(&_lower(reg)),
+            FUNCTION_CODE(fc) => self.function_code(fc),
+            FC_MASK(fc, mask) => format!("{},{}", self.function_code(fc), self.immediate(mask as u32)),
+            FC_LEVEL(fc, level, _) => format!("{},{}", self.function_code(fc), self.immediate(level as u32)),
+            //Operands of the opcode table still waiting for their
+            //extension words
+            EA(..) | BRANCH_SHORT(_) | BRANCH_LONG | REGISTER_MASK(_) | REGISTER_WORD => "?".to_string(),
+        }
+    }
+}
+
+impl<B: Bus> CPU<B> {
+    //Instruction at `addr` as far as the bus lets it be read without side
+    //effects, None when part of it can't be
+    pub fn peek_instruction(&self, addr: u32) -> Option<Instruction> {
+        let mask = self.model.address_mask();
+        let bytes: Vec<u8> = (0..MAX_LENGTH).map_while(|i| self.bus.peek(addr.wrapping_add(i) & mask)).collect();
+        decode_bytes(self.model, addr, &bytes).map(|(inst, _)| inst)
+    }
+
+    //Listing of the `len` bytes at `start` as `disassemble` makes it,
+    //stopping early where memory can't be read without side effects
+    pub fn disassemble(&self, start: u32, len: u32, entries: &[u32]) -> Vec<Line> {
+        let mask = self.model.address_mask();
+        let bytes: Vec<u8> = (0..len).map_while(|i| self.bus.peek(start.wrapping_add(i) & mask)).collect();
+        disassemble(self.model, start, &bytes, entries)
+    }
+}
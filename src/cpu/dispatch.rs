@@ -0,0 +1,164 @@
+use super::*;
+use super::decoder::decode_opcode;
+use std::convert::TryFrom;
+use std::sync::OnceLock;
+
+type Handler<B> = fn(&mut CPU<B>, &Instruction) -> Result<(), BusFault>;
+
+//What the first word of an instruction decodes to and the handler
+//executing it
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Opcode {
+    pub handler: u8,
+    //What the opcode costs before anything depending on data
+    pub cycles: u16,
+    pub inst: Instruction,
+}
+
+//Lists the handler of every mnemonic once, handler_index gives the
+//position of a mnemonic's handler in HANDLERS. The ColdFire check on
+//indexed operands comes last.
+macro_rules! handlers {
+    ($($($op:pat)|+ => $handler:ident,)*) => {
+        //Positions in HANDLERS, named after the handler
+        #[repr(u8)]
+        enum HandlerId {
+            $($handler,)*
+            COLDFIRE_INDEXED,
+        }
+
+        pub(super) fn handler_index(op: &Mnemonic) -> u8 {
+            match op {
+                $($($op)|+ => HandlerId::$handler as u8,)*
+            }
+        }
+
+        const COLDFIRE_INDEXED: u8 = HandlerId::COLDFIRE_INDEXED as u8;
+
+        impl<B: Bus> CPU<B> {
+            const HANDLERS: [Handler<B>; COLDFIRE_INDEXED as usize + 1] = [$(CPU::$handler,)* CPU::perform_coldfire_indexed];
+        }
+    };
+}
+
+handlers! {
+    MOVE | MOVEQ => perform_move,
+    MOVEA => perform_movea,
+    MOVEM => perform_movem,
+    MOVEP => perform_movep,
+    ADD | ADDI | ADDQ => perform_add,
+    ADDA => perform_adda,
+    ADDX => perform_addx,
+    SUB | SUBI | SUBQ => perform_sub,
+    SUBA => perform_suba,
+    SUBX => perform_subx,
+    CMP | CMPI | CMPM => perform_cmp,
+    CMPA => perform_cmpa,
+    NEG => perform_neg,
+    NEGX => perform_negx,
+    CLR => perform_clr,
+    TST => perform_tst,
+    AND | ANDI => perform_and,
+    OR | ORI => perform_or,
+    EOR | EORI => perform_eor,
+    NOT => perform_not,
+    MULU | MULS => perform_mul,
+    DIVU => perform_divu,
+    DIVS => perform_divs,
+    ABCD => perform_abcd,
+    SBCD => perform_sbcd,
+    NBCD => perform_nbcd,
+    ASL | ASR | LSL | LSR | ROL | ROR | ROXL | ROXR => perform_shift,
+    BTST | BCHG | BCLR | BSET => perform_bit,
+    EXG => perform_exg,
+    SWAP => perform_swap,
+    EXT | EXTB => perform_ext,
+    TAS => perform_tas,
+    LEA => perform_lea,
+    PEA => perform_pea,
+    LINK => perform_link,
+    UNLK => perform_unlk,
+    BRA | BSR | BCC(_) => perform_branch,
+    DBCC(_) => perform_dbcc,
+    SCC(_) => perform_scc,
+    JMP | JSR => perform_jump,
+    RTS => perform_rts,
+    RTR => perform_rtr,
+    RTE => perform_rte,
+    TRAP => perform_trap,
+    TRAPV => perform_trapv,
+    CHK => perform_chk,
+    NOP => perform_nop,
+    RESET => perform_reset,
+    STOP => perform_stop,
+    MOVEC => perform_movec,
+    MOVES => perform_moves,
+    RTD => perform_rtd,
+    BFTST | BFEXTU | BFCHG | BFEXTS | BFCLR | BFFFO | BFSET | BFINS => perform_bitfield,
+    DIVUL | DIVSL => perform_divl,
+    CAS => perform_cas,
+    CAS2 => perform_cas2,
+    CHK2 | CMP2 => perform_chk2,
+    PACK | UNPK => perform_pack,
+    TRAPCC(_) => perform_trapcc,
+    FOP(..) | FMOVECR | FMOVEM => perform_fgen,
+    FBCC(_) => perform_fbcc,
+    FDBCC(_) => perform_fdbcc,
+    FSCC(_) => perform_fscc,
+    FTRAPCC(_) => perform_ftrapcc,
+    FSAVE | FRESTORE => perform_fsave,
+    PMOVE | PMOVEFD | PFLUSHA | PFLUSH | PLOADR | PLOADW | PTESTR | PTESTW => perform_pmmu,
+    MOV3Q => perform_mov3q,
+    MVS | MVZ => perform_mvx,
+    SATS => perform_sats,
+    ILLEGAL | LINE_A | LINE_F => perform_illegal,
+}
+
+//One table per model, in CpuModel order
+static OPCODES: [OnceLock<Vec<Opcode>>; 6] = [const { OnceLock::new() }; 6];
+
+fn _indexed(data: &DataContainer) -> bool {
+    matches!(data, EA(6, _) | EA(7, 3))
+}
+
+//Built on first use and shared by every CPU of the model
+pub(super) fn opcode_table(model: CpuModel) -> &'static [Opcode] {
+    OPCODES[model as usize].get_or_init(|| {
+        (0..=0xffff).map(|opcode| {
+            let inst = decode_opcode(opcode, model);
+            let handler = if model.is_coldfire() && (_indexed(inst.get_lhs()) || _indexed(inst.get_trg())) {
+                COLDFIRE_INDEXED
+            }
+            else {
+                handler_index(inst.get_op())
+            };
+            let cycles = u16::try_from(timing::cycles(&inst)).expect("opcode time too long for the table");
+            Opcode { handler, cycles, inst }
+        }).collect()
+    })
+}
+
+impl<B: Bus> CPU<B> {
+
+    pub(super) fn dispatch(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        self.inst_cycles = self.cycles;
+        self.cycles += timing::cycles(inst) as u64;
+        self.run_handler(handler_index(inst.get_op()), inst)
+    }
+
+    pub(super) fn run_handler(&mut self, handler: u8, inst: &Instruction) -> Result<(), BusFault> {
+        (Self::HANDLERS[handler as usize])(self, inst)
+    }
+
+    //Fetches the opcode at PC and runs the handler the table holds for it
+    pub(super) fn execute_next(&mut self) -> Result<(), BusFault> {
+        self.inst_pc = self.get_pc();
+        self.inst_cycles = self.cycles;
+        let opcode = self.fetch_word()?;
+        self.ir = opcode;
+        let opcodes = self.opcodes;
+        let entry = &opcodes[opcode as usize];
+        self.cycles += entry.cycles as u64;
+        self.run_handler(entry.handler, &entry.inst)
+    }
+}
@@ -0,0 +1,380 @@
+use super::*;
+use super::disasm::decode_bytes;
+use super::dispatch::opcode_table;
+use std::collections::HashMap;
+use std::mem::{discriminant, Discriminant};
+use std::sync::OnceLock;
+
+// Encoding of instructions back into machine words.
+//
+// The opcode word comes from the decoding tables: the opcodes decoding to
+// the mnemonic are tried in order until one takes the operands, then the
+// extension words are built from the operands. Every encoding is decoded
+// back before being returned, which is what rejects the combinations the
+// model doesn't have or values too large for their field.
+
+//Opcodes by mnemonic, with the conditions and FPU operations left out,
+//in opcode order. Only 0x4afc stands for ILLEGAL.
+static FORMS: [OnceLock<HashMap<Discriminant<Mnemonic>, Vec<u16>>>; 6] = [const { OnceLock::new() }; 6];
+
+fn _forms(model: CpuModel) -> &'static HashMap<Discriminant<Mnemonic>, Vec<u16>> {
+    FORMS[model as usize].get_or_init(|| {
+        let mut forms = HashMap::new();
+        for (opcode, entry) in opcode_table(model).iter().enumerate() {
+            let op = entry.inst.get_op();
+            if *op != ILLEGAL || opcode == 0x4afc {
+                forms.entry(discriminant(op)).or_insert_with(Vec::new).push(opcode as u16);
+            }
+        }
+        forms
+    })
+}
+
+//Mnemonic the opcode table holds for the forms settled by an extension
+//word
+fn _table_op(op: Mnemonic) -> Mnemonic {
+    match op {
+        MULS => MULU,
+        DIVS | DIVUL | DIVSL => DIVU,
+        CMP2 => CHK2,
+        FMOVECR | FMOVEM => FOP(FpOp::MOVE, FpFormat::EXTENDED),
+        PMOVEFD | PFLUSHA | PFLUSH | PLOADR | PLOADW | PTESTR | PTESTW => PMOVE,
+        op => op,
+    }
+}
+
+fn _register_word(inst: &Instruction) -> bool {
+    matches!((inst.get_lhs(), inst.get_trg()), (REGISTER_WORD, _) | (_, REGISTER_WORD))
+}
+
+//Whether an operand from the opcode table can be completed into `data`
+fn _fits(template: &DataContainer, data: &DataContainer) -> bool {
+    match (*template, *data) {
+        (EA(5, reg), DISPLACEMENT(r, _)) | (EA(6, reg), INDEXED(r, ..)) => reg as usize == r,
+        (EA(6, reg), EXTENDED(ext)) => match ext.base {
+            Base::ADDRESS(r) => reg as usize == r,
+            base => base == Base::SUPPRESSED,
+        },
+        (EA(7, 3), EXTENDED(ext)) => matches!(ext.base, Base::PC(_) | Base::SUPPRESSED_PC),
+        (EA(7, 0), SHORT_ADDR(_)) | (EA(7, 1), MEMORY_ADDR(_)) | (EA(7, 2), PC_DISPLACEMENT(..))
+            | (EA(7, 3), PC_INDEXED(..)) | (EA(7, 4), IMEDIATE_VALUE(_) | FP_IMMEDIATE(_)) => true,
+        (BRANCH_SHORT(disp), PC_DISPLACEMENT(_, d)) => disp as i16 == d,
+        (BRANCH_LONG, EXTENDED(ext)) => matches!(ext.base, Base::PC(_)),
+        (REGISTER_MASK(_), REGISTER_LIST(_)) => true,
+        (template, data) => template == data,
+    }
+}
+
+fn _long(words: &mut Vec<u16>, val: u32) {
+    words.extend([(val >> 16) as u16, val as u16].iter());
+}
+
+//Null, word or long displacement of a full extension word, by its size
+//field
+fn _displacement(words: &mut Vec<u16>, disp: i32) -> u16 {
+    if disp == 0 {
+        1
+    }
+    else if disp as i16 as i32 == disp {
+        words.push(disp as u16);
+        2
+    }
+    else {
+        _long(words, disp as u32);
+        3
+    }
+}
+
+//Register, size and scale fields of an index
+fn _index(index: &Index) -> u16 {
+    (index.register as u16) << 12 | if index.long { 0x0800 } else { 0 } | (index.scale.trailing_zeros() as u16 & 0b11) << 9
+}
+
+//Full extension word followed by its displacements
+fn _full_extension(words: &mut Vec<u16>, ext: &Extended) {
+    let at = words.len();
+    words.push(0);
+    let mut word = 0x0100 | ext.index.as_ref().map_or(0x0040, _index);
+    if matches!(ext.base, Base::SUPPRESSED | Base::SUPPRESSED_PC) {
+        word |= 0x0080;
+    }
+    word |= _displacement(words, ext.bd) << 4;
+    word |= match ext.indirect {
+        Indirect::NONE => 0,
+        Indirect::PRE_INDEXED => _displacement(words, ext.od),
+        Indirect::POST_INDEXED => 0b100 | _displacement(words, ext.od),
+    };
+    words[at] = word;
+}
+
+//Extension words completing the operand from the opcode table into `data`
+fn _extension(words: &mut Vec<u16>, template: &DataContainer, data: &DataContainer, size: &OpSize) {
+    match (*template, *data) {
+        (BRANCH_LONG, EXTENDED(ext)) => _long(words, ext.bd as u32),
+        (REGISTER_MASK(reversed), REGISTER_LIST(mask)) => words.push(if reversed { mask.reverse_bits() } else { mask }),
+        (EA(..), DISPLACEMENT(_, disp) | PC_DISPLACEMENT(_, disp) | SHORT_ADDR(disp)) => words.push(disp as u16),
+        (EA(..), MEMORY_ADDR(addr)) => _long(words, addr),
+        (EA(..), INDEXED(_, index, disp) | PC_INDEXED(_, index, disp)) => words.push(_index(&index) | disp as u8 as u16),
+        (EA(..), EXTENDED(ext)) => _full_extension(words, &ext),
+        (EA(..), IMEDIATE_VALUE(val)) => match size {
+            LONG => _long(words, val),
+            _ => words.push(val as u16),
+        },
+        _ => {},
+    }
+}
+
+//FPU immediates as fetch_fp_immediate reads them
+fn _fp_immediate(words: &mut Vec<u16>, imm: &[u32; 3], bytes: u32) {
+    match bytes {
+        1 | 2 => words.push(imm[0] as u16),
+        _ => imm.iter().take(bytes as usize / 4).for_each(|&val| _long(words, val)),
+    }
+}
+
+//Register of the top four bits of an extension word
+fn _general(data: &DataContainer) -> Option<u16> {
+    match *data {
+        DATA_REGISTER(reg) => Some((reg as u16) << 12),
+        ADDRESS_REGISTER(reg) => Some(0x8000 | (reg as u16) << 12),
+        _ => None,
+    }
+}
+
+fn _field(param: &FieldParam, register: u16) -> u16 {
+    match *param {
+        FieldParam::IMMEDIATE(val) => val as u16 & 0b11111,
+        FieldParam::REGISTER(reg) => register | reg as u16,
+    }
+}
+
+//Register and operation fields of an FPU command word
+fn _fp_destination(op: FpOp, data: &DataContainer) -> Option<u16> {
+    match *data {
+        FP_REGISTER(reg) if op != FpOp::SINCOS => Some((reg as u16) << 7 | op.opmode()),
+        FP_REGISTER_PAIR(cos, sin) if op == FpOp::SINCOS => Some((sin as u16) << 7 | op.opmode() | cos as u16),
+        _ => None,
+    }
+}
+
+//Mode and register list fields of FMOVEM, static lists being reversed
+//unless going through -(An)
+fn _fp_list(list: &DataContainer, ea: &DataContainer) -> Option<u16> {
+    match *list {
+        FP_REGISTER_LIST(mask) if matches!(ea, PREDECREMENT(_)) => Some(mask as u16),
+        FP_REGISTER_LIST(mask) => Some(0x1000 | mask.reverse_bits() as u16),
+        FP_DYNAMIC_LIST(reg, reversed) => Some(if reversed { 0x1800 } else { 0x0800 } | (reg as u16) << 4),
+        _ => None,
+    }
+}
+
+fn _fc_select(fc: &FcSelect) -> u16 {
+    match *fc {
+        FcSelect::SFC => 0,
+        FcSelect::DFC => 1,
+        FcSelect::REGISTER(reg) => 0x08 | reg as u16 & 0b111,
+        FcSelect::IMMEDIATE(val) => 0x10 | val as u16 & 0b111,
+    }
+}
+
+//Opclass and register fields of PMOVE
+fn _mmu_register(reg: &MmuRegister) -> u16 {
+    match reg {
+        MmuRegister::TT0 => 0x0800,
+        MmuRegister::TT1 => 0x0c00,
+        MmuRegister::TC => 0x4000,
+        MmuRegister::SRP => 0x4800,
+        MmuRegister::CRP => 0x4c00,
+        MmuRegister::MMUSR => 0x6000,
+    }
+}
+
+//Words following the opcode of the FPU and MMU instructions, with the
+//effective address operand
+fn _coprocessor_command(inst: &Instruction) -> Option<(Vec<u16>, DataContainer)> {
+    let (op, lhs, trg) = (*inst.get_op(), *inst.get_lhs(), *inst.get_trg());
+    let fp_register = |data: &DataContainer| matches!(data, FP_REGISTER(_));
+    let mut words = Vec::new();
+    let ea = match (op, lhs, trg) {
+        (FDBCC(cond), DATA_REGISTER(_), PC_DISPLACEMENT(_, disp)) => {
+            words.extend([cond as u16, disp as u16].iter());
+            lhs
+        },
+        (FSCC(cond) | FTRAPCC(cond), ea, EMPTY) => {
+            words.push(cond as u16);
+            ea
+        },
+        (FOP(fop, FpFormat::EXTENDED), FP_REGISTER(src), _) if _fp_destination(fop, &trg).is_some() => {
+            words.push((src as u16) << 10 | _fp_destination(fop, &trg)?);
+            EMPTY
+        },
+        (FOP(FpOp::MOVE, format), FP_REGISTER(src), ea) => {
+            let k = match format {
+                FpFormat::PACKED(FieldParam::IMMEDIATE(k)) => k as u16 & 0x7f,
+                FpFormat::PACKED(FieldParam::REGISTER(reg)) => (reg as u16) << 4,
+                _ => 0,
+            };
+            words.push(0x6000 | format.bits() << 10 | (src as u16) << 7 | k);
+            ea
+        },
+        (FOP(fop, format), ea, _) => {
+            words.push(0x4000 | format.bits() << 10 | _fp_destination(fop, &trg)?);
+            if let FP_IMMEDIATE(imm) = ea {
+                _fp_immediate(&mut words, &imm, format.bytes());
+            }
+            ea
+        },
+        (FMOVECR, IMEDIATE_VALUE(offset), FP_REGISTER(reg)) => {
+            words.push(0x5c00 | (reg as u16) << 7 | offset as u16 & 0x7f);
+            EMPTY
+        },
+        (FMOVEM, FP_CONTROL(regs), ea) => {
+            words.push(0xa000 | (regs as u16 & 0b111) << 10);
+            ea
+        },
+        (FMOVEM, ea, FP_CONTROL(regs)) => {
+            words.push(0x8000 | (regs as u16 & 0b111) << 10);
+            if let FP_IMMEDIATE(imm) = ea {
+                _fp_immediate(&mut words, &imm, 4 * regs.count_ones());
+            }
+            ea
+        },
+        (FMOVEM, list, ea) if !fp_register(&ea) && _fp_list(&list, &ea).is_some() => {
+            words.push(0xe000 | _fp_list(&list, &ea)?);
+            ea
+        },
+        (FMOVEM, ea, list) => {
+            words.push(0xc000 | _fp_list(&list, &ea)?);
+            ea
+        },
+        (PMOVE | PMOVEFD, _, _) => {
+            let fd = if op == PMOVEFD { 0x0100 } else { 0 };
+            match (lhs, trg) {
+                (MMU_REGISTER(reg), ea) => {
+                    words.push(_mmu_register(&reg) | 0x0200 | fd);
+                    ea
+                },
+                (ea, MMU_REGISTER(reg)) => {
+                    words.push(_mmu_register(&reg) | fd);
+                    ea
+                },
+                _ => return None,
+            }
+        },
+        (PLOADR | PLOADW, FUNCTION_CODE(fc), ea) => {
+            words.push(0x2000 | if op == PLOADR { 0x0200 } else { 0 } | _fc_select(&fc));
+            ea
+        },
+        (PFLUSHA, EMPTY, EMPTY) => {
+            words.push(0x2400);
+            EMPTY
+        },
+        (PFLUSH, FC_MASK(fc, mask), ea) => {
+            let mode = if ea == EMPTY { 0x3000 } else { 0x3800 };
+            words.push(mode | (mask as u16 & 0xf) << 5 | _fc_select(&fc));
+            ea
+        },
+        (PTESTR | PTESTW, FC_LEVEL(fc, level, reg), ea) => {
+            let reg = reg.map_or(0, |reg| 0x0100 | (reg as u16 & 0b111) << 5);
+            let read = if op == PTESTR { 0x0200 } else { 0 };
+            words.push(0x8000 | (level as u16 & 0b111) << 10 | read | reg | _fc_select(&fc));
+            ea
+        },
+        _ => return None,
+    };
+    Some((words, ea))
+}
+
+//Extension words naming registers ahead of any other, as
+//resolve_register_word reads them, with the effective address operand.
+//EMPTY stands for no effective address, the opcode taking any.
+fn _register_command(inst: &Instruction) -> Option<(Vec<u16>, DataContainer)> {
+    let (op, lhs, trg) = (*inst.get_op(), *inst.get_lhs(), *inst.get_trg());
+    let signed = |signed: bool| if signed { 0x0800 } else { 0 };
+    let (word, ea) = match (op, lhs, trg) {
+        (CAS2, REGISTER_PAIR(dc1, dc2), CAS2_TARGET(du1, du2, rn1, rn2)) => {
+            let ext = |dc: usize, du: usize, rn: usize| (rn as u16) << 12 | (du as u16) << 6 | dc as u16;
+            return Some((vec![ext(dc1, du1, rn1), ext(dc2, du2, rn2)], EMPTY));
+        },
+        (MOVEC, CONTROL_REGISTER(code), reg) | (MOVEC, reg, CONTROL_REGISTER(code)) => (_general(&reg)? | code & 0xfff, EMPTY),
+        (MOVES, DATA_REGISTER(_) | ADDRESS_REGISTER(_), ea) => (_general(&lhs)? | 0x0800, ea),
+        (MOVES, ea, reg) => (_general(&reg)?, ea),
+        (MULU | MULS, ea, DATA_REGISTER(reg)) => ((reg as u16) << 12 | reg as u16 | signed(op == MULS), ea),
+        (MULU | MULS, ea, REGISTER_PAIR(low, high)) => (0x0400 | (high as u16) << 12 | low as u16 | signed(op == MULS), ea),
+        (DIVU | DIVS, ea, DATA_REGISTER(reg)) => ((reg as u16) << 12 | reg as u16 | signed(op == DIVS), ea),
+        (DIVU | DIVS, ea, REGISTER_PAIR(r, q)) => (0x0400 | (q as u16) << 12 | r as u16 | signed(op == DIVS), ea),
+        (DIVUL | DIVSL, ea, REGISTER_PAIR(r, q)) => ((q as u16) << 12 | r as u16 | signed(op == DIVSL), ea),
+        (CAS, REGISTER_PAIR(dc, du), ea) => ((du as u16) << 6 | dc as u16, ea),
+        (CHK2 | CMP2, ea, reg) => (_general(&reg)? | signed(op == CHK2), ea),
+        (BFTST | BFEXTU | BFCHG | BFEXTS | BFCLR | BFFFO | BFSET | BFINS, ea, BITFIELD(field)) => {
+            ((field.register as u16) << 12 | _field(&field.offset, 0x20) << 6 | _field(&field.width, 0x20), ea)
+        },
+        _ => return _coprocessor_command(inst),
+    };
+    Some((vec![word], ea))
+}
+
+//ColdFire only takes long indexes scaled by up to 4, which decoding
+//doesn't check
+fn _coldfire_index(data: &DataContainer) -> bool {
+    match data {
+        INDEXED(_, index, _) | PC_INDEXED(_, index, _) => index.long && index.scale != 8,
+        _ => true,
+    }
+}
+
+impl Instruction {
+    //Machine words of the instruction at `addr` on the model, the first
+    //being the opcode. PC relative operands must hold the addresses they
+    //would have been decoded with at `addr`.
+    pub fn encode(&self, model: CpuModel, addr: u32) -> Result<Vec<u16>, EncodeError> {
+        let forms = _forms(model);
+        let (op, size, lhs, trg) = (*self.get_op(), *self.get_size(), self.get_lhs(), self.get_trg());
+        let mut opcodes = forms.get(&discriminant(&op)).into_iter().flatten().collect::<Vec<_>>();
+        if _table_op(op) != op {
+            opcodes.extend(forms.get(&discriminant(&_table_op(op))).into_iter().flatten());
+        }
+        if opcodes.is_empty() {
+            return Err(EncodeError::UNSUPPORTED);
+        }
+        if model.is_coldfire() && !(_coldfire_index(lhs) && _coldfire_index(trg)) {
+            return Err(EncodeError::INVALID_OPERANDS);
+        }
+        let table = opcode_table(model);
+        let command = _register_command(self);
+        for &opcode in opcodes {
+            let form = &table[opcode as usize].inst;
+            let mut words = vec![opcode];
+            if _register_word(form) {
+                let (command, ea) = match &command {
+                    Some(command) => command,
+                    None => continue,
+                };
+                if *ea != EMPTY && !_fits(form.get_lhs(), ea) {
+                    continue;
+                }
+                words.extend(command);
+                _extension(&mut words, form.get_lhs(), ea, &size);
+            }
+            else {
+                if *form.get_op() != op || *form.get_size() != size || !_fits(form.get_lhs(), lhs) || !_fits(form.get_trg(), trg) {
+                    continue;
+                }
+                //The MOVEM mask comes before the extension words of its address
+                if let REGISTER_MASK(_) = form.get_trg() {
+                    _extension(&mut words, form.get_trg(), trg, &size);
+                    _extension(&mut words, form.get_lhs(), lhs, &size);
+                }
+                else {
+                    _extension(&mut words, form.get_lhs(), lhs, &size);
+                    _extension(&mut words, form.get_trg(), trg, &size);
+                }
+            }
+            let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+            if decode_bytes(model, addr, &bytes) == Some((*self, bytes.len() as u32)) {
+                return Ok(words);
+            }
+        }
+        Err(EncodeError::INVALID_OPERANDS)
+    }
+}
@@ -0,0 +1,229 @@
+use super::*;
+
+pub const VECTOR_BUS_ERROR: u8 = 2;
+pub const VECTOR_ADDRESS_ERROR: u8 = 3;
+pub const VECTOR_ILLEGAL: u8 = 4;
+pub const VECTOR_ZERO_DIVIDE: u8 = 5;
+pub const VECTOR_CHK: u8 = 6;
+pub const VECTOR_TRAPV: u8 = 7;
+pub const VECTOR_PRIVILEGE: u8 = 8;
+pub const VECTOR_TRACE: u8 = 9;
+pub const VECTOR_LINE_A: u8 = 10;
+pub const VECTOR_LINE_F: u8 = 11;
+pub const VECTOR_FORMAT_ERROR: u8 = 14;
+pub const VECTOR_SPURIOUS: u8 = 24;
+pub const VECTOR_TRAP: u8 = 32;
+//FPU exceptions enabled in FPCR
+pub const VECTOR_FP_BSUN: u8 = 48;
+pub const VECTOR_FP_INEX: u8 = 49;
+pub const VECTOR_FP_DZ: u8 = 50;
+pub const VECTOR_FP_UNFL: u8 = 51;
+pub const VECTOR_FP_OPERR: u8 = 52;
+pub const VECTOR_FP_OVFL: u8 = 53;
+pub const VECTOR_FP_SNAN: u8 = 54;
+pub const VECTOR_MMU_CONFIGURATION: u8 = 56;
+
+impl<B: Bus> super::CPU<B> {
+
+    fn push_word(&mut self, val: u16) -> Result<(), BusFault> {
+        let sp = self.address_register[7].wrapping_sub(2);
+        self.address_register[7] = sp;
+        self.write_word(FunctionCode::SUPERVISOR_DATA, sp, val)
+    }
+
+    fn push_long(&mut self, val: u32) -> Result<(), BusFault> {
+        let sp = self.address_register[7].wrapping_sub(4);
+        self.address_register[7] = sp;
+        self.write_long(FunctionCode::SUPERVISOR_DATA, sp, val)
+    }
+
+    //Enters supervisor mode and stacks the frame: `body` then PC and SR,
+    //with the format and vector offset word between them from the 68010
+    //on. The body is given lowest address first. Returns the old SR.
+    fn enter_exception(&mut self, vector: u8, format: u16, body: &[u16]) -> Result<u16, BusFault> {
+        let sr = self.get_sr();
+        self.set_sr((sr | 0x2000) & 0x7fff);
+        self.loop_mode = None;
+        if self.model.is_coldfire() {
+            self.push_coldfire_frame(vector, format, sr)?;
+            return Ok(sr);
+        }
+        for &word in body.iter().rev() {
+            self.push_word(word)?;
+        }
+        if self.model.has_frame_format() {
+            self.push_word(format << 12 | (vector as u16) << 2)?;
+        }
+        let pc = self.get_pc();
+        self.push_long(pc)?;
+        self.push_word(sr)?;
+        Ok(sr)
+    }
+
+    //ColdFire frames are always two longs: PC, then SR under a word giving
+    //the format, the fault status `status` and the vector. The stack is
+    //long aligned first, the format telling by how much.
+    fn push_coldfire_frame(&mut self, vector: u8, status: u16, sr: u16) -> Result<(), BusFault> {
+        let sp = self.address_register[7];
+        self.address_register[7] = sp & !0b11;
+        let pc = self.get_pc();
+        self.push_long(pc)?;
+        self.push_word(sr)?;
+        let format = 4 + (sp & 0b11) as u16;
+        self.push_word(format << 12 | (status >> 2) << 10 | (vector as u16) << 2 | (status & 0b11))
+    }
+
+    fn jump_to_vector(&mut self, vector: u8) -> Result<(), BusFault> {
+        let handler = self.read_long(FunctionCode::SUPERVISOR_DATA, self.vbr.wrapping_add(vector as u32 * 4))?;
+        self.set_pc(handler);
+        self.state = CpuState::RUNNING;
+        Ok(())
+    }
+
+    //A bus error while processing an exception halts the CPU
+    fn double_fault(&mut self, result: Result<(), BusFault>) {
+        if result.is_err() {
+            self.state = CpuState::HALTED;
+        }
+    }
+
+    //Enters supervisor mode, stacks PC and SR then jumps through `vector`.
+    //The 68020 also stacks the address of the instruction that trapped.
+    pub(super) fn raise_exception(&mut self, vector: u8) {
+        let inst_pc = self.inst_pc;
+        let result = match vector {
+            VECTOR_ZERO_DIVIDE | VECTOR_CHK | VECTOR_TRAPV | VECTOR_TRACE if self.model == CpuModel::MC68020 => {
+                self.enter_exception(vector, 2, &[(inst_pc >> 16) as u16, inst_pc as u16])
+            },
+            _ => self.enter_exception(vector, 0, &[]),
+        };
+        let result = result.and_then(|_| self.jump_to_vector(vector));
+        self.double_fault(result);
+    }
+
+    //Same as raise_exception but the stacked PC is the faulting instruction,
+    //which gets replaced by the exception in the cycle count
+    pub(super) fn raise_fault(&mut self, vector: u8) -> Result<(), BusFault> {
+        self.set_pc(self.inst_pc);
+        self.raise_exception(vector);
+        self.cycles = self.inst_cycles + 34;
+        Ok(())
+    }
+
+    //Group 0 exceptions, bus and address errors. The frame says what the
+    //access was, the internal state later models stack isn't emulated and
+    //reads as zero.
+    pub(super) fn bus_error(&mut self, fault: BusFault) {
+        let vector = if fault.address_error { VECTOR_ADDRESS_ERROR } else { VECTOR_BUS_ERROR };
+        let program = matches!(fault.fc, FunctionCode::USER_PROGRAM | FunctionCode::SUPERVISOR_PROGRAM);
+        let (addr_hi, addr_lo) = ((fault.addr >> 16) as u16, fault.addr as u16);
+        let result = match self.model {
+            CpuModel::MC68000 | CpuModel::MC68008 => {
+                //Stacked below SR
+                let access = if fault.write { 0 } else { 0b10000 } | fault.fc as u16;
+                let ir = self.ir;
+                self.set_pc(self.fault_pc(&fault));
+                self.enter_exception(vector, 0, &[]).and_then(|sr| {
+                    self.push_word(ir)?;
+                    self.push_long(fault.addr)?;
+                    self.push_word(access)?;
+                    Ok(sr)
+                })
+            },
+            //Format 8, 29 words
+            CpuModel::MC68010 => {
+                let ssw = if program { 1 << 13 } else { 1 << 12 }
+                    | if fault.size == BYTE { 1 << 9 } else { 0 }
+                    | if fault.write { 0 } else { 1 << 8 }
+                    | fault.fc as u16;
+                let mut body = [0; 25];
+                body[..3].copy_from_slice(&[ssw, addr_hi, addr_lo]);
+                self.enter_exception(vector, 8, &body)
+            },
+            //Format A, short bus cycle fault of 16 words
+            CpuModel::MC68020 => {
+                let size = match fault.size { BYTE => 1, WORD => 2, LONG => 0 };
+                let ssw = if program { 1 << 14 } else { 1 << 8 }
+                    | if fault.write { 0 } else { 1 << 6 }
+                    | size << 4
+                    | fault.fc as u16;
+                self.enter_exception(vector, 0xa, &[0, ssw, 0, 0, addr_hi, addr_lo, 0, 0, 0, 0, 0, 0])
+            },
+            //Access errors stack the faulting instruction with the fault
+            //status in place of a format
+            CpuModel::COLDFIRE_ISA_A | CpuModel::COLDFIRE_ISA_B => {
+                let status = match (program, fault.write) {
+                    (true, _) => 0b0100,
+                    (false, true) => 0b1000,
+                    (false, false) => 0b1100,
+                };
+                self.set_pc(self.inst_pc);
+                self.enter_exception(vector, status, &[])
+            },
+        };
+        let result = result.and_then(|_| self.jump_to_vector(vector));
+        self.double_fault(result);
+        self.cycles += 50;
+    }
+
+    //Words stacked after the format word by a frame of format `format`,
+    //None for formats the model doesn't use. ColdFire RTE doesn't use it.
+    pub(super) fn frame_body(&self, format: u16) -> Option<u32> {
+        match (self.model, format) {
+            (_, 0) => Some(0),
+            (CpuModel::MC68010, 8) => Some(25),
+            (CpuModel::MC68020, 2) => Some(2),
+            (CpuModel::MC68020, 0xa) => Some(12),
+            (CpuModel::MC68020, 0xb) => Some(42),
+            _ => None,
+        }
+    }
+
+    fn requested_level(&self) -> u8 {
+        self.ipl.max(self.bus.interrupt_level() & 0b111)
+    }
+
+    //Highest interrupt level currently requested, if the mask lets it through.
+    //Level 7 is non maskable and edge triggered, held it is only taken once.
+    pub(super) fn pending_interrupt(&self) -> Option<u8> {
+        let level = self.requested_level();
+        let mask = (self.sr >> 8) as u8 & 0b111;
+        if level == 7 {
+            if self.nmi_taken { None } else { Some(7) }
+        }
+        else if level > mask {
+            Some(level)
+        }
+        else {
+            None
+        }
+    }
+
+    //Takes the pending interrupt if any, returns whether one was taken
+    pub(super) fn check_interrupts(&mut self) -> bool {
+        if self.requested_level() < 7 {
+            self.nmi_taken = false;
+        }
+        let level = match self.pending_interrupt() {
+            Some(level) => level,
+            None => return false,
+        };
+        self.nmi_taken = level == 7;
+        let mut vector = None;
+        if self.ipl == level {
+            self.ipl = 0;
+        }
+        else {
+            vector = self.bus.acknowledge(level);
+        }
+        let vector = vector.unwrap_or(VECTOR_SPURIOUS + level);
+        let result = self.enter_exception(vector, 0, &[])
+            .and_then(|_| {
+                self.sr = (self.sr & 0xf8ff) | (level as u16) << 8;
+                self.jump_to_vector(vector)
+            });
+        self.double_fault(result);
+        self.cycles += 44;
+        true
+    }
+}
@@ -0,0 +1,136 @@
+use super::*;
+
+fn _is_negative(val: u32, op_size: &OpSize) -> bool {
+    val & op_size.msb() != 0
+}
+
+fn _is_null(val: u32, op_size: &OpSize) -> bool {
+    val & op_size.mask() == 0
+}
+
+fn _nz(result: u32, op_size: &OpSize) -> u8 {
+    let mut ccr = 0;
+    if _is_negative(result, op_size) {
+        ccr |= Flag::N as u8;
+    }
+    if _is_null(result, op_size) {
+        ccr |= Flag::Z as u8;
+    }
+    ccr
+}
+
+//The operation condition codes were last set by, with what is needed to
+//work them out
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Pending {
+    //Flags as given, in CCR layout
+    KNOWN(u8),
+    //N and Z from the result, V and C cleared
+    LOGIC(u32, OpSize),
+    //Source, destination and result
+    ADD(u32, u32, u32, OpSize),
+    SUB(u32, u32, u32, OpSize),
+    //N and Z from the result, V and C as given in CCR layout
+    RESULT(u32, OpSize, u8),
+    //Same for ADDX, SUBX, NEGX and the BCD instructions, Z being only ever
+    //cleared so that it holds for a whole multiprecision operation. Last is
+    //whether it is kept set.
+    EXTENDED(u32, OpSize, u8, bool),
+}
+
+impl Pending {
+    fn ccr(&self) -> u8 {
+        match *self {
+            Pending::KNOWN(ccr) => ccr,
+            Pending::LOGIC(result, size) => _nz(result, &size),
+            Pending::ADD(src, dst, result, size) => {
+                let mut ccr = _nz(result, &size);
+                if _is_negative((src & dst) | (!result & (src | dst)), &size) {
+                    ccr |= Flag::C as u8;
+                }
+                if _is_negative((src ^ result) & (dst ^ result), &size) {
+                    ccr |= Flag::V as u8;
+                }
+                ccr
+            },
+            Pending::SUB(src, dst, result, size) => {
+                let mut ccr = _nz(result, &size);
+                if _is_negative((src & !dst) | (result & !dst) | (src & result), &size) {
+                    ccr |= Flag::C as u8;
+                }
+                if _is_negative((src ^ dst) & (result ^ dst), &size) {
+                    ccr |= Flag::V as u8;
+                }
+                ccr
+            },
+            Pending::RESULT(result, size, vc) => _nz(result, &size) | vc,
+            Pending::EXTENDED(result, size, vc, z) => {
+                let ccr = (_nz(result, &size) & !(Flag::Z as u8)) | vc;
+                if z { ccr | Flag::Z as u8 } else { ccr }
+            },
+        }
+    }
+}
+
+fn _vc(overflow: bool, carry: bool) -> u8 {
+    (if overflow { Flag::V as u8 } else { 0 }) | (if carry { Flag::C as u8 } else { 0 })
+}
+
+//Condition codes, evaluated when read. X is tracked apart since most
+//instructions leave it alone.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Flags {
+    nzvc: Pending,
+    //X is the carry of this operation
+    x: Pending,
+}
+
+impl Flags {
+    pub fn new(ccr: u8) -> Flags {
+        Flags { nzvc: Pending::KNOWN(ccr), x: Pending::KNOWN(ccr) }
+    }
+
+    pub fn ccr(&self) -> u8 {
+        let x = match self.x {
+            Pending::KNOWN(ccr) => ccr & Flag::X as u8,
+            op => (op.ccr() & Flag::C as u8) << 4,
+        };
+        (self.nzvc.ccr() & 0b1111) | x
+    }
+
+    pub fn logic(&mut self, result: u32, op_size: &OpSize) {
+        self.nzvc = Pending::LOGIC(result, *op_size);
+    }
+
+    pub fn add(&mut self, src: u32, dst: u32, result: u32, op_size: &OpSize) {
+        self.nzvc = Pending::ADD(src, dst, result, *op_size);
+        self.x = self.nzvc;
+    }
+
+    pub fn sub(&mut self, src: u32, dst: u32, result: u32, op_size: &OpSize) {
+        self.nzvc = Pending::SUB(src, dst, result, *op_size);
+        self.x = self.nzvc;
+    }
+
+    //X is left alone
+    pub fn result(&mut self, result: u32, op_size: &OpSize, overflow: bool, carry: bool) {
+        self.nzvc = Pending::RESULT(result, *op_size, _vc(overflow, carry));
+    }
+
+    //X is the carry
+    pub fn extended(&mut self, result: u32, op_size: &OpSize, overflow: bool, carry: bool) {
+        //Only a null result needs to know how Z was before
+        let z = _is_null(result, op_size) && self.nzvc.ccr() & Flag::Z as u8 != 0;
+        self.nzvc = Pending::EXTENDED(result, *op_size, _vc(overflow, carry), z);
+        self.x = self.nzvc;
+    }
+
+    pub fn set_x(&mut self, x: bool) {
+        self.x = Pending::KNOWN(if x { Flag::X as u8 } else { 0 });
+    }
+
+    //Like sub but X is left alone
+    pub fn compare(&mut self, src: u32, dst: u32, result: u32, op_size: &OpSize) {
+        self.nzvc = Pending::SUB(src, dst, result, *op_size);
+    }
+}
@@ -0,0 +1,689 @@
+use std::cmp::Ordering;
+
+// Extended precision numbers of the 68881 and 68882, stored as the chip
+// does: a sign, a 15 bit biased exponent and a 64 bit mantissa with an
+// explicit integer bit. Unlike on the x87 an exponent of 0 stands for
+// 2^-16383 whether the integer bit is set or not, denormals just have it
+// clear.
+//
+// The basic operations are done on integers and give the exact result the
+// chip gives in every rounding mode and precision. Transcendental functions
+// and decimal output go through f64 and are only as good as that.
+
+//Exception status bits of FPSR
+pub const BSUN: u32 = 1 << 15;
+pub const SNAN: u32 = 1 << 14;
+pub const OPERR: u32 = 1 << 13;
+pub const OVFL: u32 = 1 << 12;
+pub const UNFL: u32 = 1 << 11;
+pub const DZ: u32 = 1 << 10;
+pub const INEX2: u32 = 1 << 9;
+pub const INEX1: u32 = 1 << 8;
+
+const BIAS: i32 = 16383;
+const EXP_MAX: u16 = 0x7fff;
+const QUIET: u64 = 1 << 62;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    NEAREST,
+    ZERO,
+    MINUS,
+    PLUS,
+}
+
+//Mantissa bits and exponent range of the integer bit results get
+//rounded to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Precision {
+    pub bits: u32,
+    pub emin: i32,
+    pub emax: i32,
+}
+
+impl Precision {
+    pub const EXTENDED: Precision = Precision { bits: 64, emin: -16383, emax: 16383 };
+    pub const DOUBLE: Precision = Precision { bits: 53, emin: -1022, emax: 1023 };
+    pub const SINGLE: Precision = Precision { bits: 24, emin: -126, emax: 127 };
+
+    //Rounding precisions of FPCR only shorten the mantissa
+    pub fn mantissa(bits: u32) -> Precision {
+        Precision { bits, ..Precision::EXTENDED }
+    }
+}
+
+//How an operation rounds and the exceptions it raised
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Context {
+    pub rounding: Rounding,
+    pub precision: Precision,
+    pub exceptions: u32,
+}
+
+impl Context {
+    pub fn new(rounding: Rounding, precision: Precision) -> Context {
+        Context { rounding, precision, exceptions: 0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Float {
+    pub sign: bool,
+    pub exp: u16,
+    pub mant: u64,
+}
+
+//Drops the low `drop` bits of `sig`, giving what is kept once rounded and
+//whether anything was lost
+fn _round_off(sig: u128, drop: u32, sign: bool, rounding: Rounding) -> (u128, bool) {
+    let (kept, half, sticky) = match drop {
+        0 => (sig, false, false),
+        1..=127 => (sig >> drop, (sig >> (drop - 1)) & 1 != 0, sig & ((1 << (drop - 1)) - 1) != 0),
+        128 => (0, sig >> 127 != 0, sig << 1 != 0),
+        _ => (0, false, sig != 0),
+    };
+    let up = match rounding {
+        Rounding::NEAREST => half && (sticky || kept & 1 != 0),
+        Rounding::ZERO => false,
+        Rounding::MINUS => sign && (half || sticky),
+        Rounding::PLUS => !sign && (half || sticky),
+    };
+    (kept + up as u128, half || sticky)
+}
+
+//Rounds ±sig * 2^(exp - 127) to `prec`, giving the exponent of the
+//integer bit and the mantissa top aligned, or None when it overflows.
+//Results below the exponent range get denormalized first.
+fn _round(sign: bool, exp: i32, sig: u128, prec: Precision, ctx: &mut Context) -> Option<(i32, u64)> {
+    let shift = sig.leading_zeros();
+    let (sig, mut exp) = (sig << shift, exp - shift as i32);
+    let tiny = exp < prec.emin;
+    let drop = 128 - prec.bits + if tiny { (prec.emin - exp).min(200) as u32 } else { 0 };
+    let (mut kept, inexact) = _round_off(sig, drop, sign, ctx.rounding);
+    if tiny {
+        exp = prec.emin;
+    }
+    else if kept >> prec.bits != 0 {
+        kept >>= 1;
+        exp += 1;
+    }
+    if exp > prec.emax {
+        ctx.exceptions |= OVFL | INEX2;
+        return None;
+    }
+    if inexact {
+        ctx.exceptions |= if tiny { INEX2 | UNFL } else { INEX2 };
+    }
+    Some((exp, (kept as u64) << (64 - prec.bits)))
+}
+
+//Overflows give infinity or the largest number depending on the rounding
+//direction
+fn _overflows_to_infinity(sign: bool, rounding: Rounding) -> bool {
+    match rounding {
+        Rounding::NEAREST => true,
+        Rounding::ZERO => false,
+        Rounding::MINUS => sign,
+        Rounding::PLUS => !sign,
+    }
+}
+
+//NaN operands give a NaN, the destination when both are, made quiet
+fn _nan(ctx: &mut Context, dst: Option<Float>, src: Float) -> Option<Float> {
+    if src.is_signaling() || dst.is_some_and(|dst| dst.is_signaling()) {
+        ctx.exceptions |= SNAN;
+    }
+    dst.filter(|dst| dst.is_nan()).or(Some(src).filter(|src| src.is_nan())).map(|nan| nan.quiet())
+}
+
+fn _operr(ctx: &mut Context) -> Float {
+    ctx.exceptions |= OPERR;
+    Float::NAN
+}
+
+//Exact conversion of a single or double, `exponent` and `fraction` bits
+//wide
+fn _from_ieee(sign: bool, exp: u32, frac: u64, exponent: u32, fraction: u32) -> Float {
+    let all_ones = (1 << exponent) - 1;
+    let bias = all_ones as i32 >> 1;
+    if exp == all_ones {
+        return if frac == 0 { Float::infinity(sign) } else { Float { sign, exp: EXP_MAX, mant: 1 << 63 | frac << (63 - fraction) } };
+    }
+    if exp != 0 {
+        return Float { sign, exp: (exp as i32 - bias + BIAS) as u16, mant: 1 << 63 | frac << (63 - fraction) };
+    }
+    if frac == 0 {
+        return Float::zero(sign);
+    }
+    let shift = frac.leading_zeros();
+    let exp = 63 - shift as i32 + 1 - bias - fraction as i32;
+    Float { sign, exp: (exp + BIAS) as u16, mant: frac << shift }
+}
+
+//Binary coded decimal digits of `val`, most significant first
+fn _bcd(val: u64, digits: usize) -> u64 {
+    let text = format!("{:0width$}", val, width = digits);
+    text.bytes().fold(0, |bcd, digit| bcd << 4 | (digit - b'0') as u64)
+}
+
+impl Float {
+    //What operations that have no meaningful result give
+    pub const NAN: Float = Float { sign: false, exp: EXP_MAX, mant: u64::MAX };
+
+    pub fn zero(sign: bool) -> Float {
+        Float { sign, exp: 0, mant: 0 }
+    }
+
+    pub fn infinity(sign: bool) -> Float {
+        Float { sign, exp: EXP_MAX, mant: 0 }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mant == 0 && self.exp != EXP_MAX
+    }
+
+    //The integer bit of infinities and NaNs doesn't matter
+    pub fn is_infinity(&self) -> bool {
+        self.exp == EXP_MAX && self.mant << 1 == 0
+    }
+
+    pub fn is_nan(&self) -> bool {
+        self.exp == EXP_MAX && self.mant << 1 != 0
+    }
+
+    pub fn is_signaling(&self) -> bool {
+        self.is_nan() && self.mant & QUIET == 0
+    }
+
+    fn quiet(self) -> Float {
+        Float { mant: self.mant | QUIET, ..self }
+    }
+
+    pub fn negate(self) -> Float {
+        Float { sign: !self.sign, ..self }
+    }
+
+    pub fn abs(self) -> Float {
+        Float { sign: false, ..self }
+    }
+
+    //Exponent of the integer bit and normalized mantissa of a finite
+    //nonzero number, whose value is mant * 2^(exp - 63)
+    fn unpack(&self) -> (i32, u64) {
+        let shift = self.mant.leading_zeros();
+        (self.exp as i32 - BIAS - shift as i32, self.mant << shift)
+    }
+
+    //±sig * 2^(exp - 127) rounded as `ctx` says
+    pub fn round(sign: bool, exp: i32, sig: u128, ctx: &mut Context) -> Float {
+        if sig == 0 {
+            return Float::zero(sign);
+        }
+        let prec = ctx.precision;
+        match _round(sign, exp, sig, prec, ctx) {
+            Some((_, 0)) => Float::zero(sign),
+            Some((exp, mant)) => Float { sign, exp: (exp + BIAS) as u16, mant },
+            None if _overflows_to_infinity(sign, ctx.rounding) => Float::infinity(sign),
+            None => Float { sign, exp: (prec.emax + BIAS) as u16, mant: u64::MAX << (64 - prec.bits) },
+        }
+    }
+
+    //The number itself rounded to the precision of `ctx`
+    pub fn round_value(self, ctx: &mut Context) -> Float {
+        if let Some(nan) = _nan(ctx, None, self) {
+            return nan;
+        }
+        if self.is_infinity() {
+            return self;
+        }
+        if self.is_zero() {
+            return Float::zero(self.sign);
+        }
+        let (exp, mant) = self.unpack();
+        Float::round(self.sign, exp, (mant as u128) << 64, ctx)
+    }
+
+    //Exact sums only give -0 when rounding toward minus infinity
+    pub fn add(self, src: Float, ctx: &mut Context) -> Float {
+        if let Some(nan) = _nan(ctx, Some(self), src) {
+            return nan;
+        }
+        match (self.is_infinity(), src.is_infinity()) {
+            (true, true) if self.sign != src.sign => return _operr(ctx),
+            (true, _) => return self,
+            (_, true) => return src,
+            _ => {},
+        }
+        match (self.is_zero(), src.is_zero()) {
+            (true, true) if self.sign == src.sign => return Float::zero(self.sign),
+            (true, true) => return Float::zero(ctx.rounding == Rounding::MINUS),
+            (true, false) => return src.round_value(ctx),
+            (false, true) => return self.round_value(ctx),
+            _ => {},
+        }
+        let (a, b) = ((self.sign, self.unpack()), (src.sign, src.unpack()));
+        let ((sign, (exp, big)), (other, (small_exp, small))) = if a.1 >= b.1 { (a, b) } else { (b, a) };
+        //Two bits of headroom for the carry, what gets shifted out is kept
+        //as a sticky bit
+        let shift = (exp - small_exp) as u32;
+        let big = (big as u128) << 62;
+        let small = (small as u128) << 62;
+        let small = if shift >= 126 { 1 } else { small >> shift | (small & ((1 << shift) - 1) != 0) as u128 };
+        let sig = if sign == other { big + small } else { big - small };
+        if sig == 0 {
+            return Float::zero(ctx.rounding == Rounding::MINUS);
+        }
+        Float::round(sign, exp + 2, sig, ctx)
+    }
+
+    pub fn sub(self, src: Float, ctx: &mut Context) -> Float {
+        if let Some(nan) = _nan(ctx, Some(self), src) {
+            return nan;
+        }
+        self.add(src.negate(), ctx)
+    }
+
+    pub fn mul(self, src: Float, ctx: &mut Context) -> Float {
+        if let Some(nan) = _nan(ctx, Some(self), src) {
+            return nan;
+        }
+        let sign = self.sign != src.sign;
+        if (self.is_infinity() && src.is_zero()) || (self.is_zero() && src.is_infinity()) {
+            return _operr(ctx);
+        }
+        if self.is_infinity() || src.is_infinity() {
+            return Float::infinity(sign);
+        }
+        if self.is_zero() || src.is_zero() {
+            return Float::zero(sign);
+        }
+        let ((a_exp, a), (b_exp, b)) = (self.unpack(), src.unpack());
+        Float::round(sign, a_exp + b_exp + 1, a as u128 * b as u128, ctx)
+    }
+
+    pub fn div(self, src: Float, ctx: &mut Context) -> Float {
+        if let Some(nan) = _nan(ctx, Some(self), src) {
+            return nan;
+        }
+        let sign = self.sign != src.sign;
+        if (self.is_infinity() && src.is_infinity()) || (self.is_zero() && src.is_zero()) {
+            return _operr(ctx);
+        }
+        if self.is_infinity() {
+            return Float::infinity(sign);
+        }
+        if src.is_zero() {
+            ctx.exceptions |= DZ;
+            return Float::infinity(sign);
+        }
+        if self.is_zero() || src.is_infinity() {
+            return Float::zero(sign);
+        }
+        //66 quotient bits then a sticky one for the remainder
+        let ((a_exp, a), (b_exp, b)) = (self.unpack(), src.unpack());
+        let (num, den) = ((a as u128) << 64, b as u128);
+        let (quotient, rest) = (num / den, num % den);
+        let (low, rest) = ((rest << 2) / den, (rest << 2) % den);
+        let sig = (quotient << 2 | low) << 1 | (rest != 0) as u128;
+        Float::round(sign, a_exp - b_exp + 60, sig, ctx)
+    }
+
+    //Bit by bit, the remainder staying small whatever the root length
+    pub fn sqrt(self, ctx: &mut Context) -> Float {
+        if let Some(nan) = _nan(ctx, None, self) {
+            return nan;
+        }
+        if self.is_zero() {
+            return self;
+        }
+        if self.sign {
+            return _operr(ctx);
+        }
+        if self.is_infinity() {
+            return self;
+        }
+        let (exp, mant) = self.unpack();
+        let (mut radicand, mut exp) = (mant as u128, exp - 63);
+        if exp.rem_euclid(2) != 0 {
+            radicand <<= 1;
+            exp -= 1;
+        }
+        let (mut root, mut rest) = (0u128, 0u128);
+        for i in (0..68).rev() {
+            let pair = if i >= 35 { (radicand >> (2 * (i - 35))) & 0b11 } else { 0 };
+            rest = rest << 2 | pair;
+            let trial = root << 2 | 1;
+            root <<= 1;
+            if rest >= trial {
+                rest -= trial;
+                root |= 1;
+            }
+        }
+        Float::round(false, exp / 2 + 91, root << 1 | (rest != 0) as u128, ctx)
+    }
+
+    //FINT and FINTRZ, the result is an integer in floating point
+    pub fn int(self, rounding: Rounding, ctx: &mut Context) -> Float {
+        if let Some(nan) = _nan(ctx, None, self) {
+            return nan;
+        }
+        if self.is_infinity() || self.is_zero() {
+            return self;
+        }
+        let (exp, mant) = self.unpack();
+        if exp >= 63 {
+            return self.round_value(ctx);
+        }
+        let (kept, inexact) = _round_off(mant as u128, (63 - exp) as u32, self.sign, rounding);
+        if inexact {
+            ctx.exceptions |= INEX2;
+        }
+        Float::round(self.sign, 127, kept, ctx)
+    }
+
+    //Integer of `bits` bits, out of range values and NaNs giving the
+    //largest one of their sign
+    pub fn to_int(self, bits: u32, ctx: &mut Context) -> i64 {
+        let max = (1i64 << (bits - 1)) - 1;
+        let saturated = if self.sign { -max - 1 } else { max };
+        if self.is_nan() || self.is_infinity() {
+            ctx.exceptions |= if self.is_signaling() { SNAN | OPERR } else { OPERR };
+            return saturated;
+        }
+        if self.is_zero() {
+            return 0;
+        }
+        let (exp, mant) = self.unpack();
+        if exp >= 63 {
+            ctx.exceptions |= OPERR;
+            return saturated;
+        }
+        let (kept, inexact) = _round_off(mant as u128, (63 - exp) as u32, self.sign, ctx.rounding);
+        let val = if self.sign { -(kept as i128) } else { kept as i128 };
+        if val > max as i128 || val < -max as i128 - 1 {
+            ctx.exceptions |= OPERR;
+            return saturated;
+        }
+        if inexact {
+            ctx.exceptions |= INEX2;
+        }
+        val as i64
+    }
+
+    //Unbiased exponent as a number
+    pub fn get_exp(self, ctx: &mut Context) -> Float {
+        if let Some(nan) = _nan(ctx, None, self) {
+            return nan;
+        }
+        if self.is_infinity() {
+            return _operr(ctx);
+        }
+        if self.is_zero() {
+            return self;
+        }
+        Float::from_i64(self.unpack().0 as i64)
+    }
+
+    //Mantissa as a number between 1 and 2
+    pub fn get_man(self, ctx: &mut Context) -> Float {
+        if let Some(nan) = _nan(ctx, None, self) {
+            return nan;
+        }
+        if self.is_infinity() {
+            return _operr(ctx);
+        }
+        if self.is_zero() {
+            return self;
+        }
+        Float { sign: self.sign, exp: BIAS as u16, mant: self.unpack().1 }.round_value(ctx)
+    }
+
+    //Multiplies by 2 to the integer part of `src`
+    pub fn scale(self, src: Float, ctx: &mut Context) -> Float {
+        if let Some(nan) = _nan(ctx, Some(self), src) {
+            return nan;
+        }
+        if src.is_infinity() {
+            return _operr(ctx);
+        }
+        if self.is_infinity() || self.is_zero() {
+            return self;
+        }
+        let factor = if src.is_zero() {
+            0
+        }
+        else {
+            let (exp, mant) = src.unpack();
+            let factor = if exp < 0 { 0 } else if exp > 16 { 1 << 17 } else { (mant >> (63 - exp)) as i32 };
+            if src.sign { -factor } else { factor }
+        };
+        let (exp, mant) = self.unpack();
+        Float::round(self.sign, exp + factor, (mant as u128) << 64, ctx)
+    }
+
+    //FMOD with the quotient truncated or FREM with it rounded to nearest
+    //even. Also gives the quotient byte of FPSR, its sign and low 7 bits.
+    pub fn remainder(self, src: Float, nearest: bool, ctx: &mut Context) -> (Float, u8) {
+        let sign = ((self.sign != src.sign) as u8) << 7;
+        if let Some(nan) = _nan(ctx, Some(self), src) {
+            return (nan, 0);
+        }
+        if self.is_infinity() || src.is_zero() {
+            return (_operr(ctx), 0);
+        }
+        if src.is_infinity() || self.is_zero() {
+            return (self.round_value(ctx), sign);
+        }
+        let ((a_exp, a), (b_exp, b)) = (self.unpack(), src.unpack());
+        let diff = a_exp - b_exp;
+        if diff < -1 {
+            return (self.round_value(ctx), sign);
+        }
+        //Long division of a * 2^diff by b, one quotient bit at a time
+        let (mut rest, divisor, exp, mut quotient) = if diff == -1 {
+            (a as u128, (b as u128) << 1, a_exp, 0)
+        }
+        else {
+            let (mut rest, divisor, mut quotient) = (a as u128, b as u128, 0u32);
+            for i in 0..=diff {
+                if i > 0 {
+                    rest <<= 1;
+                    quotient = (quotient << 1) & 0xff;
+                }
+                if rest >= divisor {
+                    rest -= divisor;
+                    quotient |= 1;
+                }
+            }
+            (rest, divisor, b_exp, quotient)
+        };
+        let mut negative = self.sign;
+        if nearest && (rest << 1 > divisor || (rest << 1 == divisor && quotient & 1 != 0)) {
+            rest = divisor - rest;
+            quotient += 1;
+            negative = !negative;
+        }
+        let result = if rest == 0 { Float::zero(self.sign) } else { Float::round(negative, exp + 64, rest, ctx) };
+        (result, sign | (quotient & 0x7f) as u8)
+    }
+
+    //None when either is a NaN, zeros are equal whatever their sign
+    pub fn compare(self, src: Float) -> Option<Ordering> {
+        if self.is_nan() || src.is_nan() {
+            return None;
+        }
+        let magnitude = |x: &Float| {
+            if x.is_zero() { (i32::MIN, 0) } else if x.is_infinity() { (i32::MAX, 0) } else { x.unpack() }
+        };
+        let negative = |x: &Float| x.sign && !x.is_zero();
+        Some(match (negative(&self), negative(&src)) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => magnitude(&self).cmp(&magnitude(&src)),
+            (true, true) => magnitude(&src).cmp(&magnitude(&self)),
+        })
+    }
+
+    pub fn from_i64(val: i64) -> Float {
+        if val == 0 {
+            return Float::zero(false);
+        }
+        let abs = val.unsigned_abs();
+        let shift = abs.leading_zeros();
+        Float { sign: val < 0, exp: (63 - shift as i32 + BIAS) as u16, mant: abs << shift }
+    }
+
+    pub fn from_single(bits: u32) -> Float {
+        _from_ieee(bits >> 31 != 0, (bits >> 23) & 0xff, (bits & 0x7fffff) as u64, 8, 23)
+    }
+
+    pub fn from_double(bits: u64) -> Float {
+        _from_ieee(bits >> 63 != 0, ((bits >> 52) & 0x7ff) as u32, bits & ((1 << 52) - 1), 11, 52)
+    }
+
+    //Single or double of precision `prec`, `exponent` bits wide
+    fn to_ieee(self, prec: Precision, exponent: u32, ctx: &mut Context) -> u64 {
+        let fraction = prec.bits - 1;
+        let all_ones = (1u64 << exponent) - 1;
+        let sign = (self.sign as u64) << (fraction + exponent);
+        if self.is_nan() {
+            if self.is_signaling() {
+                ctx.exceptions |= SNAN;
+            }
+            return sign | all_ones << fraction | ((self.mant | QUIET) << 1) >> (64 - fraction);
+        }
+        if self.is_infinity() {
+            return sign | all_ones << fraction;
+        }
+        if self.is_zero() {
+            return sign;
+        }
+        let (exp, mant) = self.unpack();
+        match _round(self.sign, exp, (mant as u128) << 64, prec, ctx) {
+            Some((_, 0)) => sign,
+            Some((exp, mant)) => {
+                let biased = if mant >> 63 != 0 { (exp + prec.emax) as u64 } else { 0 };
+                sign | biased << fraction | (mant << 1) >> (64 - fraction)
+            },
+            None if _overflows_to_infinity(self.sign, ctx.rounding) => sign | all_ones << fraction,
+            None => sign | (all_ones - 1) << fraction | ((1 << fraction) - 1),
+        }
+    }
+
+    pub fn to_single(self, ctx: &mut Context) -> u32 {
+        self.to_ieee(Precision::SINGLE, 8, ctx) as u32
+    }
+
+    pub fn to_double(self, ctx: &mut Context) -> u64 {
+        self.to_ieee(Precision::DOUBLE, 11, ctx)
+    }
+
+    //Extended precision in memory, the exponent word followed by a zero one
+    pub fn from_bits(words: [u32; 3]) -> Float {
+        Float {
+            sign: words[0] >> 31 != 0,
+            exp: ((words[0] >> 16) & 0x7fff) as u16,
+            mant: (words[1] as u64) << 32 | words[2] as u64,
+        }
+    }
+
+    pub fn to_bits(self) -> [u32; 3] {
+        [(self.sign as u32) << 31 | (self.exp as u32) << 16, (self.mant >> 32) as u32, self.mant as u32]
+    }
+
+    pub fn from_f64(val: f64) -> Float {
+        Float::from_double(val.to_bits())
+    }
+
+    pub fn to_f64(self) -> f64 {
+        f64::from_bits(self.to_double(&mut Context::new(Rounding::NEAREST, Precision::EXTENDED)))
+    }
+
+    //Unbiased exponent and mantissa between 1 and 2 of a finite nonzero
+    //number, for functions computed on f64 that would overflow it
+    pub fn split(self) -> (i32, f64) {
+        let (exp, mant) = self.unpack();
+        (exp, Float { sign: false, exp: BIAS as u16, mant }.to_f64())
+    }
+
+    //2^t rounded as `ctx` says, over the whole extended range
+    pub fn exp2(t: f64, ctx: &mut Context) -> Float {
+        if t.is_nan() {
+            return Float::NAN;
+        }
+        if t.is_infinite() {
+            return if t > 0.0 { Float::infinity(false) } else { Float::zero(false) };
+        }
+        let int = t.floor().clamp(-20000.0, 20000.0);
+        let (exp, mant) = Float::from_f64((t - int).exp2()).unpack();
+        Float::round(false, exp + int as i32, (mant as u128) << 64, ctx)
+    }
+
+    //10^n, exact up to 10^27 and a few units in the last place off above,
+    //where it comes from squaring
+    pub fn power_of_ten(n: u32) -> Float {
+        if n <= 27 {
+            let (exp, mant) = Float::from_i64(5i64.pow(n.min(27))).unpack();
+            let mut ctx = Context::new(Rounding::NEAREST, Precision::EXTENDED);
+            return Float::round(false, exp + n as i32 + 64, mant as u128, &mut ctx);
+        }
+        let mut ctx = Context::new(Rounding::NEAREST, Precision::EXTENDED);
+        let half = Float::power_of_ten(n / 2);
+        let square = half.mul(half, &mut ctx);
+        if n.is_multiple_of(2) { square } else { square.mul(Float::from_i64(10), &mut ctx) }
+    }
+
+    //Packed decimal: the signs, a 3 digit exponent of ten, the integer
+    //digit then 16 fraction ones
+    pub fn from_packed(words: [u32; 3], ctx: &mut Context) -> Float {
+        let sign = words[0] >> 31 != 0;
+        if (words[0] >> 16) & 0x7fff == 0x7fff {
+            let mant = (words[1] as u64) << 32 | words[2] as u64;
+            return if mant == 0 { Float::infinity(sign) } else { Float { sign, exp: EXP_MAX, mant: mant | 1 << 63 } };
+        }
+        let digit = |nibbles: u64, i: u32| (nibbles >> (4 * i)) & 0xf;
+        let fraction = (words[1] as u64) << 32 | words[2] as u64;
+        let digits = (0..16).rev().fold((words[0] & 0xf) as u64, |val, i| val * 10 + digit(fraction, i).min(9));
+        let exponent = (0..3).rev().fold(0, |val, i| val * 10 + digit((words[0] >> 16) as u64, i).min(9) as i32);
+        let exponent = if words[0] & 0x40000000 != 0 { -exponent } else { exponent } - 16;
+        if digits == 0 {
+            return Float::zero(sign);
+        }
+        let mut exact = Context { exceptions: 0, ..*ctx };
+        let mantissa = Float::from_i64(if sign { -(digits as i64) } else { digits as i64 });
+        let power = Float::power_of_ten(exponent.unsigned_abs());
+        let result = if exponent >= 0 { mantissa.mul(power, &mut exact) } else { mantissa.div(power, &mut exact) };
+        //Conversions of the source report inexact results as INEX1
+        ctx.exceptions |= if exact.exceptions & INEX2 != 0 { exact.exceptions & !INEX2 | INEX1 } else { exact.exceptions };
+        result
+    }
+
+    //Decimal with `k` significant digits when positive, or -k digits
+    //after the point otherwise
+    pub fn to_packed(self, k: i32, ctx: &mut Context) -> [u32; 3] {
+        let sign = (self.sign as u32) << 31;
+        if self.is_nan() || self.is_infinity() {
+            return [sign | 0x7fff0000, (self.mant >> 32) as u32, self.mant as u32];
+        }
+        if self.is_zero() {
+            return [sign, 0, 0];
+        }
+        if k > 17 {
+            ctx.exceptions |= OPERR;
+        }
+        let val = self.abs().to_f64();
+        let magnitude: i32 = format!("{:e}", val).split('e').nth(1).and_then(|exp| exp.parse().ok()).unwrap_or(0);
+        let digits = if k > 0 { k.min(17) } else { (magnitude + 1 - k).clamp(1, 17) };
+        let text = format!("{:.*e}", (digits - 1) as usize, val);
+        let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+        let exponent: i32 = exponent.parse().unwrap_or(0);
+        if text.parse::<f64>() != Ok(val) {
+            ctx.exceptions |= INEX2;
+        }
+        let mut digits = mantissa.bytes().filter(u8::is_ascii_digit).map(|digit| (digit - b'0') as u64);
+        let integer = digits.next().unwrap_or(0) as u32;
+        let fraction = (0..16).fold(0, |val, _| val << 4 | digits.next().unwrap_or(0));
+        let exp_sign = ((exponent < 0) as u32) << 30;
+        [sign | exp_sign | (_bcd(exponent.unsigned_abs() as u64, 3) as u32) << 16 | integer, (fraction >> 32) as u32, fraction as u32]
+    }
+}
@@ -0,0 +1,681 @@
+use super::*;
+use super::addressing::Location;
+use super::exception::*;
+use super::float::*;
+use std::cmp::Ordering;
+
+// The 68881 and 68882 floating point coprocessors, seen through the
+// coprocessor interface of the 68020. FPU exceptions that are enabled in
+// FPCR are taken as soon as the instruction raising them is done, and not
+// at the next FPU instruction as the chips do.
+
+//FPSR condition codes
+const FPCC_N: u32 = 1 << 27;
+const FPCC_Z: u32 = 1 << 26;
+const FPCC_I: u32 = 1 << 25;
+const FPCC_NAN: u32 = 1 << 24;
+
+//Constant ROM of FMOVECR. Powers of ten are worked out, the rest are the
+//chip's values.
+fn _constant(offset: u16) -> Float {
+    let constant = |exp: u32, high: u32, low: u32| Float::from_bits([exp << 16, high, low]);
+    match offset {
+        0x00 => constant(0x4000, 0xc90fdaa2, 0x2168c235),
+        0x0b => constant(0x3ffd, 0x9a209a84, 0xfbcff798),
+        0x0c => constant(0x4000, 0xadf85458, 0xa2bb4a9a),
+        0x0d => constant(0x3fff, 0xb8aa3b29, 0x5c17f0bc),
+        0x0e => constant(0x3ffd, 0xde5bd8a9, 0x37287195),
+        0x30 => constant(0x3ffe, 0xb17217f7, 0xd1cf79ac),
+        0x31 => constant(0x4000, 0x935d8ddd, 0xaaa8ac17),
+        0x32 => Float::from_i64(1),
+        0x33..=0x3f => Float::power_of_ten(1 << (offset - 0x33)),
+        _ => Float::zero(false),
+    }
+}
+
+fn _condition_codes(val: &Float) -> u32 {
+    if val.is_nan() {
+        return FPCC_NAN | if val.sign { FPCC_N } else { 0 };
+    }
+    (if val.sign { FPCC_N } else { 0 })
+        | if val.is_zero() { FPCC_Z } else { 0 }
+        | if val.is_infinity() { FPCC_I } else { 0 }
+}
+
+//Functions done on f64, over the whole extended range for the exponential
+//and logarithmic ones. Results are always taken as inexact.
+fn _approximate(op: FpOp, val: Float, ctx: &mut Context) -> Float {
+    if val.is_nan() {
+        return val.round_value(ctx);
+    }
+    let x = val.to_f64();
+    let log2 = |ctx: &mut Context| {
+        if val.is_zero() {
+            ctx.exceptions |= DZ;
+            return Err(Float::infinity(true));
+        }
+        if val.sign {
+            ctx.exceptions |= OPERR;
+            return Err(Float::NAN);
+        }
+        if val.is_infinity() {
+            return Err(val);
+        }
+        let (exp, mant) = val.split();
+        Ok(exp as f64 + mant.log2())
+    };
+    let y = match op {
+        FpOp::ETOX => return Float::exp2(x * std::f64::consts::LOG2_E, ctx),
+        FpOp::TWOTOX => return Float::exp2(x, ctx),
+        FpOp::TENTOX => return Float::exp2(x * std::f64::consts::LOG2_10, ctx),
+        FpOp::ETOXM1 if x > 1.0 => {
+            let exp = Float::exp2(x * std::f64::consts::LOG2_E, ctx);
+            return exp.sub(Float::from_i64(1), ctx);
+        },
+        FpOp::COSH | FpOp::SINH if x.abs() > 700.0 => {
+            let half = Float::exp2(x.abs() * std::f64::consts::LOG2_E - 1.0, ctx);
+            return if op == FpOp::SINH && x < 0.0 { half.negate() } else { half };
+        },
+        FpOp::LOGN | FpOp::LOG10 | FpOp::LOG2 => match log2(ctx) {
+            Ok(log2) => log2 / match op {
+                FpOp::LOGN => std::f64::consts::LOG2_E,
+                FpOp::LOG10 => std::f64::consts::LOG2_10,
+                _ => 1.0,
+            },
+            Err(result) => return result,
+        },
+        FpOp::SIN => x.sin(),
+        FpOp::COS => x.cos(),
+        FpOp::TAN => x.tan(),
+        FpOp::ASIN => x.asin(),
+        FpOp::ACOS => x.acos(),
+        FpOp::ATAN => x.atan(),
+        FpOp::SINH => x.sinh(),
+        FpOp::COSH => x.cosh(),
+        FpOp::TANH => x.tanh(),
+        FpOp::ATANH => x.atanh(),
+        FpOp::ETOXM1 => x.exp_m1(),
+        FpOp::LOGNP1 => x.ln_1p(),
+        other => panic!("{:?} isn't approximated", other),
+    };
+    if y.is_nan() {
+        ctx.exceptions |= OPERR;
+        return Float::NAN;
+    }
+    if y.is_infinite() && x.is_finite() {
+        ctx.exceptions |= DZ;
+    }
+    let result = Float::from_f64(y).round_value(ctx);
+    if !result.is_zero() && !result.is_infinity() && !val.is_zero() {
+        ctx.exceptions |= INEX2;
+    }
+    result
+}
+
+//Result of an operation on the destination and source, None for the
+//comparisons only setting condition codes. FMOD and FREM also give a
+//quotient byte.
+fn _operate(op: FpOp, dst: Float, src: Float, ctx: &mut Context) -> (Option<Float>, Option<u8>) {
+    //FSGLMUL and FSGLDIV truncate their operands to single precision
+    let single = |val: Float| Float { mant: val.mant & u64::MAX << 40, ..val };
+    let result = match op {
+        FpOp::MOVE => src.round_value(ctx),
+        FpOp::INT => src.int(ctx.rounding, ctx),
+        FpOp::INTRZ => src.int(Rounding::ZERO, ctx),
+        FpOp::SQRT => src.sqrt(ctx),
+        FpOp::ABS => src.abs().round_value(ctx),
+        FpOp::NEG => src.negate().round_value(ctx),
+        FpOp::GETEXP => src.get_exp(ctx),
+        FpOp::GETMAN => src.get_man(ctx),
+        FpOp::ADD => dst.add(src, ctx),
+        FpOp::SUB => dst.sub(src, ctx),
+        FpOp::MUL => dst.mul(src, ctx),
+        FpOp::DIV => dst.div(src, ctx),
+        FpOp::SGLMUL | FpOp::SGLDIV => {
+            let mut sgl = Context { precision: Precision::mantissa(24), ..*ctx };
+            let result = if op == FpOp::SGLMUL { single(dst).mul(single(src), &mut sgl) } else { single(dst).div(single(src), &mut sgl) };
+            ctx.exceptions = sgl.exceptions;
+            result
+        },
+        FpOp::MOD | FpOp::REM => {
+            let (result, quotient) = dst.remainder(src, op == FpOp::REM, ctx);
+            return (Some(result), Some(quotient));
+        },
+        FpOp::SCALE => dst.scale(src, ctx),
+        FpOp::CMP | FpOp::TST | FpOp::SINCOS => return (None, None),
+        op => _approximate(op, src, ctx),
+    };
+    (Some(result), None)
+}
+
+//State of the FPU, reset to a null state with every register a NaN
+#[derive(Debug, Clone)]
+pub(super) struct Fpu {
+    model: FpuModel,
+    regs: [Float; 8],
+    fpcr: u32,
+    fpsr: u32,
+    fpiar: u32,
+    //FSAVE gives a null frame until the FPU gets used
+    idle: bool,
+}
+
+impl Fpu {
+    fn new(model: FpuModel) -> Fpu {
+        Fpu { model, regs: [Float::NAN; 8], fpcr: 0, fpsr: 0, fpiar: 0, idle: false }
+    }
+
+    fn context(&self) -> Context {
+        let rounding = [Rounding::NEAREST, Rounding::ZERO, Rounding::MINUS, Rounding::PLUS][(self.fpcr >> 4) as usize & 0b11];
+        let precision = match (self.fpcr >> 6) & 0b11 {
+            1 => Precision::mantissa(24),
+            2 => Precision::mantissa(53),
+            _ => Precision::EXTENDED,
+        };
+        Context::new(rounding, precision)
+    }
+
+    //Sizes of the idle frame FSAVE writes after its header
+    fn frame_size(&self) -> u32 {
+        match self.model {
+            FpuModel::MC68881 => 0x18,
+            FpuModel::MC68882 => 0x38,
+        }
+    }
+}
+
+impl<B: Bus> super::CPU<B> {
+
+    //No FPU by default, the 68020 is the only model talking to one
+    pub fn set_fpu(&mut self, model: Option<FpuModel>) {
+        self.fpu = model.map(Fpu::new);
+    }
+
+    pub fn fpu_model(&self) -> Option<FpuModel> {
+        self.fpu.as_ref().map(|fpu| fpu.model)
+    }
+
+    pub fn fp_register(&self, reg: usize) -> Option<f64> {
+        self.fpu.as_ref().map(|fpu| fpu.regs[reg].to_f64())
+    }
+
+    pub fn set_fp_register(&mut self, reg: usize, val: f64) {
+        if let Some(fpu) = &mut self.fpu {
+            fpu.regs[reg] = Float::from_f64(val);
+        }
+    }
+
+    //FPCR, FPSR and FPIAR, all 0 without an FPU
+    pub(super) fn fp_control(&self, reg: Register) -> u32 {
+        match (&self.fpu, reg) {
+            (Some(fpu), Register::FPCR) => fpu.fpcr,
+            (Some(fpu), Register::FPSR) => fpu.fpsr,
+            (Some(fpu), Register::FPIAR) => fpu.fpiar,
+            _ => 0,
+        }
+    }
+
+    pub(super) fn set_fp_control(&mut self, reg: Register, val: u32) {
+        match (&mut self.fpu, reg) {
+            (Some(fpu), Register::FPCR) => fpu.fpcr = val & 0xfff0,
+            (Some(fpu), Register::FPSR) => fpu.fpsr = val & 0x0ffffff8,
+            (Some(fpu), Register::FPIAR) => fpu.fpiar = val,
+            _ => {},
+        }
+    }
+
+    fn fpu(&mut self) -> &mut Fpu {
+        let fpu = self.fpu.as_mut().expect("no FPU");
+        fpu.idle = true;
+        fpu
+    }
+
+    //Takes the line F exception when there is no FPU to run an instruction
+    fn no_fpu(&mut self) -> Option<Result<(), BusFault>> {
+        if self.fpu.is_some() { None } else { Some(self.raise_fault(VECTOR_LINE_F)) }
+    }
+
+    //Records the exceptions of an operation in FPSR and takes the highest
+    //priority one FPCR enables
+    fn fp_exceptions(&mut self, exceptions: u32) {
+        let fpu = self.fpu();
+        let accrued = if exceptions & (SNAN | OPERR) != 0 { 1 << 7 } else { 0 }
+            | if exceptions & OVFL != 0 { 1 << 6 } else { 0 }
+            | if exceptions & UNFL != 0 && exceptions & INEX2 != 0 { 1 << 5 } else { 0 }
+            | if exceptions & DZ != 0 { 1 << 4 } else { 0 }
+            | if exceptions & (INEX1 | INEX2 | OVFL) != 0 { 1 << 3 } else { 0 };
+        fpu.fpsr = (fpu.fpsr & !0xff00) | exceptions | accrued;
+        let enabled = exceptions & fpu.fpcr & 0xff00;
+        let vector = [(BSUN, VECTOR_FP_BSUN), (SNAN, VECTOR_FP_SNAN), (OPERR, VECTOR_FP_OPERR), (OVFL, VECTOR_FP_OVFL),
+            (UNFL, VECTOR_FP_UNFL), (DZ, VECTOR_FP_DZ), (INEX2 | INEX1, VECTOR_FP_INEX)]
+            .iter().find(|(bits, _)| enabled & bits != 0).map(|&(_, vector)| vector);
+        if let Some(vector) = vector {
+            self.raise_exception(vector);
+        }
+    }
+
+    fn set_fp_condition(&mut self, codes: u32) {
+        let fpu = self.fpu();
+        fpu.fpsr = (fpu.fpsr & 0x00ffffff) | codes;
+    }
+
+    //Tests a predicate on the condition codes, None when it raised BSUN
+    //and that got trapped
+    fn fp_test(&mut self, cond: FpCondition) -> Option<bool> {
+        let fpsr = self.fpu().fpsr;
+        let (nan, z, n) = (fpsr & FPCC_NAN != 0, fpsr & FPCC_Z != 0, fpsr & FPCC_N != 0);
+        if nan && cond as u8 >= 16 {
+            let fpu = self.fpu();
+            fpu.fpsr |= BSUN | 1 << 7;
+            if fpu.fpcr & BSUN != 0 {
+                let _ = self.raise_fault(VECTOR_FP_BSUN);
+                return None;
+            }
+        }
+        Some(match cond as u8 & 0xf {
+            0x0 => false,
+            0x1 => z,
+            0x2 => !(nan || z || n),
+            0x3 => z || !(nan || n),
+            0x4 => n && !(nan || z),
+            0x5 => z || (n && !nan),
+            0x6 => !(nan || z),
+            0x7 => !nan,
+            0x8 => nan,
+            0x9 => nan || z,
+            0xa => nan || !(n || z),
+            0xb => nan || z || !n,
+            0xc => nan || (n && !z),
+            0xd => nan || z || n,
+            0xe => !z,
+            _ => true,
+        })
+    }
+
+    //Where `bytes` bytes of operand are, (An)+ and -(An) stepping over all
+    //of them
+    fn fp_location(&mut self, data: &DataContainer, bytes: u32) -> Result<Location, BusFault> {
+        let bytes = if bytes == 1 && matches!(data, POSTINCREMENT(7) | PREDECREMENT(7)) { 2 } else { bytes };
+        Ok(match *data {
+            POSTINCREMENT(reg) => {
+                let addr = self.address_register[reg];
+                self.address_register[reg] = addr.wrapping_add(bytes);
+                Location::MEMORY(addr)
+            },
+            PREDECREMENT(reg) => {
+                let addr = self.address_register[reg].wrapping_sub(bytes);
+                self.address_register[reg] = addr;
+                Location::MEMORY(addr)
+            },
+            ref data => self.locate(data, &LONG)?,
+        })
+    }
+
+    //Operand as it is stored, laid out as FP_IMMEDIATE
+    fn read_fp_raw(&mut self, data: &DataContainer, bytes: u32) -> Result<[u32; 3], BusFault> {
+        if let FP_IMMEDIATE(words) = data {
+            return Ok(*words);
+        }
+        let (fc, addr) = match self.fp_location(data, bytes)? {
+            Location::MEMORY(addr) => (self.data_fc(), addr),
+            Location::PROGRAM(addr) => (self.program_fc(), addr),
+            loc => return Ok([self.read_location(&loc, &LONG)?, 0, 0]),
+        };
+        let mut words = [0; 3];
+        match bytes {
+            1 => words[0] = self.read_byte(fc, addr)? as u32,
+            2 => words[0] = self.read_word(fc, addr)? as u32,
+            _ => {
+                for (i, word) in words.iter_mut().take(bytes as usize / 4).enumerate() {
+                    *word = self.read_long(fc, addr.wrapping_add(4 * i as u32))?;
+                }
+            },
+        }
+        Ok(words)
+    }
+
+    fn write_fp_raw(&mut self, data: &DataContainer, bytes: u32, words: [u32; 3]) -> Result<(), BusFault> {
+        let addr = match self.fp_location(data, bytes)? {
+            Location::MEMORY(addr) => addr,
+            loc => {
+                let size = match bytes { 1 => BYTE, 2 => WORD, _ => LONG };
+                return self.write_location(&loc, &size, words[0]);
+            },
+        };
+        let fc = self.data_fc();
+        match bytes {
+            1 => self.write_byte(fc, addr, words[0] as u8),
+            2 => self.write_word(fc, addr, words[0] as u16),
+            _ => {
+                for (i, &word) in words.iter().take(bytes as usize / 4).enumerate() {
+                    self.write_long(fc, addr.wrapping_add(4 * i as u32), word)?;
+                }
+                Ok(())
+            },
+        }
+    }
+
+    //Source operand converted to extended precision
+    fn read_fp_operand(&mut self, data: &DataContainer, format: FpFormat, ctx: &mut Context) -> Result<Float, BusFault> {
+        if let FP_REGISTER(reg) = *data {
+            return Ok(self.fpu().regs[reg]);
+        }
+        let raw = self.read_fp_raw(data, format.bytes())?;
+        Ok(match format {
+            FpFormat::BYTE => Float::from_i64(raw[0] as i8 as i64),
+            FpFormat::WORD => Float::from_i64(raw[0] as i16 as i64),
+            FpFormat::LONG => Float::from_i64(raw[0] as i32 as i64),
+            FpFormat::SINGLE => Float::from_single(raw[0]),
+            FpFormat::DOUBLE => Float::from_double((raw[0] as u64) << 32 | raw[1] as u64),
+            FpFormat::EXTENDED => Float::from_bits(raw),
+            FpFormat::PACKED(_) => Float::from_packed(raw, ctx),
+        })
+    }
+
+    //FMOVE to memory or a data register, rounded with the FPCR mode
+    fn write_fp_operand(&mut self, data: &DataContainer, format: FpFormat, val: Float, ctx: &mut Context) -> Result<(), BusFault> {
+        let raw = match format {
+            FpFormat::BYTE => [val.to_int(8, ctx) as u32, 0, 0],
+            FpFormat::WORD => [val.to_int(16, ctx) as u32, 0, 0],
+            FpFormat::LONG => [val.to_int(32, ctx) as u32, 0, 0],
+            FpFormat::SINGLE => [val.to_single(ctx), 0, 0],
+            FpFormat::DOUBLE => {
+                let bits = val.to_double(ctx);
+                [(bits >> 32) as u32, bits as u32, 0]
+            },
+            FpFormat::EXTENDED => val.to_bits(),
+            FpFormat::PACKED(k) => {
+                let k = match k {
+                    FieldParam::IMMEDIATE(k) => k,
+                    FieldParam::REGISTER(reg) => self.data_register[reg] as u8,
+                };
+                //Signed 7 bit k-factor
+                val.to_packed(((k << 1) as i8 >> 1) as i32, ctx)
+            },
+        };
+        self.write_fp_raw(data, format.bytes(), raw)
+    }
+
+    //FMOVE, FMOVECR, FMOVEM and the arithmetic instructions, all told
+    //apart by their command word
+    pub fn perform_fgen(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if let Some(result) = self.no_fpu() {
+            return result;
+        }
+        let inst = self.resolve_register_word(inst)?;
+        match *inst.get_op() {
+            FOP(op, format) => self.fp_operation(op, format, inst.get_lhs(), inst.get_trg()),
+            FMOVECR => {
+                let (offset, reg) = match (*inst.get_lhs(), *inst.get_trg()) {
+                    (IMEDIATE_VALUE(offset), FP_REGISTER(reg)) => (offset as u16, reg),
+                    other => panic!("{:?} are no FMOVECR operands", other),
+                };
+                self.cycles += 29;
+                let mut ctx = self.fpu().context();
+                let val = _constant(offset).round_value(&mut ctx);
+                self.fpu().regs[reg] = val;
+                self.set_fp_condition(_condition_codes(&val));
+                self.fp_exceptions(ctx.exceptions);
+                Ok(())
+            },
+            FMOVEM => self.fp_move_multiple(inst.get_lhs(), inst.get_trg()),
+            _ => self.perform_illegal(&inst),
+        }
+    }
+
+    fn fp_operation(&mut self, op: FpOp, format: FpFormat, lhs: &DataContainer, trg: &DataContainer) -> Result<(), BusFault> {
+        let inst_pc = self.inst_pc;
+        self.cycles += timing::fp_cycles(op) as u64;
+        let mut ctx = self.fpu().context();
+        self.fpu().fpiar = inst_pc;
+        let dst = match *trg {
+            FP_REGISTER(reg) | FP_REGISTER_PAIR(_, reg) => reg,
+            _ => {
+                //FMOVE out, condition codes are left alone
+                let val = match *lhs {
+                    FP_REGISTER(reg) => self.fpu().regs[reg],
+                    other => panic!("{:?} can't be moved out", other),
+                };
+                self.write_fp_operand(trg, format, val, &mut ctx)?;
+                self.fp_exceptions(ctx.exceptions);
+                return Ok(());
+            },
+        };
+        let src = self.read_fp_operand(lhs, format, &mut ctx)?;
+        let val = self.fpu().regs[dst];
+        let codes = match (op, _operate(op, val, src, &mut ctx)) {
+            (_, (Some(result), quotient)) => {
+                let fpu = self.fpu();
+                fpu.regs[dst] = result;
+                if let Some(quotient) = quotient {
+                    fpu.fpsr = (fpu.fpsr & !0x00ff0000) | (quotient as u32) << 16;
+                }
+                _condition_codes(&result)
+            },
+            (FpOp::CMP, _) => {
+                let mut nan = ctx;
+                let _ = val.sub(src, &mut nan);
+                ctx.exceptions |= nan.exceptions & SNAN;
+                let negative = if val.is_infinity() && src.is_infinity() { val.sign } else { false };
+                match val.compare(src) {
+                    None => FPCC_NAN,
+                    Some(Ordering::Less) => FPCC_N,
+                    Some(Ordering::Equal) => FPCC_Z | if negative { FPCC_N } else { 0 },
+                    Some(Ordering::Greater) => 0,
+                }
+            },
+            (FpOp::TST, _) => {
+                if src.is_signaling() {
+                    ctx.exceptions |= SNAN;
+                }
+                _condition_codes(&src)
+            },
+            //Sine to FPs and cosine to FPc
+            _ => {
+                let sin = _approximate(FpOp::SIN, src, &mut ctx);
+                let cos = _approximate(FpOp::COS, src, &mut ctx);
+                if let FP_REGISTER_PAIR(c, s) = *trg {
+                    self.fpu().regs[c] = cos;
+                    self.fpu().regs[s] = sin;
+                }
+                _condition_codes(&sin)
+            },
+        };
+        self.set_fp_condition(codes);
+        self.fp_exceptions(ctx.exceptions);
+        Ok(())
+    }
+
+    //Control registers go in FPCR, FPSR, FPIAR order and data registers
+    //from FP0 up whatever the addressing mode
+    fn fp_move_multiple(&mut self, lhs: &DataContainer, trg: &DataContainer) -> Result<(), BusFault> {
+        let (data, to_memory) = match (lhs, trg) {
+            (FP_CONTROL(_) | FP_REGISTER_LIST(_) | FP_DYNAMIC_LIST(..), _) => (trg, true),
+            _ => (lhs, false),
+        };
+        let registers = if to_memory { *lhs } else { *trg };
+        let mask = match registers {
+            FP_REGISTER_LIST(mask) => mask,
+            FP_DYNAMIC_LIST(reg, reversed) => {
+                let mask = self.data_register[reg] as u8;
+                if reversed { mask.reverse_bits() } else { mask }
+            },
+            FP_CONTROL(select) => {
+                let regs: Vec<Register> = [(4, Register::FPCR), (2, Register::FPSR), (1, Register::FPIAR)].iter()
+                    .filter(|(bit, _)| select & bit != 0).map(|&(_, reg)| reg).collect();
+                return self.fp_move_control(&regs, data, to_memory);
+            },
+            other => panic!("{:?} are no FPU registers", other),
+        };
+        let count = mask.count_ones();
+        let addr = match self.fp_location(data, 12 * count)? {
+            Location::MEMORY(addr) | Location::PROGRAM(addr) => addr,
+            other => panic!("{:?} can't hold FPU registers", other),
+        };
+        let mut addr = addr;
+        for reg in (0..8).filter(|reg| mask & 1 << reg != 0) {
+            let loc = MEMORY_ADDR(addr);
+            if to_memory {
+                let words = self.fpu().regs[reg].to_bits();
+                self.write_fp_raw(&loc, 12, words)?;
+            }
+            else {
+                let words = self.read_fp_raw(&loc, 12)?;
+                self.fpu().regs[reg] = Float::from_bits(words);
+            }
+            addr = addr.wrapping_add(12);
+        }
+        self.cycles += 12 * count as u64;
+        Ok(())
+    }
+
+    fn fp_move_control(&mut self, regs: &[Register], data: &DataContainer, to_memory: bool) -> Result<(), BusFault> {
+        let bytes = 4 * regs.len() as u32;
+        if let DATA_REGISTER(_) | ADDRESS_REGISTER(_) | IMEDIATE_VALUE(_) = data {
+            return match (regs, to_memory) {
+                ([reg], true) => self.set_target(data, &LONG, self.fp_control(*reg)),
+                ([reg], false) => {
+                    let val = self.get_target(data, &LONG)?;
+                    self.set_fp_control(*reg, val);
+                    Ok(())
+                },
+                _ => self.raise_fault(VECTOR_LINE_F),
+            };
+        }
+        let words = if to_memory { [0; 3] } else { self.read_fp_raw(data, bytes)? };
+        if !to_memory {
+            for (&reg, &word) in regs.iter().zip(words.iter()) {
+                self.set_fp_control(reg, word);
+            }
+            return Ok(());
+        }
+        let mut words = [0; 3];
+        for (word, &reg) in words.iter_mut().zip(regs.iter()) {
+            *word = self.fp_control(reg);
+        }
+        self.write_fp_raw(data, bytes, words)
+    }
+
+    pub fn perform_fbcc(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if let Some(result) = self.no_fpu() {
+            return result;
+        }
+        let target = self.effective_address(inst.get_lhs())?;
+        if let FBCC(cond) = *inst.get_op() {
+            if self.fp_test(cond) == Some(true) {
+                self.set_pc(target);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn perform_fdbcc(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if let Some(result) = self.no_fpu() {
+            return result;
+        }
+        let inst = self.resolve_register_word(inst)?;
+        let cond = match *inst.get_op() {
+            FDBCC(cond) => cond,
+            _ => return self.perform_illegal(&inst),
+        };
+        let target = self.effective_address(inst.get_trg())?;
+        match self.fp_test(cond) {
+            Some(false) => {},
+            _ => return Ok(()),
+        }
+        let count = self.get_target(inst.get_lhs(), &WORD)?.wrapping_sub(1) & 0xffff;
+        self.set_target(inst.get_lhs(), &WORD, count)?;
+        if count != 0xffff {
+            self.set_pc(target);
+        }
+        Ok(())
+    }
+
+    pub fn perform_fscc(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if let Some(result) = self.no_fpu() {
+            return result;
+        }
+        let inst = self.resolve_register_word(inst)?;
+        let set = match *inst.get_op() {
+            FSCC(cond) => match self.fp_test(cond) {
+                Some(set) => set,
+                None => return Ok(()),
+            },
+            _ => return self.perform_illegal(&inst),
+        };
+        self.set_target(inst.get_lhs(), &BYTE, if set { 0xff } else { 0 })
+    }
+
+    pub fn perform_ftrapcc(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if let Some(result) = self.no_fpu() {
+            return result;
+        }
+        let inst = self.resolve_register_word(inst)?;
+        let cond = match *inst.get_op() {
+            FTRAPCC(cond) => cond,
+            _ => return self.perform_illegal(&inst),
+        };
+        if *inst.get_lhs() != EMPTY {
+            self.get_target(inst.get_lhs(), inst.get_size())?;
+        }
+        if self.fp_test(cond) == Some(true) {
+            self.raise_exception(VECTOR_TRAPV);
+            self.cycles += 30;
+        }
+        Ok(())
+    }
+
+    //FSAVE writes a null frame for an FPU that hasn't been used since its
+    //reset and an idle one otherwise, only the header means anything.
+    //FRESTORE of a null frame resets the FPU.
+    pub fn perform_fsave(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if let Some(result) = self.no_fpu() {
+            return result;
+        }
+        if !self.is_supervisor() {
+            return self.raise_fault(VECTOR_PRIVILEGE);
+        }
+        let fpu = self.fpu.as_ref().expect("no FPU");
+        let (idle, size, model) = (fpu.idle, fpu.frame_size(), fpu.model);
+        if *inst.get_op() == FSAVE {
+            let bytes = if idle { 4 + size } else { 4 };
+            let addr = match self.fp_location(inst.get_lhs(), bytes)? {
+                Location::MEMORY(addr) => addr,
+                other => panic!("{:?} can't hold a frame", other),
+            };
+            let fc = self.data_fc();
+            let header = if idle { 0x1f000000 | size << 16 } else { 0 };
+            self.write_long(fc, addr, header)?;
+            for offset in (4..bytes).step_by(4) {
+                self.write_long(fc, addr.wrapping_add(offset), 0)?;
+            }
+            return Ok(());
+        }
+        let (fc, addr) = match self.locate(&inst.get_lhs().clone(), &LONG)? {
+            Location::MEMORY(addr) => (self.data_fc(), addr),
+            Location::PROGRAM(addr) => (self.program_fc(), addr),
+            other => panic!("{:?} holds no frame", other),
+        };
+        let header = self.read_long(fc, addr)?;
+        let frame = match header >> 16 {
+            0 => 0,
+            version if version == 0x1f00 | size => size,
+            _ => {
+                if let POSTINCREMENT(reg) = *inst.get_lhs() {
+                    self.address_register[reg] = addr;
+                }
+                return self.raise_fault(VECTOR_FORMAT_ERROR);
+            },
+        };
+        if let POSTINCREMENT(reg) = *inst.get_lhs() {
+            self.address_register[reg] = addr.wrapping_add(4 + frame);
+        }
+        if frame == 0 {
+            self.fpu = Some(Fpu::new(model));
+        }
+        else {
+            self.fpu().idle = true;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use super::*;
+
+// The 256 byte instruction cache of the 68020.
+//
+// 64 long word entries indexed by bits 7-2 of the address and tagged with
+// the rest and FC2. Only instruction fetches go through it and nothing
+// writing memory clears it, CACR has to be used for that, so code patched
+// without flushing runs stale until its entries get replaced.
+
+const ENTRIES: usize = 64;
+
+//CACR bits, the clear ones read as 0
+const CACR_ENABLE: u32 = 1 << 0;
+const CACR_FREEZE: u32 = 1 << 1;
+const CACR_CLEAR_ENTRY: u32 = 1 << 2;
+const CACR_CLEAR: u32 = 1 << 3;
+
+#[derive(Debug, Clone)]
+pub(super) struct InstructionCache {
+    //Cached long words, big endian
+    data: Vec<u8>,
+    //Address bits 31-8 and FC2 in bit 0
+    tags: [u32; ENTRIES],
+    valid: u64,
+    cacr: u32,
+    caar: u32,
+    stats: CacheStats,
+}
+
+impl Default for InstructionCache {
+    fn default() -> InstructionCache {
+        InstructionCache {
+            data: vec![0; ENTRIES * 4],
+            tags: [0; ENTRIES],
+            valid: 0,
+            cacr: 0,
+            caar: 0,
+            stats: CacheStats::default(),
+        }
+    }
+}
+
+fn _entry(addr: u32) -> usize {
+    ((addr >> 2) as usize) % ENTRIES
+}
+
+fn _tag(addr: u32, supervisor: bool) -> u32 {
+    (addr & !0xff) | supervisor as u32
+}
+
+impl InstructionCache {
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.cacr & CACR_ENABLE != 0
+    }
+
+    fn lookup(&self, addr: u32, supervisor: bool) -> Option<u16> {
+        let entry = _entry(addr);
+        if self.valid & (1 << entry) == 0 || self.tags[entry] != _tag(addr, supervisor) {
+            return None;
+        }
+        let offset = entry * 4 + (addr & 2) as usize;
+        Some(u16::from_be_bytes([self.data[offset], self.data[offset + 1]]))
+    }
+
+    //Frozen caches keep what they have
+    fn fill(&mut self, addr: u32, supervisor: bool, long: u32) {
+        if self.cacr & CACR_FREEZE != 0 {
+            return;
+        }
+        let entry = _entry(addr);
+        self.data[entry * 4..entry * 4 + 4].copy_from_slice(&long.to_be_bytes());
+        self.tags[entry] = _tag(addr, supervisor);
+        self.valid |= 1 << entry;
+    }
+}
+
+impl<B: Bus> super::CPU<B> {
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats
+    }
+
+    pub fn reset_cache_stats(&mut self) {
+        self.cache.stats = CacheStats::default();
+    }
+
+    pub(super) fn cacr(&self) -> u32 {
+        self.cache.cacr
+    }
+
+    pub(super) fn caar(&self) -> u32 {
+        self.cache.caar
+    }
+
+    pub(super) fn set_caar(&mut self, val: u32) {
+        self.cache.caar = val;
+    }
+
+    //Clearing an entry uses the index in CAAR
+    pub(super) fn set_cacr(&mut self, val: u32) {
+        if val & CACR_CLEAR != 0 {
+            self.cache.valid = 0;
+        }
+        if val & CACR_CLEAR_ENTRY != 0 {
+            self.cache.valid &= !(1 << _entry(self.cache.caar));
+        }
+        self.cache.cacr = val & (CACR_ENABLE | CACR_FREEZE);
+    }
+
+    pub(super) fn icache_enabled(&self) -> bool {
+        self.model == CpuModel::MC68020 && self.cache.is_enabled()
+    }
+
+    //Hits take 2 cycles instead of the 4 the timing tables count for a
+    //fetch. Misses read the whole long word.
+    pub(super) fn fetch_cached(&mut self, pc: u32) -> Result<u16, BusFault> {
+        let (fc, supervisor) = (self.program_fc(), self.is_supervisor());
+        if let Some(word) = self.cache.lookup(pc, supervisor) {
+            self.cache.stats.hits += 1;
+            self.cycles = self.cycles.saturating_sub(2);
+            return Ok(word);
+        }
+        self.cache.stats.misses += 1;
+        if pc & 1 != 0 {
+            return self.read_word(fc, pc);
+        }
+        let long = self.read_long(fc, pc & !3)?;
+        self.cache.fill(pc, supervisor, long);
+        Ok(if pc & 2 == 0 { (long >> 16) as u16 } else { long as u16 })
+    }
+}
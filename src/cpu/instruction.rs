@@ -1,11 +1,106 @@
-#[derive(Debug)]
+use OpSize::*;
+
+#[derive(Debug, PartialEq)]
 pub enum OpSize {
     BYTE,
     WORD,
     LONG
 }
 
-#[derive(Debug)]
+//Conditions of Bcc, DBcc and Scc in encoding order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    T, F, HI, LS, CC, CS, NE, EQ,
+    VC, VS, PL, MI, GE, LT, GT, LE,
+}
+
+//Conditional predicates of the FPU in encoding order. The second half
+//tests the same as the first one but sets BSUN on unordered operands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FpCondition {
+    F, EQ, OGT, OGE, OLT, OLE, OGL, OR, UN, UEQ, UGT, UGE, ULT, ULE, NE, T,
+    SF, SEQ, GT, GE, LT, LE, GL, GLE, NGLE, NGL, NLE, NLT, NGE, NGT, SNE, ST,
+}
+
+//Operations of the general FPU instructions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FpOp {
+    MOVE,
+    INT,
+    SINH,
+    INTRZ,
+    SQRT,
+    LOGNP1,
+    ETOXM1,
+    TANH,
+    ATAN,
+    ASIN,
+    ATANH,
+    SIN,
+    TAN,
+    ETOX,
+    TWOTOX,
+    TENTOX,
+    LOGN,
+    LOG10,
+    LOG2,
+    ABS,
+    COSH,
+    NEG,
+    ACOS,
+    COS,
+    GETEXP,
+    GETMAN,
+    DIV,
+    MOD,
+    ADD,
+    MUL,
+    SGLDIV,
+    REM,
+    SCALE,
+    SGLMUL,
+    SUB,
+    SINCOS,
+    CMP,
+    TST,
+}
+
+//Data formats of FPU operands in encoding order, with the k-factor
+//packed decimals are written with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FpFormat {
+    LONG,
+    SINGLE,
+    EXTENDED,
+    PACKED(FieldParam),
+    WORD,
+    DOUBLE,
+    BYTE,
+}
+
+//Registers of the 68030 MMU moved by PMOVE, the root pointers being 64
+//bits wide
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MmuRegister {
+    TC,
+    SRP,
+    CRP,
+    TT0,
+    TT1,
+    MMUSR,
+}
+
+//Function code operand of PFLUSH, PLOAD and PTEST
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FcSelect {
+    SFC,
+    DFC,
+    //Low 3 bits of a data register
+    REGISTER(usize),
+    IMMEDIATE(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Mnemonic {
     MOVE,
     MOVEA,
@@ -13,19 +108,258 @@ pub enum Mnemonic {
     ADDA,
     LEA,
     TST,
+    NOP,
+    RESET,
+    STOP,
+    ILLEGAL,
+    ABCD,
+    ADDI,
+    ADDQ,
+    ADDX,
+    AND,
+    ANDI,
+    ASL,
+    ASR,
+    BCC(Condition),
+    BCHG,
+    BCLR,
+    BRA,
+    BSET,
+    BSR,
+    BTST,
+    CHK,
+    CLR,
+    CMP,
+    CMPA,
+    CMPI,
+    CMPM,
+    DBCC(Condition),
+    DIVS,
+    DIVU,
+    EOR,
+    EORI,
+    EXG,
+    EXT,
+    JMP,
+    JSR,
+    LINK,
+    LSL,
+    LSR,
+    MOVEM,
+    MOVEP,
+    MOVEQ,
+    MULS,
+    MULU,
+    NBCD,
+    NEG,
+    NEGX,
+    NOT,
+    OR,
+    ORI,
+    PEA,
+    ROL,
+    ROR,
+    ROXL,
+    ROXR,
+    RTE,
+    RTR,
+    RTS,
+    SBCD,
+    SCC(Condition),
+    SUB,
+    SUBA,
+    SUBI,
+    SUBQ,
+    SUBX,
+    SWAP,
+    TAS,
+    TRAP,
+    TRAPV,
+    UNLK,
+    //68010 and later
+    MOVEC,
+    MOVES,
+    RTD,
+    //68020 and later
+    BFTST,
+    BFEXTU,
+    BFCHG,
+    BFEXTS,
+    BFCLR,
+    BFFFO,
+    BFSET,
+    BFINS,
+    //32 bit quotient and remainder from a 32 bit dividend
+    DIVUL,
+    DIVSL,
+    EXTB,
+    CAS,
+    CAS2,
+    CHK2,
+    CMP2,
+    PACK,
+    UNPK,
+    TRAPCC(Condition),
+    //68881 and 68882 FPU. FMOVE is FOP(MOVE) whichever way it goes, FMOVEM
+    //moves data and control registers.
+    FOP(FpOp, FpFormat),
+    FMOVECR,
+    FMOVEM,
+    FBCC(FpCondition),
+    FDBCC(FpCondition),
+    FSCC(FpCondition),
+    FTRAPCC(FpCondition),
+    FSAVE,
+    FRESTORE,
+    //68030 MMU, FD standing for the ATC not being flushed and R and W for
+    //the access tested or loaded
+    PMOVE,
+    PMOVEFD,
+    PFLUSHA,
+    PFLUSH,
+    PLOADR,
+    PLOADW,
+    PTESTR,
+    PTESTW,
+    //ColdFire ISA_B. MOV3Q stores a 3 bit immediate, MVS and MVZ load a
+    //byte or word extended and SATS saturates after an overflow.
+    MOV3Q,
+    MVS,
+    MVZ,
+    SATS,
+    //Unimplemented instruction traps
+    LINE_A,
+    LINE_F,
+}
+
+//Index register of the d8(An,Xn) modes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Index {
+    //0-7 for D0-D7, 8-15 for A0-A7
+    pub register: usize,
+    pub long: bool,
+    pub scale: u8,
+}
+
+//Memory indirection of the full extension word modes, the index being
+//added before or after the pointer is read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Indirect {
+    NONE,
+    PRE_INDEXED,
+    POST_INDEXED,
+}
+
+//Base of the full extension word modes, a suppressed base counts as 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Base {
+    ADDRESS(usize),
+    //Address of the extension word
+    PC(u32),
+    SUPPRESSED,
+    //Suppressed PC, still a program space reference
+    SUPPRESSED_PC,
 }
 
-#[derive(Debug)]
+//(bd,An,Xn), ([bd,An],Xn,od) and ([bd,An,Xn],od) of the 68020 and their
+//PC relative forms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Extended {
+    pub base: Base,
+    pub index: Option<Index>,
+    pub bd: i32,
+    pub od: i32,
+    pub indirect: Indirect,
+}
+
+//Offset or width of a bitfield, immediate or taken from a data register
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldParam {
+    IMMEDIATE(u8),
+    REGISTER(usize),
+}
+
+//{offset:width} of the BFxxx instructions, with the data register BFEXTU,
+//BFEXTS and BFFFO write and BFINS reads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bitfield {
+    pub register: usize,
+    pub offset: FieldParam,
+    pub width: FieldParam,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DataContainer {
     DATA_REGISTER(usize),
     ADDRESS_REGISTER(usize),
-    IMEDIATE_VALUE(Vec<u8>), //4 u8 big endian
-    MEMORY_ADDR(usize),
+    IMEDIATE_VALUE(u32),
+    //Absolute long address
+    MEMORY_ADDR(u32),
     SR,
     CCR,
     EMPTY,
+    USP,
+    //(An)
+    ADDRESS_INDIRECT(usize),
+    //(An)+
+    POSTINCREMENT(usize),
+    //-(An)
+    PREDECREMENT(usize),
+    //d16(An)
+    DISPLACEMENT(usize, i16),
+    //d8(An,Xn)
+    INDEXED(usize, Index, i8),
+    //Absolute short address, sign extended
+    SHORT_ADDR(i16),
+    //PC relative modes hold the address of their extension word
+    PC_DISPLACEMENT(u32, i16),
+    PC_INDEXED(u32, Index, i8),
+    //MOVEM registers, bit 0 is D0 and bit 15 is A7 whatever the mode
+    REGISTER_LIST(u16),
+    //Only found in the opcode table, completed from the extension words
+    //when the instruction is decoded or executed
+    EA(u8, u8),
+    BRANCH_SHORT(i8),
+    //MOVEM mask, reversed when the bool is set as with -(An)
+    REGISTER_MASK(bool),
+    //MOVEC control register, by its 12-bit code
+    CONTROL_REGISTER(u16),
+    //Extension word naming registers ahead of the effective address
+    //ones, completed according to the instruction
+    REGISTER_WORD,
+    //Dh:Dl of long multiplies, Dr:Dq of long divides, Dc:Du of CAS and
+    //Dc1:Dc2 of CAS2, Dx,Dy of PACK and UNPK
+    REGISTER_PAIR(usize, usize),
+    //-(Ax),-(Ay) of PACK and UNPK
+    PREDECREMENT_PAIR(usize, usize),
+    //Du1:Du2,(Rn1):(Rn2) of CAS2, Rn being 0-15 as in Index
+    CAS2_TARGET(usize, usize, usize, usize),
+    BITFIELD(Bitfield),
+    EXTENDED(Extended),
+    //32 bit displacement of Bcc.L
+    BRANCH_LONG,
+    FP_REGISTER(usize),
+    //FPc:FPs of FSINCOS
+    FP_REGISTER_PAIR(usize, usize),
+    //FMOVEM registers, bit 0 is FP0 whatever the mode
+    FP_REGISTER_LIST(u8),
+    //FMOVEM registers in the low byte of a data register, reversed when
+    //the bool is set as with control modes and (An)+
+    FP_DYNAMIC_LIST(usize, bool),
+    //FPCR, FPSR and FPIAR selected by bits 2, 1 and 0
+    FP_CONTROL(u8),
+    //Immediate FPU operand, top aligned for the multi long formats and
+    //right aligned in the first long for the others
+    FP_IMMEDIATE([u32; 3]),
+    MMU_REGISTER(MmuRegister),
+    FUNCTION_CODE(FcSelect),
+    //Function code and mask of PFLUSH
+    FC_MASK(FcSelect, u8),
+    //Function code, level and address register of PTEST
+    FC_LEVEL(FcSelect, u8, Option<usize>),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Instruction {
     op: Mnemonic,
     size: OpSize,
@@ -41,6 +375,182 @@ impl Clone for OpSize {
     }
 }
 
+impl Condition {
+    pub fn from_bits(bits: u16) -> Condition {
+        use Condition::*;
+        [T, F, HI, LS, CC, CS, NE, EQ,
+        VC, VS, PL, MI, GE, LT, GT, LE][bits as usize & 0xf]
+    }
+}
+
+impl FpCondition {
+    pub fn from_bits(bits: u16) -> FpCondition {
+        use FpCondition::*;
+        [F, EQ, OGT, OGE, OLT, OLE, OGL, OR, UN, UEQ, UGT, UGE, ULT, ULE, NE, T,
+        SF, SEQ, GT, GE, LT, LE, GL, GLE, NGLE, NGL, NLE, NLT, NGE, NGT, SNE, ST][bits as usize & 0x1f]
+    }
+}
+
+impl FpOp {
+    //Opmode field of the command word, FSINCOS taking 8 of them
+    pub fn from_opmode(opmode: u16) -> Option<FpOp> {
+        use FpOp::*;
+        Some(match opmode & 0x7f {
+            0x00 => MOVE,
+            0x01 => INT,
+            0x02 => SINH,
+            0x03 => INTRZ,
+            0x04 => SQRT,
+            0x06 => LOGNP1,
+            0x08 => ETOXM1,
+            0x09 => TANH,
+            0x0a => ATAN,
+            0x0c => ASIN,
+            0x0d => ATANH,
+            0x0e => SIN,
+            0x0f => TAN,
+            0x10 => ETOX,
+            0x11 => TWOTOX,
+            0x12 => TENTOX,
+            0x14 => LOGN,
+            0x15 => LOG10,
+            0x16 => LOG2,
+            0x18 => ABS,
+            0x19 => COSH,
+            0x1a => NEG,
+            0x1c => ACOS,
+            0x1d => COS,
+            0x1e => GETEXP,
+            0x1f => GETMAN,
+            0x20 => DIV,
+            0x21 => MOD,
+            0x22 => ADD,
+            0x23 => MUL,
+            0x24 => SGLDIV,
+            0x25 => REM,
+            0x26 => SCALE,
+            0x27 => SGLMUL,
+            0x28 => SUB,
+            0x30..=0x37 => SINCOS,
+            0x38 => CMP,
+            0x3a => TST,
+            _ => return None,
+        })
+    }
+
+    //Inverse of from_opmode, FSINCOS with the cosine register field clear
+    pub fn opmode(&self) -> u16 {
+        use FpOp::*;
+        match self {
+            MOVE => 0x00,
+            INT => 0x01,
+            SINH => 0x02,
+            INTRZ => 0x03,
+            SQRT => 0x04,
+            LOGNP1 => 0x06,
+            ETOXM1 => 0x08,
+            TANH => 0x09,
+            ATAN => 0x0a,
+            ASIN => 0x0c,
+            ATANH => 0x0d,
+            SIN => 0x0e,
+            TAN => 0x0f,
+            ETOX => 0x10,
+            TWOTOX => 0x11,
+            TENTOX => 0x12,
+            LOGN => 0x14,
+            LOG10 => 0x15,
+            LOG2 => 0x16,
+            ABS => 0x18,
+            COSH => 0x19,
+            NEG => 0x1a,
+            ACOS => 0x1c,
+            COS => 0x1d,
+            GETEXP => 0x1e,
+            GETMAN => 0x1f,
+            DIV => 0x20,
+            MOD => 0x21,
+            ADD => 0x22,
+            MUL => 0x23,
+            SGLDIV => 0x24,
+            REM => 0x25,
+            SCALE => 0x26,
+            SGLMUL => 0x27,
+            SUB => 0x28,
+            SINCOS => 0x30,
+            CMP => 0x38,
+            TST => 0x3a,
+        }
+    }
+}
+
+impl FpFormat {
+    //Source and destination specifier of the command word, the k-factor
+    //of packed decimals coming from its low bits
+    pub fn from_bits(bits: u16, ext: u16) -> FpFormat {
+        match bits & 0b111 {
+            0 => FpFormat::LONG,
+            1 => FpFormat::SINGLE,
+            2 => FpFormat::EXTENDED,
+            3 => FpFormat::PACKED(FieldParam::IMMEDIATE((ext & 0x7f) as u8)),
+            4 => FpFormat::WORD,
+            5 => FpFormat::DOUBLE,
+            6 => FpFormat::BYTE,
+            _ => FpFormat::PACKED(FieldParam::REGISTER(((ext >> 4) & 0b111) as usize)),
+        }
+    }
+
+    //Inverse of from_bits, without the k-factor
+    pub fn bits(&self) -> u16 {
+        match self {
+            FpFormat::LONG => 0,
+            FpFormat::SINGLE => 1,
+            FpFormat::EXTENDED => 2,
+            FpFormat::PACKED(FieldParam::IMMEDIATE(_)) => 3,
+            FpFormat::WORD => 4,
+            FpFormat::DOUBLE => 5,
+            FpFormat::BYTE => 6,
+            FpFormat::PACKED(FieldParam::REGISTER(_)) => 7,
+        }
+    }
+
+    pub fn bytes(&self) -> u32 {
+        match self {
+            FpFormat::BYTE => 1,
+            FpFormat::WORD => 2,
+            FpFormat::LONG | FpFormat::SINGLE => 4,
+            FpFormat::DOUBLE => 8,
+            FpFormat::EXTENDED | FpFormat::PACKED(_) => 12,
+        }
+    }
+}
+
+impl OpSize {
+    pub fn bytes(&self) -> u32 {
+        match self {
+            BYTE => 1,
+            WORD => 2,
+            LONG => 4,
+        }
+    }
+
+    pub fn mask(&self) -> u32 {
+        match self {
+            BYTE => 0xff,
+            WORD => 0xffff,
+            LONG => 0xffffffff,
+        }
+    }
+
+    pub fn msb(&self) -> u32 {
+        match self {
+            BYTE => 0x80,
+            WORD => 0x8000,
+            LONG => 0x80000000,
+        }
+    }
+}
+
 impl Instruction {
     pub fn new(op: Mnemonic, size: OpSize, lhs: DataContainer, trg: DataContainer) -> Instruction {
         // Need to implement safety for the 
@@ -0,0 +1,347 @@
+use super::*;
+use super::addressing::Location;
+use super::cpu_actions::_sign_extend;
+use super::exception::{VECTOR_CHK, VECTOR_ILLEGAL, VECTOR_TRAPV};
+
+// Instructions the 68020 added to the 68000 set. Most of them take a
+// register word ahead of their effective address extension words, which
+// the handlers resolve first.
+
+//A bitfield read out of its operand. `window` holds the bytes around it
+//top aligned, the field starting `start` bits in.
+struct Field {
+    loc: Location,
+    window: u64,
+    start: u32,
+    width: u32,
+    //Bytes read from memory, 0 for a register
+    bytes: u32,
+    //Register rotation bringing the field to the top
+    rotation: u32,
+}
+
+impl Field {
+    fn value(&self) -> u32 {
+        ((self.window << self.start) >> (64 - self.width)) as u32
+    }
+
+    fn replace(&mut self, val: u32) {
+        let shift = 64 - self.width - self.start;
+        let mask = ((1u64 << self.width) - 1) << shift;
+        self.window = (self.window & !mask) | (((val as u64) << shift) & mask);
+    }
+
+    //N and Z of `val` as a `width` bits integer, V and C cleared
+    fn flags(&self, flags: &mut flags::Flags, val: u32) {
+        flags.logic(val << (32 - self.width), &LONG);
+    }
+}
+
+impl<B: Bus> super::CPU<B> {
+
+    fn field_param(&self, param: FieldParam) -> u32 {
+        match param {
+            FieldParam::IMMEDIATE(val) => val as u32,
+            FieldParam::REGISTER(reg) => self.data_register[reg],
+        }
+    }
+
+    //Register fields wrap around, memory ones span up to 5 bytes from a
+    //signed offset
+    //None for operands that can't hold one
+    fn read_field(&mut self, loc: Location, offset: u32, width: u32) -> Result<Option<Field>, BusFault> {
+        let mut field = Field { loc, window: 0, start: 0, width, bytes: 0, rotation: 0 };
+        match loc {
+            Location::DATA(reg) => {
+                field.rotation = offset & 31;
+                field.window = (self.data_register[reg].rotate_left(field.rotation) as u64) << 32;
+            },
+            Location::MEMORY(addr) | Location::PROGRAM(addr) => {
+                let fc = if let Location::PROGRAM(_) = loc { self.program_fc() } else { self.data_fc() };
+                let addr = addr.wrapping_add((offset as i32 >> 3) as u32);
+                field.loc = Location::MEMORY(addr);
+                field.start = offset & 7;
+                field.bytes = (field.start + width).div_ceil(8);
+                for i in 0..field.bytes {
+                    let byte = self.read_byte(fc, addr.wrapping_add(i))?;
+                    field.window |= (byte as u64) << (56 - 8 * i);
+                }
+            },
+            _ => return Ok(None),
+        }
+        Ok(Some(field))
+    }
+
+    fn write_field(&mut self, field: &Field) -> Result<(), BusFault> {
+        match field.loc {
+            Location::DATA(reg) => self.data_register[reg] = ((field.window >> 32) as u32).rotate_right(field.rotation),
+            Location::MEMORY(addr) => {
+                for i in 0..field.bytes {
+                    self.write_byte(self.data_fc(), addr.wrapping_add(i), (field.window >> (56 - 8 * i)) as u8)?;
+                }
+            },
+            _ => return self.raise_fault(VECTOR_ILLEGAL),
+        }
+        Ok(())
+    }
+
+    //Flags always come from the field as it was, but for BFINS
+    pub fn perform_bitfield(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let inst = self.resolve_register_word(inst)?;
+        let bitfield = match inst.get_trg() {
+            BITFIELD(bitfield) => *bitfield,
+            _ => return self.raise_fault(VECTOR_ILLEGAL),
+        };
+        let offset = self.field_param(bitfield.offset);
+        let width = match self.field_param(bitfield.width) & 31 {
+            0 => 32,
+            width => width,
+        };
+        let loc = self.locate(inst.get_lhs(), &LONG)?;
+        let mut field = match self.read_field(loc, offset, width)? {
+            Some(field) => field,
+            None => return self.raise_fault(VECTOR_ILLEGAL),
+        };
+        let val = field.value();
+        let reg = bitfield.register;
+        let op = *inst.get_op();
+        if op == BFINS {
+            let val = self.data_register[reg] & (u32::MAX >> (32 - width));
+            field.flags(&mut self.flags, val);
+            field.replace(val);
+            return self.write_field(&field);
+        }
+        field.flags(&mut self.flags, val);
+        match op {
+            BFEXTU => self.data_register[reg] = val,
+            BFEXTS => self.data_register[reg] = ((val << (32 - width)) as i32 >> (32 - width)) as u32,
+            BFFFO => {
+                let zeros = (val << (32 - width)).leading_zeros().min(width);
+                self.data_register[reg] = offset.wrapping_add(zeros);
+            },
+            BFCHG | BFCLR | BFSET => {
+                field.replace(match op { BFCHG => !val, BFCLR => 0, _ => u32::MAX });
+                self.write_field(&field)?;
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    //32 bit product, V telling it didn't fit, or 64 bit one in Dh:Dl
+    pub(super) fn perform_mull(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let inst = self.resolve_register_word(inst)?;
+        let coldfire = self.model.is_coldfire();
+        if coldfire && matches!(inst.get_trg(), REGISTER_PAIR(..)) {
+            return self.raise_fault(VECTOR_ILLEGAL);
+        }
+        let src = self.get_target(inst.get_lhs(), &LONG)?;
+        let signed = *inst.get_op() == MULS;
+        let product = |dst: u32| {
+            if signed { (src as i32 as i64).wrapping_mul(dst as i32 as i64) as u64 } else { src as u64 * dst as u64 }
+        };
+        match *inst.get_trg() {
+            REGISTER_PAIR(dh, dl) => {
+                let result = product(self.data_register[dl]);
+                self.data_register[dh] = (result >> 32) as u32;
+                self.data_register[dl] = result as u32;
+                //High long with the low one folded into bit 0, for Z
+                self.flags.logic((result >> 32) as u32 | (result as u32 != 0) as u32, &LONG);
+            },
+            trg => {
+                let result = product(self.get_target(&trg, &LONG)?);
+                let overflow = if signed { result as i64 != result as i32 as i64 } else { result >> 32 != 0 };
+                self.set_target(&trg, &LONG, result as u32)?;
+                //ColdFire doesn't check for overflow
+                self.flags.result(result as u32, &LONG, overflow && !coldfire, false);
+            },
+        }
+        Ok(())
+    }
+
+    //DIVx.L to Dq, DIVx.L with a 64 bit dividend in Dr:Dq and DIVxL.L
+    //keeping the remainder in Dr. Overflows leave the registers alone.
+    pub fn perform_divl(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let inst = self.resolve_register_word(inst)?;
+        let op = *inst.get_op();
+        let coldfire = self.model.is_coldfire();
+        if coldfire && matches!((op, inst.get_trg()), (DIVU | DIVS, REGISTER_PAIR(..))) {
+            return self.raise_fault(VECTOR_ILLEGAL);
+        }
+        let signed = matches!(op, DIVS | DIVSL);
+        let divisor = self.get_target(inst.get_lhs(), &LONG)?;
+        if divisor == 0 {
+            return self.divide_by_zero();
+        }
+        let (dr, dq) = match *inst.get_trg() {
+            REGISTER_PAIR(dr, dq) => (Some(dr), dq),
+            DATA_REGISTER(dq) => (None, dq),
+            _ => return self.raise_fault(VECTOR_ILLEGAL),
+        };
+        let low = self.data_register[dq];
+        let high = match (op, dr) {
+            (DIVU | DIVS, Some(dr)) => Some(self.data_register[dr]),
+            _ => None,
+        };
+        let result = if signed {
+            let dividend = match high {
+                Some(high) => ((high as u64) << 32 | low as u64) as i64,
+                None => low as i32 as i64,
+            };
+            let divisor = divisor as i32 as i64;
+            dividend.checked_div(divisor)
+                .filter(|&quotient| quotient == quotient as i32 as i64)
+                .map(|quotient| (quotient as u32, (dividend % divisor) as u32))
+        }
+        else {
+            let dividend = (high.unwrap_or(0) as u64) << 32 | low as u64;
+            let quotient = dividend / divisor as u64;
+            Some((quotient as u32, (dividend % divisor as u64) as u32)).filter(|_| quotient >> 32 == 0)
+        };
+        if signed {
+            self.cycles += 12;
+        }
+        let (quotient, remainder) = match result {
+            Some(result) => result,
+            None => {
+                self.set_flag(Flag::C, false);
+                self.set_flag(Flag::V, true);
+                return Ok(());
+            },
+        };
+        if let Some(dr) = dr {
+            self.data_register[dr] = remainder;
+        }
+        //ColdFire REMS and REMU only keep the remainder
+        if !coldfire || dr.is_none() {
+            self.data_register[dq] = quotient;
+        }
+        self.flags.logic(quotient, &LONG);
+        Ok(())
+    }
+
+    //Writes Du when the operand equals Dc, loads Dc with it otherwise
+    pub fn perform_cas(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let inst = self.resolve_register_word(inst)?;
+        let size = inst.get_size();
+        let (dc, du) = match *inst.get_lhs() {
+            REGISTER_PAIR(dc, du) => (dc, du),
+            _ => return self.raise_fault(VECTOR_ILLEGAL),
+        };
+        let loc = self.locate(inst.get_trg(), size)?;
+        let dst = self.read_location(&loc, size)?;
+        let cmp = self.data_register[dc] & size.mask();
+        self.compare(cmp, dst, size);
+        if dst == cmp {
+            self.write_location(&loc, size, self.data_register[du])
+        }
+        else {
+            self.set_target(&DATA_REGISTER(dc), size, dst)
+        }
+    }
+
+    fn general_register(&self, reg: usize) -> u32 {
+        if reg < 8 { self.data_register[reg] } else { self.address_register[reg - 8] }
+    }
+
+    //Both operands have to match for either to be updated
+    pub fn perform_cas2(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let inst = self.resolve_register_word(inst)?;
+        let size = inst.get_size();
+        let (dc1, dc2, du1, du2, rn1, rn2) = match (*inst.get_lhs(), *inst.get_trg()) {
+            (REGISTER_PAIR(dc1, dc2), CAS2_TARGET(du1, du2, rn1, rn2)) => (dc1, dc2, du1, du2, rn1, rn2),
+            _ => return self.raise_fault(VECTOR_ILLEGAL),
+        };
+        let fc = self.data_fc();
+        let (addr1, addr2) = (self.general_register(rn1), self.general_register(rn2));
+        let mem1 = self.read_memory(fc, addr1, size)?;
+        let mem2 = self.read_memory(fc, addr2, size)?;
+        let (cmp1, cmp2) = (self.data_register[dc1] & size.mask(), self.data_register[dc2] & size.mask());
+        self.compare(cmp1, mem1, size);
+        if mem1 == cmp1 {
+            self.compare(cmp2, mem2, size);
+        }
+        if mem1 == cmp1 && mem2 == cmp2 {
+            self.write_memory(fc, addr1, size, self.data_register[du1])?;
+            self.write_memory(fc, addr2, size, self.data_register[du2])
+        }
+        else {
+            self.set_target(&DATA_REGISTER(dc1), size, mem1)?;
+            self.set_target(&DATA_REGISTER(dc2), size, mem2)
+        }
+    }
+
+    //Bounds are a lower then upper pair in memory, sign extended for an
+    //address register. The range wraps so it works signed or unsigned.
+    pub fn perform_chk2(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let inst = self.resolve_register_word(inst)?;
+        let size = inst.get_size();
+        let (fc, addr) = match self.locate(inst.get_lhs(), size)? {
+            Location::PROGRAM(addr) => (self.program_fc(), addr),
+            Location::MEMORY(addr) => (self.data_fc(), addr),
+            _ => return self.raise_fault(VECTOR_ILLEGAL),
+        };
+        let lower = self.read_memory(fc, addr, size)?;
+        let upper = self.read_memory(fc, addr.wrapping_add(size.bytes()), size)?;
+        let (val, lower, upper, mask) = match *inst.get_trg() {
+            ADDRESS_REGISTER(reg) => (self.address_register[reg], _sign_extend(lower, size), _sign_extend(upper, size), u32::MAX),
+            DATA_REGISTER(reg) => (self.data_register[reg] & size.mask(), lower, upper, size.mask()),
+            _ => return self.raise_fault(VECTOR_ILLEGAL),
+        };
+        let out = val.wrapping_sub(lower) & mask > upper.wrapping_sub(lower) & mask;
+        self.set_flag(Flag::Z, val == lower || val == upper);
+        self.set_flag(Flag::C, out);
+        if out && *inst.get_op() == CHK2 {
+            self.raise_exception(VECTOR_CHK);
+            self.cycles += 30;
+        }
+        Ok(())
+    }
+
+    //BCD digits from the low nibbles of a word and back, with an
+    //adjustment added to the word. Flags are left alone.
+    pub fn perform_pack(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        let adjustment = self.get_target(inst.get_lhs(), &WORD)?;
+        let pack = *inst.get_op() == PACK;
+        let (src, dst) = match *inst.get_trg() {
+            REGISTER_PAIR(x, y) => (DATA_REGISTER(x), DATA_REGISTER(y)),
+            PREDECREMENT_PAIR(x, y) => (PREDECREMENT(x), PREDECREMENT(y)),
+            _ => return self.raise_fault(VECTOR_ILLEGAL),
+        };
+        let memory = matches!(src, PREDECREMENT(_));
+        let val = match (pack, memory) {
+            (true, true) => {
+                let low = self.get_target(&src, &BYTE)?;
+                self.get_target(&src, &BYTE)? << 8 | low
+            },
+            (true, false) => self.get_target(&src, &WORD)?,
+            (false, _) => self.get_target(&src, &BYTE)?,
+        };
+        if pack {
+            let val = val.wrapping_add(adjustment);
+            return self.set_target(&dst, &BYTE, (val >> 4) & 0xf0 | val & 0x0f);
+        }
+        let val = ((val << 4) & 0x0f00 | val & 0x0f).wrapping_add(adjustment);
+        if memory {
+            self.set_target(&dst, &BYTE, val)?;
+            self.set_target(&dst, &BYTE, val >> 8)
+        }
+        else {
+            self.set_target(&dst, &WORD, val)
+        }
+    }
+
+    //The operand is only there for the handler to look at
+    pub fn perform_trapcc(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if *inst.get_lhs() != EMPTY {
+            self.get_target(inst.get_lhs(), inst.get_size())?;
+        }
+        if let TRAPCC(cond) = *inst.get_op() {
+            if self.test_condition(cond) {
+                self.raise_exception(VECTOR_TRAPV);
+                self.cycles += 30;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,480 @@
+use super::*;
+use super::exception::*;
+
+// Paged memory management as done by the 68030, attached to the 68020 as
+// coprocessor 0 in place of a 68851. Logical addresses go through the
+// transparent translation registers, then the address translation cache
+// (ATC) and a table walk on a miss. Failed translations are bus errors
+// and with translation enabled a bus error restarts the instruction from
+// scratch once the handler returns, which is what paging kernels expect.
+
+//Translation control
+const TC_E: u32 = 1 << 31;
+const TC_SRE: u32 = 1 << 25;
+const TC_FCL: u32 = 1 << 24;
+
+//Descriptor bits, S only being found in long descriptors
+const DESC_WP: u32 = 1 << 2;
+const DESC_U: u32 = 1 << 3;
+const DESC_M: u32 = 1 << 4;
+const DESC_S: u32 = 1 << 8;
+
+//Status left by PTEST in MMUSR, the number of levels walked in the low
+//3 bits
+const MMUSR_B: u16 = 1 << 15;
+const MMUSR_L: u16 = 1 << 14;
+const MMUSR_S: u16 = 1 << 13;
+const MMUSR_W: u16 = 1 << 11;
+const MMUSR_I: u16 = 1 << 10;
+const MMUSR_M: u16 = 1 << 9;
+const MMUSR_T: u16 = 1 << 6;
+
+const ATC_SIZE: usize = 22;
+
+//Descriptors fetched by a table walk cost this much on top of the bus
+const WALK_CYCLES: u64 = 8;
+
+fn _supervisor(fc: FunctionCode) -> bool {
+    matches!(fc, FunctionCode::SUPERVISOR_DATA | FunctionCode::SUPERVISOR_PROGRAM)
+}
+
+fn _mask(bits: u32) -> u32 {
+    if bits >= 32 { u32::MAX } else { (1 << bits) - 1 }
+}
+
+//The page size and index fields have to add up to 32 bits, with pages of
+//at least 256 bytes
+fn _valid_tc(tc: u32) -> bool {
+    let fields = [12, 8, 4, 0].iter().map(|shift| (tc >> shift) & 0xf).take_while(|&bits| bits != 0);
+    let ps = (tc >> 20) & 0xf;
+    ps >= 8 && (tc >> 12) & 0xf != 0 && ps + ((tc >> 16) & 0xf) + fields.sum::<u32>() == 32
+}
+
+//Transparent translation register matching an access
+fn _transparent(tt: u32, fc: FunctionCode, addr: u32, write: bool) -> bool {
+    let (base, mask) = (tt >> 24, (tt >> 16) & 0xff);
+    let (fc_base, fc_mask) = ((tt >> 4) & 0b111, tt & 0b111);
+    let read = tt & (1 << 9) != 0;
+    tt & (1 << 15) != 0
+        && ((addr >> 24) ^ base) & !mask == 0
+        && ((fc as u32) ^ fc_base) & !fc_mask & 0b111 == 0
+        && (tt & (1 << 8) != 0 || read != write)
+}
+
+//Translation of a logical page cached in the ATC
+#[derive(Debug, Clone, Copy)]
+struct AtcEntry {
+    fc: u8,
+    page: u32,
+    frame: u32,
+    wp: bool,
+    modified: bool,
+}
+
+//Outcome of a table walk, with the MMUSR bits PTEST reports
+#[derive(Debug, Clone, Copy, Default)]
+struct Walk {
+    //Physical address of the page, None when the walk failed or stopped
+    frame: Option<u32>,
+    status: u16,
+    levels: u16,
+    //Address of the last descriptor fetched
+    descriptor: u32,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Mmu {
+    tc: u32,
+    crp: u64,
+    srp: u64,
+    tt: [u32; 2],
+    mmusr: u16,
+    //Least recently used first
+    atc: Vec<AtcEntry>,
+}
+
+impl Mmu {
+    fn new() -> Mmu {
+        Mmu { tc: 0, crp: 0, srp: 0, tt: [0; 2], mmusr: 0, atc: Vec::with_capacity(ATC_SIZE) }
+    }
+
+    fn page_shift(&self) -> u32 {
+        (self.tc >> 20) & 0xf
+    }
+
+    fn lookup(&mut self, fc: FunctionCode, page: u32) -> Option<AtcEntry> {
+        let found = self.atc.iter().position(|entry| entry.fc == fc as u8 && entry.page == page)?;
+        let entry = self.atc.remove(found);
+        self.atc.push(entry);
+        Some(entry)
+    }
+
+    fn insert(&mut self, entry: AtcEntry) {
+        self.atc.retain(|old| old.fc != entry.fc || old.page != entry.page);
+        if self.atc.len() == ATC_SIZE {
+            self.atc.remove(0);
+        }
+        self.atc.push(entry);
+    }
+
+    //Drops the entries whose function code matches `fc` in the bits set in
+    //`mask`, only for the page holding `addr` when given
+    fn flush(&mut self, fc: u8, mask: u8, addr: Option<u32>) {
+        let page = addr.map(|addr| addr >> self.page_shift());
+        self.atc.retain(|entry| (entry.fc ^ fc) & mask & 0b111 != 0 || page.is_some_and(|page| entry.page != page));
+    }
+}
+
+//What a restarted instruction gets back
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Restart {
+    sr: u16,
+    data: [u32; 8],
+    address: [u32; 8],
+}
+
+impl<B: Bus> super::CPU<B> {
+
+    //No MMU by default, the 68020 is the only model talking to one
+    pub fn set_mmu(&mut self, present: bool) {
+        self.mmu = if present { Some(Mmu::new()) } else { None };
+    }
+
+    pub fn has_mmu(&self) -> bool {
+        self.mmu.is_some()
+    }
+
+    //Root pointers are 64 bits, the other registers sit in the low bits
+    pub fn mmu_register(&self, reg: MmuRegister) -> Option<u64> {
+        self.mmu.as_ref().map(|mmu| match reg {
+            MmuRegister::TC => mmu.tc as u64,
+            MmuRegister::SRP => mmu.srp,
+            MmuRegister::CRP => mmu.crp,
+            MmuRegister::TT0 => mmu.tt[0] as u64,
+            MmuRegister::TT1 => mmu.tt[1] as u64,
+            MmuRegister::MMUSR => mmu.mmusr as u64,
+        })
+    }
+
+    //Loads a register without checking it, the ATC is flushed
+    pub fn set_mmu_register(&mut self, reg: MmuRegister, val: u64) {
+        if let Some(mmu) = &mut self.mmu {
+            match reg {
+                MmuRegister::TC => mmu.tc = val as u32,
+                MmuRegister::SRP => mmu.srp = val,
+                MmuRegister::CRP => mmu.crp = val,
+                MmuRegister::TT0 => mmu.tt[0] = val as u32,
+                MmuRegister::TT1 => mmu.tt[1] = val as u32,
+                MmuRegister::MMUSR => mmu.mmusr = val as u16,
+            }
+            mmu.atc.clear();
+        }
+    }
+
+    //Whether logical addresses may differ from physical ones
+    pub(super) fn translating(&self) -> bool {
+        self.mmu.as_ref().is_some_and(|mmu| mmu.tc & TC_E != 0 || mmu.tt.iter().any(|tt| tt & (1 << 15) != 0))
+    }
+
+    pub(super) fn restart_point(&self) -> Option<Restart> {
+        if self.translating() {
+            Some(Restart { sr: self.get_sr(), data: self.data_register, address: self.address_register })
+        }
+        else {
+            None
+        }
+    }
+
+    //Puts the registers back as they were when the instruction started
+    pub(super) fn restart(&mut self, restart: Restart) {
+        self.set_sr(restart.sr);
+        self.data_register = restart.data;
+        self.address_register = restart.address;
+        self.set_pc(self.inst_pc);
+    }
+
+    //Whether an access of `bytes` bytes spans two pages, in which case it
+    //is split into smaller ones
+    pub(super) fn splits_page(&self, addr: u32, bytes: u32) -> bool {
+        match &self.mmu {
+            Some(mmu) if mmu.tc & TC_E != 0 => {
+                let mask = _mask(mmu.page_shift());
+                (addr & mask) + bytes - 1 > mask
+            },
+            _ => false,
+        }
+    }
+
+    //Physical address of an access, None when it faults
+    pub(super) fn translate(&mut self, fc: FunctionCode, addr: u32, write: bool) -> Option<u32> {
+        let mmu = match &mut self.mmu {
+            Some(mmu) => mmu,
+            None => return Some(addr),
+        };
+        if fc == FunctionCode::CPU_SPACE || mmu.tc & TC_E == 0 || mmu.tt.iter().any(|&tt| _transparent(tt, fc, addr, write)) {
+            return Some(addr);
+        }
+        let shift = mmu.page_shift();
+        let offset = addr & _mask(shift);
+        match mmu.lookup(fc, addr >> shift) {
+            Some(entry) if write && entry.wp => return None,
+            //The first write to a page goes through the tables to set M
+            Some(entry) if !write || entry.modified => return Some(entry.frame << shift | offset),
+            _ => {},
+        }
+        let walk = self.table_walk(fc, addr, write, 7, true);
+        self.cycles += WALK_CYCLES * walk.levels as u64;
+        let frame = walk.frame?;
+        if walk.status & (MMUSR_S | MMUSR_B | MMUSR_L | MMUSR_I) != 0 {
+            return None;
+        }
+        let wp = walk.status & MMUSR_W != 0;
+        let entry = AtcEntry { fc: fc as u8, page: addr >> shift, frame: frame >> shift, wp, modified: walk.status & MMUSR_M != 0 };
+        self.mmu.as_mut().expect("no MMU").insert(entry);
+        if write && wp { None } else { Some(frame | offset) }
+    }
+
+    fn read_descriptor(&mut self, addr: u32, long: bool) -> Result<(u32, u32), BusError> {
+        let first = self.bus.read_long(FunctionCode::SUPERVISOR_DATA, addr)?;
+        let second = if long { self.bus.read_long(FunctionCode::SUPERVISOR_DATA, addr.wrapping_add(4))? } else { 0 };
+        Ok((first, second))
+    }
+
+    //Walks the translation tables for `addr` down to `max_level` levels,
+    //setting the used and modified bits on the way when `update` is set.
+    //Bus errors end the walk with B set in the status.
+    fn table_walk(&mut self, fc: FunctionCode, addr: u32, write: bool, max_level: u16, update: bool) -> Walk {
+        let mmu = self.mmu.as_ref().expect("no MMU");
+        let tc = mmu.tc;
+        let root = if tc & TC_SRE != 0 && _supervisor(fc) { mmu.srp } else { mmu.crp };
+        let shift = mmu.page_shift();
+        //Index and bits left below it for each level
+        let mut left = 32 - ((tc >> 16) & 0xf);
+        let mut levels = Vec::with_capacity(5);
+        if tc & TC_FCL != 0 {
+            levels.push((fc as u32, left));
+        }
+        for bits in [12, 8, 4, 0].iter().map(|shift| (tc >> shift) & 0xf).take_while(|&bits| bits != 0) {
+            left -= bits;
+            levels.push(((addr >> left) & _mask(bits), left));
+        }
+        let mut walk = Walk::default();
+        let (mut wp, mut supervisor) = (false, false);
+        let mut dt = (root >> 32) as u32 & 0b11;
+        let mut table = root as u32 & !0xf;
+        let mut limit = Some((root >> 63 != 0, (root >> 48) as u32 & 0x7fff));
+        //Early termination at the root maps the whole space
+        let mut page = if dt == 1 { Some((table & !0xff, 32 - ((tc >> 16) & 0xf), 0, 0)) } else { None };
+        for (level, &(index, below)) in levels.iter().enumerate() {
+            if page.is_some() || walk.levels >= max_level {
+                break;
+            }
+            if dt == 0 {
+                walk.status |= MMUSR_I;
+                return walk;
+            }
+            if let Some((lower, limit)) = limit {
+                if (lower && index < limit) || (!lower && index > limit) {
+                    walk.status |= MMUSR_L | MMUSR_I;
+                    return walk;
+                }
+            }
+            let long = dt == 3;
+            let desc_addr = table.wrapping_add(index * if long { 8 } else { 4 });
+            walk.descriptor = desc_addr;
+            walk.levels += 1;
+            let (mut first, mut second) = match self.read_descriptor(desc_addr, long) {
+                Ok(desc) => desc,
+                Err(_) => {
+                    walk.status |= MMUSR_B | MMUSR_I;
+                    return walk;
+                },
+            };
+            let mut desc_addr = desc_addr;
+            let mut long = long;
+            //Table descriptors at the last level point to page descriptors
+            if matches!(first & 0b11, 2 | 3) && level == levels.len() - 1 {
+                long = first & 0b11 == 3;
+                desc_addr = if dt == 3 { second } else { first } & !0b11;
+                walk.descriptor = desc_addr;
+                walk.levels += 1;
+                match self.read_descriptor(desc_addr, long) {
+                    Ok((f, s)) if f & 0b11 == 1 => {
+                        first = f;
+                        second = s;
+                    },
+                    Ok(_) => {
+                        walk.status |= MMUSR_I;
+                        return walk;
+                    },
+                    Err(_) => {
+                        walk.status |= MMUSR_B | MMUSR_I;
+                        return walk;
+                    },
+                }
+            }
+            wp |= first & DESC_WP != 0;
+            supervisor |= long && first & DESC_S != 0;
+            let address = if long { second } else { first };
+            match first & 0b11 {
+                0 => {
+                    walk.status |= MMUSR_I;
+                    return walk;
+                },
+                1 => page = Some((address & !0xff, below, desc_addr, first)),
+                next => {
+                    if update && first & DESC_U == 0 {
+                        let _ = self.bus.write_long(FunctionCode::SUPERVISOR_DATA, desc_addr, first | DESC_U);
+                    }
+                    dt = next;
+                    table = address & !0xf;
+                    limit = if long { Some((first >> 31 != 0, (first >> 16) & 0x7fff)) } else { None };
+                },
+            }
+        }
+        let (base, below, desc_addr, first) = match page {
+            Some(page) => page,
+            None => return walk,
+        };
+        if supervisor && !_supervisor(fc) {
+            walk.status |= MMUSR_S;
+        }
+        if wp {
+            walk.status |= MMUSR_W;
+        }
+        let mut first = first;
+        if update && walk.levels > 0 {
+            let updated = first | DESC_U | if write && !wp { DESC_M } else { 0 };
+            if updated != first {
+                let _ = self.bus.write_long(FunctionCode::SUPERVISOR_DATA, desc_addr, updated);
+                first = updated;
+            }
+        }
+        if first & DESC_M != 0 {
+            walk.status |= MMUSR_M;
+        }
+        //Index bits left over by an early termination add to the address
+        let phys = base.wrapping_add(addr & _mask(below) & !_mask(shift));
+        walk.frame = Some(phys);
+        walk
+    }
+
+    fn function_code(&self, fc: FcSelect) -> FunctionCode {
+        match fc {
+            FcSelect::SFC => self.sfc,
+            FcSelect::DFC => self.dfc,
+            FcSelect::REGISTER(reg) => FunctionCode::from_bits(self.data_register[reg]),
+            FcSelect::IMMEDIATE(bits) => FunctionCode::from_bits(bits as u32),
+        }
+    }
+
+    //PMOVE, PFLUSH, PLOAD and PTEST, told apart by their command word
+    pub fn perform_pmmu(&mut self, inst: &Instruction) -> Result<(), BusFault> {
+        if self.mmu.is_none() {
+            return self.raise_fault(VECTOR_LINE_F);
+        }
+        if !self.is_supervisor() {
+            return self.raise_fault(VECTOR_PRIVILEGE);
+        }
+        let inst = self.resolve_register_word(inst)?;
+        match (*inst.get_op(), *inst.get_lhs(), *inst.get_trg()) {
+            (PMOVE | PMOVEFD, MMU_REGISTER(reg), ea) => self.pmove_from(reg, &ea),
+            (op, ea, MMU_REGISTER(reg)) => self.pmove_to(reg, &ea, op == PMOVE),
+            (PFLUSHA, ..) => {
+                self.mmu.as_mut().expect("no MMU").atc.clear();
+                Ok(())
+            },
+            (PFLUSH, FC_MASK(fc, mask), ea) => {
+                let addr = if ea == EMPTY { None } else { Some(self.effective_address(&ea)?) };
+                let fc = self.function_code(fc) as u8;
+                self.mmu.as_mut().expect("no MMU").flush(fc, mask, addr);
+                Ok(())
+            },
+            (op @ (PLOADR | PLOADW), FUNCTION_CODE(fc), ea) => {
+                let addr = self.effective_address(&ea)?;
+                let fc = self.function_code(fc);
+                let _ = self.translate(fc, addr, op == PLOADW);
+                Ok(())
+            },
+            (op @ (PTESTR | PTESTW), FC_LEVEL(fc, level, reg), ea) => {
+                let addr = self.effective_address(&ea)?;
+                let fc = self.function_code(fc);
+                self.ptest(fc, addr, op == PTESTW, level as u16, reg);
+                Ok(())
+            },
+            _ => self.perform_illegal(&inst),
+        }
+    }
+
+    fn pmove_from(&mut self, reg: MmuRegister, ea: &DataContainer) -> Result<(), BusFault> {
+        let val = self.mmu_register(reg).unwrap_or(0);
+        match reg {
+            MmuRegister::SRP | MmuRegister::CRP => {
+                let addr = self.effective_address(ea)?;
+                let fc = self.data_fc();
+                self.write_long(fc, addr, (val >> 32) as u32)?;
+                self.write_long(fc, addr.wrapping_add(4), val as u32)
+            },
+            MmuRegister::MMUSR => self.set_target(ea, &WORD, val as u32),
+            _ => self.set_target(ea, &LONG, val as u32),
+        }
+    }
+
+    //Invalid translation setups raise the MMU configuration exception and
+    //are left unloaded
+    fn pmove_to(&mut self, reg: MmuRegister, ea: &DataContainer, flush: bool) -> Result<(), BusFault> {
+        let val = match reg {
+            MmuRegister::SRP | MmuRegister::CRP => {
+                let addr = self.effective_address(ea)?;
+                let fc = self.data_fc();
+                let high = self.read_long(fc, addr)?;
+                (high as u64) << 32 | self.read_long(fc, addr.wrapping_add(4))? as u64
+            },
+            MmuRegister::MMUSR => self.get_target(ea, &WORD)? as u64,
+            _ => self.get_target(ea, &LONG)? as u64,
+        };
+        let valid = match reg {
+            MmuRegister::TC => val as u32 & TC_E == 0 || _valid_tc(val as u32),
+            MmuRegister::SRP | MmuRegister::CRP => (val >> 32) & 0b11 != 0,
+            _ => true,
+        };
+        if !valid {
+            self.raise_exception(VECTOR_MMU_CONFIGURATION);
+            return Ok(());
+        }
+        let mmu = self.mmu.as_mut().expect("no MMU");
+        let atc = std::mem::take(&mut mmu.atc);
+        self.set_mmu_register(reg, val);
+        if !flush || reg == MmuRegister::MMUSR {
+            self.mmu.as_mut().expect("no MMU").atc = atc;
+        }
+        Ok(())
+    }
+
+    //Level 0 searches the ATC, other levels walk the tables that far
+    //without touching them or the ATC
+    fn ptest(&mut self, fc: FunctionCode, addr: u32, write: bool, level: u16, reg: Option<usize>) {
+        let mmu = self.mmu.as_mut().expect("no MMU");
+        let status = if level == 0 {
+            let shift = mmu.page_shift();
+            if mmu.tt.iter().any(|&tt| _transparent(tt, fc, addr, write)) {
+                MMUSR_T
+            }
+            else {
+                match mmu.atc.iter().find(|entry| entry.fc == fc as u8 && entry.page == addr >> shift) {
+                    Some(entry) => (if entry.wp { MMUSR_W } else { 0 }) | if entry.modified { MMUSR_M } else { 0 },
+                    None => MMUSR_I,
+                }
+            }
+        }
+        else {
+            let walk = self.table_walk(fc, addr, write, level, false);
+            self.cycles += WALK_CYCLES * walk.levels as u64;
+            if let Some(reg) = reg {
+                self.address_register[reg] = walk.descriptor;
+            }
+            walk.status | walk.levels
+        };
+        self.mmu.as_mut().expect("no MMU").mmusr = status;
+    }
+}
@@ -0,0 +1,57 @@
+use super::*;
+
+//Forked CPUs start without a monitor
+#[derive(Default)]
+pub(super) struct Monitor {
+    callback: Option<BusMonitor>,
+    //Start of the next bus cycle
+    clock: u64,
+}
+
+impl Clone for Monitor {
+    fn clone(&self) -> Monitor {
+        Monitor::default()
+    }
+}
+
+impl Monitor {
+    pub fn is_active(&self) -> bool {
+        self.callback.is_some()
+    }
+}
+
+impl<B: Bus> super::CPU<B> {
+
+    //Calls `monitor` after every bus cycle that completes, long accesses
+    //being two word cycles. Blocks aren't cached while it is set so every
+    //instruction fetch shows up.
+    pub fn set_bus_monitor(&mut self, monitor: Option<BusMonitor>) {
+        self.monitor.callback = monitor;
+    }
+
+    //Bus cycles take 4 clocks, one after the other from the start of the
+    //instruction
+    pub(super) fn bus_cycle(&mut self, fc: FunctionCode, addr: u32, op_size: &OpSize, val: u32, write: bool) {
+        if !self.monitor.is_active() {
+            return;
+        }
+        let (uds, lds) = match op_size {
+            BYTE => (addr & 1 == 0, addr & 1 != 0),
+            _ => (true, true),
+        };
+        let (words, count) = match op_size {
+            BYTE if uds => ([(val as u16) << 8, 0], 1),
+            BYTE | WORD => ([val as u16, 0], 1),
+            LONG => ([(val >> 16) as u16, val as u16], 2),
+        };
+        for (i, &data) in words[..count].iter().enumerate() {
+            let cycle = self.monitor.clock.max(self.inst_cycles);
+            self.monitor.clock = cycle + 4;
+            let addr = (addr & !1).wrapping_add(2 * i as u32);
+            let bus_cycle = BusCycle { addr, data, write, fc, uds, lds, cycle };
+            if let Some(callback) = &mut self.monitor.callback {
+                callback(&bus_cycle);
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use super::*;
+
+// The words the 68000 reads ahead of the one it is using.
+//
+// Taking a word from the queue first tops it up to two, so the next opcode
+// is read before the instruction ahead of it writes anything and code
+// writing over it still runs what was there before. Nothing is read past
+// a branch, the queue being refilled at its target instead, so its reads
+// are the 68000's own prefetch cycles. The timing tables already price
+// those, branches and exceptions included, and the cycle count is the same.
+//
+// The PC the 68000 stacks on an address or bus error is the one it holds
+// at the fault, see `fault_pc`.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Prefetch {
+    //Address of the first word queued
+    addr: u32,
+    words: [u16; 2],
+    len: usize,
+}
+
+impl Prefetch {
+    //First word queued, None when it couldn't be read
+    fn take(&mut self) -> Option<u16> {
+        if self.len == 0 {
+            return None;
+        }
+        let word = self.words[0];
+        self.words[0] = self.words[1];
+        self.len -= 1;
+        self.addr = self.addr.wrapping_add(2);
+        Some(word)
+    }
+}
+
+impl<B: Bus> super::CPU<B> {
+
+    //Off by default, the block cache is bypassed while it is on
+    pub fn set_prefetch(&mut self, enabled: bool) {
+        self.prefetch = if enabled { Some(Prefetch::default()) } else { None };
+    }
+
+    pub fn prefetch_enabled(&self) -> bool {
+        self.prefetch.is_some()
+    }
+
+    pub(super) fn flush_prefetch(&mut self) {
+        if let Some(queue) = &mut self.prefetch {
+            queue.len = 0;
+        }
+    }
+
+    //Tops the queue up and takes the word at `pc` from it. A prefetch that
+    //faults is only reported when the word actually gets used.
+    pub(super) fn fetch_prefetched(&mut self, pc: u32) -> Result<u16, BusFault> {
+        let fc = self.program_fc();
+        let mut queue = self.prefetch.unwrap_or_default();
+        if queue.len == 0 || queue.addr != pc {
+            queue = Prefetch { addr: pc, ..Prefetch::default() };
+        }
+        while queue.len < 2 {
+            match self.read_word(fc, queue.addr.wrapping_add(2 * queue.len as u32)) {
+                Ok(next) => queue.words[queue.len] = next,
+                Err(_) => break,
+            }
+            queue.len += 1;
+        }
+        let word = queue.take();
+        self.prefetch = Some(queue);
+        match word {
+            Some(word) => Ok(word),
+            None => self.read_word(fc, pc),
+        }
+    }
+
+    //PC stacked by a 68000 group 0 fault. It is the address of the first
+    //word not yet taken, except that most instructions run their last
+    //prefetch before writing their result, so a faulting write finds PC a
+    //word further on. MOVE and MOVEM write before it, but MOVE to -(An).
+    pub(super) fn fault_pc(&self, fault: &BusFault) -> u32 {
+        let pc = self.get_pc();
+        let ir = self.ir;
+        let moves = matches!(ir >> 12, 1..=3) && (ir >> 6) & 0b111 != 0b100 || ir & 0xfb80 == 0x4880;
+        if self.prefetch.is_some() && fault.write && !moves { pc.wrapping_add(2) } else { pc }
+    }
+}
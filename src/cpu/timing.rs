@@ -0,0 +1,284 @@
+use super::*;
+
+// 68000 execution times in clock cycles, from the tables of the user's
+// manual. What only depends on the opcode is looked up here, the parts
+// depending on data (taken branches, shift counts, multiplier bits,
+// divisions, MOVEM register counts) are added by the handlers.
+
+//Dn, An, (An), (An)+, -(An), d16(An), d8(An,Xn), abs.W, abs.L,
+//d16(PC), d8(PC,Xn) and #imm, same order as the decoder
+fn _mode(data: &DataContainer) -> usize {
+    match data {
+        DATA_REGISTER(_) => 0,
+        ADDRESS_REGISTER(_) => 1,
+        ADDRESS_INDIRECT(_) => 2,
+        POSTINCREMENT(_) => 3,
+        PREDECREMENT(_) => 4,
+        EA(5, _) | DISPLACEMENT(..) => 5,
+        EA(6, _) | INDEXED(..) => 6,
+        EXTENDED(Extended { base: Base::PC(_), .. }) | EXTENDED(Extended { base: Base::SUPPRESSED_PC, .. }) => 10,
+        EXTENDED(_) => 6,
+        EA(7, 0) | SHORT_ADDR(_) => 7,
+        EA(7, 1) | MEMORY_ADDR(_) => 8,
+        EA(7, 2) | PC_DISPLACEMENT(..) => 9,
+        EA(7, 3) | PC_INDEXED(..) => 10,
+        _ => 11,
+    }
+}
+
+fn _is_register(data: &DataContainer) -> bool {
+    _mode(data) < 2
+}
+
+//Effective address calculation time, longs take two more bus cycles
+fn _ea(data: &DataContainer, op_size: &OpSize) -> u32 {
+    let time = [0, 0, 4, 4, 6, 8, 10, 8, 12, 8, 10, 4][_mode(data)];
+    if *op_size == LONG && time != 0 { time + 4 } else { time }
+}
+
+//Writing the destination of a MOVE, -(An) costs no more than (An) here
+fn _move_destination(data: &DataContainer, op_size: &OpSize) -> u32 {
+    let time = [0, 0, 4, 4, 4, 8, 10, 8, 12, 0, 0, 0][_mode(data)];
+    if *op_size == LONG && time != 0 { time + 4 } else { time }
+}
+
+//Control addressing modes of LEA, JMP and MOVEM
+fn _control(data: &DataContainer, times: [u32; 11]) -> u32 {
+    times[_mode(data).min(10)]
+}
+
+//ADD, SUB, AND and OR from <ea> to Dn, long operations take 2 cycles more
+//with register and immediate sources
+fn _to_register(lhs: &DataContainer, op_size: &OpSize) -> u32 {
+    let base = match op_size {
+        LONG if _is_register(lhs) || _mode(lhs) == 11 => 8,
+        LONG => 6,
+        _ => 4,
+    };
+    base + _ea(lhs, op_size)
+}
+
+//Read-modify-write on a register or memory, CLR, NEG, NOT and the like
+fn _single(data: &DataContainer, op_size: &OpSize, register: [u32; 2], memory: [u32; 2]) -> u32 {
+    let long = (*op_size == LONG) as usize;
+    if _is_register(data) { register[long] } else { memory[long] + _ea(data, op_size) }
+}
+
+//Cycles of `inst` known from its opcode alone
+pub(crate) fn cycles(inst: &Instruction) -> u32 {
+    let size = inst.get_size();
+    let long = *size == LONG;
+    let (lhs, trg) = (inst.get_lhs(), inst.get_trg());
+    match *inst.get_op() {
+        MOVE => match (lhs, trg) {
+            (_, SR) | (_, CCR) => 12 + _ea(lhs, &WORD),
+            (SR, _) | (CCR, _) => if _is_register(trg) { 6 } else { 8 + _ea(trg, &WORD) },
+            (USP, _) | (_, USP) => 4,
+            _ => 4 + _ea(lhs, size) + _move_destination(trg, size),
+        },
+        MOVEA => 4 + _ea(lhs, size),
+        MOVEQ => 4,
+        MOVEM => {
+            //Plus 4 cycles per word or 8 per long transferred
+            if let REGISTER_MASK(_) | REGISTER_LIST(_) = lhs {
+                _control(trg, [0, 0, 8, 0, 8, 12, 14, 12, 16, 0, 0])
+            }
+            else {
+                _control(lhs, [0, 0, 12, 12, 0, 16, 18, 16, 20, 16, 18])
+            }
+        },
+        MOVEP => if long { 24 } else { 16 },
+        ADD | SUB | AND | OR => match trg {
+            DATA_REGISTER(_) => _to_register(lhs, size),
+            _ => _single(trg, size, [0, 0], [8, 12]),
+        },
+        ADDA | SUBA => if long { _to_register(lhs, size) } else { 8 + _ea(lhs, size) },
+        CMP => (if long { 6 } else { 4 }) + _ea(lhs, size),
+        CMPA => 6 + _ea(lhs, size),
+        EOR => _single(trg, size, [4, 8], [8, 12]),
+        ADDI | SUBI | ANDI | ORI | EORI => match trg {
+            SR | CCR => 20,
+            _ => _single(trg, size, [8, 16], [12, 20]),
+        },
+        CMPI => _single(trg, size, [8, 14], [8, 12]),
+        ADDQ | SUBQ => match trg {
+            ADDRESS_REGISTER(_) => 8,
+            _ => _single(trg, size, [4, 8], [8, 12]),
+        },
+        CLR | NEG | NEGX | NOT => _single(lhs, size, [4, 6], [8, 12]),
+        TST => 4 + _ea(lhs, size),
+        NBCD => _single(lhs, size, [6, 6], [8, 8]),
+        TAS => _single(lhs, size, [4, 4], [14, 14]),
+        //Plus 2 when the condition is true
+        SCC(_) => _single(lhs, size, [4, 4], [8, 8]),
+        //Plus 2 per bit of the multiplier
+        MULU | MULS if long => 43 + _ea(lhs, &LONG),
+        MULU | MULS => 38 + _ea(lhs, &WORD),
+        //The division itself depends on the operands. Long ones take the
+        //same time whatever the data, plus 12 when signed.
+        DIVU | DIVS if !long => _ea(lhs, &WORD),
+        DIVU | DIVS | DIVUL | DIVSL => 78 + _ea(lhs, &LONG),
+        ABCD | SBCD => if _is_register(lhs) { 6 } else { 18 },
+        ADDX | SUBX => match (_is_register(lhs), long) {
+            (true, false) => 4,
+            (true, true) => 8,
+            (false, false) => 18,
+            (false, true) => 30,
+        },
+        CMPM => if long { 20 } else { 12 },
+        //Plus 2 per bit shifted on registers
+        ASL | ASR | LSL | LSR | ROL | ROR | ROXL | ROXR => match trg {
+            EMPTY => 8 + _ea(lhs, &WORD),
+            _ => if long { 8 } else { 6 },
+        },
+        //Bit numbers below 16 on registers take 2 cycles less to change
+        BTST | BCHG | BCLR | BSET => {
+            let op = *inst.get_op();
+            let dynamic = _is_register(lhs);
+            match (_is_register(trg), op) {
+                (true, BTST) => if dynamic { 6 } else { 10 },
+                (true, BCLR) => if dynamic { 10 } else { 14 },
+                (true, _) => if dynamic { 8 } else { 12 },
+                (false, BTST) => (if dynamic { 4 } else { 8 }) + _ea(trg, &BYTE),
+                (false, _) => (if dynamic { 8 } else { 12 }) + _ea(trg, &BYTE),
+            }
+        },
+        EXG => 6,
+        SWAP | EXT => 4,
+        LEA => _control(lhs, [0, 0, 4, 0, 0, 8, 12, 8, 12, 8, 12]),
+        PEA => 8 + _control(lhs, [0, 0, 4, 0, 0, 8, 12, 8, 12, 8, 12]),
+        JMP => _control(lhs, [0, 0, 8, 0, 0, 10, 14, 10, 12, 10, 14]),
+        JSR => 8 + _control(lhs, [0, 0, 8, 0, 0, 10, 14, 10, 12, 10, 14]),
+        LINK => if *size == LONG { 20 } else { 16 },
+        UNLK => 12,
+        BRA => 10,
+        BSR => 18,
+        //Taken or not and loop outcome are charged by the handlers
+        BCC(_) | DBCC(_) => 0,
+        RTS => 16,
+        RTR | RTE => 20,
+        TRAP => 34,
+        //Plus 30 when trapping
+        TRAPV => 4,
+        CHK => 10 + _ea(lhs, size),
+        NOP | STOP => 4,
+        RESET => 132,
+        //68010 times, plus 2 for MOVEC to a control register
+        MOVEC => 10,
+        MOVES => 14 + if _is_register(lhs) { _ea(trg, size) } else { _ea(lhs, size) },
+        RTD => 16,
+        //68020 times, which mostly overlap with the cache and pipeline,
+        //taken from the cache case
+        BFTST | BFCHG | BFCLR | BFSET => if _is_register(lhs) { 12 } else { 20 },
+        BFEXTU | BFEXTS | BFINS => if _is_register(lhs) { 10 } else { 18 },
+        BFFFO => if _is_register(lhs) { 20 } else { 28 },
+        EXTB => 4,
+        CAS => 16,
+        CAS2 => 26,
+        //Plus 30 when CHK2 traps
+        CHK2 | CMP2 => 18 + _ea(lhs, size),
+        PACK | UNPK => if let PREDECREMENT_PAIR(..) = trg { 14 } else { 6 },
+        //Plus 30 when trapping
+        TRAPCC(_) => 4,
+        //68881 times through the coprocessor interface, the operation
+        //itself is added by the handler with fp_cycles
+        FOP(..) | FMOVECR if matches!(lhs, FP_REGISTER(_) | FP_REGISTER_PAIR(..)) => 0,
+        FOP(..) | FMOVECR => _ea(lhs, size),
+        FMOVEM => 12 + _ea(lhs, &LONG) + _ea(trg, &LONG),
+        FBCC(_) => 10,
+        FDBCC(_) | FSCC(_) => 14 + _ea(lhs, &BYTE),
+        //Plus 30 when trapping
+        FTRAPCC(_) => 12,
+        FSAVE | FRESTORE => 18 + _ea(lhs, &LONG),
+        //68030 MMU times, table walks are added as they happen
+        PMOVE | PMOVEFD => 20 + _ea(lhs, &LONG),
+        PFLUSHA | PFLUSH => 12,
+        PLOADR | PLOADW | PTESTR | PTESTW => 22,
+        //ColdFire instructions, on the 68000 scale like the rest
+        MOV3Q => 4 + _move_destination(trg, &LONG),
+        MVS | MVZ => 4 + _ea(lhs, size),
+        SATS => 4,
+        //Charged as exceptions
+        ILLEGAL | LINE_A | LINE_F => 0,
+    }
+}
+
+//Register to register times of the 68881 arithmetic
+pub(super) fn fp_cycles(op: FpOp) -> u32 {
+    match op {
+        FpOp::MOVE | FpOp::CMP | FpOp::TST => 33,
+        FpOp::ABS | FpOp::NEG => 35,
+        FpOp::INT | FpOp::INTRZ => 55,
+        FpOp::SQRT => 107,
+        FpOp::ADD | FpOp::SUB => 51,
+        FpOp::MUL => 71,
+        FpOp::DIV => 103,
+        FpOp::SGLMUL => 59,
+        FpOp::SGLDIV => 69,
+        FpOp::MOD => 70,
+        FpOp::REM => 100,
+        FpOp::SCALE => 41,
+        FpOp::GETEXP => 45,
+        FpOp::GETMAN => 31,
+        FpOp::SINCOS => 451,
+        FpOp::ETOX | FpOp::TWOTOX | FpOp::TENTOX | FpOp::ETOXM1 => 497,
+        FpOp::LOGN | FpOp::LOG10 | FpOp::LOG2 | FpOp::LOGNP1 => 525,
+        FpOp::SIN | FpOp::COS => 391,
+        FpOp::TAN => 473,
+        FpOp::ASIN | FpOp::ACOS | FpOp::ATAN => 581,
+        FpOp::SINH | FpOp::COSH | FpOp::TANH | FpOp::ATANH => 687,
+    }
+}
+
+//MULU takes 2 cycles per bit set in the multiplier, MULS per change
+//between adjacent bits with a 0 appended below it
+pub(crate) fn mul_cycles(op: &Mnemonic, src: u16) -> u32 {
+    let bits = if *op == MULS { (src ^ (src << 1)).count_ones() } else { src.count_ones() };
+    2 * bits
+}
+
+//The division algorithm of the 68000 step by step, as worked out by
+//Jorge Cwik. Overflows are detected early and cost less.
+pub(crate) fn divu_cycles(dividend: u32, divisor: u16) -> u32 {
+    if dividend >> 16 >= divisor as u32 {
+        return 10;
+    }
+    let mut cycles = 38;
+    let divisor = (divisor as u32) << 16;
+    let mut dividend = dividend;
+    for _ in 0..15 {
+        let carry = dividend & 0x80000000 != 0;
+        dividend <<= 1;
+        if carry {
+            dividend = dividend.wrapping_sub(divisor);
+        }
+        else if dividend >= divisor {
+            dividend -= divisor;
+            cycles += 1;
+        }
+        else {
+            cycles += 2;
+        }
+    }
+    2 * cycles
+}
+
+pub(crate) fn divs_cycles(dividend: i32, divisor: i16) -> u32 {
+    let mut cycles = if dividend < 0 { 7 } else { 6 };
+    let (dividend_abs, divisor_abs) = (dividend.unsigned_abs(), divisor.unsigned_abs() as u32);
+    if dividend_abs >> 16 >= divisor_abs {
+        return 2 * (cycles + 2);
+    }
+    let mut quotient = dividend_abs / divisor_abs;
+    cycles += 55;
+    if divisor >= 0 {
+        if dividend >= 0 { cycles -= 1 } else { cycles += 1 }
+    }
+    for _ in 0..15 {
+        if quotient & 0x8000 == 0 {
+            cycles += 1;
+        }
+        quotient <<= 1;
+    }
+    2 * cycles
+}
@@ -0,0 +1,52 @@
+use crate::bus::{BusError, BusResult};
+
+// Peripherals living on the bus.
+//
+// Reads and writes receive the offset from the start of the window the
+// device is mapped at. Every method has a default so a device only
+// implements what it actually does, accessing a device that has no
+// registers is a bus error.
+pub trait Device {
+    fn read_byte(&mut self, _offset: u32) -> BusResult<u8> {
+        Err(BusError)
+    }
+
+    fn write_byte(&mut self, _offset: u32, _val: u8) -> BusResult<()> {
+        Err(BusError)
+    }
+
+    fn read_word(&mut self, offset: u32) -> BusResult<u16> {
+        let hi = self.read_byte(offset)? as u16;
+        let lo = self.read_byte(offset + 1)? as u16;
+        Ok(hi << 8 | lo)
+    }
+
+    fn write_word(&mut self, offset: u32, val: u16) -> BusResult<()> {
+        self.write_byte(offset, (val >> 8) as u8)?;
+        self.write_byte(offset + 1, val as u8)
+    }
+
+    // Called when the CPU executes RESET, the CPU itself is not reset.
+    fn reset(&mut self) {}
+
+    // Interrupt priority level requested by the device, 0 when idle.
+    fn interrupt_level(&self) -> u8 {
+        0
+    }
+
+    // Called when the CPU acknowledges an interrupt at `level`.
+    // Returns the vector number to use, None for an autovector.
+    // A device should drop its request here.
+    fn acknowledge(&mut self, _level: u8) -> Option<u8> {
+        None
+    }
+
+    // Let `cycles` CPU clock cycles elapse.
+    fn tick(&mut self, _cycles: u64) {}
+
+    // Cycles until the device changes state on its own, None if it never
+    // does. Lets a stopped CPU skip straight to the next event.
+    fn next_event(&self) -> Option<u64> {
+        None
+    }
+}
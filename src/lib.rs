@@ -0,0 +1,9 @@
+#![allow(non_camel_case_types, clippy::upper_case_acronyms)]
+
+pub mod asm;
+pub mod bus;
+pub mod cpu;
+pub mod device;
+#[cfg(test)]
+#[allow(clippy::useless_vec, clippy::needless_borrow)]
+mod test;
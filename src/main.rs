@@ -1,10 +1,110 @@
-mod cpu;
-#[cfg(test)]
-mod test;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use emu68k::asm;
+use emu68k::bus::MemoryMap;
+use emu68k::cpu::*;
 
-use cpu::*;
+const USAGE: &str = "usage: 68kemu disasm FILE [--org ADDR] [--model MODEL] [--entry ADDR]... [--mit]
+       68kemu asm FILE [-o OUT] [--model MODEL]
+models: 68000, 68008, 68010, 68020, coldfire-a, coldfire-b";
+
+//Numbers given as $hex, 0xhex or decimal
+fn _number(arg: &str) -> Option<u32> {
+    if let Some(hex) = arg.strip_prefix('$').or_else(|| arg.strip_prefix("0x")) {
+        u32::from_str_radix(hex, 16).ok()
+    }
+    else {
+        arg.parse().ok()
+    }
+}
+
+fn _model(arg: &str) -> Option<CpuModel> {
+    match arg {
+        "68000" => Some(CpuModel::MC68000),
+        "68008" => Some(CpuModel::MC68008),
+        "68010" => Some(CpuModel::MC68010),
+        "68020" => Some(CpuModel::MC68020),
+        "coldfire-a" => Some(CpuModel::COLDFIRE_ISA_A),
+        "coldfire-b" => Some(CpuModel::COLDFIRE_ISA_B),
+        _ => None,
+    }
+}
+
+fn _usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2)
+}
+
+//Lists the file as loaded at the origin, following control flow from the
+//entry points when some are given
+fn _disasm(args: &[String]) -> Result<(), String> {
+    let mut file = None;
+    let mut org = 0;
+    let mut model = CpuModel::MC68000;
+    let mut entries = Vec::new();
+    let mut syntax = Syntax::MOTOROLA;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let bad = || format!("bad or missing value for {}", arg);
+        match arg.as_str() {
+            "--org" => org = args.next().and_then(|val| _number(val)).ok_or_else(bad)?,
+            "--entry" => entries.push(args.next().and_then(|val| _number(val)).ok_or_else(bad)?),
+            "--model" => model = args.next().and_then(|val| _model(val)).ok_or_else(bad)?,
+            "--mit" => syntax = Syntax::MIT,
+            _ if file.is_none() && !arg.starts_with("--") => file = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    let file = file.ok_or("no file given")?;
+    let bytes = fs::read(file).map_err(|err| format!("{}: {}", file, err))?;
+    for line in disassemble(model, org, &bytes, &entries) {
+        println!("{}", line.text(syntax));
+    }
+    Ok(())
+}
+
+//Assembles the file into a flat image starting at its lowest address,
+//written next to it with a .bin extension unless given an output
+fn _asm(args: &[String]) -> Result<(), String> {
+    let mut file = None;
+    let mut out = None;
+    let mut model = CpuModel::MC68000;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let bad = || format!("bad or missing value for {}", arg);
+        match arg.as_str() {
+            "-o" => out = Some(args.next().ok_or_else(bad)?),
+            "--model" => model = args.next().and_then(|val| _model(val)).ok_or_else(bad)?,
+            _ if file.is_none() && !arg.starts_with('-') => file = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    let file = Path::new(file.ok_or("no file given")?);
+    let out = out.map_or_else(|| file.with_extension("bin"), |out| Path::new(out).to_path_buf());
+    let program = asm::assemble_file(file, model).map_err(|err| err.to_string())?;
+    let (org, image) = program.image();
+    fs::write(&out, &image).map_err(|err| format!("{}: {}", out.display(), err))?;
+    println!("{}: {} bytes at ${:x}", out.display(), image.len(), org);
+    Ok(())
+}
 
 fn main() {
-    let mut cpu = CPU::default();
-    cpu.foo();
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("disasm") => if let Err(err) = _disasm(&args[1..]) {
+            eprintln!("68kemu: {}", err);
+            _usage();
+        },
+        Some("asm") => if let Err(err) = _asm(&args[1..]) {
+            eprintln!("68kemu: {}", err);
+            process::exit(1);
+        },
+        Some(_) => _usage(),
+        None => {
+            let cpu = CPU::new(MemoryMap::default());
+            println!("{}", cpu);
+        },
+    }
 }
@@ -1,4 +1,9 @@
+use super::asm::{self, assemble, assemble_file, AsmError};
 use super::cpu::*;
+use super::cpu::cpu_actions::{_is_negative, _is_null, _perform_add};
+use super::cpu::flags::Flags;
+use super::cpu::float::*;
+use super::cpu::timing::{cycles, divs_cycles, divu_cycles, mul_cycles};
 
 fn _get_size_from_op(size: &OpSize) -> usize {
     match size {
@@ -11,69 +16,37 @@ fn _get_size_from_op(size: &OpSize) -> usize {
 fn _move_imediate_to_mem(cpu: &mut CPU, size: OpSize) {
     let byte_size = _get_size_from_op(&size);
     let inst = Instruction::new(MOVE, size, 
-        IMEDIATE_VALUE(vec![0xde, 0xad, 0xbe, 0xef]), MEMORY_ADDR(0x100));
+        IMEDIATE_VALUE(0xdeadbeef), MEMORY_ADDR(0x100));
     cpu.execute(&inst);
     assert_eq!(cpu.get_memory_offset(0x100, byte_size), Some(&(vec![0xde, 0xad, 0xbe, 0xef])[(4 - byte_size).. 4]));
 }
 
 fn _move_imediate_to_data(cpu: &mut CPU, size: OpSize, i: usize) {
-    let byte_size = _get_size_from_op(&size);
     let inst = Instruction::new(MOVE, size,
-        IMEDIATE_VALUE(vec![0xde, 0xad, 0xbe, 0xef]), DATA_REGISTER(i));
+        IMEDIATE_VALUE(0xdeadbeef), DATA_REGISTER(i));
     cpu.execute(&inst);
-    let mut k: usize = 4 - byte_size;
-    let v = vec![0xde, 0xad, 0xbe, 0xef];
-    let mut ret = vec![0;4];
-    while k < 4 {
-        ret[k] = v[k]; 
-        k += 1;
-    }
-    assert_eq!(cpu.get_data_reg(i), Some(&ret[..]))
+    assert_eq!(cpu.reg(Register::data(i)), 0xdeadbeef & size.mask())
 }
 
 fn _move_imediate_to_addr(cpu: &mut CPU, size: OpSize, i: usize) {
-    let byte_size = _get_size_from_op(&size);
     let inst = Instruction::new(MOVE, size,
-        IMEDIATE_VALUE(vec![0xde, 0xad, 0xbe, 0xef]), ADDRESS_REGISTER(i));
+        IMEDIATE_VALUE(0xdeadbeef), ADDRESS_REGISTER(i));
     cpu.execute(&inst);
-    let mut k: usize = 4 - byte_size;
-    let v = vec![0xde, 0xad, 0xbe, 0xef];
-    let mut ret = vec![0;4];
-    while k < 4 {
-        ret[k] = v[k]; 
-        k += 1;
-    }
-    assert_eq!(cpu.get_addr_reg(i), Some(&ret[..]))
+    assert_eq!(cpu.reg(Register::addr(i)), 0xdeadbeef & size.mask())
 }
 
 fn _check_integrity(cpu: &mut CPU, size: OpSize, i: usize, j: usize) {
-    let byte_size = _get_size_from_op(&size);
     let inst = Instruction::new(MOVE, size,
         DATA_REGISTER(i), DATA_REGISTER(j));
     cpu.execute(&inst);
-    let mut k: usize = 4 - byte_size;
-    let v = vec![0xde, 0xad, 0xbe, 0xef];
-    let mut ret = vec![0;4];
-    while k < 4 {
-        ret[k] = v[k]; 
-        k += 1;
-    }
-    assert_eq!(cpu.get_data_reg(i), Some(&v[..]));
-    assert_eq!(cpu.get_data_reg(j), Some(&ret[..]));
+    assert_eq!(cpu.reg(Register::data(i)), 0xdeadbeef);
+    assert_eq!(cpu.reg(Register::data(j)), 0xdeadbeef & size.mask());
 }
 
-fn _set_byte_on_vec(size: &OpSize, val1: u8, val2: u8, val3: u8) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
-    let mut v1: Vec<u8> = vec![0, 0, 0, 0];
-    let mut v2: Vec<u8> = vec![0, 0, 0, 0];
-    let mut v3: Vec<u8> = vec![0, 0, 0, 0];
-
-    let adj = 4 - _get_size_from_op(&size);
-
-    v1[adj] = val1;
-    v2[adj] = val2;
-    v3[adj] = val3;
-
-    (v1, v2, v3)
+//Puts each value in the most significant byte of `size`
+fn _set_byte_on_vec(size: &OpSize, val1: u8, val2: u8, val3: u8) -> (u32, u32, u32) {
+    let shift = 8 * (_get_size_from_op(size) - 1);
+    ((val1 as u32) << shift, (val2 as u32) << shift, (val3 as u32) << shift)
 }
 
 fn _check_add_v_flag(size: OpSize) {
@@ -151,35 +124,35 @@ fn check_move_integrity(){
 fn move_a_small_part_registers(){
     let mut cpu : CPU = CPU::default();
     let inst = Instruction::new(MOVE, LONG, 
-        IMEDIATE_VALUE(vec![0xde, 0xad, 0xbe, 0xef]), DATA_REGISTER(5));
+        IMEDIATE_VALUE(0xdeadbeef), DATA_REGISTER(5));
     cpu.execute(&inst);
     let inst = Instruction::new(MOVE, BYTE,
-        IMEDIATE_VALUE(vec![0, 0, 0, 0xff]), DATA_REGISTER(5));
+        IMEDIATE_VALUE(0xff), DATA_REGISTER(5));
     cpu.execute(&inst);
 
-    assert_eq!(cpu.get_data_reg(5), Some(&(vec![0xde, 0xad, 0xbe, 0xff])[..]));
+    assert_eq!(cpu.reg(Register::D5), 0xdeadbeff);
 
     let inst = Instruction::new(MOVE, WORD,
-        IMEDIATE_VALUE(vec![0, 0, 0xff, 0xfe]), DATA_REGISTER(5));
+        IMEDIATE_VALUE(0xfffe), DATA_REGISTER(5));
     cpu.execute(&inst);
 
-    assert_eq!(cpu.get_data_reg(5), Some(&(vec![0xde, 0xad, 0xff, 0xfe])[..]))
+    assert_eq!(cpu.reg(Register::D5), 0xdeadfffe)
 }
 
 #[test]
 fn move_a_small_part_mem(){
     let mut cpu : CPU = CPU::default();
     let inst = Instruction::new(MOVE, LONG, 
-        IMEDIATE_VALUE(vec![0xde, 0xad, 0xbe, 0xef]), MEMORY_ADDR(0x50));
+        IMEDIATE_VALUE(0xdeadbeef), MEMORY_ADDR(0x50));
     cpu.execute(&inst);
     let inst = Instruction::new(MOVE, BYTE,
-        IMEDIATE_VALUE(vec![0, 0, 0, 0xff]), MEMORY_ADDR(0x53));
+        IMEDIATE_VALUE(0xff), MEMORY_ADDR(0x53));
     cpu.execute(&inst);
 
     assert_eq!(cpu.get_memory_offset(0x50, 4), Some(&(vec![0xde, 0xad, 0xbe, 0xff])[..]));
 
     let inst = Instruction::new(MOVE, WORD,
-        IMEDIATE_VALUE(vec![0, 0, 0xff, 0xfe]), MEMORY_ADDR(0x52));
+        IMEDIATE_VALUE(0xfffe), MEMORY_ADDR(0x52));
     cpu.execute(&inst);
 
     assert_eq!(cpu.get_memory_offset(0x50, 4), Some(&(vec![0xde, 0xad, 0xff, 0xfe])[..]))
@@ -189,17 +162,17 @@ fn move_a_small_part_mem(){
 fn move_zero(){
     let mut cpu = CPU::default();
     let inst = Instruction::new(MOVE, BYTE,
-        IMEDIATE_VALUE(vec![0xff, 0xff, 0xff, 0x00]), MEMORY_ADDR(0x6969));
+        IMEDIATE_VALUE(0xffffff00), MEMORY_ADDR(0x6969));
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0b00000100);
     
     let inst = Instruction::new(MOVE, LONG, 
-        IMEDIATE_VALUE(vec![0x00, 0x00, 0x00, 0x00]), MEMORY_ADDR(0x4242));
+        IMEDIATE_VALUE(0), MEMORY_ADDR(0x4242));
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0b00000100);
 
     let inst = Instruction::new(MOVE, WORD,
-        IMEDIATE_VALUE(vec![0xff, 0xbe, 0x00, 0x00]), MEMORY_ADDR(0x4242));
+        IMEDIATE_VALUE(0xffbe0000), MEMORY_ADDR(0x4242));
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0b00000100)
 }
@@ -208,17 +181,17 @@ fn move_zero(){
 fn move_non_zero() {
     let mut cpu = CPU::default();
     let inst = Instruction::new(MOVE, BYTE,
-        IMEDIATE_VALUE(vec![0x00, 0x00, 0x00, 0x7f]), MEMORY_ADDR(0x6969));
+        IMEDIATE_VALUE(0x7f), MEMORY_ADDR(0x6969));
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0);
     
     let inst = Instruction::new(MOVE, LONG, 
-        IMEDIATE_VALUE(vec![0x00, 0x01, 0x00, 0x00]), MEMORY_ADDR(0x4242));
+        IMEDIATE_VALUE(0x10000), MEMORY_ADDR(0x4242));
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0);
 
     let inst = Instruction::new(MOVE, WORD,
-        IMEDIATE_VALUE(vec![0xff, 0xbe, 0x02, 0x00]), MEMORY_ADDR(0x4242));
+        IMEDIATE_VALUE(0xffbe0200), MEMORY_ADDR(0x4242));
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0)
 }
@@ -227,17 +200,17 @@ fn move_non_zero() {
 fn move_neg() {
     let mut cpu = CPU::default();
     let inst = Instruction::new(MOVE, BYTE,
-        IMEDIATE_VALUE(vec![0x00, 0x00, 0x00, 0x8f]), MEMORY_ADDR(0x6969));
+        IMEDIATE_VALUE(0x8f), MEMORY_ADDR(0x6969));
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0b00001000);
     
     let inst = Instruction::new(MOVE, LONG, 
-        IMEDIATE_VALUE(vec![0xff, 0x01, 0x00, 0x00]), MEMORY_ADDR(0x4242));
+        IMEDIATE_VALUE(0xff010000), MEMORY_ADDR(0x4242));
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0b00001000);
 
     let inst = Instruction::new(MOVE, WORD,
-        IMEDIATE_VALUE(vec![0xff, 0xbe, 0xe2, 0x00]), MEMORY_ADDR(0x4242));
+        IMEDIATE_VALUE(0xffbee200), MEMORY_ADDR(0x4242));
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0b00001000)
 
@@ -247,21 +220,27 @@ fn move_neg() {
 fn move_other_flags_behaviout(){
     let mut cpu = CPU::default();
 
-    cpu.cpu_flag_move_test();
+    cpu.set_flag(Flag::N, true);
+    cpu.set_flag(Flag::C, true);
+    cpu.set_flag(Flag::X, true);
     let inst = Instruction::new(MOVE, BYTE,
-        IMEDIATE_VALUE(vec![0x00, 0x00, 0x00, 0x8f]), MEMORY_ADDR(0x6969));
+        IMEDIATE_VALUE(0x8f), MEMORY_ADDR(0x6969));
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0b00011000);
 
-    cpu.cpu_flag_move_test();
+    cpu.set_flag(Flag::N, true);
+    cpu.set_flag(Flag::C, true);
+    cpu.set_flag(Flag::X, true);
     let inst = Instruction::new(MOVE, LONG, 
-        IMEDIATE_VALUE(vec![0xff, 0x01, 0x00, 0x00]), MEMORY_ADDR(0x4242));
+        IMEDIATE_VALUE(0xff010000), MEMORY_ADDR(0x4242));
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0b00011000);
 
-    cpu.cpu_flag_move_test();
+    cpu.set_flag(Flag::N, true);
+    cpu.set_flag(Flag::C, true);
+    cpu.set_flag(Flag::X, true);
     let inst = Instruction::new(MOVE, WORD,
-        IMEDIATE_VALUE(vec![0xff, 0xbe, 0xe2, 0x00]), MEMORY_ADDR(0x4242));
+        IMEDIATE_VALUE(0xffbee200), MEMORY_ADDR(0x4242));
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0b00011000)
 }
@@ -269,11 +248,11 @@ fn move_other_flags_behaviout(){
 #[test]
 fn test_tst_functional() {
     let mut cpu = CPU::default();
-    let inst = Instruction::new(TST, BYTE, IMEDIATE_VALUE(vec![0x00, 0x00, 0x00, 0x80]), EMPTY);
+    let inst = Instruction::new(TST, BYTE, IMEDIATE_VALUE(0x80), EMPTY);
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0b00001000);
     
-    let inst = Instruction::new(TST, BYTE, IMEDIATE_VALUE(vec![0x32, 0x41, 0x23, 0x00]), EMPTY);
+    let inst = Instruction::new(TST, BYTE, IMEDIATE_VALUE(0x32412300), EMPTY);
     cpu.execute(&inst);
     assert_eq!(cpu.get_ccr(), 0b00000100);
 }
@@ -281,7 +260,7 @@ fn test_tst_functional() {
 #[test]
 fn test_add_right_result_byte() {
     let mut cpu = CPU::default();
-    let inst = Instruction::new(ADD, BYTE, IMEDIATE_VALUE(vec![0, 0, 0, 0xfe]),
+    let inst = Instruction::new(ADD, BYTE, IMEDIATE_VALUE(0xfe),
         MEMORY_ADDR(0));
     cpu.execute(&inst);
     assert_eq!(cpu.get_memory_offset(0, 1), Some(&(vec![0xfe])[..]));
@@ -290,7 +269,7 @@ fn test_add_right_result_byte() {
 #[test]
 fn test_add_right_result_word() {
     let mut cpu = CPU::default();
-    let inst = Instruction::new(ADD, WORD, IMEDIATE_VALUE(vec![0, 0, 0xff, 0xfe]),
+    let inst = Instruction::new(ADD, WORD, IMEDIATE_VALUE(0xfffe),
         MEMORY_ADDR(0));
     cpu.execute(&inst);
     assert_eq!(cpu.get_memory_offset(0, 2), Some(&(vec![0xff, 0xfe])[..]));
@@ -299,7 +278,7 @@ fn test_add_right_result_word() {
 #[test]
 fn test_add_right_result_long() {
     let mut cpu = CPU::default();
-    let inst = Instruction::new(ADD, LONG, IMEDIATE_VALUE(vec![0x12, 0, 0xff, 0xfe]),
+    let inst = Instruction::new(ADD, LONG, IMEDIATE_VALUE(0x1200fffe),
         MEMORY_ADDR(0));
     cpu.execute(&inst);
     assert_eq!(cpu.get_memory_offset(0, 4), Some(&(vec![0x12, 0, 0xff, 0xfe])[..]));
@@ -312,7 +291,7 @@ fn _test_add_correct_n_flag(size: OpSize) {
     let inst = Instruction::new(MOVE, size, IMEDIATE_VALUE(v1),
         DATA_REGISTER(1));
     cpu.execute(&inst);
-    let inst = Instruction::new(ADD, size, IMEDIATE_VALUE(v2.to_vec()),
+    let inst = Instruction::new(ADD, size, IMEDIATE_VALUE(v2),
         DATA_REGISTER(1));
     cpu.execute(&inst);
 
@@ -395,3 +374,2026 @@ fn test_add_z_flag() {
     _test_z_flag(WORD);
     _test_z_flag(LONG);
 }
+
+use super::device::Device;
+use std::cell::Cell;
+use std::rc::Rc;
+
+//Raises `level` once `fire_at` cycles have elapsed
+struct _TestTimer {
+    fire_at: u64,
+    level: u8,
+    elapsed: u64,
+    ticks: Rc<Cell<u32>>,
+    resets: Rc<Cell<u32>>,
+}
+
+impl Device for _TestTimer {
+    fn reset(&mut self) {
+        self.resets.set(self.resets.get() + 1);
+    }
+
+    fn interrupt_level(&self) -> u8 {
+        if self.elapsed >= self.fire_at { self.level } else { 0 }
+    }
+
+    fn acknowledge(&mut self, _level: u8) -> Option<u8> {
+        self.fire_at = u64::MAX;
+        None
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.ticks.set(self.ticks.get() + 1);
+        self.elapsed += cycles;
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        self.fire_at.checked_sub(self.elapsed)
+    }
+}
+
+fn _test_timer(fire_at: u64, level: u8) -> (_TestTimer, Rc<Cell<u32>>, Rc<Cell<u32>>) {
+    let ticks = Rc::new(Cell::new(0));
+    let resets = Rc::new(Cell::new(0));
+    let timer = _TestTimer { fire_at, level, elapsed: 0, ticks: ticks.clone(), resets: resets.clone() };
+    (timer, ticks, resets)
+}
+
+fn _supervisor_cpu(program: &[u8]) -> CPU<MemoryMap> {
+    _model_cpu(CpuModel::MC68000, program)
+}
+
+fn _model_cpu(model: CpuModel, program: &[u8]) -> CPU<MemoryMap> {
+    let mut cpu = CPU::with_model(MemoryMap::default(), model);
+    cpu.set_sr(0x2700);
+    cpu.set_reg(Register::A7, 0x8000);
+    cpu.load(0x400, program);
+    cpu.set_pc(0x400);
+    cpu
+}
+
+#[test]
+fn nop_only_advances_pc() {
+    let mut cpu = _supervisor_cpu(&[0x4e, 0x71]);
+    assert_eq!(cpu.step(), 4);
+    assert_eq!(cpu.get_pc(), 0x402);
+    assert_eq!(cpu.get_sr(), 0x2700);
+}
+
+#[test]
+fn stop_loads_sr_and_waits() {
+    let mut cpu = _supervisor_cpu(&[0x4e, 0x72, 0x20, 0x00]);
+    cpu.load(0x68, &[0x00, 0x00, 0x10, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_state(), CpuState::STOPPED);
+    assert_eq!(cpu.get_sr(), 0x2000);
+    assert_eq!(cpu.get_pc(), 0x404);
+
+    assert_eq!(cpu.run(100), 100);
+    assert_eq!(cpu.get_state(), CpuState::STOPPED);
+    assert_eq!(cpu.get_pc(), 0x404);
+
+    cpu.request_interrupt(2);
+    cpu.step();
+    assert_eq!(cpu.get_state(), CpuState::RUNNING);
+    assert_eq!(cpu.get_pc(), 0x1000);
+    assert_eq!(cpu.get_sr(), 0x2200);
+}
+
+#[test]
+fn stop_ignores_masked_interrupts() {
+    let mut cpu = _supervisor_cpu(&[0x4e, 0x72, 0x23, 0x00]);
+    cpu.load(0x6c, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.load(0x7c, &[0x00, 0x00, 0x30, 0x00]);
+    cpu.step();
+    cpu.request_interrupt(3);
+    cpu.run(1000);
+    assert_eq!(cpu.get_state(), CpuState::STOPPED);
+
+    cpu.request_interrupt(7);
+    cpu.step();
+    assert_eq!(cpu.get_state(), CpuState::RUNNING);
+    assert_eq!(cpu.get_pc(), 0x3000);
+    assert_eq!(cpu.get_sr(), 0x2700);
+}
+
+//Interrupt line held at whatever level the test sets until it clears it
+struct _HeldLine(Rc<Cell<u8>>);
+
+impl Device for _HeldLine {
+    fn interrupt_level(&self) -> u8 {
+        self.0.get()
+    }
+}
+
+#[test]
+fn held_level_7_interrupts_once() {
+    let mut cpu = _supervisor_cpu(&[0x4e, 0x71]);
+    let line = Rc::new(Cell::new(0));
+    cpu.bus_mut().attach(Box::new(_HeldLine(line.clone())));
+    cpu.load(0x7c, &[0x00, 0x00, 0x30, 0x00]);
+    cpu.load(0x3000, &[0x4e, 0x71, 0x4e, 0x71, 0x4e, 0x71]);
+    line.set(7);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x3000);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x3002);
+    assert_eq!(cpu.reg(Register::A7), 0x7ffa);
+    //Dropping the line and raising it again is a new edge
+    line.set(0);
+    cpu.step();
+    line.set(7);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x3000);
+    assert_eq!(cpu.reg(Register::A7), 0x7ff4);
+}
+
+#[test]
+fn stop_skips_idle_time_to_device_event() {
+    //STOP #$2000 at 0x400, handler at 0x1000 stops again
+    let mut cpu = _supervisor_cpu(&[0x4e, 0x72, 0x20, 0x00]);
+    cpu.load(0x70, &[0x00, 0x00, 0x10, 0x00]);
+    cpu.load(0x1000, &[0x4e, 0x72, 0x20, 0x00]);
+    let (timer, ticks, _) = _test_timer(1_000_000, 4);
+    cpu.bus_mut().attach(Box::new(timer));
+
+    assert_eq!(cpu.run(10_000_000), 10_000_000);
+    assert_eq!(cpu.get_state(), CpuState::STOPPED);
+    assert_eq!(cpu.get_pc(), 0x1004);
+    assert!(ticks.get() < 10);
+    //The return address of the interrupt is the instruction after STOP
+    assert_eq!(cpu.get_memory_offset(0x7ffc, 4), Some(&(vec![0x00, 0x00, 0x04, 0x04])[..]));
+}
+
+#[test]
+fn stop_is_privileged() {
+    let mut cpu = _supervisor_cpu(&[0x4e, 0x72, 0x27, 0x00]);
+    cpu.set_sr(0);
+    cpu.load(0x20, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_state(), CpuState::RUNNING);
+    assert_eq!(cpu.get_pc(), 0x2000);
+    assert!(cpu.is_supervisor());
+    assert_eq!(cpu.get_memory_offset(0x7ffc, 4), Some(&(vec![0x00, 0x00, 0x04, 0x00])[..]));
+}
+
+#[test]
+fn reset_only_resets_devices() {
+    let mut cpu = _supervisor_cpu(&[0x4e, 0x70]);
+    let (timer, _, resets) = _test_timer(u64::MAX, 1);
+    cpu.bus_mut().attach(Box::new(timer));
+    cpu.set_reg(Register::D0, 0xdeadbeef);
+    assert_eq!(cpu.step(), 132);
+    assert_eq!(resets.get(), 1);
+    assert_eq!(cpu.get_pc(), 0x402);
+    assert_eq!(cpu.get_sr(), 0x2700);
+    assert_eq!(cpu.reg(Register::D0), 0xdeadbeef);
+}
+
+#[test]
+fn undecodable_opcode_is_illegal() {
+    let mut cpu = _supervisor_cpu(&[0x4a, 0xfc]);
+    cpu.load(0x10, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+    assert_eq!(cpu.get_memory_offset(0x7ffc, 4), Some(&(vec![0x00, 0x00, 0x04, 0x00])[..]));
+}
+
+use super::bus::*;
+
+//RAM that records every access it sees
+struct _TraceBus {
+    ram: Ram,
+    trace: Vec<(FunctionCode, u32, bool)>,
+}
+
+impl Bus for _TraceBus {
+    fn read_byte(&mut self, fc: FunctionCode, addr: u32) -> BusResult<u8> {
+        self.trace.push((fc, addr, false));
+        self.ram.read_byte(fc, addr)
+    }
+
+    fn write_byte(&mut self, fc: FunctionCode, addr: u32, val: u8) -> BusResult<()> {
+        self.trace.push((fc, addr, true));
+        self.ram.write_byte(fc, addr, val)
+    }
+}
+
+#[test]
+fn cpu_uses_custom_bus() {
+    let mut ram = Ram::new(0x10000);
+    //move.w $1234.w,d0
+    ram.load(0x400, &[0x30, 0x38, 0x12, 0x34]);
+    ram.load(0x1234, &[0xbe, 0xef]);
+    let mut cpu = CPU::new(_TraceBus { ram, trace: Vec::new() });
+    cpu.set_pc(0x400);
+    cpu.step();
+
+    assert_eq!(cpu.reg(Register::D0), 0xbeef);
+    assert_eq!(cpu.bus().trace, vec![
+        (FunctionCode::USER_PROGRAM, 0x400, false),
+        (FunctionCode::USER_PROGRAM, 0x401, false),
+        (FunctionCode::USER_PROGRAM, 0x402, false),
+        (FunctionCode::USER_PROGRAM, 0x403, false),
+        (FunctionCode::USER_DATA, 0x1234, false),
+        (FunctionCode::USER_DATA, 0x1235, false),
+    ]);
+
+    cpu.bus_mut().trace.clear();
+    cpu.set_sr(0x2000);
+    cpu.set_pc(0x400);
+    cpu.step();
+    assert_eq!(cpu.bus().trace[0], (FunctionCode::SUPERVISOR_PROGRAM, 0x400, false));
+    assert_eq!(cpu.bus().trace[4], (FunctionCode::SUPERVISOR_DATA, 0x1234, false));
+}
+
+#[test]
+fn ram_access_out_of_range_is_bus_error() {
+    let mut ram = Ram::new(0x10000);
+    //move.l d0,$20000
+    ram.load(0x400, &[0x23, 0xc0, 0x00, 0x02, 0x00, 0x00]);
+    ram.load(0x08, &[0x00, 0x00, 0x20, 0x00]);
+    let mut cpu = CPU::new(ram);
+    cpu.set_sr(0x2700);
+    cpu.set_reg(Register::A7, 0x8000);
+    cpu.set_pc(0x400);
+    cpu.step();
+
+    assert_eq!(cpu.get_pc(), 0x2000);
+    //access info, address, opcode, SR, PC
+    assert_eq!(cpu.get_memory_offset(0x7ff2, 14), Some(&(vec![
+        0x00, 0x05, 0x00, 0x02, 0x00, 0x00, 0x23, 0xc0,
+        0x27, 0x04, 0x00, 0x00, 0x04, 0x06])[..]));
+    assert_eq!(cpu.reg(Register::A7), 0x7ff2);
+}
+
+#[test]
+fn ram_allocates_pages_on_first_write() {
+    let mut ram = Ram::default();
+    assert_eq!(ram.len(), 0x1000000);
+    assert_eq!(ram.resident(), 0);
+    assert_eq!(ram.read_long(FunctionCode::USER_DATA, 0xfff000), Ok(0));
+    assert_eq!(ram.resident(), 0);
+
+    //A long across a page boundary touches both pages
+    ram.write_long(FunctionCode::USER_DATA, 0x1ffe, 0xdeadbeef).unwrap();
+    assert_eq!(ram.resident(), 0x2000);
+    assert_eq!(ram.read_word(FunctionCode::USER_DATA, 0x1fff), Ok(0xadbe));
+    assert_eq!(ram.read(0x1ffd, 6), Some(vec![0, 0xde, 0xad, 0xbe, 0xef, 0]));
+    assert_eq!(ram.get(0x1ffd, 6), Some(&[0, 0xde, 0xad, 0xbe, 0xef, 0][..]));
+    assert_eq!(ram.get(0xfffffe, 4), None);
+}
+
+#[test]
+fn fork_copies_pages_on_write() {
+    let mut ram = Ram::new(0x10000);
+    //moveq #1,d0 ; move.l d0,$2000.w
+    ram.load(0x400, &[0x70, 0x01, 0x21, 0xc0, 0x20, 0x00]);
+    let mut parent = CPU::new(ram);
+    parent.set_pc(0x400);
+    parent.step();
+
+    let mut child = parent.fork();
+    assert_eq!(child.bus().shared(), 0x1000);
+    child.step();
+    assert_eq!(child.get_memory_offset(0x2000, 4), Some(&(vec![0, 0, 0, 1])[..]));
+    assert_eq!(parent.get_memory_offset(0x2000, 4), Some(&(vec![0, 0, 0, 0])[..]));
+    assert_eq!(child.bus().shared(), 0x1000);
+
+    //Writing to a shared page copies it
+    parent.set_reg(Register::D0, 2);
+    parent.load(0x402, &[0x21, 0xc0, 0x04, 0x10]);
+    assert_eq!(parent.bus().shared(), 0);
+    parent.step();
+    assert_eq!(parent.get_memory_offset(0x410, 4), Some(&(vec![0, 0, 0, 2])[..]));
+    assert_eq!(child.get_memory_offset(0x402, 4), Some(&(vec![0x21, 0xc0, 0x20, 0x00])[..]));
+    assert_eq!(child.reg(Register::D0), 1);
+}
+
+#[test]
+fn bus_error_while_stacking_halts() {
+    let mut ram = Ram::new(0x10000);
+    ram.load(0x400, &[0x4a, 0xfc]);
+    let mut cpu = CPU::new(ram);
+    cpu.set_sr(0x2700);
+    cpu.set_reg(Register::A7, 0x100000);
+    cpu.set_pc(0x400);
+    cpu.step();
+    assert_eq!(cpu.get_state(), CpuState::HALTED);
+    assert_eq!(cpu.run(100), 0);
+}
+
+use std::cell::RefCell;
+
+type _AciaOutput = Rc<RefCell<Vec<(u32, u8)>>>;
+
+//Serial port with a status and a data register, raises an interrupt
+//on vector 0x40 when a byte gets written
+struct _TestAcia {
+    output: _AciaOutput,
+    pending: bool,
+}
+
+impl Device for _TestAcia {
+    fn read_byte(&mut self, offset: u32) -> BusResult<u8> {
+        match offset {
+            0 => Ok(0x02),
+            _ => Err(BusError),
+        }
+    }
+
+    fn write_byte(&mut self, offset: u32, val: u8) -> BusResult<()> {
+        self.output.borrow_mut().push((offset, val));
+        self.pending = true;
+        Ok(())
+    }
+
+    fn interrupt_level(&self) -> u8 {
+        if self.pending { 5 } else { 0 }
+    }
+
+    fn acknowledge(&mut self, _level: u8) -> Option<u8> {
+        self.pending = false;
+        Some(0x40)
+    }
+}
+
+fn _acia_cpu(program: &[u8]) -> (CPU<MemoryMap>, _AciaOutput) {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut cpu = _supervisor_cpu(program);
+    cpu.bus_mut().map(0xff0000, 0xff0001, Box::new(_TestAcia { output: output.clone(), pending: false }));
+    (cpu, output)
+}
+
+#[test]
+fn mapped_device_gets_accesses_instead_of_ram() {
+    //move.b d0,$ff0001 ; move.b $ff0000,d1
+    let (mut cpu, output) = _acia_cpu(&[0x13, 0xc0, 0x00, 0xff, 0x00, 0x01, 0x12, 0x39, 0x00, 0xff, 0x00, 0x00]);
+    cpu.load(0xff0000, &[0xaa, 0xbb]);
+    cpu.set_reg(Register::D0, 0x41);
+    cpu.step();
+    cpu.step();
+
+    assert_eq!(*output.borrow(), vec![(1, 0x41)]);
+    assert_eq!(cpu.reg(Register::D1), 0x2);
+    assert_eq!(cpu.get_memory_offset(0xff0000, 2), Some(&(vec![0xaa, 0xbb])[..]));
+}
+
+#[test]
+fn word_across_device_boundary_is_split() {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut map = MemoryMap::default();
+    map.map(0x1001, 0x1002, Box::new(_TestAcia { output: output.clone(), pending: false }));
+    map.write_word(FunctionCode::SUPERVISOR_DATA, 0x1000, 0x1234).unwrap();
+    map.write_word(FunctionCode::SUPERVISOR_DATA, 0x1002, 0x5678).unwrap();
+
+    assert_eq!(*output.borrow(), vec![(0, 0x34), (1, 0x56)]);
+    assert_eq!(map.as_mut().get(0x1000, 4), Some(&(vec![0x12, 0, 0, 0x78])[..]));
+    assert_eq!(map.read_word(FunctionCode::SUPERVISOR_DATA, 0x1000), Ok(0x1202));
+    assert_eq!(map.read_word(FunctionCode::SUPERVISOR_DATA, 0x1002), Err(BusError));
+}
+
+#[test]
+#[should_panic]
+fn overlapping_devices_are_rejected() {
+    let mut map = MemoryMap::default();
+    map.map(0x1000, 0x1fff, Box::new(_TestAcia { output: Rc::default(), pending: false }));
+    map.map(0x0800, 0x1000, Box::new(_TestAcia { output: Rc::default(), pending: false }));
+}
+
+#[test]
+fn mapped_device_raises_vectored_interrupt() {
+    //move.b d0,$ff0001 ; nop
+    let (mut cpu, _) = _acia_cpu(&[0x13, 0xc0, 0x00, 0xff, 0x00, 0x01, 0x4e, 0x71]);
+    cpu.load(0x100, &[0x00, 0x00, 0x30, 0x00]);
+    cpu.set_sr(0x2000);
+    cpu.step();
+    cpu.step();
+
+    assert_eq!(cpu.get_pc(), 0x3000);
+    assert_eq!(cpu.get_sr(), 0x2504);
+    assert_eq!(cpu.bus().interrupt_level(), 0);
+}
+
+fn _rom_cpu(policy: WritePolicy) -> CPU<MemoryMap> {
+    //move.w d0,$100.w
+    let mut cpu = _supervisor_cpu(&[0x31, 0xc0, 0x01, 0x00]);
+    cpu.bus_mut().map_rom(0x08, &[0, 0, 0x20, 0], policy);
+    cpu.bus_mut().map_rom(0x100, &[0xca, 0xfe], policy);
+    cpu.set_reg(Register::D0, 0x1234);
+    cpu
+}
+
+#[test]
+fn rom_writes_are_ignored() {
+    for policy in [WritePolicy::IGNORE, WritePolicy::LOG] {
+        let mut cpu = _rom_cpu(policy);
+        cpu.step();
+        assert_eq!(cpu.get_pc(), 0x404);
+        assert_eq!(cpu.get_memory_offset(0x100, 2), Some(&(vec![0xca, 0xfe])[..]));
+        assert_eq!(cpu.bus().rom_writes(), 1);
+        let logged = if policy == WritePolicy::LOG { vec![RomWrite { addr: 0x100, val: 0x1234, len: 2 }] } else { vec![] };
+        assert_eq!(cpu.bus_mut().take_rom_log(), logged);
+        assert_eq!(cpu.bus_mut().take_rom_log(), vec![]);
+    }
+}
+
+#[test]
+fn rom_write_can_be_a_bus_error() {
+    let mut cpu = _rom_cpu(WritePolicy::BUS_ERROR);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+    assert_eq!(cpu.get_memory_offset(0x100, 2), Some(&(vec![0xca, 0xfe])[..]));
+    //Write in supervisor data space at 0x100
+    assert_eq!(cpu.get_memory_offset(0x7ff2, 6), Some(&(vec![0x00, 0x05, 0x00, 0x00, 0x01, 0x00])[..]));
+}
+
+#[test]
+fn protected_ram_is_only_checked_on_overlap() {
+    let mut map = MemoryMap::default();
+    map.protect(0x2000, 0x2003, WritePolicy::BUS_ERROR);
+    assert_eq!(map.write_word(FunctionCode::USER_DATA, 0x1ffe, 0x1111), Ok(()));
+    assert_eq!(map.write_byte(FunctionCode::USER_DATA, 0x2004, 0x22), Ok(()));
+    assert_eq!(map.write_word(FunctionCode::USER_DATA, 0x1fff, 0x3333), Err(BusError));
+    assert_eq!(map.write_long(FunctionCode::USER_DATA, 0x2000, 0x44444444), Err(BusError));
+    assert_eq!(map.read_long(FunctionCode::USER_DATA, 0x1ffe), Ok(0x11110000));
+    assert_eq!(map.rom_writes(), 2);
+}
+
+#[test]
+fn protected_long_writes_are_refused_as_a_whole() {
+    let mut map = MemoryMap::default();
+    map.map_rom(0, &[], WritePolicy::BUS_ERROR);
+    map.map_rom(0x2002, &[0xca, 0xfe], WritePolicy::BUS_ERROR);
+    let output = _AciaOutput::default();
+    map.map(0x3000, 0x3001, Box::new(_TestAcia { output: output.clone(), pending: false }));
+    map.protect(0x3002, 0x3003, WritePolicy::BUS_ERROR);
+    assert_eq!(map.write_long(FunctionCode::USER_DATA, 0x2000, 0x11223344), Err(BusError));
+    assert_eq!(map.read_long(FunctionCode::USER_DATA, 0x2000), Ok(0x0000cafe));
+    assert_eq!(map.write_long(FunctionCode::USER_DATA, 0x3000, 0x55667788), Err(BusError));
+    assert!(output.borrow().is_empty());
+    assert_eq!(map.write_long(FunctionCode::USER_DATA, 0x1ffc, 0x99aabbcc), Ok(()));
+    assert_eq!(map.rom_writes(), 2);
+}
+
+#[test]
+fn registers_read_back_as_u32() {
+    let mut cpu = CPU::default();
+    for i in 0..8 {
+        cpu.set_reg(Register::data(i), 0x11111111 * i as u32);
+        cpu.set_reg(Register::addr(i), 0x80000000 | i as u32);
+    }
+    cpu.set_reg(Register::PC, 0x1234);
+    for i in 0..8 {
+        assert_eq!(cpu.reg(Register::data(i)), 0x11111111 * i as u32);
+        assert_eq!(cpu.reg(Register::addr(i)), 0x80000000 | i as u32);
+    }
+    assert_eq!(cpu.reg(Register::D3), 0x33333333);
+    assert_eq!(cpu.reg(Register::PC), 0x1234);
+}
+
+#[test]
+fn stack_pointers_follow_supervisor_bit() {
+    let mut cpu = CPU::default();
+    cpu.set_reg(Register::A7, 0x1000);
+    cpu.set_reg(Register::SSP, 0x8000);
+    assert_eq!(cpu.reg(Register::USP), 0x1000);
+
+    cpu.set_reg(Register::SR, 0x2700);
+    assert_eq!(cpu.reg(Register::A7), 0x8000);
+    assert_eq!(cpu.reg(Register::SSP), 0x8000);
+    assert_eq!(cpu.reg(Register::USP), 0x1000);
+
+    cpu.set_reg(Register::USP, 0x2000);
+    cpu.set_reg(Register::SR, 0x0000);
+    assert_eq!(cpu.reg(Register::A7), 0x2000);
+    assert_eq!(cpu.reg(Register::SSP), 0x8000);
+}
+
+#[test]
+fn flags_map_to_ccr_bits() {
+    let mut cpu = CPU::default();
+    cpu.set_flag(Flag::X, true);
+    cpu.set_flag(Flag::Z, true);
+    cpu.set_flag(Flag::C, true);
+    assert_eq!(cpu.get_ccr(), 0b00010101);
+    assert_eq!(cpu.reg(Register::SR), 0x0015);
+    cpu.set_flag(Flag::C, false);
+    assert!(cpu.flag(Flag::X));
+    assert!(!cpu.flag(Flag::C));
+    assert!(!cpu.flag(Flag::N));
+
+    //Only the implemented SR bits stick
+    cpu.set_reg(Register::SR, 0xffff);
+    assert_eq!(cpu.reg(Register::SR), 0xa71f);
+}
+
+fn _run(cpu: &mut CPU<MemoryMap>, steps: usize) {
+    for _ in 0..steps {
+        cpu.step();
+    }
+}
+
+#[test]
+fn decode_completes_operands_from_extension_words() {
+    //move.l d0,(a1)+ ; lea 8(a6),a0 ; bne.s $400
+    let mut cpu = _supervisor_cpu(&[0x22, 0xc0, 0x41, 0xee, 0x00, 0x08, 0x66, 0xf8]);
+    assert_eq!(cpu.decode(), Ok(Instruction::new(MOVE, LONG, DATA_REGISTER(0), POSTINCREMENT(1))));
+    assert_eq!(cpu.decode(), Ok(Instruction::new(LEA, LONG, DISPLACEMENT(6, 8), ADDRESS_REGISTER(0))));
+    assert_eq!(cpu.decode(), Ok(Instruction::new(BCC(Condition::NE), BYTE, PC_DISPLACEMENT(0x408, -8), EMPTY)));
+    assert_eq!(cpu.get_pc(), 0x408);
+}
+
+#[test]
+fn opcode_table_decodes_every_word() {
+    //Extension words read as zero
+    let mut cpu = _supervisor_cpu(&[]);
+    for opcode in 0..=0xffff_u16 {
+        cpu.load(0x400, &opcode.to_be_bytes());
+        cpu.set_pc(0x400);
+        let inst = cpu.decode().unwrap();
+        let expected = match opcode {
+            0x4e71 => Some(NOP),
+            0x4afc => Some(ILLEGAL),
+            0xa000..=0xafff => Some(LINE_A),
+            0xf000..=0xffff => Some(LINE_F),
+            _ => None,
+        };
+        if let Some(op) = expected {
+            assert_eq!(*inst.get_op(), op);
+        }
+    }
+}
+
+#[test]
+fn moves_through_every_addressing_mode() {
+    //move.w (a0)+,d0 ; move.w d0,-(a1) ; move.l 4(a0),d1 ; move.b 2(a0,d2.w),d3
+    //move.w $1000.w,d4 ; move.w $400(pc),d5 ; move.w (a1),d6
+    let mut cpu = _supervisor_cpu(&[0x30, 0x18, 0x33, 0x00, 0x22, 0x28, 0x00, 0x04, 0x16, 0x30, 0x20, 0x02,
+        0x38, 0x38, 0x10, 0x00, 0x3a, 0x3a, 0xff, 0xee, 0x3c, 0x11]);
+    cpu.load(0x1000, &[0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x11, 0x22]);
+    cpu.set_reg(Register::A0, 0x1000);
+    cpu.set_reg(Register::A1, 0x2000);
+    cpu.set_reg(Register::D2, 1);
+    for _ in 0..7 {
+        cpu.step();
+    }
+    assert_eq!(cpu.reg(Register::D0), 0x1234);
+    assert_eq!(cpu.reg(Register::A0), 0x1002);
+    assert_eq!(cpu.reg(Register::A1), 0x1ffe);
+    assert_eq!(cpu.reg(Register::D6), 0x1234);
+    assert_eq!(cpu.reg(Register::D1), 0xdef01122);
+    assert_eq!(cpu.reg(Register::D3), 0xbc);
+    assert_eq!(cpu.reg(Register::D4), 0x1234);
+    assert_eq!(cpu.reg(Register::D5), 0x3018);
+}
+
+#[test]
+fn counted_loop_runs_to_completion() {
+    //moveq #10,d0 ; moveq #0,d1 ; loop: add.w d0,d1 ; subq.w #1,d0 ; bne.s loop
+    let mut cpu = _supervisor_cpu(&[0x70, 0x0a, 0x72, 0x00, 0xd2, 0x40, 0x53, 0x40, 0x66, 0xfa]);
+    _run(&mut cpu, 32);
+    assert_eq!(cpu.reg(Register::D1), 55);
+    assert_eq!(cpu.reg(Register::D0), 0);
+    assert!(cpu.flag(Flag::Z));
+    assert_eq!(cpu.get_pc(), 0x40a);
+}
+
+#[test]
+fn subroutine_call_and_return() {
+    //jsr $410.w ; nop ... $410: moveq #1,d0 ; rts
+    let mut cpu = _supervisor_cpu(&[0x4e, 0xb8, 0x04, 0x10, 0x4e, 0x71]);
+    cpu.load(0x410, &[0x70, 0x01, 0x4e, 0x75]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x410);
+    assert_eq!(cpu.get_memory_offset(0x7ffc, 4), Some(&(vec![0x00, 0x00, 0x04, 0x04])[..]));
+    _run(&mut cpu, 2);
+    assert_eq!(cpu.get_pc(), 0x404);
+    assert_eq!(cpu.reg(Register::D0), 1);
+    assert_eq!(cpu.reg(Register::A7), 0x8000);
+}
+
+#[test]
+fn frames_loops_and_conditional_sets() {
+    //link a6,#-8 ; moveq #2,d0 ; loop: dbra d0,loop ; seq d1 ; unlk a6
+    let mut cpu = _supervisor_cpu(&[0x4e, 0x56, 0xff, 0xf8, 0x70, 0x02, 0x51, 0xc8, 0xff, 0xfe,
+        0x57, 0xc1, 0x4e, 0x5e]);
+    cpu.set_reg(Register::A6, 0x1234);
+    cpu.set_reg(Register::D1, 0x12345678);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::A6), 0x7ffc);
+    assert_eq!(cpu.reg(Register::A7), 0x7ff4);
+    _run(&mut cpu, 6);
+    assert_eq!(cpu.reg(Register::D0), 0xffff);
+    assert_eq!(cpu.reg(Register::D1), 0x12345600);
+    assert_eq!(cpu.reg(Register::A6), 0x1234);
+    assert_eq!(cpu.reg(Register::A7), 0x8000);
+    assert_eq!(cpu.get_pc(), 0x40e);
+}
+
+#[test]
+fn movem_saves_and_restores_registers() {
+    //movem.l d0-d1/a0,-(a7) ; moveq #0,d0 ; moveq #0,d1 ; movem.l (a7)+,d0-d1/a0
+    let mut cpu = _supervisor_cpu(&[0x48, 0xe7, 0xc0, 0x80, 0x70, 0x00, 0x72, 0x00, 0x4c, 0xdf, 0x01, 0x03]);
+    cpu.set_reg(Register::D0, 0x11111111);
+    cpu.set_reg(Register::D1, 0x22222222);
+    cpu.set_reg(Register::A0, 0x33333333);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::A7), 0x7ff4);
+    assert_eq!(cpu.get_memory_offset(0x7ff4, 4), Some(&(vec![0x11, 0x11, 0x11, 0x11])[..]));
+    assert_eq!(cpu.get_memory_offset(0x7ffc, 4), Some(&(vec![0x33, 0x33, 0x33, 0x33])[..]));
+    _run(&mut cpu, 3);
+    assert_eq!(cpu.reg(Register::D0), 0x11111111);
+    assert_eq!(cpu.reg(Register::D1), 0x22222222);
+    assert_eq!(cpu.reg(Register::A0), 0x33333333);
+    assert_eq!(cpu.reg(Register::A7), 0x8000);
+}
+
+#[test]
+fn indexed_operand_multiply_and_divide() {
+    //lea $2000.w,a1 ; moveq #4,d2 ; move.w 6(a1,d2.w),d3 ; mulu.w #3,d3 ; divu.w #7,d3
+    let mut cpu = _supervisor_cpu(&[0x43, 0xf8, 0x20, 0x00, 0x74, 0x04, 0x36, 0x31, 0x20, 0x06,
+        0xc6, 0xfc, 0x00, 0x03, 0x86, 0xfc, 0x00, 0x07]);
+    cpu.load(0x200a, &[0x00, 0x10]);
+    _run(&mut cpu, 4);
+    assert_eq!(cpu.reg(Register::D3), 48);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D3), 0x00060006);
+}
+
+#[test]
+fn shifts_and_rotates_set_flags() {
+    //asl.b #2,d0 ; ror.w #1,d1
+    let mut cpu = _supervisor_cpu(&[0xe5, 0x00, 0xe2, 0x59]);
+    cpu.set_reg(Register::D0, 0x60);
+    cpu.set_reg(Register::D1, 0x1);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D0), 0x80);
+    assert_eq!(cpu.get_ccr(), 0b00011011);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D1), 0x8000);
+    //X is left alone by ROR
+    assert_eq!(cpu.get_ccr(), 0b00011001);
+}
+
+#[test]
+fn abcd_adds_packed_decimal() {
+    //abcd d0,d1
+    let mut cpu = _supervisor_cpu(&[0xc3, 0x00]);
+    cpu.set_reg(Register::D0, 0x19);
+    cpu.set_reg(Register::D1, 0x28);
+    cpu.set_flag(Flag::Z, true);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D1), 0x47);
+    assert!(!cpu.flag(Flag::C));
+    assert!(!cpu.flag(Flag::Z));
+}
+
+#[test]
+fn extended_arithmetic_chains_through_x() {
+    //add.l d3,d1 ; addx.l d2,d0 ; cmp.l d0,d2
+    let mut cpu = _supervisor_cpu(&[0xd2, 0x83, 0xd1, 0x82, 0xb4, 0x80]);
+    cpu.set_reg(Register::D0, 1);
+    cpu.set_reg(Register::D1, 0xffffffff);
+    cpu.set_reg(Register::D2, 2);
+    cpu.set_reg(Register::D3, 1);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D1), 0);
+    assert!(cpu.flag(Flag::X) && cpu.flag(Flag::Z));
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D0), 4);
+    assert!(!cpu.flag(Flag::X) && !cpu.flag(Flag::Z));
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D2), 2);
+    assert!(cpu.flag(Flag::C) && cpu.flag(Flag::N));
+    assert!(!cpu.flag(Flag::X));
+}
+
+#[test]
+fn test_perform_add_basic() {
+    assert_eq!(_perform_add(&BYTE, 0xff, 0x1), (true, 0));
+    assert_eq!(_perform_add(&BYTE, 0x1, 0x2), (false, 0x3));
+    assert_eq!(_perform_add(&BYTE, 0x1, 0), (false, 0x1));
+}
+
+#[test]
+fn test_perform_add_report() {
+    let v1 = 0x000000ff;
+    let v2 = 0x00000001;
+
+    assert_eq!(_perform_add(&LONG, v1, v2), (false, 0x100));
+    assert_eq!(_perform_add(&WORD, v1, v2), (false, 0x100));
+    assert_eq!(_perform_add(&BYTE, v1, v2), (true, 0));
+}
+
+#[test]
+fn test_perform_add_max() {
+    let v1 = 0xff0000ff;
+    let v2 = 0x01000001;
+
+    assert_eq!(_perform_add(&LONG, v1, v2), (true, 0x00000100));
+    assert_eq!(_perform_add(&WORD, v1, v2), (false, 0x100));
+}
+
+#[test]
+fn test_sized_flags_ignore_upper_bits() {
+    assert!(_is_null(0xffffff00, &BYTE));
+    assert!(!_is_null(0xffffff00, &WORD));
+    assert!(_is_negative(0x00008000, &WORD));
+    assert!(!_is_negative(0x00008000, &LONG));
+}
+
+//CCR of `dst + src + x` or `dst - src - x` worked out on wider integers,
+//Z only being cleared when `extended`
+fn _eager_ccr(sub: bool, src: u32, dst: u32, x: bool, extended: bool, z: bool, size: OpSize) -> u8 {
+    let bits = 8 * _get_size_from_op(&size) as u32;
+    let signed = |val: u32| (val as i64) << (64 - bits) >> (64 - bits);
+    let (x, mask) = (x as i64, size.mask() as i64);
+    let (wide, unsigned) = if sub {
+        (signed(dst) - signed(src) - x, (dst as i64 & mask) - (src as i64 & mask) - x)
+    }
+    else {
+        (signed(dst) + signed(src) + x, (dst as i64 & mask) + (src as i64 & mask) + x)
+    };
+    let result = wide & mask;
+    let mut ccr = 0;
+    if result >> (bits - 1) != 0 { ccr |= Flag::N as u8; }
+    if result == 0 && (!extended || z) { ccr |= Flag::Z as u8; }
+    if wide != signed(result as u32) { ccr |= Flag::V as u8; }
+    if !(0..=mask).contains(&unsigned) { ccr |= Flag::C as u8 | Flag::X as u8; }
+    ccr
+}
+
+#[test]
+fn lazy_flags_hold_for_every_size() {
+    let values = [0, 1, 0x7f, 0x80, 0xff, 0x7fff, 0x8000, 0xffff, 0x7fffffff, 0x80000000, 0xffffffff];
+    for size in [BYTE, WORD, LONG] {
+        for &src in &values {
+            for &dst in &values {
+                for ccr in [0, 0b10100] {
+                    let (x, z) = (ccr & 0b10000 != 0, ccr & 0b00100 != 0);
+                    for (op, sub, extended) in [(ADD, false, false), (SUB, true, false), (ADDX, false, true), (SUBX, true, true)] {
+                        let mut cpu = CPU::default();
+                        cpu.set_ccr(ccr);
+                        cpu.set_reg(Register::D0, src);
+                        cpu.set_reg(Register::D1, dst);
+                        cpu.execute(&Instruction::new(op, size, DATA_REGISTER(0), DATA_REGISTER(1)));
+                        let x = x && extended;
+                        assert_eq!(cpu.get_ccr(), _eager_ccr(sub, src, dst, x, extended, z, size),
+                            "{:?}.{:?} {:#x},{:#x} with ccr {:#x}", op, size, src, dst, ccr);
+                    }
+                    let mut cpu = CPU::default();
+                    cpu.set_ccr(ccr);
+                    cpu.set_reg(Register::D0, src);
+                    cpu.execute(&Instruction::new(NEGX, size, DATA_REGISTER(0), EMPTY));
+                    assert_eq!(cpu.get_ccr(), _eager_ccr(true, src, 0, x, true, z, size));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn x_carries_through_extended_chains() {
+    //A 96 bit 0x1_ffffffff_ffffffff + 1 then subtracting it back
+    let mut cpu = CPU::default();
+    let (lo, mid, hi) = (DATA_REGISTER(0), DATA_REGISTER(1), DATA_REGISTER(2));
+    cpu.set_reg(Register::D0, 0xffffffff);
+    cpu.set_reg(Register::D1, 0xffffffff);
+    cpu.set_reg(Register::D2, 1);
+    cpu.set_reg(Register::D3, 1);
+    cpu.execute(&Instruction::new(ADD, LONG, DATA_REGISTER(3), lo));
+    cpu.execute(&Instruction::new(ADDX, LONG, DATA_REGISTER(4), mid));
+    assert_eq!(cpu.get_ccr(), 0b10101);
+    cpu.execute(&Instruction::new(ADDX, LONG, DATA_REGISTER(4), hi));
+    assert_eq!((cpu.reg(Register::D2), cpu.reg(Register::D1), cpu.reg(Register::D0)), (2, 0, 0));
+    assert_eq!(cpu.get_ccr(), 0);
+
+    cpu.execute(&Instruction::new(SUB, LONG, DATA_REGISTER(3), lo));
+    cpu.execute(&Instruction::new(SUBX, LONG, DATA_REGISTER(4), mid));
+    assert_eq!(cpu.get_ccr(), 0b11001);
+    cpu.execute(&Instruction::new(SUBX, LONG, DATA_REGISTER(4), hi));
+    assert_eq!((cpu.reg(Register::D2), cpu.reg(Register::D1), cpu.reg(Register::D0)), (1, 0xffffffff, 0xffffffff));
+    assert_eq!(cpu.get_ccr(), 0);
+
+    //Shifts carry into X but a null count leaves it
+    cpu.set_reg(Register::D5, 0x8001);
+    cpu.execute(&Instruction::new(LSL, WORD, IMEDIATE_VALUE(1), DATA_REGISTER(5)));
+    assert_eq!(cpu.get_ccr(), 0b10001);
+    cpu.execute(&Instruction::new(LSR, WORD, DATA_REGISTER(4), DATA_REGISTER(5)));
+    assert_eq!(cpu.get_ccr(), 0b10000);
+    cpu.execute(&Instruction::new(ROXR, WORD, IMEDIATE_VALUE(1), DATA_REGISTER(5)));
+    assert_eq!(cpu.reg(Register::D5), 0x8001);
+    assert_eq!(cpu.get_ccr(), 0b01000);
+}
+
+#[test]
+fn lazy_flags_match_eager_evaluation() {
+    for src in 0..=0xffu32 {
+        for dst in 0..=0xffu32 {
+            let mut flags = Flags::new(0);
+            flags.add(src, dst, (src + dst) & 0xff, &BYTE);
+            assert_eq!(flags.ccr(), _eager_ccr(false, src, dst, false, false, false, BYTE));
+            flags.sub(src, dst, dst.wrapping_sub(src) & 0xff, &BYTE);
+            assert_eq!(flags.ccr(), _eager_ccr(true, src, dst, false, false, false, BYTE));
+        }
+    }
+}
+
+#[test]
+fn logic_and_compare_keep_x() {
+    let mut flags = Flags::new(0);
+    flags.add(0xff, 0x01, 0, &BYTE);
+    flags.logic(0x8000, &WORD);
+    assert_eq!(flags.ccr(), 0b11000);
+    flags.compare(1, 0, 0xffffffff, &LONG);
+    assert_eq!(flags.ccr(), 0b11001);
+    flags.sub(0, 1, 1, &LONG);
+    assert_eq!(flags.ccr(), 0);
+}
+
+#[test]
+fn traps_stack_the_next_instruction() {
+    //divu.w d1,d0 with d1 = 0, then trap #3
+    let mut cpu = _supervisor_cpu(&[0x80, 0xc1, 0x4e, 0x43]);
+    cpu.load(0x14, &[0x00, 0x00, 0x04, 0x02]);
+    cpu.load(0x8c, &[0x00, 0x00, 0x30, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x402);
+    assert_eq!(cpu.get_memory_offset(0x7ffc, 4), Some(&(vec![0x00, 0x00, 0x04, 0x02])[..]));
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x3000);
+    assert_eq!(cpu.get_memory_offset(0x7ff6, 4), Some(&(vec![0x00, 0x00, 0x04, 0x04])[..]));
+}
+
+#[test]
+fn line_a_traps_on_the_opcode() {
+    let mut cpu = _supervisor_cpu(&[0xa0, 0x00]);
+    cpu.load(0x28, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+    assert_eq!(cpu.get_memory_offset(0x7ffc, 4), Some(&(vec![0x00, 0x00, 0x04, 0x00])[..]));
+}
+
+#[test]
+fn trace_bit_traps_after_each_instruction() {
+    let mut cpu = _supervisor_cpu(&[0x4e, 0x71]);
+    cpu.set_sr(0xa700);
+    cpu.load(0x24, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+    assert_eq!(cpu.get_sr(), 0x2700);
+    assert_eq!(cpu.get_memory_offset(0x7ffa, 6), Some(&(vec![0xa7, 0x00, 0x00, 0x00, 0x04, 0x02])[..]));
+}
+
+#[test]
+fn block_cache_matches_uncached_execution() {
+    //moveq #10,d0 ; moveq #0,d1 ; loop: add.w d0,d1 ; subq.w #1,d0 ; bne.s loop
+    let program = [0x70, 0x0a, 0x72, 0x00, 0xd2, 0x40, 0x53, 0x40, 0x66, 0xfa];
+    let mut uncached = _supervisor_cpu(&program);
+    let mut cached = _supervisor_cpu(&program);
+    cached.set_block_cache(true);
+    for _ in 0..32 {
+        assert_eq!(cached.step(), uncached.step());
+        assert_eq!(cached.get_pc(), uncached.get_pc());
+    }
+    assert_eq!(cached.reg(Register::D1), 55);
+    assert_eq!(cached.get_sr(), uncached.get_sr());
+    assert_eq!(cached.get_cycles(), uncached.get_cycles());
+}
+
+#[test]
+fn block_cache_sees_self_modifying_code() {
+    //move.w #$7007,$406.w ; moveq #1,d0 ; move.w #$7205,$40e.w ; bra.s $40e
+    let mut cpu = _supervisor_cpu(&[0x31, 0xfc, 0x70, 0x07, 0x04, 0x06, 0x70, 0x01,
+        0x31, 0xfc, 0x72, 0x05, 0x04, 0x0e, 0x60, 0xfe]);
+    cpu.set_block_cache(true);
+    _run(&mut cpu, 2);
+    assert_eq!(cpu.reg(Register::D0), 7);
+    //The branch to itself got cached before being overwritten
+    _run(&mut cpu, 2);
+    assert_eq!(cpu.reg(Register::D1), 5);
+    assert_eq!(cpu.get_pc(), 0x410);
+}
+
+#[test]
+fn block_cache_shares_blocks_between_aliases() {
+    //jsr $01002000 ; move.w #$7009,$2000.w ; jsr $01002000
+    let mut cpu = _supervisor_cpu(&[0x4e, 0xb9, 0x01, 0x00, 0x20, 0x00, 0x31, 0xfc, 0x70, 0x09, 0x20, 0x00,
+        0x4e, 0xb9, 0x01, 0x00, 0x20, 0x00]);
+    cpu.set_block_cache(true);
+    //moveq #3,d0 ; rts
+    cpu.load(0x2000, &[0x70, 0x03, 0x4e, 0x75]);
+    _run(&mut cpu, 2);
+    assert_eq!(cpu.reg(Register::D0), 3);
+    assert_eq!(cpu.get_pc(), 0x01002002);
+    _run(&mut cpu, 4);
+    assert_eq!(cpu.reg(Register::D0), 9);
+    _run(&mut cpu, 1);
+    assert_eq!(cpu.get_pc(), 0x412);
+}
+
+#[test]
+fn block_cache_runs_code_copied_from_rom() {
+    //jsr $2000.w ; move.l $1000.w,$2000.w ; jsr $2000.w
+    let mut cpu = _supervisor_cpu(&[0x4e, 0xb8, 0x20, 0x00, 0x21, 0xf8, 0x10, 0x00, 0x20, 0x00,
+        0x4e, 0xb8, 0x20, 0x00]);
+    cpu.set_block_cache(true);
+    //moveq #9,d0 ; rts in ROM, moveq #3,d0 ; rts in RAM
+    cpu.bus_mut().map_rom(0x1000, &[0x70, 0x09, 0x4e, 0x75], WritePolicy::BUS_ERROR);
+    cpu.load(0x2000, &[0x70, 0x03, 0x4e, 0x75]);
+    _run(&mut cpu, 3);
+    assert_eq!(cpu.reg(Register::D0), 3);
+    _run(&mut cpu, 3);
+    assert_eq!(cpu.reg(Register::D0), 9);
+    _run(&mut cpu, 1);
+    assert_eq!(cpu.get_pc(), 0x40e);
+}
+
+fn _cycles(op: Mnemonic, size: OpSize, lhs: DataContainer, trg: DataContainer) -> u32 {
+    cycles(&Instruction::new(op, size, lhs, trg))
+}
+
+#[test]
+fn opcode_times_match_the_manual() {
+    assert_eq!(_cycles(MOVE, WORD, DATA_REGISTER(0), DATA_REGISTER(1)), 4);
+    assert_eq!(_cycles(MOVE, WORD, PREDECREMENT(0), PREDECREMENT(1)), 14);
+    assert_eq!(_cycles(MOVE, LONG, MEMORY_ADDR(0), EA(7, 1)), 36);
+    assert_eq!(_cycles(MOVE, BYTE, EA(6, 0), EA(6, 1)), 24);
+    assert_eq!(_cycles(ADD, LONG, DATA_REGISTER(0), DATA_REGISTER(1)), 8);
+    assert_eq!(_cycles(ADD, LONG, ADDRESS_INDIRECT(0), DATA_REGISTER(1)), 14);
+    assert_eq!(_cycles(ADD, WORD, DATA_REGISTER(1), EA(5, 0)), 16);
+    assert_eq!(_cycles(ADDA, WORD, EA(7, 4), ADDRESS_REGISTER(0)), 12);
+    assert_eq!(_cycles(ADDI, LONG, EA(7, 4), DATA_REGISTER(0)), 16);
+    assert_eq!(_cycles(CMPI, LONG, EA(7, 4), DATA_REGISTER(0)), 14);
+    assert_eq!(_cycles(ADDQ, WORD, IMEDIATE_VALUE(1), ADDRESS_REGISTER(0)), 8);
+    assert_eq!(_cycles(CLR, LONG, POSTINCREMENT(0), EMPTY), 20);
+    assert_eq!(_cycles(LEA, LONG, EA(7, 3), ADDRESS_REGISTER(0)), 12);
+    assert_eq!(_cycles(JSR, LONG, EA(7, 1), EMPTY), 20);
+    assert_eq!(_cycles(MOVEM, LONG, POSTINCREMENT(7), REGISTER_MASK(false)), 12);
+    assert_eq!(_cycles(BSET, BYTE, EA(7, 4), DATA_REGISTER(0)), 12);
+    assert_eq!(_cycles(ASL, WORD, EA(7, 0), EMPTY), 16);
+}
+
+#[test]
+fn data_dependent_times() {
+    assert_eq!(mul_cycles(&MULU, 0xffff), 32);
+    assert_eq!(mul_cycles(&MULS, 0x5555), 32);
+    assert_eq!(mul_cycles(&MULS, 0xffff), 2);
+    assert_eq!(divu_cycles(0x10000, 1), 10);
+    //Bounds given by the manual
+    assert!((76..=136).contains(&divu_cycles(12345678, 1234)));
+    assert_eq!(divu_cycles(0, 1), 136);
+    assert_eq!(divs_cycles(0, 1), 2 * (6 + 55 - 1 + 15));
+    assert!((120..=156).contains(&divs_cycles(-100000, -7)));
+}
+
+#[test]
+fn instructions_take_their_documented_cycles() {
+    //mulu.w #$ffff,d0 ; lsl.l d2,d3 ; dbf d1,* ; bne.s * ; move.w #0,sr
+    let mut cpu = _supervisor_cpu(&[0xc0, 0xfc, 0xff, 0xff, 0xe5, 0xab, 0x51, 0xc9, 0xff, 0xfe,
+        0x66, 0xfe, 0x46, 0xfc, 0x00, 0x00]);
+    cpu.load(0x20, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.set_reg(Register::D1, 1);
+    cpu.set_reg(Register::D2, 5);
+    assert_eq!(cpu.step(), 74);
+    assert_eq!(cpu.step(), 18);
+    assert_eq!(cpu.step(), 10);
+    assert_eq!(cpu.step(), 14);
+    //Z was left set by the shift
+    assert_eq!(cpu.step(), 8);
+    cpu.set_sr(0x0000);
+    assert_eq!(cpu.step(), 34);
+    assert_eq!(cpu.get_pc(), 0x2000);
+}
+
+#[test]
+fn counted_loop_cycles() {
+    //moveq #10,d0 ; moveq #0,d1 ; loop: add.w d0,d1 ; subq.w #1,d0 ; bne.s loop
+    let mut cpu = _supervisor_cpu(&[0x70, 0x0a, 0x72, 0x00, 0xd2, 0x40, 0x53, 0x40, 0x66, 0xfa]);
+    while cpu.get_pc() != 0x40a {
+        cpu.step();
+    }
+    assert_eq!(cpu.get_cycles(), 4 + 4 + 10 * (4 + 4) + 9 * 10 + 8);
+}
+
+#[test]
+fn prefetched_words_ignore_writes() {
+    //move.w #$7205,$406.w ; moveq #1,d1 ; move.w #$7409,$412.w ; nop ; nop ; moveq #0,d2
+    let program = [0x31, 0xfc, 0x72, 0x05, 0x04, 0x06, 0x72, 0x01, 0x31, 0xfc, 0x74, 0x09, 0x04, 0x12,
+        0x4e, 0x71, 0x4e, 0x71, 0x74, 0x00];
+    let mut cpu = _supervisor_cpu(&program);
+    cpu.set_prefetch(true);
+    _run(&mut cpu, 6);
+    //The next instruction was already in the queue, three words ahead wasn't
+    assert_eq!(cpu.reg(Register::D1), 1);
+    assert_eq!(cpu.reg(Register::D2), 9);
+    assert_eq!(cpu.get_memory_offset(0x406, 2), Some(&(vec![0x72, 0x05])[..]));
+
+    let mut cpu = _supervisor_cpu(&program);
+    _run(&mut cpu, 6);
+    assert_eq!(cpu.reg(Register::D1), 5);
+}
+
+#[test]
+fn address_errors_stack_prefetched_pc() {
+    //move.w d0,(a0) ; move.w d0,-(a0) ; addq.w #1,(a0)
+    for &(opcode, a0, prefetched, plain) in &[(0x3080, 0x1001, 0x402, 0x402), (0x3100, 0x1003, 0x404, 0x402),
+        (0x5250, 0x1001, 0x402, 0x402)] {
+        let mut cpu = _supervisor_cpu(&[(opcode >> 8) as u8, opcode as u8]);
+        cpu.set_prefetch(true);
+        cpu.load(0x0c, &[0x00, 0x00, 0x20, 0x00]);
+        cpu.set_reg(Register::A0, a0);
+        cpu.step();
+        assert_eq!(cpu.get_pc(), 0x2000);
+        assert_eq!(cpu.get_memory_offset(0x7ffc, 4), Some(&(prefetched as u32).to_be_bytes()[..]));
+
+        let mut cpu = _supervisor_cpu(&[(opcode >> 8) as u8, opcode as u8]);
+        cpu.load(0x0c, &[0x00, 0x00, 0x20, 0x00]);
+        cpu.set_reg(Register::A0, a0);
+        cpu.step();
+        assert_eq!(cpu.get_memory_offset(0x7ffc, 4), Some(&(plain as u32).to_be_bytes()[..]));
+    }
+}
+
+#[test]
+fn prefetch_reads_nothing_past_a_branch() {
+    use std::sync::{Arc, Mutex};
+    //bra.s *+6 ; nop ; nop ; nop
+    let mut cpu = _supervisor_cpu(&[0x60, 0x04, 0x4e, 0x71, 0x4e, 0x71, 0x4e, 0x71]);
+    cpu.set_prefetch(true);
+    let reads = Arc::new(Mutex::new(Vec::new()));
+    let seen = reads.clone();
+    cpu.set_bus_monitor(Some(Box::new(move |cycle: &BusCycle| seen.lock().unwrap().push(cycle.addr))));
+    _run(&mut cpu, 2);
+    assert_eq!(*reads.lock().unwrap(), vec![0x400, 0x402, 0x406, 0x408]);
+    assert_eq!(cpu.get_cycles(), 10 + 4);
+}
+
+#[test]
+fn bus_monitor_sees_every_cycle() {
+    use std::sync::{Arc, Mutex};
+    //move.b d0,$1001.w ; move.l d0,-(a7)
+    let mut cpu = _supervisor_cpu(&[0x11, 0xc0, 0x10, 0x01, 0x2f, 0x00]);
+    cpu.set_block_cache(true);
+    cpu.set_reg(Register::D0, 0x12345678);
+    let cycles = Arc::new(Mutex::new(Vec::new()));
+    let seen = cycles.clone();
+    cpu.set_bus_monitor(Some(Box::new(move |cycle: &BusCycle| seen.lock().unwrap().push(*cycle))));
+    _run(&mut cpu, 2);
+
+    let program = FunctionCode::SUPERVISOR_PROGRAM;
+    let data = FunctionCode::SUPERVISOR_DATA;
+    let cycle = |addr, data, write, fc, uds, lds, cycle| BusCycle { addr, data, write, fc, uds, lds, cycle };
+    assert_eq!(*cycles.lock().unwrap(), vec![
+        cycle(0x400, 0x11c0, false, program, true, true, 0),
+        cycle(0x402, 0x1001, false, program, true, true, 4),
+        cycle(0x1000, 0x0078, true, data, false, true, 8),
+        cycle(0x404, 0x2f00, false, program, true, true, 12),
+        cycle(0x7ffc, 0x1234, true, data, true, true, 16),
+        cycle(0x7ffe, 0x5678, true, data, true, true, 20),
+    ]);
+    assert_eq!(cpu.get_cycles(), 12 + 12);
+}
+
+#[test]
+fn address_bus_width_depends_on_model() {
+    //move.l d0,$120000
+    let program = [0x23, 0xc0, 0x00, 0x12, 0x00, 0x00];
+    let mut cpu = _model_cpu(CpuModel::MC68008, &program);
+    cpu.set_reg(Register::D0, 0xcafebabe);
+    cpu.step();
+    assert_eq!(cpu.get_memory_offset(0x20000, 4), Some(&(vec![0xca, 0xfe, 0xba, 0xbe])[..]));
+
+    let mut cpu = _model_cpu(CpuModel::MC68000, &program);
+    cpu.set_reg(Register::D0, 0xcafebabe);
+    cpu.step();
+    assert_eq!(cpu.get_memory_offset(0x20000, 4), Some(&(vec![0; 4])[..]));
+    assert_eq!(cpu.get_memory_offset(0x120000, 4), Some(&(vec![0xca, 0xfe, 0xba, 0xbe])[..]));
+}
+
+#[test]
+fn odd_word_access_is_address_error() {
+    //move.w d0,$1001.w
+    let program = [0x31, 0xc0, 0x10, 0x01];
+    let mut cpu = _model_cpu(CpuModel::MC68000, &program);
+    cpu.load(0x0c, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.set_reg(Register::D0, 0x1234);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+    //Write in supervisor data space at 0x1001
+    assert_eq!(cpu.get_memory_offset(0x7ff2, 8), Some(&(vec![0x00, 0x05, 0x00, 0x00, 0x10, 0x01, 0x31, 0xc0])[..]));
+
+    //The 68020 splits it in bus cycles instead
+    let mut cpu = _model_cpu(CpuModel::MC68020, &program);
+    cpu.set_reg(Register::D0, 0x1234);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x404);
+    assert_eq!(cpu.get_memory_offset(0x1001, 2), Some(&(vec![0x12, 0x34])[..]));
+}
+
+#[test]
+fn frames_carry_format_from_68010() {
+    //trap #0, its handler being rte
+    let mut cpu = _model_cpu(CpuModel::MC68010, &[0x4e, 0x40]);
+    cpu.load(0x80, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.load(0x2000, &[0x4e, 0x73]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+    assert_eq!(cpu.reg(Register::A7), 0x7ff8);
+    //SR, PC, then format 0 and vector offset
+    assert_eq!(cpu.get_memory_offset(0x7ff8, 8), Some(&(vec![0x27, 0x00, 0x00, 0x00, 0x04, 0x02, 0x00, 0x80])[..]));
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x402);
+    assert_eq!(cpu.reg(Register::A7), 0x8000);
+
+    //An unknown format is a format error, the frame is left alone
+    cpu.load(0x38, &[0x00, 0x00, 0x30, 0x00]);
+    cpu.set_reg(Register::A7, 0x7ff8);
+    cpu.load(0x7ffe, &[0x70, 0x80]);
+    cpu.set_pc(0x2000);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x3000);
+    assert_eq!(cpu.reg(Register::A7), 0x7ff0);
+    assert_eq!(cpu.get_memory_offset(0x7ff6, 2), Some(&(vec![0x00, 0x38])[..]));
+}
+
+#[test]
+fn legal_instructions_depend_on_model() {
+    //tst.w a0
+    let mut cpu = _model_cpu(CpuModel::MC68000, &[0x4a, 0x48]);
+    cpu.load(0x10, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+
+    let mut cpu = _model_cpu(CpuModel::MC68020, &[0x4a, 0x48]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x402);
+    assert!(cpu.flag(Flag::Z));
+}
+
+#[test]
+fn vbr_relocates_vectors() {
+    //movec d0,vbr ; trap #0, the handler doing movec vbr,d1
+    let mut cpu = _model_cpu(CpuModel::MC68010, &[0x4e, 0x7b, 0x08, 0x01, 0x4e, 0x40]);
+    cpu.load(0x1080, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.load(0x2000, &[0x4e, 0x7a, 0x18, 0x01]);
+    cpu.set_reg(Register::D0, 0x1000);
+    _run(&mut cpu, 3);
+    assert_eq!(cpu.reg(Register::VBR), 0x1000);
+    assert_eq!(cpu.reg(Register::D1), 0x1000);
+    assert_eq!(cpu.get_pc(), 0x2004);
+
+    //No MOVEC on the 68000
+    let mut cpu = _model_cpu(CpuModel::MC68000, &[0x4e, 0x7b, 0x08, 0x01]);
+    cpu.load(0x10, &[0x00, 0x00, 0x30, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x3000);
+}
+
+#[test]
+fn moves_uses_the_alternate_spaces() {
+    use std::sync::{Arc, Mutex};
+    //moves.w d0,(a0) ; moves.w (a0),a1
+    let mut cpu = _model_cpu(CpuModel::MC68010, &[0x0e, 0x50, 0x08, 0x00, 0x0e, 0x50, 0x90, 0x00]);
+    cpu.set_reg(Register::DFC, 1);
+    cpu.set_reg(Register::SFC, 2);
+    cpu.set_reg(Register::D0, 0x8001);
+    cpu.set_reg(Register::A0, 0x1000);
+    let spaces = Arc::new(Mutex::new(Vec::new()));
+    let seen = spaces.clone();
+    cpu.set_bus_monitor(Some(Box::new(move |cycle: &BusCycle| {
+        if cycle.addr == 0x1000 {
+            seen.lock().unwrap().push(cycle.fc);
+        }
+    })));
+    _run(&mut cpu, 2);
+    assert_eq!(*spaces.lock().unwrap(), vec![FunctionCode::USER_DATA, FunctionCode::USER_PROGRAM]);
+    assert_eq!(cpu.reg(Register::A1), 0xffff8001);
+}
+
+#[test]
+fn rtd_pops_its_arguments() {
+    //rtd #8
+    let mut cpu = _model_cpu(CpuModel::MC68010, &[0x4e, 0x74, 0x00, 0x08]);
+    cpu.load(0x7ffc, &[0x00, 0x00, 0x12, 0x34]);
+    cpu.set_reg(Register::A7, 0x7ffc);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x1234);
+    assert_eq!(cpu.reg(Register::A7), 0x8008);
+}
+
+#[test]
+fn move_from_sr_is_privileged_from_68010() {
+    //move sr,d0 ; move ccr,d1
+    let program = [0x40, 0xc0, 0x42, 0xc1];
+    let mut cpu = _model_cpu(CpuModel::MC68010, &program);
+    cpu.load(0x20, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.set_sr(0x0015);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+    assert_eq!(cpu.reg(Register::D0), 0);
+
+    let mut cpu = _model_cpu(CpuModel::MC68010, &program[2..]);
+    cpu.set_sr(0x0015);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D1), 0x15);
+
+    let mut cpu = _model_cpu(CpuModel::MC68000, &program);
+    cpu.set_sr(0x0015);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D0), 0x15);
+}
+
+#[test]
+fn loop_mode_saves_fetches() {
+    //lea $1000,a0 ; moveq #9,d0 ; clr.w (a0)+ ; dbf d0,*-2
+    let program = [0x41, 0xf9, 0x00, 0x00, 0x10, 0x00, 0x70, 0x09, 0x42, 0x58, 0x51, 0xc8, 0xff, 0xfc];
+    let mut mc68000 = _model_cpu(CpuModel::MC68000, &program);
+    let mut mc68010 = _model_cpu(CpuModel::MC68010, &program);
+    _run(&mut mc68000, 22);
+    _run(&mut mc68010, 22);
+    assert_eq!(mc68010.get_pc(), 0x40e);
+    //Looping from the second time round
+    assert_eq!(mc68000.get_cycles() - mc68010.get_cycles(), 8 * 8);
+}
+
+#[test]
+fn bitfields() {
+    let program = [
+        //bfextu d0{4:8},d1 ; bfexts d0{28:8},d2
+        0xe9, 0xc0, 0x11, 0x08, 0xeb, 0xc0, 0x27, 0x08,
+        //bfins d3,(a0){6:12} ; bfffo d5{0:32},d4
+        0xef, 0xd0, 0x31, 0x8c, 0xed, 0xc5, 0x40, 0x00];
+    let mut cpu = _model_cpu(CpuModel::MC68020, &program);
+    cpu.load(0x1000, &[0xff, 0xff, 0xff]);
+    cpu.set_reg(Register::D0, 0x12345678);
+    cpu.set_reg(Register::D3, 0xabc);
+    cpu.set_reg(Register::D5, 0x00010000);
+    cpu.set_reg(Register::A0, 0x1000);
+    _run(&mut cpu, 3);
+    assert_eq!(cpu.reg(Register::D1), 0x23);
+    //Register fields wrap around
+    assert_eq!(cpu.reg(Register::D2), 0xffffff81);
+    assert_eq!(cpu.get_memory_offset(0x1000, 3), Some(&(vec![0xfe, 0xaf, 0x3f])[..]));
+    assert!(cpu.flag(Flag::N));
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D4), 15);
+
+    let mut cpu = _model_cpu(CpuModel::MC68000, &program);
+    cpu.load(0x10, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+}
+
+#[test]
+fn long_multiply_and_divide() {
+    let program = [
+        //mulu.l d0,d1 ; muls.l d0,d3:d2
+        0x4c, 0x00, 0x10, 0x00, 0x4c, 0x00, 0x2c, 0x03,
+        //divul.l d0,d5:d4 ; divs.l d0,d7:d6
+        0x4c, 0x40, 0x40, 0x05, 0x4c, 0x40, 0x6c, 0x07];
+    let mut cpu = _model_cpu(CpuModel::MC68020, &program);
+    cpu.set_reg(Register::D0, 0x10000);
+    cpu.set_reg(Register::D1, 0x10000);
+    cpu.set_reg(Register::D2, 0xffff0000);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D1), 0);
+    assert!(cpu.flag(Flag::V));
+    cpu.step();
+    assert_eq!((cpu.reg(Register::D3), cpu.reg(Register::D2)), (0xffffffff, 0));
+    assert!(cpu.flag(Flag::N));
+
+    cpu.set_reg(Register::D0, 7);
+    cpu.set_reg(Register::D4, 100);
+    cpu.step();
+    assert_eq!((cpu.reg(Register::D4), cpu.reg(Register::D5)), (14, 2));
+    //0x1_00000000 / -2
+    cpu.set_reg(Register::D0, 0xfffffffe);
+    cpu.set_reg(Register::D6, 0);
+    cpu.set_reg(Register::D7, 1);
+    cpu.step();
+    assert_eq!((cpu.reg(Register::D6), cpu.reg(Register::D7)), (0x80000000, 0));
+    assert!(!cpu.flag(Flag::V));
+}
+
+#[test]
+fn extended_addressing_modes() {
+    //lea (8,a0,d0.l*4),a1 ; move.l ([4,a0],d0.w*2,6),d1
+    let mut cpu = _model_cpu(CpuModel::MC68020, &[0x43, 0xf0, 0x0c, 0x08, 0x22, 0x30, 0x03, 0x26, 0x00, 0x04, 0x00, 0x06]);
+    cpu.load(0x1004, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.load(0x2010, &[0xde, 0xad, 0xbe, 0xef]);
+    cpu.set_reg(Register::A0, 0x1000);
+    cpu.set_reg(Register::D0, 5);
+    _run(&mut cpu, 2);
+    assert_eq!(cpu.reg(Register::A1), 0x101c);
+    assert_eq!(cpu.reg(Register::D1), 0xdeadbeef);
+    assert_eq!(cpu.get_pc(), 0x40c);
+
+    //The 68000 ignores the scale
+    let mut cpu = _model_cpu(CpuModel::MC68000, &[0x43, 0xf0, 0x0c, 0x08]);
+    cpu.set_reg(Register::A0, 0x1000);
+    cpu.set_reg(Register::D0, 5);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::A1), 0x100d);
+}
+
+#[test]
+fn compare_and_swap() {
+    let program = [
+        //cas.l d0,d1,(a0) twice
+        0x0e, 0xd0, 0x00, 0x40, 0x0e, 0xd0, 0x00, 0x40,
+        //cas2.l d2:d3,d4:d5,(a0):(a1)
+        0x0e, 0xfc, 0x81, 0x02, 0x91, 0x43];
+    let mut cpu = _model_cpu(CpuModel::MC68020, &program);
+    cpu.load(0x1000, &[0x00, 0x00, 0x00, 0x05]);
+    cpu.set_reg(Register::A0, 0x1000);
+    cpu.set_reg(Register::A1, 0x1004);
+    cpu.set_reg(Register::D0, 5);
+    cpu.set_reg(Register::D1, 9);
+    cpu.step();
+    assert!(cpu.flag(Flag::Z));
+    assert_eq!(cpu.get_memory_offset(0x1000, 4), Some(&(vec![0, 0, 0, 9])[..]));
+    cpu.step();
+    assert!(!cpu.flag(Flag::Z));
+    assert_eq!(cpu.reg(Register::D0), 9);
+
+    cpu.set_reg(Register::D2, 9);
+    cpu.set_reg(Register::D3, 0);
+    cpu.set_reg(Register::D4, 1);
+    cpu.set_reg(Register::D5, 2);
+    cpu.step();
+    assert!(cpu.flag(Flag::Z));
+    assert_eq!(cpu.get_memory_offset(0x1000, 8), Some(&(vec![0, 0, 0, 1, 0, 0, 0, 2])[..]));
+}
+
+#[test]
+fn bounds_pack_and_conditional_traps() {
+    let program = [
+        //cmp2.w (a0),d0 ; chk2.w (a0),d1
+        0x02, 0xd0, 0x00, 0x00, 0x02, 0xd0, 0x18, 0x00];
+    let mut cpu = _model_cpu(CpuModel::MC68020, &program);
+    cpu.load(0x18, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.load(0x1000, &[0x00, 0x0a, 0x00, 0x14]);
+    cpu.set_reg(Register::A0, 0x1000);
+    cpu.set_reg(Register::D0, 20);
+    cpu.set_reg(Register::D1, 25);
+    cpu.step();
+    assert!(cpu.flag(Flag::Z));
+    assert!(!cpu.flag(Flag::C));
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+
+    let program = [
+        //pack d0,d1,#0 ; unpk d1,d2,#$3030 ; extb.l d3
+        0x83, 0x40, 0x00, 0x00, 0x85, 0x81, 0x30, 0x30, 0x49, 0xc3,
+        //trapne.w #1 ; trapeq
+        0x56, 0xfa, 0x00, 0x01, 0x57, 0xfc];
+    let mut cpu = _model_cpu(CpuModel::MC68020, &program);
+    cpu.load(0x1c, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.set_reg(Register::D0, 0x0304);
+    cpu.set_reg(Register::D3, 0x80);
+    _run(&mut cpu, 3);
+    assert_eq!(cpu.reg(Register::D1), 0x34);
+    assert_eq!(cpu.reg(Register::D2), 0x3334);
+    assert_eq!(cpu.reg(Register::D3), 0xffffff80);
+    cpu.set_flag(Flag::Z, true);
+    _run(&mut cpu, 2);
+    assert_eq!(cpu.get_pc(), 0x2000);
+    //Format 2 frame with the address of the trap
+    assert_eq!(cpu.get_memory_offset(0x7ff4, 12), Some(&(vec![
+        0x27, 0x0c, 0x00, 0x00, 0x04, 0x10, 0x20, 0x1c, 0x00, 0x00, 0x04, 0x0e])[..]));
+}
+
+#[test]
+fn long_branches() {
+    //bra.l *+$10002
+    let mut cpu = _model_cpu(CpuModel::MC68020, &[0x60, 0xff, 0x00, 0x01, 0x00, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x10402);
+}
+
+#[test]
+fn instruction_cache_keeps_stale_code() {
+    //moveq #1,d0 ; movec d0,cacr ; bsr.w $420 ; move.w #$7202,$420 ; bsr.w $420
+    //moveq #9,d0 ; movec d0,cacr ; bsr.w $420 ; $420: moveq #1,d1 ; rts
+    let mut cpu = _model_cpu(CpuModel::MC68020, &[
+        0x70, 0x01, 0x4e, 0x7b, 0x00, 0x02, 0x61, 0x00, 0x00, 0x18,
+        0x33, 0xfc, 0x72, 0x02, 0x00, 0x00, 0x04, 0x20, 0x61, 0x00, 0x00, 0x0c,
+        0x70, 0x09, 0x4e, 0x7b, 0x00, 0x02, 0x61, 0x00, 0x00, 0x02,
+        0x72, 0x01, 0x4e, 0x75]);
+    _run(&mut cpu, 5);
+    assert_eq!(cpu.reg(Register::D1), 1);
+    assert_eq!(cpu.reg(Register::CACR), 1);
+    //The write does not reach the cache
+    _run(&mut cpu, 4);
+    assert_eq!(cpu.get_memory_offset(0x420, 2), Some(&[0x72, 0x02][..]));
+    assert_eq!(cpu.reg(Register::D1), 1);
+    let stats = cpu.cache_stats();
+    assert!(stats.hits > 0 && stats.misses > 0);
+    //Clearing it picks up the patch
+    _run(&mut cpu, 5);
+    assert_eq!(cpu.reg(Register::D1), 2);
+}
+
+#[test]
+fn cacr_needs_a_68020() {
+    //movec d0,cacr
+    let mut cpu = _model_cpu(CpuModel::MC68010, &[0x4e, 0x7b, 0x00, 0x02]);
+    cpu.load(0x10, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.set_reg(Register::D0, 1);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+}
+
+fn _ctx(rounding: Rounding) -> Context {
+    Context::new(rounding, Precision::EXTENDED)
+}
+
+#[test]
+fn conversions_are_exact() {
+    for val in [1.0, -2.5, 0.1, 1e300, -4.9e-324, f64::MAX, 0.0] {
+        assert_eq!(Float::from_f64(val).to_f64(), val);
+    }
+    assert_eq!(Float::from_single(0x3fc00000).to_f64(), 1.5);
+    assert_eq!(Float::from_i64(-3).to_bits(), [0xc0000000, 0xc0000000, 0]);
+    assert_eq!(Float::from_f64(1.0).to_single(&mut _ctx(Rounding::NEAREST)), 0x3f800000);
+}
+
+#[test]
+fn rounding_modes() {
+    let (one, three) = (Float::from_i64(1), Float::from_i64(3));
+    let third = |rounding| one.div(three, &mut _ctx(rounding)).mant;
+    assert_eq!(third(Rounding::NEAREST), 0xaaaaaaaaaaaaaaab);
+    assert_eq!(third(Rounding::ZERO), 0xaaaaaaaaaaaaaaaa);
+    assert_eq!(third(Rounding::PLUS), 0xaaaaaaaaaaaaaaab);
+    let mut ctx = Context::new(Rounding::NEAREST, Precision::mantissa(24));
+    assert_eq!(one.div(three, &mut ctx).mant, 0xaaaaab0000000000);
+    assert_eq!(ctx.exceptions, INEX2);
+}
+
+#[test]
+fn basic_operations() {
+    let mut ctx = _ctx(Rounding::NEAREST);
+    let f = |val: f64| Float::from_f64(val);
+    assert_eq!(f(1.5).add(f(2.25), &mut ctx).to_f64(), 3.75);
+    assert_eq!(f(1.5).sub(f(1.5), &mut ctx), Float::zero(false));
+    assert_eq!(f(-1.5).mul(f(4.0), &mut ctx).to_f64(), -6.0);
+    assert_eq!(f(7.0).remainder(f(2.0), true, &mut ctx), (f(-1.0), 4));
+    assert_eq!(f(-7.0).remainder(f(2.0), false, &mut ctx), (f(-1.0), 0x83));
+    assert_eq!(ctx.exceptions, 0);
+    assert_eq!(f(2.5).int(Rounding::NEAREST, &mut ctx).to_f64(), 2.0);
+    assert_eq!(f(2.0).sqrt(&mut ctx).to_f64(), 2f64.sqrt());
+    assert_eq!(ctx.exceptions, INEX2);
+    assert!(f(-1.0).sqrt(&mut ctx).is_nan());
+    assert_eq!(ctx.exceptions, INEX2 | OPERR);
+}
+
+#[test]
+fn packed_decimals() {
+    let mut ctx = _ctx(Rounding::NEAREST);
+    //-1.25e+2
+    let packed = [0x80020001, 0x25000000, 0];
+    assert_eq!(Float::from_packed(packed, &mut ctx).to_f64(), -125.0);
+    assert_eq!(Float::from_f64(-125.0).to_packed(3, &mut ctx), packed);
+}
+
+fn _fpu_cpu(program: &[u8]) -> CPU<MemoryMap> {
+    let mut cpu = _model_cpu(CpuModel::MC68020, program);
+    cpu.set_fpu(Some(FpuModel::MC68881));
+    cpu
+}
+
+#[test]
+fn fpu_arithmetic_and_branches() {
+    //fmove.l #3,fp0 ; fmove.l #2,fp1 ; fdiv.x fp1,fp0 ; fadd.x fp0,fp0
+    //fsqrt.x fp0,fp2 ; fcmp.x fp1,fp0 ; fbogt.w *+6 ; moveq #1,d0
+    //moveq #2,d1 ; fmove.l fp2,d2 ; fmove.l fpsr,d3 ; fmovecr #0,fp3
+    //ftwotox.l #3,fp4
+    let mut cpu = _fpu_cpu(&[
+        0xf2, 0x3c, 0x40, 0x00, 0x00, 0x00, 0x00, 0x03, 0xf2, 0x3c, 0x40, 0x80, 0x00, 0x00, 0x00, 0x02,
+        0xf2, 0x00, 0x04, 0x20, 0xf2, 0x00, 0x00, 0x22,
+        0xf2, 0x00, 0x01, 0x04, 0xf2, 0x00, 0x04, 0x38, 0xf2, 0x82, 0x00, 0x04, 0x70, 0x01,
+        0x72, 0x02, 0xf2, 0x02, 0x61, 0x00, 0xf2, 0x03, 0xa8, 0x00, 0xf2, 0x00, 0x5d, 0x80,
+        0xf2, 0x3c, 0x42, 0x11, 0x00, 0x00, 0x00, 0x03]);
+    _run(&mut cpu, 4);
+    assert_eq!(cpu.fp_register(0), Some(3.0));
+    _run(&mut cpu, 3);
+    assert_eq!(cpu.fp_register(2), Some(3f64.sqrt()));
+    assert_eq!(cpu.get_pc(), 0x426);
+    _run(&mut cpu, 5);
+    assert_eq!(cpu.reg(Register::D0), 0);
+    assert_eq!(cpu.reg(Register::D1), 2);
+    assert_eq!(cpu.reg(Register::D2), 2);
+    //Greater than, the conversion to long was inexact
+    assert_eq!(cpu.reg(Register::D3), 0x00000208);
+    assert_eq!(cpu.reg(Register::FPIAR), 0x434);
+    assert_eq!(cpu.fp_register(3), Some(std::f64::consts::PI));
+    assert_eq!(cpu.fp_register(4), Some(8.0));
+}
+
+#[test]
+fn fpcr_selects_the_rounding_mode() {
+    //fmove.l #mode,fpcr ; fmove.l #1,fp0 ; fdiv.l #3,fp0 ; fmove.x fp0,(a0)
+    for (mode, low) in [(0x00, 0xab), (0x10, 0xaa), (0x20, 0xaa), (0x30, 0xab)] {
+        let mut cpu = _fpu_cpu(&[
+            0xf2, 0x3c, 0x90, 0x00, 0x00, 0x00, 0x00, mode, 0xf2, 0x3c, 0x40, 0x00, 0x00, 0x00, 0x00, 0x01,
+            0xf2, 0x3c, 0x40, 0x20, 0x00, 0x00, 0x00, 0x03, 0xf2, 0x10, 0x68, 0x00]);
+        cpu.set_reg(Register::A0, 0x1000);
+        _run(&mut cpu, 4);
+        assert_eq!(cpu.reg(Register::FPCR), mode as u32);
+        assert_eq!(cpu.get_memory_offset(0x1000, 12), Some(&[
+            0x3f, 0xfd, 0x00, 0x00, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, low][..]));
+    }
+}
+
+#[test]
+fn fpu_exceptions_trap_when_enabled() {
+    //fmove.l #$400,fpcr ; fmove.l #1,fp0 ; fdiv.l #0,fp0
+    let mut cpu = _fpu_cpu(&[
+        0xf2, 0x3c, 0x90, 0x00, 0x00, 0x00, 0x04, 0x00, 0xf2, 0x3c, 0x40, 0x00, 0x00, 0x00, 0x00, 0x01,
+        0xf2, 0x3c, 0x40, 0x20, 0x00, 0x00, 0x00, 0x00]);
+    cpu.load(0xc8, &[0x00, 0x00, 0x20, 0x00]);
+    _run(&mut cpu, 3);
+    assert_eq!(cpu.get_pc(), 0x2000);
+    assert_eq!(cpu.fp_register(0), Some(f64::INFINITY));
+    assert_eq!(cpu.reg(Register::FPSR), 0x02000410);
+}
+
+#[test]
+fn undefined_fpu_predicates_are_f_line() {
+    //fbcc.w, fscc d1 and fdbcc d1 with predicate $21
+    for program in [&[0xf2, 0xa1, 0x00, 0x04][..], &[0xf2, 0x41, 0x00, 0x21], &[0xf2, 0x49, 0x00, 0x21, 0xff, 0xfc]] {
+        let mut cpu = _fpu_cpu(program);
+        cpu.load(0x2c, &[0x00, 0x00, 0x20, 0x00]);
+        cpu.set_reg(Register::D1, 5);
+        cpu.step();
+        assert_eq!(cpu.get_pc(), 0x2000, "{:02x?}", program);
+        assert_eq!(cpu.reg(Register::D1), 5);
+        assert_eq!(disassemble(CpuModel::MC68020, 0x400, program, &[])[0].inst, None);
+    }
+}
+
+#[test]
+fn f_line_without_an_fpu() {
+    //fmove.l #3,fp0
+    let mut cpu = _model_cpu(CpuModel::MC68020, &[0xf2, 0x3c, 0x40, 0x00, 0x00, 0x00, 0x00, 0x03]);
+    cpu.load(0x2c, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+    assert_eq!(cpu.fp_register(0), None);
+}
+
+fn _load_long(cpu: &mut CPU<MemoryMap>, addr: usize, val: u32) {
+    cpu.load(addr, &val.to_be_bytes());
+}
+
+#[test]
+fn mmu_translates_through_page_tables() {
+    //pmove (a0),crp ; pmove (a1),tc ; move.l #$12345678,$3000 ; move.l $6000,d0
+    //ptestr #5,$3000,#7,a2 ; pmove mmusr,(a3) ; move.l d0,$4000
+    let mut cpu = _model_cpu(CpuModel::MC68020, &[
+        0xf0, 0x10, 0x4c, 0x00, 0xf0, 0x11, 0x40, 0x00,
+        0x23, 0xfc, 0x12, 0x34, 0x56, 0x78, 0x00, 0x00, 0x30, 0x00, 0x20, 0x39, 0x00, 0x00, 0x60, 0x00,
+        0xf0, 0x39, 0x9f, 0x55, 0x00, 0x00, 0x30, 0x00, 0xf0, 0x13, 0x62, 0x00,
+        0x23, 0xc0, 0x00, 0x00, 0x40, 0x00]);
+    cpu.set_mmu(true);
+    cpu.load(0x08, &[0x00, 0x00, 0x20, 0x00]);
+    //4K pages and two levels of 10 bits, all short descriptors
+    _load_long(&mut cpu, 0x200, 0x7fff0002);
+    _load_long(&mut cpu, 0x204, 0x10000);
+    _load_long(&mut cpu, 0x208, 0x80c0aa00);
+    _load_long(&mut cpu, 0x10000, 0x11002);
+    //Identity but for $3000 at $5000, $4000 invalid and $6000 write protected
+    for page in 0..16 {
+        let desc = match page {
+            3 => 0x5001,
+            4 => 0,
+            6 => 0x6005,
+            _ => page << 12 | 1,
+        };
+        _load_long(&mut cpu, 0x11000 + 4 * page as usize, desc);
+    }
+    cpu.set_reg(Register::A0, 0x200);
+    cpu.set_reg(Register::A1, 0x208);
+    cpu.set_reg(Register::A3, 0x300);
+    _run(&mut cpu, 4);
+    assert_eq!(cpu.mmu_register(MmuRegister::TC), Some(0x80c0aa00));
+    assert_eq!(cpu.get_memory_offset(0x5000, 4), Some(&[0x12, 0x34, 0x56, 0x78][..]));
+    assert_eq!(cpu.get_memory_offset(0x3000, 4), Some(&[0, 0, 0, 0][..]));
+    //Used and modified got set
+    assert_eq!(cpu.get_memory_offset(0x1100c, 4), Some(&[0x00, 0x00, 0x50, 0x19][..]));
+    _run(&mut cpu, 2);
+    assert_eq!(cpu.reg(Register::A2), 0x1100c);
+    assert_eq!(cpu.get_memory_offset(0x300, 2), Some(&[0x02, 0x02][..]));
+    //Bus error on the invalid page, the move gets restarted by RTE
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+    assert_eq!(cpu.reg(Register::A7), 0x7fe0);
+    assert_eq!(cpu.get_memory_offset(0x7fe2, 6), Some(&[0x00, 0x00, 0x04, 0x24, 0xa0, 0x08][..]));
+    assert_eq!(cpu.get_memory_offset(0x7ff0, 4), Some(&[0x00, 0x00, 0x40, 0x00][..]));
+}
+
+#[test]
+fn block_cache_sees_writes_through_translation() {
+    //jsr $2000.w ; pmove (a0),crp ; pmove (a1),tc ; move.w #$7009,$3000.w
+    //pmove (a2),tc ; jsr $2000.w
+    let mut cpu = _model_cpu(CpuModel::MC68020, &[
+        0x4e, 0xb8, 0x20, 0x00, 0xf0, 0x10, 0x4c, 0x00, 0xf0, 0x11, 0x40, 0x00,
+        0x31, 0xfc, 0x70, 0x09, 0x30, 0x00, 0xf0, 0x12, 0x40, 0x00, 0x4e, 0xb8, 0x20, 0x00]);
+    cpu.set_mmu(true);
+    cpu.set_block_cache(true);
+    //moveq #3,d0 ; rts
+    cpu.load(0x2000, &[0x70, 0x03, 0x4e, 0x75]);
+    _load_long(&mut cpu, 0x200, 0x7fff0002);
+    _load_long(&mut cpu, 0x204, 0x10000);
+    _load_long(&mut cpu, 0x208, 0x80c0aa00);
+    _load_long(&mut cpu, 0x10000, 0x11002);
+    //Identity but for $3000 at $2000
+    for page in 0..16 {
+        _load_long(&mut cpu, 0x11000 + 4 * page as usize, if page == 3 { 0x2001 } else { page << 12 | 1 });
+    }
+    cpu.set_reg(Register::A0, 0x200);
+    cpu.set_reg(Register::A1, 0x208);
+    cpu.set_reg(Register::A2, 0x210);
+    _run(&mut cpu, 3);
+    assert_eq!(cpu.reg(Register::D0), 3);
+    _run(&mut cpu, 4);
+    assert_eq!(cpu.get_memory_offset(0x2000, 2), Some(&[0x70, 0x09][..]));
+    _run(&mut cpu, 2);
+    assert_eq!(cpu.reg(Register::D0), 9);
+}
+
+#[test]
+fn pmove_checks_the_translation_setup() {
+    //pmove (a0),tc
+    for mmu in [true, false] {
+        let mut cpu = _model_cpu(CpuModel::MC68020, &[0xf0, 0x10, 0x40, 0x00]);
+        cpu.load(0xe0, &[0x00, 0x00, 0x20, 0x00]);
+        cpu.load(0x2c, &[0x00, 0x00, 0x30, 0x00]);
+        //Index fields short of 32 bits
+        _load_long(&mut cpu, 0x200, 0x80c0a000);
+        cpu.set_reg(Register::A0, 0x200);
+        cpu.set_mmu(mmu);
+        cpu.step();
+        assert_eq!(cpu.get_pc(), if mmu { 0x2000 } else { 0x3000 });
+        assert_eq!(cpu.mmu_register(MmuRegister::TC), if mmu { Some(0) } else { None });
+    }
+}
+
+#[test]
+fn link_and_chk_take_longs_on_68020() {
+    //link.l a6,#-$10000 ; chk.l d1,d0 ; chk.l d1,d2
+    let program = [0x48, 0x0e, 0xff, 0xff, 0x00, 0x00, 0x41, 0x01, 0x45, 0x01];
+    let mut cpu = _model_cpu(CpuModel::MC68020, &program);
+    cpu.load(0x18, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.set_reg(Register::A6, 0x1234);
+    cpu.set_reg(Register::A7, 0x20000);
+    cpu.set_reg(Register::D0, 0x10000);
+    cpu.set_reg(Register::D1, 0x20000);
+    cpu.set_reg(Register::D2, 0x30000);
+    _run(&mut cpu, 2);
+    assert_eq!(cpu.reg(Register::A6), 0x1fffc);
+    assert_eq!(cpu.reg(Register::A7), 0xfffc);
+    assert_eq!(cpu.get_memory_offset(0x1fffc, 4), Some(&(vec![0, 0, 0x12, 0x34])[..]));
+    assert_eq!(cpu.get_pc(), 0x408);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+    assert!(!cpu.flag(Flag::N));
+
+    //The 68000 has neither
+    let mut cpu = _model_cpu(CpuModel::MC68000, &program);
+    cpu.load(0x10, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+}
+
+#[test]
+fn movec_to_missing_registers_doesnt_decode() {
+    //movec d0,msp
+    let bytes = [0x4e, 0x7b, 0x08, 0x03];
+    assert_eq!(disassemble(CpuModel::MC68020, 0x400, &bytes, &[])[0].inst, None);
+    let mut cpu = _model_cpu(CpuModel::MC68020, &bytes);
+    cpu.load(0x10, &[0x00, 0x00, 0x20, 0x00]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x2000);
+}
+
+#[test]
+fn malformed_68020_operands_are_illegal() {
+    let bitfield = BITFIELD(Bitfield { register: 0, offset: FieldParam::IMMEDIATE(0), width: FieldParam::IMMEDIATE(8) });
+    for inst in [
+        Instruction::new(BFEXTU, LONG, IMEDIATE_VALUE(0), bitfield),
+        Instruction::new(DIVUL, LONG, DATA_REGISTER(1), ADDRESS_REGISTER(0)),
+        Instruction::new(CAS, LONG, DATA_REGISTER(0), ADDRESS_INDIRECT(0)),
+        Instruction::new(CHK2, LONG, DATA_REGISTER(0), DATA_REGISTER(1)),
+        Instruction::new(PACK, WORD, IMEDIATE_VALUE(0), DATA_REGISTER(0)),
+    ] {
+        let mut cpu = _model_cpu(CpuModel::MC68020, &[]);
+        cpu.load(0x10, &[0x00, 0x00, 0x20, 0x00]);
+        cpu.set_reg(Register::D1, 1);
+        cpu.execute(&inst);
+        assert_eq!(cpu.get_pc(), 0x2000, "{:?}", inst);
+    }
+}
+
+#[test]
+fn coldfire_isa_b_instructions() {
+    //mov3q #-1,d0 ; mov3q #3,a1 ; mvs.b d2,d3 ; mvz.w d2,d4 ; addq.l #1,d5 ; sats d5
+    let mut cpu = _model_cpu(CpuModel::COLDFIRE_ISA_B, &[
+        0xa1, 0x40, 0xa7, 0x49, 0x77, 0x02, 0x79, 0xc2, 0x52, 0x85, 0x4c, 0x85]);
+    cpu.set_reg(Register::D2, 0x12345680);
+    cpu.set_reg(Register::D5, 0x7fffffff);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D0), 0xffffffff);
+    assert!(cpu.flag(Flag::N));
+    cpu.step();
+    assert_eq!(cpu.reg(Register::A1), 3);
+    assert!(!cpu.flag(Flag::N));
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D3), 0xffffff80);
+    assert!(cpu.flag(Flag::N));
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D4), 0x5680);
+    assert!(!cpu.flag(Flag::N));
+    cpu.step();
+    assert!(cpu.flag(Flag::V));
+    cpu.step();
+    assert_eq!(cpu.reg(Register::D5), 0x7fffffff);
+    assert!(!cpu.flag(Flag::V) && !cpu.flag(Flag::N));
+    assert_eq!(cpu.get_pc(), 0x40c);
+}
+
+#[test]
+fn coldfire_drops_what_it_does_not_implement() {
+    //mov3q #-1,d0 on ISA_A, abcd d0,d1, rol.l #1,d0, add.w d0,d1, move.l 0(a0,d1.w),d2
+    //then move.l 0(a0,d1.l),d2 which is fine
+    let programs: [(&[u8], u32); 6] = [
+        (&[0xa1, 0x40], 0x3000),
+        (&[0xc3, 0x00], 0x2000),
+        (&[0xe3, 0x98], 0x2000),
+        (&[0xd2, 0x40], 0x2000),
+        (&[0x24, 0x30, 0x10, 0x00], 0x2000),
+        (&[0x24, 0x30, 0x18, 0x00], 0x404),
+    ];
+    for (program, pc) in programs {
+        let mut cpu = _model_cpu(CpuModel::COLDFIRE_ISA_A, program);
+        _load_long(&mut cpu, 0x10, 0x2000);
+        _load_long(&mut cpu, 0x28, 0x3000);
+        cpu.step();
+        assert_eq!(cpu.get_pc(), pc);
+    }
+}
+
+#[test]
+fn coldfire_exception_frame() {
+    //trap #1, rte in the handler
+    let mut cpu = _model_cpu(CpuModel::COLDFIRE_ISA_A, &[0x4e, 0x41]);
+    _load_long(&mut cpu, 0x84, 0x2000);
+    cpu.load(0x2000, &[0x4e, 0x73]);
+    cpu.set_reg(Register::A7, 0x8002);
+    cpu.step();
+    assert_eq!(cpu.reg(Register::A7), 0x7ff8);
+    assert_eq!(cpu.get_memory_offset(0x7ff8, 8), Some(&[0x60, 0x84, 0x27, 0x00, 0x00, 0x00, 0x04, 0x02][..]));
+    cpu.step();
+    assert_eq!(cpu.reg(Register::A7), 0x8002);
+    assert_eq!(cpu.get_pc(), 0x402);
+    //Illegal instructions stack themselves, RTE refuses formats below 4
+    let mut cpu = _model_cpu(CpuModel::COLDFIRE_ISA_A, &[0x4e, 0x76]);
+    _load_long(&mut cpu, 0x10, 0x2000);
+    _load_long(&mut cpu, 0x38, 0x3000);
+    cpu.load(0x2000, &[0x4e, 0x73]);
+    cpu.step();
+    assert_eq!(cpu.get_memory_offset(0x7ff8, 8), Some(&[0x40, 0x10, 0x27, 0x00, 0x00, 0x00, 0x04, 0x00][..]));
+    cpu.load(0x7ff8, &[0x00, 0x10]);
+    cpu.step();
+    assert_eq!(cpu.get_pc(), 0x3000);
+}
+
+#[test]
+fn instructions_display_in_both_syntaxes() {
+    let cases: [(CpuModel, &[u8], &str, &str); 11] = [
+        (CpuModel::MC68000, &[0x22, 0xc0], "move.l d0,(a1)+", "movel %d0,%a1@+"),
+        (CpuModel::MC68000, &[0x41, 0xee, 0x00, 0x08], "lea 8(a6),a0", "lea %a6@(8),%a0"),
+        (CpuModel::MC68000, &[0x66, 0x32], "bne.s $434", "bnes 0x434"),
+        (CpuModel::MC68000, &[0x70, 0xff], "moveq #-1,d0", "moveq #-1,%d0"),
+        (CpuModel::MC68000, &[0x48, 0xe7, 0xe0, 0x83], "movem.l d0-d2/a0/a6-a7,-(sp)", "moveml %d0-%d2/%a0/%a6-%a7,%sp@-"),
+        (CpuModel::MC68000, &[0x06, 0x78, 0x12, 0x34, 0x20, 0x00], "addi.w #$1234,$2000.w", "addiw #0x1234,0x2000:w"),
+        (CpuModel::MC68000, &[0x34, 0x30, 0x10, 0x04], "move.w 4(a0,d1.w),d2", "movew %a0@(4,%d1:w),%d2"),
+        (CpuModel::MC68000, &[0x51, 0xc8, 0xff, 0xfe], "dbf d0,$400", "dbf %d0,0x400"),
+        (CpuModel::MC68000, &[0x4e, 0xb9, 0x00, 0x01, 0x23, 0x45], "jsr $12345", "jsr 0x12345"),
+        (CpuModel::MC68020, &[0xe9, 0xc0, 0x11, 0x08], "bfextu d0{4:8},d1", "bfextu %d0{4:8},%d1"),
+        (CpuModel::MC68020, &[0x43, 0xf0, 0x1d, 0x20, 0x00, 0x08], "lea (8,a0,d1.l*4),a1", "lea %a0@(8,%d1:l:4),%a1"),
+    ];
+    for (model, program, motorola, mit) in cases {
+        let mut cpu = _model_cpu(model, program);
+        let inst = cpu.decode().unwrap();
+        assert_eq!(inst.to_string(), motorola);
+        assert_eq!(inst.display(Syntax::MIT).to_string(), mit);
+    }
+    let cpu = _supervisor_cpu(&[0x22, 0xc0]);
+    assert!(cpu.to_string().starts_with("* PC = 0x00000400: move.l d0,(a1)+\n"));
+}
+
+#[test]
+fn listings_separate_code_from_data() {
+    //moveq #5,d0; beq.s to the bra; rts; a word of data; bra.s back to the rts;
+    //moveq only reached as data; an odd byte
+    let program = [0x70, 0x05, 0x67, 0x04, 0x4e, 0x75, 0xff, 0xff, 0x60, 0xfa, 0x72, 0x01, 0x12];
+    let swept: Vec<String> = disassemble(CpuModel::MC68000, 0x1000, &program, &[]).iter().map(|line| line.to_string()).collect();
+    assert_eq!(swept[3], "00001006  ffff                     dc.w $ffff");
+    assert_eq!(swept[5], "0000100a  7201                     moveq #1,d1");
+    assert_eq!(swept[6], "0000100c  12                       dc.b $12");
+    let followed = disassemble(CpuModel::MC68000, 0x1000, &program, &[0x1000]);
+    assert_eq!(followed.iter().map(|line| line.inst.is_some()).collect::<Vec<_>>(),
+        [true, true, true, false, true, false, false]);
+    assert_eq!(followed[5].text(Syntax::MIT), "0000100a  7201                     .word 0x7201");
+    let mut cpu = _supervisor_cpu(&program);
+    assert_eq!(cpu.disassemble(0x400, 13, &[0x400]).len(), 7);
+    cpu.set_pc(0x408);
+    let lines = cpu.disassemble(0x408, 2, &[]);
+    assert_eq!((lines[0].addr, &lines[0].bytes, &lines[0].inst), (0x408, &vec![0x60, 0xfa], &cpu.decode().ok()));
+}
+
+#[test]
+fn instructions_encode_to_the_words_they_decode_from() {
+    let cases: [(CpuModel, &[u16]); 26] = [
+        (CpuModel::MC68000, &[0x22c0]),
+        (CpuModel::MC68000, &[0x3430, 0x1004]),
+        (CpuModel::MC68000, &[0x51c8, 0xfffe]),
+        (CpuModel::MC68000, &[0x4eb9, 0x0001, 0x2345]),
+        (CpuModel::MC68000, &[0x48e7, 0xfffe]),
+        (CpuModel::MC68000, &[0x4cdf, 0x7fff]),
+        (CpuModel::MC68000, &[0x0000, 0x0012]),
+        (CpuModel::MC68000, &[0x6000, 0x0100]),
+        (CpuModel::MC68000, &[0x66fe]),
+        (CpuModel::MC68000, &[0x203a, 0x0010]),
+        (CpuModel::MC68000, &[0x7080]),
+        (CpuModel::MC68010, &[0x0e90, 0x8000]),
+        (CpuModel::MC68010, &[0x4e7b, 0x0801]),
+        (CpuModel::MC68020, &[0xe9c0, 0x1108]),
+        (CpuModel::MC68020, &[0x43f0, 0x1d20, 0x0008]),
+        (CpuModel::MC68020, &[0x2070, 0x0162, 0x0010, 0x0020]),
+        (CpuModel::MC68020, &[0x4c00, 0x1402]),
+        (CpuModel::MC68020, &[0x4c41, 0x2002]),
+        (CpuModel::MC68020, &[0x60ff, 0x0000, 0x1000]),
+        (CpuModel::MC68020, &[0xf200, 0x0422]),
+        (CpuModel::MC68020, &[0xf23c, 0x4400, 0x3f80, 0x0000]),
+        (CpuModel::MC68020, &[0xf000, 0x2400]),
+        (CpuModel::MC68020, &[0x480e, 0xffff, 0x0000]),
+        (CpuModel::MC68020, &[0x4101]),
+        (CpuModel::COLDFIRE_ISA_B, &[0xa140]),
+        (CpuModel::COLDFIRE_ISA_B, &[0x7180]),
+    ];
+    for (model, words) in cases {
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        let mut cpu = _model_cpu(model, &bytes);
+        let inst = cpu.decode().unwrap();
+        assert_eq!(inst.encode(model, 0x400).as_deref(), Ok(words), "{}", inst);
+    }
+    let inst = Instruction::new(MOVE, LONG, DATA_REGISTER(1), PREDECREMENT(7));
+    assert_eq!(inst.encode(CpuModel::MC68000, 0), Ok(vec![0x2f01]));
+    let index = Index { register: 1, long: false, scale: 4 };
+    let bad = [
+        (CpuModel::MC68000, Instruction::new(MOVEQ, LONG, IMEDIATE_VALUE(300), DATA_REGISTER(0)), EncodeError::INVALID_OPERANDS),
+        (CpuModel::MC68000, Instruction::new(ADDQ, WORD, IMEDIATE_VALUE(9), DATA_REGISTER(0)), EncodeError::INVALID_OPERANDS),
+        (CpuModel::MC68000, Instruction::new(MOVE, BYTE, DATA_REGISTER(0), ADDRESS_REGISTER(0)), EncodeError::INVALID_OPERANDS),
+        (CpuModel::MC68000, Instruction::new(LEA, LONG, INDEXED(0, index, 0), ADDRESS_REGISTER(1)), EncodeError::INVALID_OPERANDS),
+        (CpuModel::MC68000, Instruction::new(BRA, BYTE, PC_DISPLACEMENT(0x402, 0), EMPTY), EncodeError::INVALID_OPERANDS),
+        (CpuModel::MC68000, Instruction::new(EXTB, LONG, DATA_REGISTER(0), EMPTY), EncodeError::UNSUPPORTED),
+        (CpuModel::MC68020, Instruction::new(MOVEC, LONG, DATA_REGISTER(0), CONTROL_REGISTER(0x803)), EncodeError::INVALID_OPERANDS),
+        (CpuModel::COLDFIRE_ISA_A, Instruction::new(ADD, WORD, DATA_REGISTER(0), DATA_REGISTER(1)), EncodeError::INVALID_OPERANDS),
+    ];
+    for (model, inst, err) in bad {
+        assert_eq!(inst.encode(model, 0x400), Err(err), "{}", inst);
+    }
+}
+
+#[test]
+fn listings_reassemble() {
+    //spl $ffffe595.w ; lea $ffffa563(pc),a0 ; bra.w $fffffff4 ; move.w $8000.w,d0
+    let bytes = [0x5a, 0xf8, 0xe5, 0x95, 0x41, 0xfa, 0xa5, 0x4d, 0x60, 0x00, 0xff, 0xda, 0x30, 0x38, 0x80, 0x00];
+    let lines = disassemble(CpuModel::MC68000, 0x10, &bytes, &[]);
+    let text: Vec<String> = lines.iter().map(|line| line.inst.unwrap().to_string()).collect();
+    assert_eq!(text, ["spl $ffffe595.w", "lea $ffffa563(pc),a0", "bra.w $fffffff4", "move.w $ffff8000.w,d0"]);
+    let source: String = text.iter().map(|line| format!("        {}\n", line)).collect();
+    let program = assemble(&format!("        org $10\n{}", source), CpuModel::MC68000).unwrap();
+    assert_eq!(program.image(), (0x10, bytes.to_vec()));
+
+    //Sized on the wrapped displacement too
+    let program = assemble("        org $4\n        bra $fffffff4\n", CpuModel::MC68000).unwrap();
+    assert_eq!(program.image(), (4, vec![0x60, 0xee]));
+}
+
+fn _assembled(source: &str) -> Vec<u8> {
+    let program = assemble(source, CpuModel::MC68000).unwrap();
+    program.image().1
+}
+
+#[test]
+fn assembler_resolves_labels_and_picks_branch_sizes() {
+    let program = assemble("
+start   moveq #3,d0
+.loop   subq.w #1,d0
+        bne.s .loop
+        bra done
+        ds.b 200
+done:   lea table(pc),a0
+        rts
+next    bra .loop
+.loop   nop
+table   dc.w done-start", CpuModel::MC68000).unwrap();
+    let (org, image) = program.image();
+    assert_eq!(org, 0);
+    assert_eq!(image[..10], [0x70, 0x03, 0x53, 0x40, 0x66, 0xfc, 0x60, 0x00, 0x00, 0xca]);
+    //A branch to the next instruction needs the word form
+    assert_eq!(image[210..], [0x41, 0xfa, 0x00, 0x0a, 0x4e, 0x75, 0x60, 0x00, 0x00, 0x02, 0x4e, 0x71, 0x00, 0xd2]);
+    assert_eq!(program.symbols["start.loop"], 2);
+    assert_eq!(program.symbols["next.loop"], 220);
+    assert_eq!(_assembled(" bra *+2"), [0x60, 0x00, 0x00, 0x00]);
+    assert_eq!(_assembled(" bra.s *+4\n nop"), [0x60, 0x02, 0x4e, 0x71]);
+    assert_eq!(_assembled(" add.l #1,d0\n move.w d1,a0\n and.b #$f0,(a1)\n cmp.w (a0)+,(a1)+"),
+        [0x06, 0x80, 0, 0, 0, 1, 0x30, 0x41, 0x02, 0x11, 0x00, 0xf0, 0xb3, 0x48]);
+    let mut cpu = _supervisor_cpu(&[]);
+    assemble("
+        org $400
+        moveq #0,d0
+        moveq #9,d1
+.add    add.w d1,d0
+        dbra d1,.add
+        move.w d0,result.w
+        stop #$2700
+result  equ $600", CpuModel::MC68000).unwrap().load(&mut cpu).unwrap();
+    _run(&mut cpu, 25);
+    assert_eq!(cpu.get_memory_offset(0x600, 2), Some(&[0, 45][..]));
+}
+
+#[test]
+fn assembler_directives() {
+    let dir = std::env::temp_dir().join(format!("68kemu-asm-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("defs.i"), "size equ 3\n").unwrap();
+    std::fs::write(dir.join("blob.bin"), [0xaa, 0xbb]).unwrap();
+    std::fs::write(dir.join("main.s"), "
+        org $100
+        include defs.i
+text    dc.b 'it''s',0
+        even
+words   dc.w size*2,-1,text
+        dc.l $12345678
+        ds.w size
+        incbin \"blob.bin\"
+        org $200
+        dc.b %101,@17,$ff,'A'
+        end
+        dc.b 1").unwrap();
+    let program = assemble_file(&dir.join("main.s"), CpuModel::MC68000).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    //DS leaves a gap between chunks
+    assert_eq!(program.chunks.len(), 3);
+    assert_eq!(program.chunks[0].org, 0x100);
+    assert_eq!(program.chunks[0].bytes, [b'i', b't', b'\'', b's', 0, 0, 0, 6, 0xff, 0xff, 0x01, 0x00,
+        0x12, 0x34, 0x56, 0x78]);
+    assert_eq!(program.chunks[1], asm::Chunk { org: 0x116, bytes: vec![0xaa, 0xbb] });
+    assert_eq!(program.chunks[2], asm::Chunk { org: 0x200, bytes: vec![5, 0o17, 0xff, b'A'] });
+    assert_eq!(program.symbols["words"], 0x106);
+    assert_eq!(program.image().1.len(), 0x104);
+    assert_eq!(program.image().1[0x10..0x18], [0, 0, 0, 0, 0, 0, 0xaa, 0xbb]);
+
+    //Reserving most of the address space takes no memory
+    let program = assemble(" ds.l $3fffffff\nlast dc.l 1", CpuModel::MC68020).unwrap();
+    assert_eq!(program.chunks, vec![asm::Chunk { org: 0xfffffffc, bytes: vec![0, 0, 0, 1] }]);
+}
+
+#[test]
+fn programs_load_on_the_model_address_bus() {
+    let program = assemble(" org $1000400\n moveq #1,d0", CpuModel::MC68020).unwrap();
+    let mut cpu = _model_cpu(CpuModel::MC68000, &[]);
+    program.load(&mut cpu).unwrap();
+    assert_eq!(cpu.get_memory_offset(0x400, 2), Some(&[0x70, 0x01][..]));
+
+    let mut cpu = _model_cpu(CpuModel::MC68020, &[]);
+    assert_eq!(program.load(&mut cpu), Err("2 bytes at $1000400 don't fit in the RAM".to_string()));
+}
+
+#[test]
+fn assembler_reports_errors_with_their_line() {
+    let error = |source: &str, model: CpuModel| assemble(source, model).unwrap_err();
+    let at = |line: usize, message: &str| AsmError { file: "<source>".to_string(), line, message: message.to_string() };
+    assert_eq!(error(" nop\n bra nowhere", CpuModel::MC68000), at(2, "undefined symbol nowhere"));
+    assert_eq!(error("x nop\nx nop", CpuModel::MC68000), at(2, "x defined twice"));
+    assert_eq!(error("x equ 1\nx equ 2", CpuModel::MC68000), at(2, "x defined twice"));
+    assert_eq!(error("x nop\nx equ 2", CpuModel::MC68000), at(2, "x defined twice"));
+    assert_eq!(error(" ds.l $7fffffff", CpuModel::MC68000), at(1, "DS runs past the end of the address space"));
+    assert_eq!(error(" org $fffffff0\n ds.b 16\n org $fffffff0\n ds.w 9", CpuModel::MC68000), at(4, "DS runs past the end of the address space"));
+    assert_eq!(error(" dc.b 0\n nop", CpuModel::MC68000), at(2, "instruction at an odd address"));
+    assert_eq!(error(" dc.b 256", CpuModel::MC68000), at(1, "256 doesn't fit in 8 bits"));
+    assert_eq!(error(" move.b d0,a0", CpuModel::MC68000), at(1, "invalid operands"));
+    assert_eq!(error(" extb.l d0", CpuModel::MC68000), at(1, "instruction not available on the MC68000"));
+    assert_eq!(error(" frob d0", CpuModel::MC68000), at(1, "unknown instruction frob"));
+    assert_eq!(error(" bra far\n ds.b 40000\nfar nop", CpuModel::MC68000), at(1, "branch target out of range"));
+    assert_eq!(assemble(" bra far\n ds.b 40000\nfar nop", CpuModel::MC68020).unwrap().chunks[0].bytes[..6],
+        [0x60, 0xff, 0, 0, 0x9c, 0x44]);
+    assert_eq!(error(" moveq #1,d0 oops\n move.w d0,(a0)x", CpuModel::MC68000).line, 2);
+    assert_eq!(error(" add.w #1,d0\n move.w #$12345,d0", CpuModel::MC68000), at(2, "74565 doesn't fit in 16 bits"));
+}